@@ -0,0 +1,189 @@
+//! Benchmarks comparing `understanding_async_await::mpmc` with other async
+//! channels.
+//!
+//! Run all the benchmarks with:
+//!
+//! ```sh
+//! cargo bench --bench channels
+//! ```
+//!
+//! Pass `--profile` to skip divan and run each workload in a loop for a few
+//! seconds instead, which gives a flamegraph without the benchmark harness in
+//! it. Any other arguments filter the workloads by name:
+//!
+//! ```sh
+//! cargo flamegraph --bench channels -- --profile mpmc/mpmc
+//! ```
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use divan::{counter::ItemsCount, Bencher};
+use mpmc_bench::{
+    run, supports, AsyncChannel, Flavor, Flume, Mpmc, Pattern, Tokio, MESSAGES, MPMC, MPSC,
+    PATTERNS, SPMC, SPSC,
+};
+use tokio::runtime::Runtime;
+
+const CAPACITIES: &[usize] = &[1, 16, 1024];
+const SIZES: &[usize] = &[8, 256, 4096];
+
+/// How long each workload is run for in profile mode.
+const PROFILE_DURATION: Duration = Duration::from_secs(5);
+/// The capacity and message size used in profile mode.
+const PROFILE_CAPACITY: usize = 16;
+const PROFILE_SIZE: usize = 256;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--profile") {
+        let filters: Vec<&str> = args
+            .iter()
+            .filter(|arg| !arg.starts_with("--"))
+            .map(String::as_str)
+            .collect();
+        profile(&filters);
+    } else {
+        divan::main();
+    }
+}
+
+/// A multi-threaded runtime shared by all the benchmarks, so that starting
+/// the runtime isn't measured.
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(4)
+            .build()
+            .expect("failed to build runtime")
+    })
+}
+
+fn bench<F: Flavor>(bencher: Bencher, pattern: Pattern, capacity: usize, size: usize) {
+    let runtime = runtime();
+    bencher
+        .counter(ItemsCount::new(MESSAGES))
+        .bench_local(|| runtime.block_on(run::<F>(pattern, capacity, size)));
+}
+
+#[divan::bench_group(sample_count = 20)]
+mod mpmc {
+    use super::*;
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn spsc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<Mpmc>(bencher, SPSC, CAPACITY, size);
+    }
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn mpsc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<Mpmc>(bencher, MPSC, CAPACITY, size);
+    }
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn spmc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<Mpmc>(bencher, SPMC, CAPACITY, size);
+    }
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn mpmc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<Mpmc>(bencher, MPMC, CAPACITY, size);
+    }
+}
+
+/// `tokio::sync::mpsc` only has a single receiver, so it can't be used for the
+/// multi-consumer patterns.
+#[divan::bench_group(sample_count = 20)]
+mod tokio_mpsc {
+    use super::*;
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn spsc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<Tokio>(bencher, SPSC, CAPACITY, size);
+    }
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn mpsc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<Tokio>(bencher, MPSC, CAPACITY, size);
+    }
+}
+
+#[divan::bench_group(sample_count = 20)]
+mod flume {
+    use super::*;
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn spsc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<Flume>(bencher, SPSC, CAPACITY, size);
+    }
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn mpsc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<Flume>(bencher, MPSC, CAPACITY, size);
+    }
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn spmc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<Flume>(bencher, SPMC, CAPACITY, size);
+    }
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn mpmc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<Flume>(bencher, MPMC, CAPACITY, size);
+    }
+}
+
+#[divan::bench_group(sample_count = 20)]
+mod async_channel {
+    use super::*;
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn spsc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<AsyncChannel>(bencher, SPSC, CAPACITY, size);
+    }
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn mpsc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<AsyncChannel>(bencher, MPSC, CAPACITY, size);
+    }
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn spmc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<AsyncChannel>(bencher, SPMC, CAPACITY, size);
+    }
+
+    #[divan::bench(consts = CAPACITIES, args = SIZES)]
+    fn mpmc<const CAPACITY: usize>(bencher: Bencher, size: usize) {
+        bench::<AsyncChannel>(bencher, MPMC, CAPACITY, size);
+    }
+}
+
+/// Runs each workload whose name contains one of `filters` (or all of them
+/// if there are no filters) in a loop.
+fn profile(filters: &[&str]) {
+    profile_flavor::<Mpmc>(filters);
+    profile_flavor::<Tokio>(filters);
+    profile_flavor::<Flume>(filters);
+    profile_flavor::<AsyncChannel>(filters);
+}
+
+fn profile_flavor<F: Flavor>(filters: &[&str]) {
+    let runtime = runtime();
+    for pattern in PATTERNS {
+        let name = format!("{}/{}", F::NAME, pattern.name);
+        let selected = filters.is_empty() || filters.iter().any(|filter| name.contains(filter));
+        if !selected || !supports::<F>(pattern) {
+            continue;
+        }
+
+        let start = Instant::now();
+        let mut runs = 0_u64;
+        while start.elapsed() < PROFILE_DURATION {
+            runtime.block_on(run::<F>(pattern, PROFILE_CAPACITY, PROFILE_SIZE));
+            runs += 1;
+        }
+        let messages = runs * MESSAGES as u64;
+        let per_message = start.elapsed() / messages as u32;
+        println!("{name}: {messages} messages, {per_message:?} per message");
+    }
+}
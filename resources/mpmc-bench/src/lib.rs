@@ -0,0 +1,234 @@
+//! Shared code for benchmarking `understanding_async_await::mpmc` against
+//! other async channels.
+//!
+//! Each channel implementation is wrapped in a [`Flavor`], so that the same
+//! workload in [`run`] can be used for all of them.
+use std::future::Future;
+
+use tokio::task::JoinSet;
+use understanding_async_await::mpmc;
+
+/// How many tasks send and receive on the channel.
+#[derive(Debug, Clone, Copy)]
+pub struct Pattern {
+    pub name: &'static str,
+    pub producers: usize,
+    pub consumers: usize,
+}
+
+pub const SPSC: Pattern = Pattern {
+    name: "spsc",
+    producers: 1,
+    consumers: 1,
+};
+pub const MPSC: Pattern = Pattern {
+    name: "mpsc",
+    producers: 4,
+    consumers: 1,
+};
+pub const SPMC: Pattern = Pattern {
+    name: "spmc",
+    producers: 1,
+    consumers: 4,
+};
+pub const MPMC: Pattern = Pattern {
+    name: "mpmc",
+    producers: 4,
+    consumers: 4,
+};
+
+pub const PATTERNS: [Pattern; 4] = [SPSC, MPSC, SPMC, MPMC];
+
+/// The number of messages sent through the channel in each run.
+pub const MESSAGES: usize = 1024;
+
+/// An async channel implementation to benchmark.
+///
+/// Our mpmc channel only carries `String`s, so all the flavors do the same
+/// and the message size is the length of the string.
+pub trait Flavor {
+    /// The name used when filtering runs in profile mode.
+    const NAME: &'static str;
+
+    type Sender: Clone + Send + 'static;
+    type Receiver: Send + 'static;
+
+    fn channel(capacity: usize) -> (Self::Sender, Self::Receiver);
+
+    /// Returns another receiver for the same channel, or `None` if the
+    /// channel only supports a single consumer.
+    fn clone_receiver(rx: &Self::Receiver) -> Option<Self::Receiver>;
+
+    /// Sends a message, returning `false` if the channel is closed.
+    fn send(tx: &Self::Sender, value: String) -> impl Future<Output = bool> + Send;
+
+    /// Receives a message, returning `None` once the channel is closed and
+    /// empty.
+    fn recv(rx: &mut Self::Receiver) -> impl Future<Output = Option<String>> + Send;
+}
+
+/// `understanding_async_await::mpmc`, this is what we're measuring.
+pub struct Mpmc;
+
+impl Flavor for Mpmc {
+    const NAME: &'static str = "mpmc";
+
+    type Sender = mpmc::Sender;
+    type Receiver = mpmc::Receiver;
+
+    fn channel(capacity: usize) -> (Self::Sender, Self::Receiver) {
+        mpmc::channel(capacity)
+    }
+
+    fn clone_receiver(rx: &Self::Receiver) -> Option<Self::Receiver> {
+        Some(rx.clone())
+    }
+
+    async fn send(tx: &Self::Sender, value: String) -> bool {
+        tx.send(value).await.is_ok()
+    }
+
+    async fn recv(rx: &mut Self::Receiver) -> Option<String> {
+        rx.recv().await.ok()
+    }
+}
+
+/// `tokio::sync::mpsc`, which only supports a single consumer.
+pub struct Tokio;
+
+impl Flavor for Tokio {
+    const NAME: &'static str = "tokio";
+
+    type Sender = tokio::sync::mpsc::Sender<String>;
+    type Receiver = tokio::sync::mpsc::Receiver<String>;
+
+    fn channel(capacity: usize) -> (Self::Sender, Self::Receiver) {
+        tokio::sync::mpsc::channel(capacity)
+    }
+
+    fn clone_receiver(_rx: &Self::Receiver) -> Option<Self::Receiver> {
+        None
+    }
+
+    async fn send(tx: &Self::Sender, value: String) -> bool {
+        tx.send(value).await.is_ok()
+    }
+
+    async fn recv(rx: &mut Self::Receiver) -> Option<String> {
+        rx.recv().await
+    }
+}
+
+/// The `flume` crate.
+pub struct Flume;
+
+impl Flavor for Flume {
+    const NAME: &'static str = "flume";
+
+    type Sender = flume::Sender<String>;
+    type Receiver = flume::Receiver<String>;
+
+    fn channel(capacity: usize) -> (Self::Sender, Self::Receiver) {
+        flume::bounded(capacity)
+    }
+
+    fn clone_receiver(rx: &Self::Receiver) -> Option<Self::Receiver> {
+        Some(rx.clone())
+    }
+
+    async fn send(tx: &Self::Sender, value: String) -> bool {
+        tx.send_async(value).await.is_ok()
+    }
+
+    async fn recv(rx: &mut Self::Receiver) -> Option<String> {
+        rx.recv_async().await.ok()
+    }
+}
+
+/// The `async-channel` crate.
+pub struct AsyncChannel;
+
+impl Flavor for AsyncChannel {
+    const NAME: &'static str = "async-channel";
+
+    type Sender = async_channel::Sender<String>;
+    type Receiver = async_channel::Receiver<String>;
+
+    fn channel(capacity: usize) -> (Self::Sender, Self::Receiver) {
+        async_channel::bounded(capacity)
+    }
+
+    fn clone_receiver(rx: &Self::Receiver) -> Option<Self::Receiver> {
+        Some(rx.clone())
+    }
+
+    async fn send(tx: &Self::Sender, value: String) -> bool {
+        tx.send(value).await.is_ok()
+    }
+
+    async fn recv(rx: &mut Self::Receiver) -> Option<String> {
+        rx.recv().await.ok()
+    }
+}
+
+/// Returns whether the flavor can be run with the pattern.
+pub fn supports<F: Flavor>(pattern: Pattern) -> bool {
+    let (_tx, rx) = F::channel(1);
+    pattern.consumers == 1 || F::clone_receiver(&rx).is_some()
+}
+
+/// Sends [`MESSAGES`] messages of `size` bytes through a channel with the
+/// given capacity, split between the producers and consumers of `pattern`.
+///
+/// Each producer and consumer is spawned as a separate task. Returns once
+/// every message has been received.
+pub async fn run<F: Flavor>(pattern: Pattern, capacity: usize, size: usize) {
+    let (tx, rx) = F::channel(capacity);
+    let payload = "x".repeat(size);
+
+    let mut producers = JoinSet::new();
+    for producer in 0..pattern.producers {
+        let tx = tx.clone();
+        let payload = payload.clone();
+        // Spread the remainder over the first few producers.
+        let count = MESSAGES / pattern.producers
+            + usize::from(producer < MESSAGES % pattern.producers);
+        producers.spawn(async move {
+            for _ in 0..count {
+                if !F::send(&tx, payload.clone()).await {
+                    panic!("channel closed before all messages were sent");
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    let mut receivers = Vec::with_capacity(pattern.consumers);
+    for _ in 1..pattern.consumers {
+        let Some(rx) = F::clone_receiver(&rx) else {
+            panic!("{} doesn't support multiple consumers", F::NAME);
+        };
+        receivers.push(rx);
+    }
+    receivers.push(rx);
+
+    let mut consumers = JoinSet::new();
+    for mut rx in receivers {
+        consumers.spawn(async move {
+            let mut received = 0;
+            while F::recv(&mut rx).await.is_some() {
+                received += 1;
+            }
+            received
+        });
+    }
+
+    while let Some(result) = producers.join_next().await {
+        result.expect("producer panicked");
+    }
+    let mut received = 0;
+    while let Some(result) = consumers.join_next().await {
+        received += result.expect("consumer panicked");
+    }
+    assert_eq!(received, MESSAGES, "{} lost messages", F::NAME);
+}
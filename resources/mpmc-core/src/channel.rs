@@ -0,0 +1,598 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+use core::task::Waker;
+
+/// The inner mpmc channel implementation.
+///
+/// This is a sync object. All methods return immediately.
+///
+/// A channel is shared between its senders and receivers behind a [`Lock`].
+/// The async layer calls [`send`] or [`recv`] and if the channel is full or
+/// empty, registers the task's waker so that it is woken once it can try
+/// again. Each waiter is identified by the id returned when it first
+/// registers, which it passes back in later calls.
+///
+/// Wakers are woken while the lock is held, so a waker must not try to poll
+/// the task it wakes straight away.
+///
+/// [`Lock`]: trait@crate::Lock
+/// [`send`]: fn@Self::send
+/// [`recv`]: fn@Self::recv
+pub struct Channel<T> {
+    /// The message buffer
+    buffer: VecDeque<T>,
+    /// The capacity of the channel, this many messages can be buffered before
+    /// sending will error.
+    capacity: usize,
+    /// Indicates when the channel has been closed.
+    closed: bool,
+    /// Whether free slots and messages are reserved for the waiters which are
+    /// woken for them.
+    fair: bool,
+
+    /// The number of connected `Sender`s.
+    senders: usize,
+    /// The number of active `Receiver`s.
+    receivers: usize,
+
+    /// A queue of wakers for senders awaiting free capacity in the channel,
+    /// together with the id of the waiter which registered them.
+    sender_wakers: VecDeque<(u64, Waker)>,
+    /// The id which will be given to the next registered sender waiter.
+    next_sender_id: u64,
+    /// Senders which have been woken and have a free slot reserved for them.
+    ///
+    /// Only used in fair mode.
+    reserved_senders: Vec<u64>,
+    /// A queue of wakers for receivers awaiting a new message in the channel,
+    /// together with the id of the waiter which registered them.
+    receiver_wakers: VecDeque<(u64, Waker)>,
+    /// The id which will be given to the next registered receiver waiter.
+    next_receiver_id: u64,
+    /// Receivers which have been woken and have a message reserved for them.
+    ///
+    /// Only used in fair mode.
+    reserved_receivers: Vec<u64>,
+    /// Wakers for tasks waiting for the channel to close.
+    closed_wakers: Vec<Waker>,
+}
+
+impl<T> Channel<T> {
+    /// Creates a new empty channel.
+    ///
+    /// With `fair` set, free slots and messages are reserved for the waiters
+    /// which are woken for them, so that waiters are served in strict FIFO
+    /// order.
+    pub fn new(capacity: usize, fair: bool) -> Self {
+        Self {
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            closed: false,
+            fair,
+
+            senders: 0,
+            receivers: 0,
+
+            sender_wakers: VecDeque::new(),
+            next_sender_id: 0,
+            reserved_senders: Vec::new(),
+            receiver_wakers: VecDeque::new(),
+            next_receiver_id: 0,
+            reserved_receivers: Vec::new(),
+            closed_wakers: Vec::new(),
+        }
+    }
+
+    /// Sends a message across the channel.
+    ///
+    /// The sender waiter `id` is used to claim a slot which was reserved for
+    /// it in fair mode. If the message can be sent, the next receiver waker in
+    /// the queue (if any) will be woken as there is now an additional message
+    /// which can be received.
+    ///
+    /// An error will be returned if the channel is full or closed. In fair
+    /// mode, the channel is also full for a sender without a reservation if
+    /// other senders are already waiting.
+    pub fn send(&mut self, id: Option<u64>, value: T) -> Result<(), SendError> {
+        if self.closed {
+            return Err(SendError::Closed);
+        }
+
+        let reserved = id.is_some_and(|id| take(&mut self.reserved_senders, id));
+        let has_capacity = if self.fair {
+            self.sender_wakers.is_empty()
+                && self.buffer.len() + self.reserved_senders.len() < self.capacity
+        } else {
+            self.buffer.len() < self.capacity
+        };
+
+        if reserved || has_capacity {
+            self.buffer.push_back(value);
+            self.wake_next_receiver();
+            Ok(())
+        } else {
+            Err(SendError::Full)
+        }
+    }
+
+    /// Receives a message from the channel.
+    ///
+    /// The receiver waiter `id` is used to claim a message which was reserved
+    /// for it in fair mode. If a message can be received, then the next sender
+    /// waker in the queue (if any) will be woken as there is now additional
+    /// free capacity to send another message.
+    ///
+    /// An error will be returned if the channel is empty. The error will
+    /// depend on whether the channel is also closed. In fair mode, the channel
+    /// is also empty for a receiver without a reservation if all the messages
+    /// are reserved or other receivers are already waiting.
+    pub fn recv(&mut self, id: Option<u64>) -> Result<T, RecvError> {
+        let reserved = id.is_some_and(|id| take(&mut self.reserved_receivers, id));
+        let available = reserved
+            || !self.fair
+            || self.closed
+            || (self.receiver_wakers.is_empty()
+                && self.buffer.len() > self.reserved_receivers.len());
+
+        let value = if available {
+            self.buffer.pop_front()
+        } else {
+            None
+        };
+
+        match value {
+            Some(value) => {
+                self.wake_next_sender();
+                Ok(value)
+            }
+            None => {
+                if !self.closed {
+                    Err(RecvError::Empty)
+                } else {
+                    Err(RecvError::Closed)
+                }
+            }
+        }
+    }
+
+    /// Puts a message which was received but couldn't be delivered back at
+    /// the front of the buffer, so that it is the next one received.
+    ///
+    /// This may take the buffer over its capacity, like a message which was
+    /// never taken out. The next receiver waker in the queue (if any) will be
+    /// woken, even if the channel is closed, as the message can still be
+    /// drained.
+    pub fn requeue(&mut self, value: T) {
+        self.buffer.push_front(value);
+        self.wake_next_receiver();
+    }
+
+    /// Returns whether the channel has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Registers a waker to be woken when the channel closes.
+    ///
+    /// Unlike sender and receiver waiters, these wakers don't take part in
+    /// the FIFO queues, so waiting for the channel to close never uses up a
+    /// wake up meant for a sender or receiver. A waker which would wake the
+    /// same task as one which is already registered isn't added again.
+    pub fn register_closed_waker(&mut self, waker: Waker) {
+        if !self.closed_wakers.iter().any(|w| w.will_wake(&waker)) {
+            self.closed_wakers.push(waker);
+        }
+    }
+
+    /// Registers a waker to be woken when capacity is available.
+    ///
+    /// Senders are woken in FIFO order. A waiter which is still in the queue
+    /// from a previous registration keeps its place, only its waker is
+    /// replaced. Otherwise it is added to the back of the queue.
+    ///
+    /// Returns the id which identifies the waiter in the queue.
+    pub fn register_sender_waker(&mut self, id: Option<u64>, waker: Waker) -> u64 {
+        register(&mut self.sender_wakers, &mut self.next_sender_id, id, waker)
+    }
+
+    /// Removes a sender waiter from the queue.
+    ///
+    /// This is called when a sender stops waiting without having sent its
+    /// message. If the waiter is no longer in the queue, then it has already
+    /// been woken for capacity which it won't use (in fair mode, a slot may
+    /// have been reserved for it), so the next sender is woken in its place.
+    pub fn deregister_sender_waker(&mut self, id: u64) {
+        if take(&mut self.reserved_senders, id)
+            || (!self.remove_sender_waker(id) && self.buffer.len() < self.capacity)
+        {
+            self.wake_next_sender();
+        }
+    }
+
+    /// Removes a sender waiter from the queue if it is still there.
+    ///
+    /// Returns whether the waiter was found in the queue.
+    pub fn remove_sender_waker(&mut self, id: u64) -> bool {
+        remove(&mut self.sender_wakers, id)
+    }
+
+    /// Registers a waker to be woken when a message is available.
+    ///
+    /// Receivers are woken in FIFO order. A waiter which is still in the queue
+    /// from a previous registration keeps its place, only its waker is
+    /// replaced. Otherwise it is added to the back of the queue.
+    ///
+    /// Returns the id which identifies the waiter in the queue.
+    pub fn register_receiver_waker(&mut self, id: Option<u64>, waker: Waker) -> u64 {
+        register(
+            &mut self.receiver_wakers,
+            &mut self.next_receiver_id,
+            id,
+            waker,
+        )
+    }
+
+    /// Removes a receiver waiter from the queue.
+    ///
+    /// This is called when a receiver stops waiting without having received a
+    /// message. If the waiter is no longer in the queue, then it has already
+    /// been woken for a message which it won't receive (in fair mode, the
+    /// message may have been reserved for it), so the next receiver is woken
+    /// in its place.
+    pub fn deregister_receiver_waker(&mut self, id: u64) {
+        if take(&mut self.reserved_receivers, id)
+            || (!self.remove_receiver_waker(id) && !self.buffer.is_empty())
+        {
+            self.wake_next_receiver();
+        }
+    }
+
+    /// Removes a receiver waiter from the queue if it is still there.
+    ///
+    /// Returns whether the waiter was found in the queue.
+    pub fn remove_receiver_waker(&mut self, id: u64) -> bool {
+        remove(&mut self.receiver_wakers, id)
+    }
+
+    /// Wakes the sender at the front of the queue.
+    ///
+    /// In fair mode, a slot is reserved for the woken sender. If no sender
+    /// wakers are registered, this method does nothing.
+    fn wake_next_sender(&mut self) {
+        if let Some((id, waker)) = self.sender_wakers.pop_front() {
+            if self.fair && !self.closed {
+                self.reserved_senders.push(id);
+            }
+            waker.wake();
+        }
+    }
+
+    /// Wakes the receiver at the front of the queue.
+    ///
+    /// In fair mode, a message is reserved for the woken receiver. If no
+    /// receiver wakers are registered, this method does nothing.
+    fn wake_next_receiver(&mut self) {
+        if let Some((id, waker)) = self.receiver_wakers.pop_front() {
+            if self.fair && !self.closed {
+                self.reserved_receivers.push(id);
+            }
+            waker.wake();
+        }
+    }
+
+    /// Increment the sender count.
+    pub fn inc_senders(&mut self) {
+        self.senders += 1;
+    }
+
+    /// Decrement the sender count.
+    ///
+    /// If the count reaches zero, close the channel.
+    pub fn dec_senders(&mut self) {
+        self.senders -= 1;
+        if self.senders == 0 {
+            self.close();
+        }
+    }
+
+    /// Increment the receiver count.
+    pub fn inc_receivers(&mut self) {
+        self.receivers += 1;
+    }
+
+    /// Decrement the receiver count.
+    ///
+    /// If the count reaches zero, close the channel.
+    pub fn dec_receivers(&mut self) {
+        self.receivers -= 1;
+        if self.receivers == 0 {
+            self.close();
+        }
+    }
+
+    /// Close the channel.
+    ///
+    /// All sender, receiver and closed wakers which have been registered, but
+    /// not yet woken will get woken now.
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.reserved_senders.clear();
+        self.reserved_receivers.clear();
+
+        while let Some((_, waker)) = self.sender_wakers.pop_front() {
+            waker.wake();
+        }
+        while let Some((_, waker)) = self.receiver_wakers.pop_front() {
+            waker.wake();
+        }
+        for waker in self.closed_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// Adds a waiter to a waker queue, or replaces its waker if it's already
+/// there.
+///
+/// Returns the id of the waiter.
+fn register(
+    queue: &mut VecDeque<(u64, Waker)>,
+    next_id: &mut u64,
+    id: Option<u64>,
+    waker: Waker,
+) -> u64 {
+    if let Some(id) = id {
+        if let Some((_, registered)) = queue.iter_mut().find(|(waiter_id, _)| *waiter_id == id) {
+            *registered = waker;
+            return id;
+        }
+    }
+
+    let id = *next_id;
+    *next_id += 1;
+    queue.push_back((id, waker));
+    id
+}
+
+/// Removes a waiter from a waker queue.
+///
+/// Returns whether the waiter was found in the queue.
+fn remove(queue: &mut VecDeque<(u64, Waker)>, id: u64) -> bool {
+    match queue.iter().position(|(waiter_id, _)| *waiter_id == id) {
+        Some(position) => {
+            queue.remove(position);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes `id` from a list of reservations.
+///
+/// Returns whether the reservation was found.
+fn take(reservations: &mut Vec<u64>, id: u64) -> bool {
+    match reservations.iter().position(|reserved| *reserved == id) {
+        Some(position) => {
+            reservations.swap_remove(position);
+            true
+        }
+        None => false,
+    }
+}
+
+/// The reason a message couldn't be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    /// The channel has no free capacity.
+    Full,
+    /// The channel has been closed.
+    Closed,
+}
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for SendError {}
+
+/// The reason a message couldn't be received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// The channel has no messages available.
+    Empty,
+    /// The channel has been closed and is empty.
+    Closed,
+}
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for RecvError {}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use alloc::task::Wake;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::Waker;
+
+    use super::{Channel, RecvError, SendError};
+
+    /// Counts how many times it has been woken.
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn waker() -> (Arc<CountingWaker>, Waker) {
+        let counter = Arc::new(CountingWaker::default());
+        (Arc::clone(&counter), Waker::from(counter))
+    }
+
+    fn wakes(counter: &CountingWaker) -> usize {
+        counter.0.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn reregistered_waiter_keeps_its_place() {
+        let mut channel = Channel::new(1, false);
+        let (first, first_waker) = waker();
+        let (second, second_waker) = waker();
+
+        let first_id = channel.register_receiver_waker(None, first_waker.clone());
+        channel.register_receiver_waker(None, second_waker);
+        assert_eq!(
+            channel.register_receiver_waker(Some(first_id), first_waker),
+            first_id
+        );
+
+        channel.send(None, "a").unwrap();
+        assert_eq!((wakes(&first), wakes(&second)), (1, 0));
+    }
+
+    #[test]
+    fn removed_waiter_isnt_woken() {
+        let mut channel = Channel::new(1, false);
+        let (first, first_waker) = waker();
+        let (second, second_waker) = waker();
+
+        let first_id = channel.register_receiver_waker(None, first_waker);
+        channel.register_receiver_waker(None, second_waker);
+        assert!(channel.remove_receiver_waker(first_id));
+        assert!(!channel.remove_receiver_waker(first_id));
+
+        channel.send(None, "a").unwrap();
+        assert_eq!((wakes(&first), wakes(&second)), (0, 1));
+    }
+
+    #[test]
+    fn deregistering_a_woken_waiter_passes_on_the_wake() {
+        let mut channel = Channel::new(1, false);
+        let (first, first_waker) = waker();
+        let (second, second_waker) = waker();
+
+        let first_id = channel.register_receiver_waker(None, first_waker);
+        channel.register_receiver_waker(None, second_waker);
+        channel.send(None, "a").unwrap();
+        assert_eq!((wakes(&first), wakes(&second)), (1, 0));
+
+        channel.deregister_receiver_waker(first_id);
+        assert_eq!((wakes(&first), wakes(&second)), (1, 1));
+    }
+
+    #[test]
+    fn deregistering_a_queued_waiter_wakes_nobody() {
+        let mut channel = Channel::new(1, false);
+        channel.send(None, "a").unwrap();
+        let (first, first_waker) = waker();
+        let (second, second_waker) = waker();
+
+        let first_id = channel.register_sender_waker(None, first_waker);
+        channel.register_sender_waker(None, second_waker);
+        channel.deregister_sender_waker(first_id);
+        assert_eq!((wakes(&first), wakes(&second)), (0, 0));
+
+        channel.recv(None).unwrap();
+        assert_eq!((wakes(&first), wakes(&second)), (0, 1));
+    }
+
+    #[test]
+    fn fair_channel_reserves_a_message_for_the_woken_receiver() {
+        let mut channel = Channel::new(1, true);
+        let (_counter, waker) = waker();
+
+        let id = channel.register_receiver_waker(None, waker);
+        channel.send(None, "a").unwrap();
+        assert_eq!(channel.recv(None), Err(RecvError::Empty));
+        assert_eq!(channel.recv(Some(id)), Ok("a"));
+    }
+
+    #[test]
+    fn fair_channel_reserves_a_slot_for_the_woken_sender() {
+        let mut channel = Channel::new(1, true);
+        let (_counter, waker) = waker();
+
+        channel.send(None, "a").unwrap();
+        assert_eq!(channel.send(None, "b"), Err(SendError::Full));
+        let id = channel.register_sender_waker(None, waker);
+        assert_eq!(channel.recv(None), Ok("a"));
+
+        assert_eq!(channel.send(None, "c"), Err(SendError::Full));
+        assert_eq!(channel.send(Some(id), "b"), Ok(()));
+    }
+
+    #[test]
+    fn deregistered_reservation_passes_to_the_next_waiter() {
+        let mut channel = Channel::new(1, true);
+        let (first, first_waker) = waker();
+        let (second, second_waker) = waker();
+
+        let first_id = channel.register_receiver_waker(None, first_waker);
+        let second_id = channel.register_receiver_waker(None, second_waker);
+        channel.send(None, "a").unwrap();
+        channel.deregister_receiver_waker(first_id);
+
+        assert_eq!((wakes(&first), wakes(&second)), (1, 1));
+        assert_eq!(channel.recv(Some(first_id)), Err(RecvError::Empty));
+        assert_eq!(channel.recv(Some(second_id)), Ok("a"));
+    }
+
+    #[test]
+    fn close_wakes_every_waiter_and_drains() {
+        let mut channel = Channel::new(1, true);
+        let (sender, sender_waker) = waker();
+        let (receiver, receiver_waker) = waker();
+        let (closed, closed_waker) = waker();
+
+        channel.send(None, "a").unwrap();
+        channel.register_sender_waker(None, sender_waker);
+        channel.register_receiver_waker(None, receiver_waker);
+        channel.register_closed_waker(closed_waker);
+        channel.close();
+
+        assert!(channel.is_closed());
+        assert_eq!(
+            (wakes(&sender), wakes(&receiver), wakes(&closed)),
+            (1, 1, 1)
+        );
+        assert_eq!(channel.send(None, "b"), Err(SendError::Closed));
+        assert_eq!(channel.recv(None), Ok("a"));
+        assert_eq!(channel.recv(None), Err(RecvError::Closed));
+    }
+
+    #[test]
+    fn dropping_the_last_sender_closes() {
+        let mut channel = Channel::<&str>::new(1, false);
+        channel.inc_senders();
+        channel.inc_senders();
+
+        channel.dec_senders();
+        assert!(!channel.is_closed());
+        channel.dec_senders();
+        assert!(channel.is_closed());
+    }
+
+    #[test]
+    fn requeued_message_is_received_next() {
+        let mut channel = Channel::new(2, false);
+        channel.send(None, "a").unwrap();
+        channel.send(None, "b").unwrap();
+
+        let value = channel.recv(None).unwrap();
+        let (counter, waker) = waker();
+        channel.register_receiver_waker(None, waker);
+        channel.requeue(value);
+
+        assert_eq!(wakes(&counter), 1);
+        assert_eq!(channel.recv(None), Ok("a"));
+        assert_eq!(channel.recv(None), Ok("b"));
+    }
+}
@@ -0,0 +1,24 @@
+//! The synchronous core of the mpmc channel from `understanding-async-await`.
+//!
+//! A [`Channel`] holds the message buffer, the sender and receiver counts,
+//! and the queues of wakers for senders and receivers which are waiting. All
+//! its methods return immediately, the async `Sender` and `Receiver` are
+//! built on top of it, sharing a `Channel` behind a lock.
+//!
+//! This crate is `no_std` and only needs `alloc`, so that the channel can be
+//! used on targets without an operating system. Any lock which implements
+//! [`Lock`] can be used to share a channel, [`SpinLock`] is provided for
+//! targets which don't have anything better. With the `std` feature enabled,
+//! `std::sync::Mutex` implements `Lock` as well, and [`SendError`] and
+//! [`RecvError`] implement `std::error::Error`.
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod channel;
+mod lock;
+
+pub use channel::{Channel, RecvError, SendError};
+pub use lock::{Lock, SpinLock, SpinLockGuard};
@@ -0,0 +1,157 @@
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::hint;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A lock which gives exclusive access to a value.
+///
+/// This is all a [`Channel`] needs to be shared between senders and
+/// receivers. The channel never blocks while it holds the lock, it only
+/// modifies its buffer and queues and wakes wakers, so even a spinlock is a
+/// reasonable choice.
+///
+/// [`Channel`]: struct@crate::Channel
+pub trait Lock<T> {
+    /// The guard returned by [`lock`]. The lock is released when it is dropped.
+    ///
+    /// [`lock`]: fn@Self::lock
+    type Guard<'a>: DerefMut<Target = T>
+    where
+        Self: 'a;
+
+    /// Creates a new unlocked lock containing `value`.
+    fn new(value: T) -> Self;
+
+    /// Acquires the lock, waiting until it is available.
+    fn lock(&self) -> Self::Guard<'_>;
+}
+
+/// A simple spinlock.
+///
+/// Waiting for the lock spins in a loop, so it should only be held for short
+/// periods of time. This is the default lock for targets without an operating
+/// system.
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: The lock only gives out access to the value to one guard at a time,
+// so it can be shared between threads as long as the value can be sent between
+// them.
+unsafe impl<T: Send> Send for SpinLock<T> {}
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    /// Creates a new unlocked spinlock containing `value`.
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Acquires the lock, spinning until it is available.
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            // Only read while waiting, so that the cache line isn't bounced
+            // between cores by failed writes.
+            while self.locked.load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+        }
+    }
+
+    /// Acquires the lock if it is available right now.
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        self.locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| SpinLockGuard { lock: self })
+    }
+
+    /// Consumes the lock, returning the value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SpinLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("SpinLock");
+        match self.try_lock() {
+            Some(guard) => d.field("value", &&*guard),
+            None => d.field("value", &format_args!("<locked>")),
+        };
+        d.finish()
+    }
+}
+
+impl<T> Lock<T> for SpinLock<T> {
+    type Guard<'a>
+        = SpinLockGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        SpinLock::new(value)
+    }
+
+    fn lock(&self) -> Self::Guard<'_> {
+        SpinLock::lock(self)
+    }
+}
+
+/// The guard for a [`SpinLock`], the lock is released when it is dropped.
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The guard holds the lock, so there is no other access.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The guard holds the lock, so there is no other access.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> Lock<T> for std::sync::Mutex<T> {
+    type Guard<'a>
+        = std::sync::MutexGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        std::sync::Mutex::new(value)
+    }
+
+    /// Acquires the mutex.
+    ///
+    /// A channel which was being modified when another thread panicked can't
+    /// be trusted, so this panics if the mutex is poisoned.
+    fn lock(&self) -> Self::Guard<'_> {
+        match std::sync::Mutex::lock(self) {
+            Ok(guard) => guard,
+            Err(_) => panic!("MPMC Channel has become corrupted."),
+        }
+    }
+}
@@ -1,22 +1,27 @@
 use std::time::Duration;
 
-use understanding_async_await::mpmc;
+use understanding_async_await::mpmc::{self, ChannelClosedError};
+use understanding_async_await::scope::TaskScope;
+use understanding_async_await::time;
 
 #[tokio::main]
 async fn main() {
-    let mut tx_tasks = Vec::new();
-    let mut rx_tasks = Vec::new();
+    let mut scope = TaskScope::<(), ChannelClosedError>::new(tokio::runtime::Handle::current());
 
     let (tx, rx) = mpmc::channel(10);
 
+    // The receivers take a little longer over each value than the senders
+    // do. The timer has a resolution of one millisecond, shorter sleeps are
+    // rounded up.
+
     for idx in 0..2 {
         let rx = rx.clone();
-        let jh = tokio::spawn(async move {
+        scope.spawn(async move {
             loop {
                 match rx.recv().await {
                     Ok(val) => {
                         println!("rx-{idx:0>2}: received value: {val}");
-                        tokio::time::sleep(Duration::from_micros(100)).await;
+                        time::sleep(Duration::from_millis(10)).await;
                     }
                     Err(_) => {
                         println!("rx-{idx:0>2}: channel closed");
@@ -24,27 +29,27 @@ async fn main() {
                     }
                 }
             }
+            Ok(())
         });
-        rx_tasks.push(jh);
     }
 
     for idx in 0..3 {
         let tx = tx.clone();
-        let jh = tokio::spawn(async move {
+        scope.spawn(async move {
             for val in 0..2 {
                 let value = format!("{val}-from-tx-{idx:0>2}");
                 println!("tx-{idx:0>2}: sending value: {value}");
-                if tx.send(value).await.is_err() {
-                    println!("tx-{idx:0>2}: channel closed");
-                    break;
-                }
-                tokio::time::sleep(Duration::from_micros(80)).await;
+                tx.send(value).await?;
+                time::sleep(Duration::from_millis(8)).await;
             }
+            Ok(())
         });
-        tx_tasks.push(jh);
     }
 
-    for jh in tx_tasks {
-        _ = jh.await;
+    // Once the senders are done, the channel closes and the receivers finish
+    // too, so the scope waits for all of them.
+    drop((tx, rx));
+    if let Err(err) = scope.join_all().await {
+        println!("task failed: {err}");
     }
 }
@@ -0,0 +1,59 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use understanding_async_await::mpmc::{self, Receiver, Sender};
+
+const SENDERS: u64 = 32;
+const MESSAGES_PER_SENDER: u64 = 200;
+
+#[tokio::main(flavor = "multi_thread", worker_threads = 4)]
+async fn main() {
+    let (tx, rx) = mpmc::channel(1);
+    let worst = measure_worst_wait(tx, rx).await;
+    println!("mpmc::channel: worst case a sender was overtaken by {worst} sends");
+
+    let (tx, rx) = mpmc::fair_channel(1);
+    let worst = measure_worst_wait(tx, rx).await;
+    println!("mpmc::fair_channel: worst case a sender was overtaken by {worst} sends");
+}
+
+/// Sends from many senders at once, returning the largest number of other
+/// sends which completed while a single send was waiting.
+///
+/// With a fair channel, this is at most the number of other senders. If the
+/// machine has fewer cores than the runtime has worker threads, a worker can
+/// be descheduled in the middle of a measurement, which will inflate it.
+async fn measure_worst_wait(tx: Sender, rx: Receiver) -> u64 {
+    let completed = Arc::new(AtomicU64::new(0));
+
+    let receiver_handle = tokio::spawn(async move { while rx.recv().await.is_ok() {} });
+
+    let mut sender_handles = Vec::new();
+    for idx in 0..SENDERS {
+        let tx = tx.clone();
+        let completed = Arc::clone(&completed);
+        sender_handles.push(tokio::spawn(async move {
+            let mut worst = 0;
+            for val in 0..MESSAGES_PER_SENDER {
+                let start = completed.load(Ordering::SeqCst);
+                tx.send(format!("{val}-from-tx-{idx:0>2}"))
+                    .await
+                    .expect("the channel has closed early");
+                let end = completed.fetch_add(1, Ordering::SeqCst);
+                worst = worst.max(end - start);
+            }
+            worst
+        }));
+    }
+    drop(tx);
+
+    let mut worst = 0;
+    for jh in sender_handles {
+        worst = worst.max(jh.await.expect("sender task panicked"));
+    }
+    _ = receiver_handle.await;
+
+    worst
+}
@@ -0,0 +1,110 @@
+//! Shows what a `Checked` future reports when it is polled after completion.
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::pin;
+
+use understanding_async_await::future::FutureExt;
+use understanding_async_await::runtime;
+use understanding_async_await::testing::{poll_once, poll_until_ready, MockWaker};
+
+#[path = "hello.rs"]
+#[allow(dead_code)]
+mod hello;
+#[path = "two_step_async.rs"]
+#[allow(dead_code)]
+mod two_step_async;
+
+fn main() {
+    tracing_subscriber::fmt()
+        .with_target(false)
+        .without_time()
+        .init();
+
+    report_with_debug_state();
+    report_without_debug_state();
+    log_instead_of_panicking();
+    checked_await();
+}
+
+fn report_with_debug_state() {
+    let waker = MockWaker::new();
+    let mut triple_add = pin!(two_step_async::manual_future::triple_add(1, 2, 3)
+        .checked()
+        .with_debug_state());
+    let (sum, _) = poll_until_ready(triple_add.as_mut(), &waker, 10);
+    assert_eq!(sum, 6);
+    assert!(triple_add.is_completed());
+
+    let line = line!() + 1;
+    let message = expect_panic(|| poll_once(triple_add, &waker));
+    println!("{message}\n");
+    assert!(message.contains("manual_future::TripleAdd polled after completion"));
+    assert!(message.contains(&format!("checked_futures.rs:{line}:")));
+    assert!(message.contains("state: Done"));
+    println!("ok: a poll after completion reports its location, the type and the state\n");
+}
+
+fn report_without_debug_state() {
+    // `hello` returns `impl Future`, so its state can't be shown.
+    let waker = MockWaker::new();
+    let mut hello = pin!(hello::manual_future::hello("world").checked());
+    poll_once(hello.as_mut(), &waker).assert_ready();
+
+    let message = expect_panic(|| poll_once(hello, &waker));
+    println!("{message}\n");
+    assert!(message.contains("state: <not Debug>"));
+    assert!(
+        !message.contains("Please stop polling me!"),
+        "the wrapped future mustn't be polled again"
+    );
+    println!("ok: a future without Debug is still reported\n");
+}
+
+fn log_instead_of_panicking() {
+    let waker = MockWaker::new();
+    let mut triple_add = pin!(two_step_async::manual_future::triple_add(1, 2, 3)
+        .checked()
+        .with_debug_state()
+        .log_repolls());
+    poll_until_ready(triple_add.as_mut(), &waker, 10);
+
+    // Logged as an error, then `Pending` without a waker, like a fused
+    // future.
+    for _ in 0..2 {
+        poll_once(triple_add.as_mut(), &waker).assert_pending_without_waker();
+    }
+    println!("ok: in log mode, a poll after completion is logged and returns Pending\n");
+}
+
+fn checked_await() {
+    // Awaiting a checked future is no different from awaiting the future
+    // itself.
+    let sum = runtime::block_on(async {
+        two_step_async::manual_future::triple_add2(1, 2, 3)
+            .checked()
+            .with_debug_state()
+            .await
+    });
+    assert_eq!(sum, 6);
+    println!("ok: a checked future can be awaited as usual");
+}
+
+/// Runs `f`, checks that it panics and returns the panic message, without
+/// printing it.
+fn expect_panic<R>(f: impl FnOnce() -> R) -> String {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    panic::set_hook(hook);
+
+    let payload = match result {
+        Ok(_) => panic!("expected a panic"),
+        Err(payload) => payload,
+    };
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .unwrap_or_default(),
+    }
+}
@@ -0,0 +1,53 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use understanding_async_await::mpmc;
+
+const MESSAGES: usize = 10_000;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let ticks = consume_with_budget(None).await;
+    println!("Without a budget, the sibling task ticked {ticks} times");
+
+    let ticks = consume_with_budget(Some(mpmc::DEFAULT_BUDGET)).await;
+    println!(
+        "With a budget of {}, the sibling task ticked {ticks} times",
+        mpmc::DEFAULT_BUDGET
+    );
+}
+
+/// Receives every message from a full channel while a sibling task on the
+/// same thread tries to make progress.
+///
+/// Returns the number of times the sibling task ran before all the messages
+/// had been received.
+async fn consume_with_budget(budget: Option<u32>) -> u64 {
+    let (tx, mut rx) = mpmc::channel(MESSAGES);
+    rx.set_budget(budget);
+    for val in 0..MESSAGES {
+        tx.send(format!("{val}"))
+            .await
+            .expect("the channel has closed early");
+    }
+    drop(tx);
+
+    let ticks = Arc::new(AtomicU64::new(0));
+    let sibling = tokio::spawn({
+        let ticks = Arc::clone(&ticks);
+        async move {
+            loop {
+                ticks.fetch_add(1, Ordering::SeqCst);
+                tokio::task::yield_now().await;
+            }
+        }
+    });
+
+    while rx.recv().await.is_ok() {}
+    let ticks = ticks.load(Ordering::SeqCst);
+    sibling.abort();
+
+    ticks
+}
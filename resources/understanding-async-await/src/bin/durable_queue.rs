@@ -0,0 +1,70 @@
+use std::{fs, io::Write, path::Path};
+
+use understanding_async_await::mpmc::durable;
+
+#[tokio::main]
+async fn main() {
+    let dir = std::env::temp_dir().join("understanding-async-await-durable-queue");
+    _ = fs::remove_dir_all(&dir);
+
+    println!("First run:");
+    {
+        let (tx, rx) = durable::open(&dir, 10).expect("failed to open durable channel");
+        for idx in 0..5 {
+            tx.send(format!("job-{idx}"))
+                .await
+                .expect("failed to send job");
+        }
+
+        let message = rx.recv().await.expect("the channel has closed early");
+        println!("  Received and committed: {message:?}");
+        rx.commit(message.offset).expect("failed to commit");
+
+        let message = rx.recv().await.expect("the channel has closed early");
+        println!("  Received, crashing before commit: {message:?}");
+    }
+
+    // Simulate the process crashing halfway through writing a message.
+    simulate_torn_write(&dir);
+
+    println!("Second run:");
+    {
+        let (tx, rx) = durable::open(&dir, 10).expect("failed to open durable channel");
+        drop(tx);
+
+        while let Ok(message) = rx.recv().await {
+            println!("  Received and committed: {message:?}");
+            rx.commit(message.offset).expect("failed to commit");
+        }
+    }
+
+    println!("Third run:");
+    {
+        let (tx, rx) = durable::open(&dir, 10).expect("failed to open durable channel");
+        drop(tx);
+
+        match rx.recv().await {
+            Ok(message) => println!("  Unexpectedly received: {message:?}"),
+            Err(_) => println!("  Nothing left to receive."),
+        }
+    }
+
+    _ = fs::remove_dir_all(&dir);
+}
+
+fn simulate_torn_write(dir: &Path) {
+    let segment = fs::read_dir(dir)
+        .expect("failed to read channel directory")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .max()
+        .expect("no segment files found");
+
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(segment)
+        .expect("failed to open segment");
+    // A record header claiming a 100 byte value, followed by only 3 bytes.
+    file.write_all(&[100, 0, 0, 0, 1, 2, 3, 4, b'j', b'o', b'b'])
+        .expect("failed to write to segment");
+}
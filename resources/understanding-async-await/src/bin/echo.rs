@@ -0,0 +1,150 @@
+//! Echoes TCP and UDP over loopback with the `epoll` reactor in `net`, which
+//! is only available on Linux.
+
+#[cfg(target_os = "linux")]
+fn main() {
+    echo::main();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The echo example uses the epoll reactor, which is only available on Linux.");
+}
+
+#[cfg(target_os = "linux")]
+mod echo {
+    use std::net::{Shutdown, SocketAddr};
+    use std::sync::Arc;
+
+    use understanding_async_await::net::{TcpListener, TcpStream, UdpSocket};
+    use understanding_async_await::runtime::{self, Runtime};
+
+    pub fn main() {
+        // Pass an address to keep the server running, for example
+        // `cargo run --bin echo -- 127.0.0.1:7878`, and then connect to it
+        // with `nc 127.0.0.1 7878`.
+        let serve = std::env::args().nth(1);
+
+        Runtime::multi_thread(2).block_on(async move {
+            let addr = serve.as_deref().unwrap_or("127.0.0.1:0");
+            let listener = TcpListener::bind(addr).expect("failed to bind listener");
+            let addr = listener.local_addr().expect("no local address");
+            println!("tcp: listening on {addr}");
+            let server = runtime::spawn(serve_tcp(listener));
+
+            if serve.is_some() {
+                _ = server.await;
+                return;
+            }
+
+            let clients: Vec<_> = (0..3)
+                .map(|idx| runtime::spawn(tcp_client(idx, addr)))
+                .collect();
+            for client in clients {
+                client
+                    .await
+                    .expect("client failed")
+                    .expect("client I/O error");
+            }
+            server.abort();
+
+            udp_echo().await.expect("udp I/O error");
+        });
+    }
+
+    /// Accepts connections and spawns a task to echo each one.
+    async fn serve_tcp(listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    println!("tcp: accepted connection from {peer}");
+                    runtime::spawn(async move {
+                        if let Err(err) = echo(stream).await {
+                            println!("tcp: connection from {peer} failed: {err}");
+                        }
+                    });
+                }
+                Err(err) => println!("tcp: accept failed: {err}"),
+            }
+        }
+    }
+
+    /// Writes back everything which is read, until the peer shuts down writing.
+    async fn echo(stream: TcpStream) -> std::io::Result<()> {
+        let mut buf = [0; 1024];
+        loop {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            stream.write_all(&buf[..n]).await?;
+        }
+    }
+
+    /// Sends a large message, so that both reading and writing have to wait for
+    /// readiness, and checks that it comes back unchanged.
+    async fn tcp_client(idx: usize, addr: SocketAddr) -> std::io::Result<()> {
+        let stream = Arc::new(TcpStream::connect(addr).await?);
+        let message: Vec<u8> = (0..1_000_000).map(|i| (i % 251) as u8).collect();
+
+        // Write from another task while this one reads, otherwise both sides
+        // could fill their buffers and wait for each other.
+        let writer = {
+            let stream = Arc::clone(&stream);
+            let message = message.clone();
+            runtime::spawn(async move {
+                stream.write_all(&message).await?;
+                stream.shutdown(Shutdown::Write)
+            })
+        };
+
+        let mut echoed = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            echoed.extend_from_slice(&buf[..n]);
+        }
+        writer.await.expect("writer failed")?;
+
+        println!(
+            "tcp: client-{idx} got back {} of {} bytes, matching: {}",
+            echoed.len(),
+            message.len(),
+            echoed == message,
+        );
+        Ok(())
+    }
+
+    /// Echoes a few datagrams over UDP.
+    async fn udp_echo() -> std::io::Result<()> {
+        let server = UdpSocket::bind("127.0.0.1:0")?;
+        let server_addr = server.local_addr()?;
+        println!("udp: listening on {server_addr}");
+
+        let server = runtime::spawn(async move {
+            let mut buf = [0; 1024];
+            for _ in 0..3 {
+                let (n, peer) = server.recv_from(&mut buf).await?;
+                server.send_to(&buf[..n], peer).await?;
+            }
+            Ok::<_, std::io::Error>(())
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0")?;
+        client.connect(server_addr)?;
+        let mut buf = [0; 1024];
+        for message in ["ping", "pong", "bye"] {
+            client.send(message.as_bytes()).await?;
+            let n = client.recv(&mut buf).await?;
+            println!(
+                "udp: sent {message:?}, got back {:?}",
+                String::from_utf8_lossy(&buf[..n])
+            );
+        }
+
+        server.await.expect("udp server failed")
+    }
+}
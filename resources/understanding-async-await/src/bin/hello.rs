@@ -1,20 +1,23 @@
-#[tokio::main]
-async fn main() {
-    // Hello, World async function
-    async_await::hello("world").await;
-    manual_future::hello("world").await;
+use understanding_async_await::runtime;
+
+fn main() {
+    runtime::block_on(async {
+        // Hello, World async function
+        async_await::hello("world").await;
+        manual_future::hello("world").await;
+    });
 }
 
-mod async_await {
+pub mod async_await {
     pub async fn hello(name: &'static str) {
         println!("hello, {name}!");
     }
 }
 
-mod manual_future {
-    use std::{future::Future, task::Poll};
+pub mod manual_future {
+    use std::{fmt, future::Future, task::Poll};
 
-    pub fn hello(name: &'static str) -> impl Future<Output = ()> {
+    pub fn hello(name: &'static str) -> impl Future<Output = ()> + fmt::Debug {
         Hello::Init { name }
     }
 
@@ -0,0 +1,97 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use serde::{Deserialize, Serialize};
+use understanding_async_await::mpmc::ipc;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Job {
+    id: u32,
+    from: String,
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("producer") => producer(PathBuf::from(&args[2]), &args[3]).await,
+        Some("consumer") => consumer(PathBuf::from(&args[2]), &args[3]).await,
+        _ => parent().await,
+    }
+}
+
+/// Binds the channel and runs producers and a consumer in child processes.
+async fn parent() {
+    let path = env::temp_dir().join("understanding-async-await-ipc.sock");
+    _ = std::fs::remove_file(&path);
+
+    let (tx, rx) = ipc::bind::<Job>(&path, 2).expect("failed to bind channel");
+
+    let producers = ["producer-a", "producer-b"].map(|name| spawn_child("producer", &path, name));
+    let mut consumer = spawn_child("consumer", &path, "consumer-remote");
+
+    // Keep a handle until the remote consumer has finished, so the socket is
+    // still there if it's slow to connect.
+    let keep_open = rx.clone();
+    let local_consumer = tokio::spawn(async move {
+        while let Ok(job) = rx.recv().await {
+            println!("consumer-local: received {job:?}");
+        }
+        println!("consumer-local: channel closed");
+    });
+
+    for mut producer in producers {
+        _ = producer.wait().await;
+    }
+    // All the remote senders have disconnected, dropping the local sender
+    // closes the channel.
+    drop(tx);
+
+    _ = local_consumer.await;
+    _ = consumer.wait().await;
+    drop(keep_open);
+    _ = std::fs::remove_file(&path);
+}
+
+fn spawn_child(role: &str, path: &Path, name: &str) -> tokio::process::Child {
+    tokio::process::Command::new(env::current_exe().expect("no current exe"))
+        .arg(role)
+        .arg(path)
+        .arg(name)
+        .stdout(Stdio::inherit())
+        .spawn()
+        .expect("failed to spawn child process")
+}
+
+async fn producer(path: PathBuf, name: &str) {
+    let tx = ipc::connect_sender::<Job>(&path)
+        .await
+        .expect("failed to connect");
+
+    for id in 0..3 {
+        let job = Job {
+            id,
+            from: name.to_owned(),
+        };
+        println!("{name}: sending {job:?}");
+        if tx.send(job).await.is_err() {
+            println!("{name}: channel closed");
+            break;
+        }
+    }
+}
+
+async fn consumer(path: PathBuf, name: &str) {
+    let rx = ipc::connect_receiver::<Job>(&path)
+        .await
+        .expect("failed to connect");
+
+    while let Ok(job) = rx.recv().await {
+        println!("{name}: received {job:?}");
+        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+    }
+    println!("{name}: channel closed");
+}
@@ -0,0 +1,27 @@
+use std::future::pending;
+
+use understanding_async_await::runtime::{self, JoinError};
+
+fn main() {
+    runtime::block_on(async {
+        let answer = runtime::spawn(async { 6 * 7 });
+        println!("answer: {:?}", answer.await);
+
+        // Silence the default panic message, the panic is expected.
+        std::panic::set_hook(Box::new(|_| {}));
+        let panicked = runtime::spawn(async { panic!("oh no") });
+        match panicked.await {
+            Err(JoinError::Panicked(payload)) => {
+                let message = payload.downcast_ref::<&str>().unwrap_or(&"<unknown>");
+                println!("panicked: {message}");
+            }
+            other => println!("unexpected result: {other:?}"),
+        }
+
+        let forever = runtime::spawn(pending::<()>());
+        runtime::yield_now().await;
+        println!("forever finished: {}", forever.is_finished());
+        forever.abort();
+        println!("forever after abort: {:?}", forever.await);
+    });
+}
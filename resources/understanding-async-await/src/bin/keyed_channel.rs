@@ -0,0 +1,27 @@
+use understanding_async_await::mpmc::keyed;
+
+#[tokio::main]
+async fn main() {
+    let (tx, rx) = keyed::channel(2);
+
+    for version in 0..3 {
+        for key in ["sensor-a", "sensor-b"] {
+            let value = format!("{key}-v{version}");
+            println!("Sending: {value}");
+            tx.send(key, value)
+                .await
+                .expect("the channel has closed early");
+        }
+    }
+    drop(tx);
+
+    loop {
+        match rx.recv().await {
+            Ok((key, value)) => println!("Received: {key} = {value}"),
+            Err(_) => {
+                println!("Channel closed, exiting.");
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Shows the warnings logged for futures which can never be woken again.
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context as LayerContext, Layer};
+use tracing_subscriber::prelude::*;
+use understanding_async_await::future::FutureExt;
+use understanding_async_await::runtime::{self, Runtime};
+use understanding_async_await::testing::{poll_once, poll_until_ready, MockWaker};
+use understanding_async_await::time;
+
+#[path = "ready_pending.rs"]
+#[allow(dead_code)]
+mod ready_pending;
+#[path = "yield_now.rs"]
+#[allow(dead_code)]
+mod yield_now;
+
+fn main() {
+    let warnings = CountWarnings::default();
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().without_time())
+        .with(warnings.clone())
+        .init();
+
+    pending_without_waker(&warnings);
+    every_waker_dropped(&warnings);
+    well_behaved(&warnings);
+    runtime_hook(&warnings);
+}
+
+fn pending_without_waker(warnings: &CountWarnings) {
+    let waker = MockWaker::new();
+    let before = warnings.count();
+    let pending = pin!(ready_pending::pending().detect_lost_wakeups());
+    poll_once(pending, &waker).assert_pending();
+    assert_eq!(warnings.count() - before, 1);
+    println!("ok: Pending is reported as soon as it returns without a waker\n");
+}
+
+fn every_waker_dropped(warnings: &CountWarnings) {
+    let waker = MockWaker::new();
+    let before = warnings.count();
+
+    // The waker is kept, so returning `Pending` is fine...
+    let (forgetful, slot) = forgetful();
+    let mut forgetful = pin!(forgetful.detect_lost_wakeups());
+    poll_once(forgetful.as_mut(), &waker).assert_pending();
+    assert_eq!(warnings.count(), before);
+
+    // ...until it's dropped without being woken.
+    drop(slot.lock().unwrap().take());
+    assert_eq!(warnings.count() - before, 1);
+
+    // Waking it instead is fine.
+    poll_once(forgetful.as_mut(), &waker).assert_pending();
+    let stored = slot.lock().unwrap().take().expect("the waker was stored");
+    stored.wake();
+    assert_eq!(waker.counts().total_wakes(), 1);
+    assert_eq!(warnings.count() - before, 1);
+    println!("ok: a waiting future is reported when its last waker is dropped\n");
+}
+
+fn well_behaved(warnings: &CountWarnings) {
+    let waker = MockWaker::new();
+    let before = warnings.count();
+
+    let yield_now = pin!(yield_now::manual_future::yield_now().detect_lost_wakeups());
+    poll_until_ready(yield_now, &waker, 10);
+
+    let sleep = pin!(time::sleep(Duration::from_millis(10)).detect_lost_wakeups());
+    runtime::block_on(sleep);
+
+    assert_eq!(warnings.count(), before);
+    println!("ok: futures which wake themselves or keep their waker aren't reported\n");
+}
+
+fn runtime_hook(warnings: &CountWarnings) {
+    let before = warnings.count();
+    let runtime = Runtime::current_thread().detect_lost_wakeups();
+    let (forgetful, slot) = forgetful();
+    runtime.block_on(async {
+        let fine = runtime::spawn(async {
+            runtime::yield_now().await;
+            time::sleep(Duration::from_millis(10)).await;
+        });
+        runtime::spawn(ready_pending::pending());
+        runtime::spawn(forgetful);
+
+        time::sleep(Duration::from_millis(20)).await;
+        drop(slot.lock().unwrap().take());
+        fine.await.expect("task shouldn't fail");
+    });
+    assert_eq!(warnings.count() - before, 2);
+    println!("ok: the runtime reports the spawned tasks which can never be woken");
+}
+
+/// Returns a future which stores its waker in the returned slot and never
+/// completes.
+fn forgetful() -> (Forgetful, Arc<Mutex<Option<Waker>>>) {
+    let slot = Arc::new(Mutex::new(None));
+    let forgetful = Forgetful {
+        slot: Arc::clone(&slot),
+    };
+    (forgetful, slot)
+}
+
+struct Forgetful {
+    slot: Arc<Mutex<Option<Waker>>>,
+}
+
+impl Future for Forgetful {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        *self.slot.lock().unwrap() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Counts the warnings which have been logged.
+#[derive(Clone, Default)]
+struct CountWarnings {
+    count: Arc<AtomicUsize>,
+}
+
+impl CountWarnings {
+    fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+}
+
+impl<S: Subscriber> Layer<S> for CountWarnings {
+    fn on_event(&self, event: &Event<'_>, _cx: LayerContext<'_, S>) {
+        if *event.metadata().level() == Level::WARN {
+            self.count.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
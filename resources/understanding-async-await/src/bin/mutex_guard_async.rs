@@ -1,44 +1,32 @@
+use std::sync::Arc;
+
+use understanding_async_await::sync::Mutex;
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
     let data = Arc::new(Mutex::new(0_u64));
 
-    tokio::spawn(yieldless_mutex_access(Arc::clone(&data)));
-    hold_mutex_guard(Arc::clone(&data))
-        .await
-        .expect("failed to perform operation");
+    let yieldless = tokio::spawn(yieldless_mutex_access(Arc::clone(&data)));
+    hold_mutex_guard(Arc::clone(&data)).await;
+    yieldless.await.expect("yieldless task panicked");
 }
 
-use std::sync::{Arc, Mutex};
-
-async fn hold_mutex_guard(data: Arc<Mutex<u64>>) -> Result<(), DataAccessError> {
-    let mut guard = data.lock().map_err(|_| DataAccessError {})?;
+async fn hold_mutex_guard(data: Arc<Mutex<u64>>) {
+    let mut guard = data.lock().await;
     println!("existing value: {}", *guard);
 
+    // The other task will try to lock the mutex while we yield. With an async
+    // mutex it waits for us to release the lock instead of blocking the thread.
     tokio::task::yield_now().await;
 
-    *guard = *guard + 1;
+    *guard += 1;
     println!("new value: {}", *guard);
-
-    Ok(())
 }
 
-async fn yieldless_mutex_access(data: Arc<Mutex<u64>>) -> Result<(), DataAccessError> {
-    let mut guard = data.lock().map_err(|_| DataAccessError {})?;
+async fn yieldless_mutex_access(data: Arc<Mutex<u64>>) {
+    let mut guard = data.lock().await;
     println!("existing value: {}", *guard);
 
-    *guard = *guard + 1;
+    *guard += 1;
     println!("new value: {}", *guard);
-
-    Ok(())
-}
-
-use std::{error::Error, fmt};
-
-#[derive(Debug)]
-struct DataAccessError {}
-impl fmt::Display for DataAccessError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "there was an error accessing the shared data")
-    }
 }
-impl Error for DataAccessError {}
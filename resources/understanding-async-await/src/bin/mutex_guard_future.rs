@@ -1,96 +1,88 @@
 use std::{
+    fmt,
     future::Future,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 
+use understanding_async_await::sync::{LockOwned, Mutex, OwnedMutexGuard};
+
 fn main() {
     let body = async {
         let data = Arc::new(Mutex::new(0_u64));
 
-        tokio::spawn(yieldless_mutex_access(Arc::clone(&data)));
-        hold_mutex_guard(Arc::clone(&data))
-            .await
-            .expect("failed to perform operation");
+        let yieldless = tokio::spawn(yieldless_mutex_access(Arc::clone(&data)));
+        hold_mutex_guard(Arc::clone(&data)).await;
+        yieldless.await.expect("yieldless task panicked");
     };
 
-    return tokio::runtime::Builder::new_current_thread()
+    tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .expect("failed to build runtime")
-        .block_on(body);
+        .block_on(body)
 }
 
-fn hold_mutex_guard(data: Arc<Mutex<u64>>) -> impl Future<Output = Result<(), DataAccessError>> {
+pub fn hold_mutex_guard(data: Arc<Mutex<u64>>) -> impl Future<Output = ()> + fmt::Debug {
     HoldMutexGuard::Init { data }
 }
 
-use std::sync::{Arc, Mutex, MutexGuard};
-
-enum HoldMutexGuard<'a> {
-    Init {
-        data: Arc<Mutex<u64>>,
-    },
-    Yielded {
-        guard: MutexGuard<'a, u64>,
-        _data: Arc<Mutex<u64>>,
-    },
+/// The hand-written equivalent of `hold_mutex_guard` in the
+/// `mutex_guard_async` example.
+///
+/// The owned guard keeps the mutex alive itself, so it can be stored in the
+/// state machine without borrowing from `data`.
+#[derive(Debug)]
+enum HoldMutexGuard {
+    Init { data: Arc<Mutex<u64>> },
+    Locking { lock: LockOwned<u64> },
+    Yielded { guard: OwnedMutexGuard<u64> },
     Done,
 }
 
-impl<'a> Future for HoldMutexGuard<'a> {
-    type Output = Result<(), DataAccessError>;
+impl Future for HoldMutexGuard {
+    type Output = ();
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let state = &mut *self;
-        match state {
-            Self::Init { data } => {
-                let guard = unsafe {
-                    // SAFETY: We will hold on to the Arc containing the mutex as long
-                    //         as we hold onto the guard.
-                    std::mem::transmute::<MutexGuard<'_, u64>, MutexGuard<'static, u64>>(
-                        data.lock().map_err(|_| DataAccessError {})?,
-                    )
-                };
-                println!("existing value: {}", *guard);
-
-                cx.waker().wake_by_ref();
-                *state = Self::Yielded {
-                    guard: guard,
-                    _data: Arc::clone(data),
-                };
-
-                Poll::Pending
-            }
-            Self::Yielded { guard, _data } => {
-                println!("new value: {}", *guard);
-
-                *state = Self::Done;
-
-                Poll::Ready(Ok(()))
+        loop {
+            match state {
+                Self::Init { data } => {
+                    *state = Self::Locking {
+                        lock: Arc::clone(data).lock_owned(),
+                    };
+                }
+                Self::Locking { lock } => {
+                    let Poll::Ready(guard) = Pin::new(lock).poll(cx) else {
+                        return Poll::Pending;
+                    };
+                    println!("existing value: {}", *guard);
+
+                    cx.waker().wake_by_ref();
+                    *state = Self::Yielded { guard };
+
+                    return Poll::Pending;
+                }
+                Self::Yielded { guard } => {
+                    **guard += 1;
+                    println!("new value: {}", **guard);
+
+                    // Dropping the guard releases the lock.
+                    *state = Self::Done;
+
+                    return Poll::Ready(());
+                }
+                Self::Done => panic!("Please stop polling me!"),
             }
-            Self::Done => panic!("Please stop polling me!"),
         }
     }
 }
 
-async fn yieldless_mutex_access(data: Arc<Mutex<u64>>) -> Result<(), DataAccessError> {
-    let mut guard = data.lock().map_err(|_| DataAccessError {})?;
+async fn yieldless_mutex_access(data: Arc<Mutex<u64>>) {
+    let mut guard = data.lock().await;
     println!("existing value: {}", *guard);
 
-    *guard = *guard + 1;
+    *guard += 1;
     println!("new value: {}", *guard);
-
-    Ok(())
-}
-
-use std::{error::Error, fmt};
-
-#[derive(Debug)]
-struct DataAccessError {}
-impl fmt::Display for DataAccessError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "there was an error accessing the shared data")
-    }
 }
-impl Error for DataAccessError {}
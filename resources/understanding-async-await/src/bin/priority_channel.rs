@@ -0,0 +1,29 @@
+use understanding_async_await::mpmc::priority;
+
+#[tokio::main]
+async fn main() {
+    let (tx, rx) = priority::channel(10);
+
+    for idx in 0..5 {
+        tx.send(format!("data-{idx:0>2}"))
+            .await
+            .expect("the channel has closed early");
+    }
+    tx.send_with_priority("shutdown".into(), 10)
+        .await
+        .expect("the channel has closed early");
+    drop(tx);
+
+    loop {
+        match rx.recv().await {
+            Ok(value) if value == "shutdown" => {
+                println!("Received: {value}, draining remaining values");
+            }
+            Ok(value) => println!("Received: {value}"),
+            Err(_) => {
+                println!("Channel closed, exiting.");
+                break;
+            }
+        }
+    }
+}
@@ -1,23 +1,27 @@
-use std::{future::Future, task::Poll};
-
-#[tokio::main]
-async fn main() {
-    // Function that returns ready immediately
-    println!("Before ready().await");
-    ready().await;
-    println!("After ready().await");
-
-    // Function that returns pending immediately
-    println!("Before pending().await");
-    pending().await;
-    println!("After pending().await");
+use std::{fmt, future::Future, task::Poll};
+
+use understanding_async_await::runtime;
+
+fn main() {
+    runtime::block_on(async {
+        // Function that returns ready immediately
+        println!("Before ready().await");
+        ready().await;
+        println!("After ready().await");
+
+        // Function that returns pending immediately
+        println!("Before pending().await");
+        pending().await;
+        println!("After pending().await");
+    });
 }
 
-fn ready() -> Ready {
+pub fn ready() -> Ready {
     Ready {}
 }
 
-struct Ready;
+#[derive(Debug)]
+pub struct Ready;
 
 impl Future for Ready {
     type Output = ();
@@ -31,10 +35,11 @@ impl Future for Ready {
     }
 }
 
-fn pending() -> impl Future<Output = ()> {
+pub fn pending() -> impl Future<Output = ()> + fmt::Debug {
     Pending {}
 }
 
+#[derive(Debug)]
 struct Pending;
 
 impl Future for Pending {
@@ -0,0 +1,38 @@
+use understanding_async_await::mpmc::{self, Select};
+
+#[tokio::main]
+async fn main() {
+    let (data_tx, data_rx) = mpmc::channel(10);
+    let (control_tx, control_rx) = mpmc::channel(1);
+
+    let data_handle = tokio::spawn(async move {
+        for val in 0..5 {
+            let value = format!("data-{val}");
+            if data_tx.send(value).await.is_err() {
+                break;
+            }
+        }
+    });
+    let control_handle = tokio::spawn(async move {
+        for command in ["reconfigure", "shutdown"] {
+            if control_tx.send(command.into()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut select = Select::new().receiver(&data_rx).receiver(&control_rx);
+    loop {
+        match select.recv().await {
+            Ok((0, value)) => println!("Received data: {value}"),
+            Ok((_, command)) => println!("Received command: {command}"),
+            Err(_) => {
+                println!("All channels closed, exiting.");
+                break;
+            }
+        }
+    }
+
+    _ = data_handle.await;
+    _ = control_handle.await;
+}
@@ -0,0 +1,55 @@
+use std::{sync::Arc, time::Duration};
+
+use understanding_async_await::sync::Semaphore;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let semaphore = Arc::new(Semaphore::new(4));
+
+    let held = semaphore.acquire(3).await.expect("semaphore closed");
+    println!("main: holding {} permits", held.num_permits());
+
+    // The large request can't be served yet, there's only 1 permit free.
+    let large = tokio::spawn(acquire(Arc::clone(&semaphore), "large", 4));
+    tokio::task::yield_now().await;
+
+    // There is a permit free for the small request, but it has to wait behind
+    // the large one.
+    let small = tokio::spawn(acquire(Arc::clone(&semaphore), "small", 1));
+    tokio::task::yield_now().await;
+    println!(
+        "main: try_acquire(1) = {:?}",
+        semaphore.try_acquire(1).err()
+    );
+
+    println!("main: releasing {} permits", held.num_permits());
+    drop(held);
+    large.await.expect("large task panicked");
+    small.await.expect("small task panicked");
+
+    // A cancelled acquire gives up its place in the queue.
+    let result = tokio::time::timeout(Duration::from_millis(10), semaphore.acquire(5)).await;
+    println!(
+        "main: acquire(5) timed out: {}, available permits: {}",
+        result.is_err(),
+        semaphore.available_permits(),
+    );
+
+    let waiting = tokio::spawn(acquire(Arc::clone(&semaphore), "waiting", 5));
+    tokio::task::yield_now().await;
+    println!("main: closing the semaphore");
+    semaphore.close();
+    waiting.await.expect("waiting task panicked");
+}
+
+async fn acquire(semaphore: Arc<Semaphore>, name: &str, permits: usize) {
+    println!("{name}: acquiring {permits} permits");
+    match semaphore.acquire_owned(permits).await {
+        Ok(permit) => {
+            println!("{name}: acquired {} permits", permit.num_permits());
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            println!("{name}: releasing {} permits", permit.num_permits());
+        }
+        Err(err) => println!("{name}: failed to acquire permits: {err}"),
+    }
+}
@@ -1,17 +1,20 @@
-#[tokio::main]
-async fn main() {
-    // Simplest async function
-    dbg!(async_await::add(1, 2).await);
-    dbg!(manual_future::add(1, 2).await);
+use understanding_async_await::runtime;
+
+fn main() {
+    runtime::block_on(async {
+        // Simplest async function
+        dbg!(async_await::add(1, 2).await);
+        dbg!(manual_future::add(1, 2).await);
+    });
 }
 
-mod async_await {
+pub mod async_await {
     pub async fn add(x: u64, y: u64) -> u64 {
         x + y
     }
 }
 
-mod manual_future {
+pub mod manual_future {
     use std::{future::Future, task::Poll};
 
     pub fn add(x: u64, y: u64) -> Add {
@@ -0,0 +1,32 @@
+use understanding_async_await::runtime::{self, Runtime};
+
+fn main() {
+    // A current-thread runtime only polls the spawned tasks once the main
+    // task yields.
+    Runtime::current_thread().block_on(async {
+        log("main: spawning spawn_again");
+        runtime::spawn(spawn_again());
+        log("main: awaiting do_nothing");
+        do_nothing().await;
+
+        log("main: yielding");
+        runtime::yield_now().await;
+        log("main: yielding");
+        runtime::yield_now().await;
+        log("main: done");
+    });
+}
+
+async fn spawn_again() {
+    log("spawn_again: spawning do_nothing");
+    runtime::spawn(do_nothing());
+}
+
+async fn do_nothing() {
+    log("do_nothing: doing nothing");
+}
+
+fn log(message: &str) {
+    let thread = std::thread::current();
+    println!("[{}] {message}", thread.name().unwrap_or("unnamed"));
+}
@@ -0,0 +1,36 @@
+use understanding_async_await::runtime::{self, Runtime};
+
+fn main() {
+    // A multi-thread runtime polls spawned tasks on its worker threads, in
+    // parallel with the main task. The exact order will vary between runs.
+    Runtime::multi_thread(2).block_on(async {
+        log("main: spawning spawn_again");
+        let handle = runtime::spawn(spawn_again());
+        log("main: awaiting do_nothing");
+        do_nothing().await;
+
+        log("main: yielding");
+        runtime::yield_now().await;
+        log("main: yielding");
+        runtime::yield_now().await;
+
+        handle.await.expect("spawn_again failed");
+        log("main: done");
+    });
+}
+
+async fn spawn_again() {
+    log("spawn_again: spawning do_nothing");
+    runtime::spawn(do_nothing())
+        .await
+        .expect("do_nothing failed");
+}
+
+async fn do_nothing() {
+    log("do_nothing: doing nothing");
+}
+
+fn log(message: &str) {
+    let thread = std::thread::current();
+    println!("[{}] {message}", thread.name().unwrap_or("unnamed"));
+}
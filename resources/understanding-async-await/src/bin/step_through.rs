@@ -0,0 +1,77 @@
+//! Steps through the hand-written example futures one poll at a time.
+//!
+//! Run it without arguments to press enter before each poll. Pass a script
+//! of commands to run without input, for example `-- s,s,s,c`, or `-- c` to
+//! print the whole trace straight away. Add `pending` to also spawn the
+//! `Pending` future, which can never be woken.
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use understanding_async_await::runtime::{Command, Stepper};
+use understanding_async_await::sync::Mutex;
+use understanding_async_await::time;
+
+#[path = "hello.rs"]
+#[allow(dead_code)]
+mod hello;
+#[path = "mutex_guard_future.rs"]
+#[allow(dead_code)]
+mod mutex_guard_future;
+#[path = "ready_pending.rs"]
+#[allow(dead_code)]
+mod ready_pending;
+#[path = "simplest_async.rs"]
+#[allow(dead_code)]
+mod simplest_async;
+#[path = "two_step_async.rs"]
+#[allow(dead_code)]
+mod two_step_async;
+#[path = "yield_now.rs"]
+#[allow(dead_code)]
+mod yield_now;
+
+fn main() {
+    let mut script = None;
+    let mut spawn_pending = false;
+    for arg in env::args().skip(1) {
+        if arg == "pending" {
+            spawn_pending = true;
+            continue;
+        }
+        let commands: Result<Vec<Command>, _> = arg.split(',').map(str::parse).collect();
+        match commands {
+            Ok(commands) => script = Some(commands),
+            Err(err) => {
+                eprintln!("invalid script {arg:?}: {err}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut stepper = match script {
+        Some(script) => Stepper::scripted(script),
+        None => Stepper::interactive(),
+    };
+
+    stepper.spawn(hello::manual_future::hello("world"));
+    stepper.spawn(simplest_async::manual_future::add(1, 2));
+    stepper.spawn(two_step_async::manual_future::triple_add(1, 2, 3));
+    stepper.spawn(two_step_async::manual_future::triple_add2(1, 2, 3));
+    stepper.spawn(yield_now::manual_future::yield_now());
+
+    // The second task has to wait for the first one to release the lock,
+    // and is woken by it.
+    let data = Arc::new(Mutex::new(0_u64));
+    stepper.spawn(mutex_guard_future::hold_mutex_guard(Arc::clone(&data)));
+    stepper.spawn(mutex_guard_future::hold_mutex_guard(Arc::clone(&data)));
+
+    // Woken by the timer's driver thread, from outside of a poll.
+    stepper.spawn(time::sleep(Duration::from_millis(20)));
+
+    if spawn_pending {
+        stepper.spawn(ready_pending::pending());
+    }
+
+    stepper.run();
+}
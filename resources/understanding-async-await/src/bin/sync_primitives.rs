@@ -0,0 +1,92 @@
+//! Compares the waker events of tokio's `Barrier` and `Notify` with those of
+//! the primitives in `understanding_async_await::sync`.
+//!
+//! Tokio emits its events with the target `tokio::task::waker`, ours use
+//! `understanding_async_await::sync::waker`. Each section is introduced by an
+//! info event, so the output can be read side by side.
+//!
+//! Tokio only emits its waker events when built with `--cfg tokio_unstable`:
+//!
+//! ```sh
+//! RUSTFLAGS="--cfg tokio_unstable" cargo run --bin sync_primitives
+//! ```
+use std::sync::Arc;
+
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::prelude::*;
+use understanding_async_await::sync;
+
+#[tokio::main]
+async fn main() {
+    // Tokio emits plenty of other events, only keep the waker events and our
+    // own section headers. Tokio only gives a waker event the id of its task
+    // if the task's span, with the target `tokio::task`, is enabled.
+    let wakers_and_sections = filter_fn(|metadata| {
+        matches!(
+            metadata.target(),
+            "tokio::task"
+                | "tokio::task::waker"
+                | "understanding_async_await::sync::waker"
+                | "sync_primitives"
+        )
+    });
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .without_time()
+                .with_filter(wakers_and_sections),
+        )
+        .init();
+
+    tracing::info!(primitive = "tokio::sync::Barrier");
+    {
+        let barrier = Arc::new(tokio::sync::Barrier::new(2));
+        let other = tokio::spawn({
+            let barrier = Arc::clone(&barrier);
+            async move { barrier.wait().await.is_leader() }
+        });
+        let is_leader = barrier.wait().await.is_leader();
+        let other_is_leader = other.await.unwrap();
+        tracing::info!(is_leader, other_is_leader, "released");
+    }
+
+    tracing::info!(primitive = "understanding_async_await::sync::Barrier");
+    {
+        let barrier = Arc::new(sync::Barrier::new(2));
+        let other = tokio::spawn({
+            let barrier = Arc::clone(&barrier);
+            async move { barrier.wait().await.is_leader() }
+        });
+        let is_leader = barrier.wait().await.is_leader();
+        let other_is_leader = other.await.unwrap();
+        tracing::info!(is_leader, other_is_leader, "released");
+    }
+
+    tracing::info!(primitive = "tokio::sync::Notify");
+    {
+        let notify = Arc::new(tokio::sync::Notify::new());
+        let waiter = tokio::spawn({
+            let notify = Arc::clone(&notify);
+            async move { notify.notified().await }
+        });
+        // Give the waiter time to start waiting, so that no permit is stored.
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        notify.notify_one();
+        waiter.await.unwrap();
+        tracing::info!("notified");
+    }
+
+    tracing::info!(primitive = "understanding_async_await::sync::Notify");
+    {
+        let notify = Arc::new(sync::Notify::new());
+        let waiter = tokio::spawn({
+            let notify = Arc::clone(&notify);
+            async move { notify.notified().await }
+        });
+        // Give the waiter time to start waiting, so that no permit is stored.
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        notify.notify_one();
+        waiter.await.unwrap();
+        tracing::info!("notified");
+    }
+}
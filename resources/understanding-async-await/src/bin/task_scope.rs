@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use understanding_async_await::runtime::{self, Runtime};
+use understanding_async_await::scope::{ScopeError, TaskScope};
+use understanding_async_await::time;
+
+fn main() {
+    // Run the same scope on our own runtime and on tokio.
+    Runtime::multi_thread(2).block_on(async {
+        println!("runtime:");
+        let scope = TaskScope::new(|task| {
+            runtime::spawn(task);
+        });
+        completion_order(scope).await;
+
+        let scope = TaskScope::new(|task| {
+            runtime::spawn(task);
+        });
+        first_error_cancels(scope).await;
+    });
+
+    tokio::runtime::Runtime::new()
+        .expect("failed to build tokio runtime")
+        .block_on(async {
+            println!("tokio:");
+            let handle = tokio::runtime::Handle::current();
+            completion_order(TaskScope::new(handle.clone())).await;
+            first_error_cancels(TaskScope::new(handle.clone())).await;
+            panic_propagates(TaskScope::new(handle)).await;
+        });
+}
+
+/// Results come back in the order the children complete, not the order
+/// they were spawned in.
+async fn completion_order(mut scope: TaskScope<u64, String>) {
+    for millis in [30, 10, 20] {
+        scope.spawn(async move {
+            time::sleep(Duration::from_millis(millis)).await;
+            Ok(millis)
+        });
+    }
+
+    while let Some(result) = scope.join_next().await {
+        println!("  completed: {result:?}");
+    }
+}
+
+/// The first error cancels the other children, which are dropped before
+/// `join_all` returns.
+async fn first_error_cancels(mut scope: TaskScope<u64, String>) {
+    scope.spawn(async {
+        time::sleep(Duration::from_millis(10)).await;
+        Err("out of coffee".to_string())
+    });
+    for idx in 0..2 {
+        let guard = DropGuard(idx);
+        scope.spawn(async move {
+            let _guard = guard;
+            time::sleep(Duration::from_secs(10)).await;
+            Ok(idx)
+        });
+    }
+
+    match scope.join_all().await {
+        Ok(values) => println!("  all succeeded: {values:?}"),
+        Err(err) => println!("  join_all returned: {err}"),
+    }
+}
+
+/// A panic in a child is caught and returned as an error, the other children
+/// are cancelled.
+async fn panic_propagates(mut scope: TaskScope<u64, String>) {
+    // Silence the default panic message, the panic is expected.
+    std::panic::set_hook(Box::new(|_| {}));
+    scope.spawn(async { panic!("boom") });
+    let guard = DropGuard(7);
+    scope.spawn(async move {
+        let _guard = guard;
+        std::future::pending().await
+    });
+
+    match scope.join_next().await {
+        Some(Err(ScopeError::Panicked(payload))) => {
+            let message = payload.downcast_ref::<&str>().unwrap_or(&"<unknown>");
+            println!("  child panicked: {message}");
+        }
+        other => println!("  unexpected result: {other:?}"),
+    }
+    scope.shutdown().await;
+    println!("  children left after shutdown: {}", scope.len());
+}
+
+struct DropGuard(u64);
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        println!("  child {} dropped", self.0);
+    }
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use understanding_async_await::mpmc::timed::{self, MockClock};
+
+#[tokio::main]
+async fn main() {
+    let clock = MockClock::new();
+    let (tx, rx) = timed::channel_with_clock(10, clock.clone());
+
+    tx.send_with_ttl("expires-soon".into(), Duration::from_secs(1))
+        .await
+        .expect("the channel has closed early");
+    tx.send_delayed("retry-after-backoff".into(), Duration::from_secs(5))
+        .await
+        .expect("the channel has closed early");
+    tx.send("right-away".into())
+        .await
+        .expect("the channel has closed early");
+    drop(tx);
+
+    clock.advance(Duration::from_secs(2));
+    println!("Advanced clock by 2s, expired: {}", rx.expired_count());
+
+    let receiver_handle = tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(value) => println!("Received: {value}"),
+                Err(_) => {
+                    println!("Channel closed, expired: {}", rx.expired_count());
+                    break;
+                }
+            }
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    println!("Advancing clock by 3s");
+    clock.advance(Duration::from_secs(3));
+
+    _ = receiver_handle.await;
+}
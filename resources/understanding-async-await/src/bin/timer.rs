@@ -0,0 +1,80 @@
+use std::time::{Duration, Instant};
+
+use understanding_async_await::runtime::{self, Runtime};
+use understanding_async_await::time::{self, Timer};
+
+fn main() {
+    // With a paused timer, time only moves when we advance it, so the output
+    // is the same every time.
+    let timer = Timer::paused();
+    let start = timer.now();
+    let since_start = move |instant: Instant| (instant - start).as_millis();
+
+    Runtime::current_thread().block_on(async move {
+        let sleeper = {
+            let timer = timer.clone();
+            runtime::spawn(async move {
+                timer.sleep(Duration::from_millis(50)).await;
+                println!("sleeper: woke at {}ms", since_start(timer.now()));
+            })
+        };
+
+        let ticker = {
+            let timer = timer.clone();
+            runtime::spawn(async move {
+                let mut interval = timer.interval(Duration::from_millis(20));
+                for _ in 0..4 {
+                    let scheduled = interval.tick().await;
+                    println!("ticker: tick scheduled for {}ms", since_start(scheduled));
+                }
+            })
+        };
+
+        let timeouts = {
+            let timer = timer.clone();
+            runtime::spawn(async move {
+                let fast = timer.timeout(
+                    Duration::from_millis(30),
+                    timer.sleep(Duration::from_millis(10)),
+                );
+                println!("timeouts: fast sleep returned {:?}", fast.await);
+                let slow = timer.timeout(
+                    Duration::from_millis(30),
+                    timer.sleep(Duration::from_millis(100)),
+                );
+                println!("timeouts: slow sleep returned {:?}", slow.await);
+            })
+        };
+
+        // Let the tasks register their timers before moving the clock.
+        runtime::yield_now().await;
+        for _ in 0..7 {
+            timer.advance(Duration::from_millis(10));
+            println!("main: advanced to {}ms", since_start(timer.now()));
+            runtime::yield_now().await;
+        }
+
+        for handle in [sleeper, ticker, timeouts] {
+            handle.await.expect("task failed");
+        }
+    });
+
+    // The global timer follows the system clock, its driver thread wakes the
+    // task, whichever executor is polling it.
+    let start = Instant::now();
+    runtime::block_on(time::sleep(Duration::from_millis(20)));
+    println!(
+        "runtime: slept for at least 20ms: {}",
+        start.elapsed() >= Duration::from_millis(20)
+    );
+
+    let start = Instant::now();
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("failed to build tokio runtime")
+        .block_on(time::sleep(Duration::from_millis(20)));
+    println!(
+        "tokio: slept for at least 20ms: {}",
+        start.elapsed() >= Duration::from_millis(20)
+    );
+}
@@ -1,25 +1,30 @@
-#[tokio::main]
-async fn main() {
-    let section = "Two step async function";
-    dbg!(
-        section,
-        async_await::triple_add(1, 2, 3).await,
-        manual_future::triple_add(1, 2, 3).await,
-        manual_future::triple_add2(1, 2, 3).await,
-    );
+use understanding_async_await::runtime;
+
+fn main() {
+    runtime::block_on(async {
+        let section = "Two step async function";
+        dbg!(
+            section,
+            async_await::triple_add(1, 2, 3).await,
+            manual_future::triple_add(1, 2, 3).await,
+            manual_future::triple_add2(1, 2, 3).await,
+        );
+    });
 }
 
-mod async_await {
+pub mod async_await {
+    use understanding_async_await::runtime;
+
     pub async fn triple_add(x: u64, y: u64, z: u64) -> u64 {
         let c = x + y;
 
-        tokio::task::yield_now().await;
+        runtime::yield_now().await;
 
         c + z
     }
 }
 
-mod manual_future {
+pub mod manual_future {
     use std::{future::Future, pin::Pin, task::Poll};
 
     pub fn triple_add(x: u64, y: u64, z: u64) -> TripleAdd {
@@ -66,6 +71,7 @@ mod manual_future {
         yielded: bool,
     }
 
+    #[allow(clippy::new_without_default)]
     impl YieldNow {
         pub fn new() -> Self {
             YieldNow { yielded: false }
@@ -75,6 +81,8 @@ mod manual_future {
     impl Future for YieldNow {
         type Output = ();
 
+        // Written the same way as in the blog post.
+        #[allow(clippy::bool_comparison)]
         fn poll(
             mut self: std::pin::Pin<&mut Self>,
             cx: &mut std::task::Context<'_>,
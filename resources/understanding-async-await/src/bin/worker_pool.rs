@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use understanding_async_await::{
+    mpmc,
+    pool::{WorkerPool, WorkerStats},
+};
+
+#[tokio::main]
+async fn main() {
+    let (tx, rx) = mpmc::channel(8);
+
+    let mut pool = WorkerPool::new(rx, 2, |job: String| async move {
+        if job.ends_with('7') {
+            panic!("job {job} is unlucky");
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    });
+
+    // Silence the default panic message, the panics are expected.
+    std::panic::set_hook(Box::new(|_| {}));
+
+    let producer = tokio::spawn(async move {
+        for id in 0..40 {
+            tx.send(format!("job-{id}"))
+                .await
+                .expect("the pool has shut down early");
+        }
+        tx
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    println!("Growing the pool to 4 workers");
+    pool.resize(4).await;
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    println!("Shrinking the pool to 3 workers");
+    pool.resize(3).await;
+    print_stats("Running", &pool.stats());
+
+    let tx = producer.await.expect("the producer panicked");
+    let stats = pool.shutdown().await;
+    print_stats("After shutdown", &stats);
+
+    let total: u64 = stats.iter().map(|s| s.completed + s.panicked).sum();
+    println!("Jobs processed: {total}");
+
+    let result = tx.send("too-late".into()).await;
+    println!("Sending after shutdown: {result:?}");
+}
+
+fn print_stats(label: &str, stats: &[WorkerStats]) {
+    println!("{label}:");
+    for worker in stats {
+        println!(
+            "  worker {}: completed={} panicked={} busy={}",
+            worker.id, worker.completed, worker.panicked, worker.busy
+        );
+    }
+}
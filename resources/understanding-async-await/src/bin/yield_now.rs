@@ -1,19 +1,24 @@
-#[tokio::main]
-async fn main() {
-    // Function that yields back to the runtime immediately
-    println!("Before yield_now().await");
-    dbg!(async_await::yield_now().await);
-    dbg!(manual_future::yield_now().await);
-    println!("After yield_now().await");
+use understanding_async_await::runtime;
+
+fn main() {
+    runtime::block_on(async {
+        // Function that yields back to the runtime immediately
+        println!("Before yield_now().await");
+        dbg!(async_await::yield_now().await);
+        dbg!(manual_future::yield_now().await);
+        println!("After yield_now().await");
+    });
 }
 
-mod async_await {
+pub mod async_await {
+    use understanding_async_await::runtime;
+
     pub async fn yield_now() {
-        tokio::task::yield_now().await
+        runtime::yield_now().await
     }
 }
 
-mod manual_future {
+pub mod manual_future {
     use std::{future::Future, task::Poll};
 
     pub fn yield_now() -> YieldNow {
@@ -28,6 +33,8 @@ mod manual_future {
     impl Future for YieldNow {
         type Output = ();
 
+        // Written the same way as in the blog post.
+        #[allow(clippy::bool_comparison)]
         fn poll(
             mut self: std::pin::Pin<&mut Self>,
             cx: &mut std::task::Context<'_>,
@@ -0,0 +1,54 @@
+//! Combinators which run several futures at once.
+//!
+//! Each combinator is a hand-written future, in the same style as the
+//! `manual_future` examples. The futures it combines are boxed, so the
+//! combinators themselves are `Unpin` and easy to poll by hand.
+//!
+//! All the futures in a combinator are polled by the same task, with the same
+//! waker. When any of them is woken, the task polls the combinator, which
+//! polls the futures which haven't completed yet.
+//!
+//! There are also adapters which wrap a single future to check how it is
+//! polled, see [`FutureExt`].
+mod checked;
+mod join;
+mod lost_wakeups;
+mod maybe_done;
+mod race;
+mod select;
+mod timeout;
+
+pub(crate) use maybe_done::MaybeDone;
+
+pub use checked::{checked, Checked};
+pub use join::{join, join3, join4, join_all, Join, Join3, Join4, JoinAll};
+pub use lost_wakeups::{detect_lost_wakeups, DetectLostWakeups};
+pub use race::{race, Race};
+pub use select::{select, Either, Select};
+pub use timeout::{timeout, Elapsed, Timeout};
+
+use std::future::Future;
+
+/// Adapters for any [`Future`].
+pub trait FutureExt: Future {
+    /// Reports polls after completion, see [`checked`].
+    #[track_caller]
+    fn checked(self) -> Checked<Self>
+    where
+        Self: Sized,
+    {
+        checked(self)
+    }
+
+    /// Reports futures which return `Pending` without arranging to be woken,
+    /// see [`detect_lost_wakeups`].
+    #[track_caller]
+    fn detect_lost_wakeups(self) -> DetectLostWakeups<Self>
+    where
+        Self: Sized,
+    {
+        detect_lost_wakeups(self)
+    }
+}
+
+impl<F: Future + ?Sized> FutureExt for F {}
@@ -0,0 +1,233 @@
+use std::any;
+use std::fmt;
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Wraps `future` so that polling it after it has completed is reported.
+///
+/// Polling a future again after it has returned `Ready` is a bug in whatever
+/// polls it. The hand-written example futures panic with "Please stop polling
+/// me!", which doesn't say who polled them or in what state they were. A
+/// checked future never polls the future it wraps after completion, instead
+/// it panics with:
+///
+/// - the location of the poll after completion,
+/// - the type name of the wrapped future,
+/// - where the checked future was created and where it completed,
+/// - the `Debug` state of the wrapped future, see [`with_debug_state`].
+///
+/// With [`log_repolls`], the report is logged as a `tracing` error instead,
+/// and the checked future returns `Pending`, like a fused future.
+///
+/// [`with_debug_state`]: fn@Checked::with_debug_state
+/// [`log_repolls`]: fn@Checked::log_repolls
+#[track_caller]
+pub fn checked<F: Future>(future: F) -> Checked<F> {
+    Checked {
+        future: Box::pin(future),
+        created_at: Location::caller(),
+        completed_at: None,
+        log_repolls: false,
+        debug_state: None,
+    }
+}
+
+/// The future returned by [`checked`].
+pub struct Checked<F> {
+    /// Kept after completion, so that its state can be reported.
+    future: Pin<Box<F>>,
+    created_at: &'static Location<'static>,
+    /// Where the poll which returned `Ready` came from, `None` until then.
+    completed_at: Option<&'static Location<'static>>,
+    log_repolls: bool,
+    /// Formats the wrapped future, if it implements `Debug`.
+    debug_state: Option<DebugFn<F>>,
+}
+
+type DebugFn<F> = fn(&F, &mut fmt::Formatter<'_>) -> fmt::Result;
+
+impl<F> Checked<F> {
+    /// Logs polls after completion instead of panicking.
+    ///
+    /// Each one is logged as an error with the `tracing` target
+    /// `understanding_async_await::future::checked`, and returns `Pending`.
+    pub fn log_repolls(mut self) -> Self {
+        self.log_repolls = true;
+        self
+    }
+
+    /// Returns whether the wrapped future has completed.
+    pub fn is_completed(&self) -> bool {
+        self.completed_at.is_some()
+    }
+
+    fn state(&self) -> State<'_, F> {
+        State {
+            future: &self.future,
+            debug_state: self.debug_state,
+        }
+    }
+}
+
+impl<F: fmt::Debug> Checked<F> {
+    /// Includes the `Debug` state of the wrapped future in reports.
+    ///
+    /// For a hand-written state machine this shows which state it was left
+    /// in, usually its `Done` variant.
+    pub fn with_debug_state(mut self) -> Self {
+        self.debug_state = Some(<F as fmt::Debug>::fmt);
+        self
+    }
+}
+
+impl<F: Future> Future for Checked<F> {
+    type Output = F::Output;
+
+    #[track_caller]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let polled_at = Location::caller();
+        let Some(completed_at) = self.completed_at else {
+            let output = std::task::ready!(self.future.as_mut().poll(cx));
+            self.completed_at = Some(polled_at);
+            return Poll::Ready(output);
+        };
+
+        let type_name = any::type_name::<F>();
+        if !self.log_repolls {
+            panic!(
+                "{type_name} polled after completion at {polled_at}\n  \
+                 created at {created_at}\n  \
+                 completed at {completed_at}\n  \
+                 state: {state:?}",
+                created_at = self.created_at,
+                state = self.state(),
+            );
+        }
+
+        tracing::error!(
+            target: "understanding_async_await::future::checked",
+            type_name,
+            state = ?self.state(),
+            polled_at = %polled_at,
+            created_at = %self.created_at,
+            completed_at = %completed_at,
+            "future polled after completion"
+        );
+        Poll::Pending
+    }
+}
+
+impl<F> fmt::Debug for Checked<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checked")
+            .field("type_name", &any::type_name::<F>())
+            .field("created_at", &self.created_at)
+            .field("completed_at", &self.completed_at)
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+/// Formats the wrapped future with its `Debug` implementation, if there is
+/// one.
+struct State<'a, F> {
+    future: &'a F,
+    debug_state: Option<DebugFn<F>>,
+}
+
+impl<F> fmt::Debug for State<'_, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.debug_state {
+            Some(debug_state) => debug_state(self.future, f),
+            None => f.write_str("<not Debug>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::pin::pin;
+
+    use tracing::Level;
+
+    use super::checked;
+    use crate::testing::fixtures::{Capture, Log};
+    use crate::testing::{poll_once, MockWaker};
+
+    /// Runs `f`, which must panic, and returns the panic message.
+    fn panic_message<R>(f: impl FnOnce() -> R) -> String {
+        let payload = match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(_) => panic!("expected a panic"),
+            Err(payload) => payload,
+        };
+        match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(_) => panic!("expected a formatted panic message"),
+        }
+    }
+
+    #[test]
+    fn repoll_panics_with_type_location_and_state() {
+        let log = Log::default();
+        let waker = MockWaker::new();
+        let mut future = pin!(checked(log.probe("probe", 0)).with_debug_state());
+        assert_eq!(poll_once(future.as_mut(), &waker).assert_ready(), "probe");
+        assert!(future.is_completed());
+
+        let line = line!() + 1;
+        let message = panic_message(|| poll_once(future.as_mut(), &waker));
+
+        assert!(
+            message.starts_with("understanding_async_await::testing::fixtures::Logged<"),
+            "{message}"
+        );
+        assert!(message.contains("polled after completion"), "{message}");
+        assert!(
+            message.contains(&format!("at {}:{line}:", file!())),
+            "{message}"
+        );
+        assert!(
+            message.contains(r#"state: Probe { name: "probe", pending: 0 }"#),
+            "{message}"
+        );
+        // The wrapped future wasn't polled again.
+        assert_eq!(log.take(), ["poll probe"]);
+    }
+
+    #[test]
+    fn repoll_without_debug_state_is_still_reported() {
+        let waker = MockWaker::new();
+        let mut future = pin!(checked(async { 1 }));
+        poll_once(future.as_mut(), &waker).assert_ready();
+
+        let message = panic_message(|| poll_once(future.as_mut(), &waker));
+        assert!(message.contains("state: <not Debug>"), "{message}");
+    }
+
+    #[test]
+    fn logged_repoll_returns_pending_and_emits_an_error() {
+        let capture = Capture::default();
+        let waker = MockWaker::new();
+        let mut future = pin!(checked(async { 1 }).log_repolls());
+        poll_once(future.as_mut(), &waker).assert_ready();
+        assert!(capture.take().is_empty());
+
+        capture.run(|| {
+            for _ in 0..2 {
+                poll_once(future.as_mut(), &waker).assert_pending_without_waker();
+            }
+        });
+
+        let events = capture.take();
+        assert_eq!(events.len(), 2);
+        let event = &events[0];
+        assert_eq!(event.level, Level::ERROR);
+        assert_eq!(event.target, "understanding_async_await::future::checked");
+        assert_eq!(event.fields["message"], "future polled after completion");
+        assert!(event.fields["type_name"].contains("checked::tests"));
+        assert!(event.fields["polled_at"].starts_with(file!()));
+    }
+}
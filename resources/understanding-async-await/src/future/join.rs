@@ -0,0 +1,271 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::maybe_done::MaybeDone;
+
+/// Waits for two futures to complete, returning both outputs.
+///
+/// Each time the join is polled, it polls every future which hasn't completed
+/// yet, in the order they were given. All the futures share the waker of the
+/// task polling the join, so waking any one of them polls them all again.
+pub fn join<A, B>(a: A, b: B) -> Join<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    Join {
+        a: MaybeDone::new(a),
+        b: MaybeDone::new(b),
+    }
+}
+
+/// The future returned by [`join`].
+pub struct Join<A: Future, B: Future> {
+    a: MaybeDone<A>,
+    b: MaybeDone<B>,
+}
+
+impl<A: Future, B: Future> Future for Join<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        // Poll both, even if the first one is pending, so that they make
+        // progress concurrently.
+        let a_ready = this.a.poll(cx);
+        let b_ready = this.b.poll(cx);
+        if a_ready && b_ready {
+            Poll::Ready((this.a.take(), this.b.take()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<A: Future, B: Future> fmt::Debug for Join<A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Join")
+            .field("a", &self.a)
+            .field("b", &self.b)
+            .finish()
+    }
+}
+
+/// Waits for three futures to complete, returning all three outputs.
+///
+/// See [`join`] for how the futures are polled.
+pub fn join3<A, B, C>(a: A, b: B, c: C) -> Join3<A, B, C>
+where
+    A: Future,
+    B: Future,
+    C: Future,
+{
+    Join3 {
+        a: MaybeDone::new(a),
+        b: MaybeDone::new(b),
+        c: MaybeDone::new(c),
+    }
+}
+
+/// The future returned by [`join3`].
+pub struct Join3<A: Future, B: Future, C: Future> {
+    a: MaybeDone<A>,
+    b: MaybeDone<B>,
+    c: MaybeDone<C>,
+}
+
+impl<A: Future, B: Future, C: Future> Future for Join3<A, B, C> {
+    type Output = (A::Output, B::Output, C::Output);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let a_ready = this.a.poll(cx);
+        let b_ready = this.b.poll(cx);
+        let c_ready = this.c.poll(cx);
+        if a_ready && b_ready && c_ready {
+            Poll::Ready((this.a.take(), this.b.take(), this.c.take()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<A: Future, B: Future, C: Future> fmt::Debug for Join3<A, B, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Join3")
+            .field("a", &self.a)
+            .field("b", &self.b)
+            .field("c", &self.c)
+            .finish()
+    }
+}
+
+/// Waits for four futures to complete, returning all four outputs.
+///
+/// See [`join`] for how the futures are polled.
+pub fn join4<A, B, C, D>(a: A, b: B, c: C, d: D) -> Join4<A, B, C, D>
+where
+    A: Future,
+    B: Future,
+    C: Future,
+    D: Future,
+{
+    Join4 {
+        a: MaybeDone::new(a),
+        b: MaybeDone::new(b),
+        c: MaybeDone::new(c),
+        d: MaybeDone::new(d),
+    }
+}
+
+/// The future returned by [`join4`].
+pub struct Join4<A: Future, B: Future, C: Future, D: Future> {
+    a: MaybeDone<A>,
+    b: MaybeDone<B>,
+    c: MaybeDone<C>,
+    d: MaybeDone<D>,
+}
+
+impl<A: Future, B: Future, C: Future, D: Future> Future for Join4<A, B, C, D> {
+    type Output = (A::Output, B::Output, C::Output, D::Output);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let a_ready = this.a.poll(cx);
+        let b_ready = this.b.poll(cx);
+        let c_ready = this.c.poll(cx);
+        let d_ready = this.d.poll(cx);
+        if a_ready && b_ready && c_ready && d_ready {
+            Poll::Ready((this.a.take(), this.b.take(), this.c.take(), this.d.take()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<A: Future, B: Future, C: Future, D: Future> fmt::Debug for Join4<A, B, C, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Join4")
+            .field("a", &self.a)
+            .field("b", &self.b)
+            .field("c", &self.c)
+            .field("d", &self.d)
+            .finish()
+    }
+}
+
+/// Waits for every future in `futures` to complete, returning their outputs
+/// in the same order.
+///
+/// See [`join`] for how the futures are polled. Every pending future is
+/// polled each time the join is polled, so this is best suited to a small
+/// number of futures.
+pub fn join_all<I>(futures: I) -> JoinAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    JoinAll {
+        futures: futures.into_iter().map(MaybeDone::new).collect(),
+    }
+}
+
+/// The future returned by [`join_all`].
+pub struct JoinAll<F: Future> {
+    futures: Vec<MaybeDone<F>>,
+}
+
+impl<F: Future> Future for JoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut all_ready = true;
+        for future in &mut self.futures {
+            all_ready &= future.poll(cx);
+        }
+
+        if all_ready {
+            Poll::Ready(self.futures.iter_mut().map(MaybeDone::take).collect())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<F: Future> fmt::Debug for JoinAll<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.futures).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::task::Poll;
+
+    use crate::future::{join, join3, join_all};
+    use crate::testing::fixtures::{gate, Log};
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn join_polls_pending_futures_in_order() {
+        let log = Log::default();
+        let waker = MockWaker::new();
+        let mut join = join(log.probe("a", 1), log.probe("b", 0));
+
+        poll_once(Pin::new(&mut join), &waker).assert_woke_itself();
+        // `b` is dropped as soon as it completes, not when the join does.
+        assert_eq!(log.take(), ["poll a", "poll b", "drop b"]);
+
+        assert_eq!(
+            poll_once(Pin::new(&mut join), &waker).poll,
+            Poll::Ready(("a", "b"))
+        );
+        // The completed `b` isn't polled again.
+        assert_eq!(log.take(), ["poll a", "drop a"]);
+    }
+
+    #[test]
+    fn join_propagates_wakers() {
+        let (gate_a, opener_a) = gate();
+        let (gate_b, opener_b) = gate();
+        let waker = MockWaker::new();
+        let mut join = join3(gate_a, gate_b, std::future::ready(3));
+
+        poll_once(Pin::new(&mut join), &waker).assert_registered_waker();
+        opener_b.open();
+        assert_eq!(waker.counts().total_wakes(), 1);
+        poll_once(Pin::new(&mut join), &waker).assert_pending();
+        opener_a.open();
+        assert_eq!(waker.counts().total_wakes(), 2);
+        assert_eq!(
+            poll_once(Pin::new(&mut join), &waker).poll,
+            Poll::Ready(((), (), 3))
+        );
+        assert_eq!(waker.live(), 0);
+    }
+
+    #[test]
+    fn join_all_keeps_output_order() {
+        let log = Log::default();
+        let waker = MockWaker::new();
+        let probes = [("x", 2), ("y", 0), ("z", 1)].map(|(name, pending)| log.probe(name, pending));
+        let mut join = join_all(probes);
+
+        poll_once(Pin::new(&mut join), &waker).assert_woke_itself();
+        poll_once(Pin::new(&mut join), &waker).assert_woke_itself();
+        assert_eq!(
+            poll_once(Pin::new(&mut join), &waker).poll,
+            Poll::Ready(vec!["x", "y", "z"])
+        );
+        assert_eq!(
+            log.take(),
+            [
+                "poll x", "poll y", "drop y", "poll z", "poll x", "poll z", "drop z", "poll x",
+                "drop x"
+            ]
+        );
+    }
+}
@@ -0,0 +1,361 @@
+use std::any;
+use std::fmt;
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// Wraps `future` so that lost wake ups are reported.
+///
+/// A future which returns `Pending` must make sure that it will be woken,
+/// usually by keeping a clone of the waker and waking it later. If it
+/// doesn't, like the `Pending` example future, an executor never polls it
+/// again and the task hangs without saying why.
+///
+/// The wrapped future is polled with a waker which counts its clones and
+/// wakes. A warning is logged with `tracing`, naming the wrapped future's type
+/// and the location this function was called from, when:
+///
+/// - the future returns `Pending` without having woken the waker, and no
+///   clone of it is left, or
+/// - the future is waiting and the last clone of the waker is dropped without
+///   having been woken.
+///
+/// Either way, nothing is left which could wake the future. The warnings
+/// have the target `understanding_async_await::future::lost_wakeups`.
+///
+/// A [`Runtime`] can wrap every task with this, see
+/// [`Runtime::detect_lost_wakeups`].
+///
+/// [`Runtime`]: struct@crate::runtime::Runtime
+/// [`Runtime::detect_lost_wakeups`]: fn@crate::runtime::Runtime::detect_lost_wakeups
+#[track_caller]
+pub fn detect_lost_wakeups<F: Future>(future: F) -> DetectLostWakeups<F> {
+    DetectLostWakeups::with_location(future, any::type_name::<F>(), Location::caller())
+}
+
+/// The future returned by [`detect_lost_wakeups`].
+pub struct DetectLostWakeups<F> {
+    future: Pin<Box<F>>,
+    tracker: Arc<Tracker>,
+}
+
+/// The state shared by the wrapper and the wakers it has handed out.
+struct Tracker {
+    type_name: &'static str,
+    location: &'static Location<'static>,
+    /// The waker from the most recent poll, which our wakers forward to.
+    waker: Mutex<Option<Waker>>,
+    /// The number of our wakers which exist.
+    wakers: AtomicUsize,
+    /// Whether one of our wakers has been woken since the last poll started.
+    woken: AtomicBool,
+    /// Whether the future has returned `Pending` and hasn't been woken since.
+    ///
+    /// Whoever clears this flag when it finds no waker left logs the
+    /// warning, so each lost wake up is only reported once.
+    waiting: AtomicBool,
+}
+
+impl<F: Future> DetectLostWakeups<F> {
+    /// Wraps `future`, reporting it as `type_name` from `location`.
+    pub(crate) fn with_location(
+        future: F,
+        type_name: &'static str,
+        location: &'static Location<'static>,
+    ) -> Self {
+        Self {
+            future: Box::pin(future),
+            tracker: Arc::new(Tracker {
+                type_name,
+                location,
+                waker: Mutex::new(None),
+                wakers: AtomicUsize::new(0),
+                woken: AtomicBool::new(false),
+                waiting: AtomicBool::new(false),
+            }),
+        }
+    }
+}
+
+impl<F: Future> Future for DetectLostWakeups<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let tracker = &this.tracker;
+        tracker.waiting.store(false, Ordering::SeqCst);
+        tracker.woken.store(false, Ordering::SeqCst);
+        {
+            let mut waker = tracker.lock_waker();
+            match &mut *waker {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                waker => *waker = Some(cx.waker().clone()),
+            }
+        }
+
+        let poll = {
+            let waker = tracker.waker();
+            let mut cx = Context::from_waker(&waker);
+            this.future.as_mut().poll(&mut cx)
+        };
+        if poll.is_ready() || tracker.woken.load(Ordering::SeqCst) {
+            return poll;
+        }
+
+        // Set the flag before counting the wakers, so that if the last one is
+        // dropped on another thread in between, one of us sees it.
+        tracker.waiting.store(true, Ordering::SeqCst);
+        if tracker.wakers.load(Ordering::SeqCst) == 0
+            && tracker.waiting.swap(false, Ordering::SeqCst)
+        {
+            tracing::warn!(
+                target: "understanding_async_await::future::lost_wakeups",
+                type_name = tracker.type_name,
+                location = %tracker.location,
+                "future returned Pending without keeping or waking the waker, \
+                 it will never be polled again"
+            );
+        }
+        Poll::Pending
+    }
+}
+
+impl<F> Drop for DetectLostWakeups<F> {
+    fn drop(&mut self) {
+        // The wakers stored inside the future are about to be dropped, which
+        // isn't a lost wake up.
+        self.tracker.waiting.store(false, Ordering::SeqCst);
+        // Our wakers may also be stored elsewhere, don't let them keep the
+        // task's waker alive.
+        self.tracker.lock_waker().take();
+    }
+}
+
+impl<F> fmt::Debug for DetectLostWakeups<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DetectLostWakeups")
+            .field("type_name", &self.tracker.type_name)
+            .field("location", &self.tracker.location)
+            .field("wakers", &self.tracker.wakers.load(Ordering::SeqCst))
+            .field("waiting", &self.tracker.waiting.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+impl Tracker {
+    /// Creates one of our wakers.
+    fn waker(self: &Arc<Self>) -> Waker {
+        self.wakers.fetch_add(1, Ordering::SeqCst);
+        let data = Arc::into_raw(Arc::clone(self)).cast::<()>();
+        // SAFETY: The vtable functions below uphold the `RawWaker` contract,
+        // each waker owns one strong reference to the tracker.
+        unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) }
+    }
+
+    fn wake(&self) {
+        self.woken.store(true, Ordering::SeqCst);
+        self.waiting.store(false, Ordering::SeqCst);
+        let waker = self.lock_waker().clone();
+        // Wake outside of the lock, the task may be polled straight away on
+        // another thread.
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Called when one of our wakers is dropped without being woken.
+    fn drop_waker(&self) {
+        if self.wakers.fetch_sub(1, Ordering::SeqCst) == 1
+            && self.waiting.swap(false, Ordering::SeqCst)
+        {
+            tracing::warn!(
+                target: "understanding_async_await::future::lost_wakeups",
+                type_name = self.type_name,
+                location = %self.location,
+                "every waker of a waiting future was dropped without being woken, \
+                 it will never be polled again"
+            );
+        }
+    }
+
+    fn lock_waker(&self) -> MutexGuard<'_, Option<Waker>> {
+        match self.waker.lock() {
+            Ok(guard) => guard,
+            Err(_) => panic!("DetectLostWakeups state has become corrupted."),
+        }
+    }
+}
+
+const VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake_by_ref, drop_waker);
+
+unsafe fn clone_waker(data: *const ()) -> RawWaker {
+    // SAFETY: The waker being cloned is still alive, so the tracker is too.
+    let tracker = unsafe { &*data.cast::<Tracker>() };
+    tracker.wakers.fetch_add(1, Ordering::SeqCst);
+    // SAFETY: `data` came from `Arc::into_raw` and the waker being cloned
+    // still holds its strong reference, the new waker gets its own.
+    unsafe { Arc::increment_strong_count(data.cast::<Tracker>()) };
+    RawWaker::new(data, &VTABLE)
+}
+
+unsafe fn wake(data: *const ()) {
+    // SAFETY: The waker owns one strong reference, which we take back.
+    let tracker = unsafe { Arc::from_raw(data.cast::<Tracker>()) };
+    tracker.wake();
+    // The waker has been used up, it wasn't lost.
+    tracker.wakers.fetch_sub(1, Ordering::SeqCst);
+}
+
+unsafe fn wake_by_ref(data: *const ()) {
+    // SAFETY: The waker is still alive, so the tracker is too.
+    let tracker = unsafe { &*data.cast::<Tracker>() };
+    tracker.wake();
+}
+
+unsafe fn drop_waker(data: *const ()) {
+    // SAFETY: The waker owns one strong reference, which we drop.
+    let tracker = unsafe { Arc::from_raw(data.cast::<Tracker>()) };
+    tracker.drop_waker();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{pending, Future};
+    use std::pin::{pin, Pin};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+    use std::thread;
+
+    use tracing::Level;
+
+    use super::detect_lost_wakeups;
+    use crate::runtime::{self, Runtime};
+    use crate::testing::fixtures::{Capture, CapturedEvent};
+    use crate::testing::{poll_once, MockWaker};
+
+    /// Stores its waker in a slot and completes once `done` is set.
+    struct Stored {
+        slot: Arc<Mutex<Option<Waker>>>,
+        done: Arc<AtomicBool>,
+    }
+
+    impl Future for Stored {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            if self.done.load(Ordering::SeqCst) {
+                return Poll::Ready(());
+            }
+            *self.slot.lock().unwrap() = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn stored() -> (Stored, Arc<Mutex<Option<Waker>>>, Arc<AtomicBool>) {
+        let slot = Arc::new(Mutex::new(None));
+        let done = Arc::new(AtomicBool::new(false));
+        let future = Stored {
+            slot: Arc::clone(&slot),
+            done: Arc::clone(&done),
+        };
+        (future, slot, done)
+    }
+
+    fn warnings(capture: &Capture) -> Vec<CapturedEvent> {
+        capture
+            .take()
+            .into_iter()
+            .filter(|event| event.level == Level::WARN)
+            .collect()
+    }
+
+    #[test]
+    fn pending_without_waker_warns_once() {
+        let capture = Capture::default();
+        let waker = MockWaker::new();
+
+        let line = line!() + 2;
+        capture.run(|| {
+            let future = pin!(detect_lost_wakeups(pending::<()>()));
+            poll_once(future, &waker).assert_pending();
+        });
+
+        let warnings = warnings(&capture);
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        let warning = &warnings[0];
+        assert_eq!(
+            warning.target,
+            "understanding_async_await::future::lost_wakeups"
+        );
+        assert!(warning.fields["type_name"].contains("Pending"));
+        assert!(warning.fields["location"].starts_with(&format!("{}:{line}:", file!())));
+    }
+
+    #[test]
+    fn well_behaved_future_never_warns() {
+        let capture = Capture::default();
+        let waker = MockWaker::new();
+        let (future, slot, done) = stored();
+
+        capture.run(|| {
+            let mut future = pin!(detect_lost_wakeups(future));
+            poll_once(future.as_mut(), &waker).assert_pending();
+            poll_once(future.as_mut(), &waker).assert_pending();
+
+            let stored = slot.lock().unwrap().take().unwrap();
+            thread::spawn(move || {
+                done.store(true, Ordering::SeqCst);
+                stored.wake();
+            })
+            .join()
+            .unwrap();
+            assert_eq!(waker.counts().total_wakes(), 1);
+            poll_once(future.as_mut(), &waker).assert_ready();
+        });
+
+        assert!(warnings(&capture).is_empty());
+    }
+
+    #[test]
+    fn dropping_the_last_waker_while_waiting_warns_once() {
+        let capture = Capture::default();
+        let waker = MockWaker::new();
+        let (future, slot, _done) = stored();
+
+        capture.run(|| {
+            let mut future = pin!(detect_lost_wakeups(future));
+            poll_once(future.as_mut(), &waker).assert_pending();
+            assert!(warnings(&capture).is_empty());
+
+            drop(slot.lock().unwrap().take());
+        });
+
+        let warnings = warnings(&capture);
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        assert!(warnings[0].fields["message"].contains("every waker"));
+        assert!(warnings[0].fields["type_name"].contains("Stored"));
+    }
+
+    #[test]
+    fn runtime_reports_spawned_tasks() {
+        let capture = Capture::default();
+
+        let line = line!() + 4;
+        capture.run(|| {
+            let runtime = Runtime::current_thread().detect_lost_wakeups();
+            runtime.block_on(async {
+                let _lost = runtime::spawn(pending::<()>());
+                runtime::yield_now().await;
+                runtime::yield_now().await;
+            });
+        });
+
+        let warnings = warnings(&capture);
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        assert!(warnings[0].fields["location"].starts_with(&format!("{}:{line}:", file!())));
+    }
+}
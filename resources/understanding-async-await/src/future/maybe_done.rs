@@ -0,0 +1,69 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A future which keeps its output once it has completed, until it's taken.
+///
+/// This is what lets a join poll each of its futures until they have all
+/// completed, without polling a completed future again. It's also used by
+/// [`Buffered`] streams.
+///
+/// [`Buffered`]: struct@crate::stream::Buffered
+pub(crate) enum MaybeDone<F: Future> {
+    Future(Pin<Box<F>>),
+    Done(F::Output),
+    Taken,
+}
+
+// The output is never pinned, and the future is pinned in its box, so moving a
+// `MaybeDone` is fine even if the output isn't `Unpin`.
+impl<F: Future> Unpin for MaybeDone<F> {}
+
+impl<F: Future> MaybeDone<F> {
+    pub(crate) fn new(future: F) -> Self {
+        Self::Future(Box::pin(future))
+    }
+
+    /// Polls the future if it hasn't completed yet.
+    ///
+    /// Returns `true` once the output is available.
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> bool {
+        match self {
+            Self::Future(future) => match future.as_mut().poll(cx) {
+                Poll::Ready(output) => {
+                    // Replacing the future drops it straight away, it may be
+                    // holding on to resources.
+                    *self = Self::Done(output);
+                    true
+                }
+                Poll::Pending => false,
+            },
+            Self::Done(_) => true,
+            Self::Taken => panic!("MaybeDone polled after its output was taken"),
+        }
+    }
+
+    /// Takes the output.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the future hasn't completed, or the output has already been
+    /// taken.
+    pub(crate) fn take(&mut self) -> F::Output {
+        match std::mem::replace(self, Self::Taken) {
+            Self::Done(output) => output,
+            _ => panic!("MaybeDone output taken before it was ready"),
+        }
+    }
+}
+
+impl<F: Future> fmt::Debug for MaybeDone<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Future(_) => write!(f, "Pending"),
+            Self::Done(_) => write!(f, "Done"),
+            Self::Taken => write!(f, "Taken"),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Waits for the first of two futures with the same output to complete.
+///
+/// Unlike [`select`], a race is fair. The future which is polled first
+/// alternates on each poll, so a future which is always ready can't starve
+/// the other one. The losing future is dropped as soon as the race completes.
+///
+/// [`select`]: fn@super::select
+pub fn race<A, B>(a: A, b: B) -> Race<A, B>
+where
+    A: Future,
+    B: Future<Output = A::Output>,
+{
+    Race {
+        a: Some(Box::pin(a)),
+        b: Some(Box::pin(b)),
+        b_first: false,
+    }
+}
+
+/// The future returned by [`race`].
+pub struct Race<A, B> {
+    /// Both futures are `None` once the race has completed.
+    a: Option<Pin<Box<A>>>,
+    b: Option<Pin<Box<B>>>,
+    /// Whether `b` is polled first on the next poll.
+    b_first: bool,
+}
+
+impl<A, B> Future for Race<A, B>
+where
+    A: Future,
+    B: Future<Output = A::Output>,
+{
+    type Output = A::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let (Some(a), Some(b)) = (&mut this.a, &mut this.b) else {
+            panic!("Race polled after completion");
+        };
+
+        let b_first = this.b_first;
+        this.b_first = !b_first;
+        let result = if b_first {
+            match b.as_mut().poll(cx) {
+                Poll::Ready(output) => Poll::Ready(output),
+                Poll::Pending => a.as_mut().poll(cx),
+            }
+        } else {
+            match a.as_mut().poll(cx) {
+                Poll::Ready(output) => Poll::Ready(output),
+                Poll::Pending => b.as_mut().poll(cx),
+            }
+        };
+
+        if result.is_ready() {
+            this.a = None;
+            this.b = None;
+        }
+        result
+    }
+}
+
+impl<A, B> fmt::Debug for Race<A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Race")
+            .field("completed", &self.a.is_none())
+            .field("b_first", &self.b_first)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::task::Poll;
+
+    use crate::future::race;
+    use crate::testing::fixtures::Log;
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn race_alternates_which_future_is_polled_first() {
+        let log = Log::default();
+        let waker = MockWaker::new();
+        let mut race = race(log.probe("a", 1), log.probe("b", 1));
+
+        poll_once(Pin::new(&mut race), &waker).assert_woke_itself();
+        // Both are ready now, but `b` gets polled first this time.
+        assert_eq!(
+            poll_once(Pin::new(&mut race), &waker).poll,
+            Poll::Ready("b")
+        );
+        assert_eq!(
+            log.take(),
+            ["poll a", "poll b", "poll b", "drop a", "drop b"]
+        );
+    }
+}
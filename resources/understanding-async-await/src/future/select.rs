@@ -0,0 +1,109 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// The output of a [`select`], from one of two futures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Either<L, R> {
+    /// The first future completed first.
+    Left(L),
+    /// The second future completed first.
+    Right(R),
+}
+
+/// Waits for the first of two futures to complete.
+///
+/// The futures are always polled in order, `a` first, so if both are ready
+/// at the same time, `a` wins. As soon as one future completes, the other one
+/// is dropped, before the output is returned. Anything the losing future was
+/// doing is cancelled at that point, so it should be cancel safe.
+pub fn select<A, B>(a: A, b: B) -> Select<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    Select {
+        a: Some(Box::pin(a)),
+        b: Some(Box::pin(b)),
+    }
+}
+
+/// The future returned by [`select`].
+pub struct Select<A, B> {
+    /// Both futures are `None` once the select has completed.
+    a: Option<Pin<Box<A>>>,
+    b: Option<Pin<Box<B>>>,
+}
+
+impl<A: Future, B: Future> Future for Select<A, B> {
+    type Output = Either<A::Output, B::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let (Some(a), Some(b)) = (&mut this.a, &mut this.b) else {
+            panic!("Select polled after completion");
+        };
+
+        let output = if let Poll::Ready(output) = a.as_mut().poll(cx) {
+            Either::Left(output)
+        } else if let Poll::Ready(output) = b.as_mut().poll(cx) {
+            Either::Right(output)
+        } else {
+            return Poll::Pending;
+        };
+
+        // Drop the losing future now, rather than whenever the select itself
+        // is dropped, so that it can't hold on to anything.
+        this.a = None;
+        this.b = None;
+        Poll::Ready(output)
+    }
+}
+
+impl<A, B> fmt::Debug for Select<A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Select")
+            .field("completed", &self.a.is_none())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::task::Poll;
+
+    use crate::future::{select, Either};
+    use crate::testing::fixtures::{gate, Log};
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn select_drops_the_losing_branch() {
+        let log = Log::default();
+        let (gate, _opener) = gate();
+        let waker = MockWaker::new();
+        let mut select = select(log.wrap("gate", gate), log.probe("b", 1));
+
+        poll_once(Pin::new(&mut select), &waker).assert_woke_itself();
+        assert_eq!(
+            poll_once(Pin::new(&mut select), &waker).poll,
+            Poll::Ready(Either::Right("b"))
+        );
+        // The gate was dropped before the select returned, not when it's
+        // dropped.
+        assert_eq!(
+            log.take(),
+            [
+                "poll gate",
+                "poll b",
+                "poll gate",
+                "poll b",
+                "drop gate",
+                "drop b"
+            ]
+        );
+        drop(select);
+        assert_eq!(log.take(), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,109 @@
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Requires `future` to complete before `deadline` does.
+///
+/// The deadline can be any future, usually a [`Sleep`], but a test can use a
+/// future which it completes itself. If the deadline completes first, the
+/// future is dropped and [`Elapsed`] is returned.
+///
+/// The future is polled before the deadline, so if both are ready at the same
+/// time, the future's output is returned rather than an error.
+///
+/// [`Sleep`]: struct@crate::time::Sleep
+pub fn timeout<F, D>(future: F, deadline: D) -> Timeout<F, D>
+where
+    F: Future,
+    D: Future<Output = ()>,
+{
+    Timeout {
+        future: Some(Box::pin(future)),
+        deadline: Box::pin(deadline),
+    }
+}
+
+/// The future returned by [`timeout`].
+pub struct Timeout<F, D> {
+    /// `None` once the timeout has completed.
+    future: Option<Pin<Box<F>>>,
+    deadline: Pin<Box<D>>,
+}
+
+impl<F, D> Future for Timeout<F, D>
+where
+    F: Future,
+    D: Future<Output = ()>,
+{
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let Some(future) = &mut this.future else {
+            panic!("Timeout polled after completion");
+        };
+
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            this.future = None;
+            return Poll::Ready(Ok(output));
+        }
+
+        match this.deadline.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                // Drop the future straight away, that's what cancels it.
+                this.future = None;
+                Poll::Ready(Err(Elapsed {}))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<F, D> fmt::Debug for Timeout<F, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timeout")
+            .field("completed", &self.future.is_none())
+            .finish()
+    }
+}
+
+/// Error returned by [`Timeout`] when the deadline is reached before the
+/// future completes.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Elapsed {}
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "deadline has elapsed")
+    }
+}
+impl Error for Elapsed {}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::task::Poll;
+
+    use crate::future::{timeout, Elapsed};
+    use crate::testing::fixtures::{gate, Log};
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn timeout_cancels_the_future() {
+        let log = Log::default();
+        let (work, _work_opener) = gate();
+        let (deadline, deadline_opener) = gate();
+        let waker = MockWaker::new();
+        let mut timeout = timeout(log.wrap("work", work), deadline);
+
+        poll_once(Pin::new(&mut timeout), &waker).assert_registered_waker();
+        deadline_opener.open();
+        assert_eq!(waker.counts().total_wakes(), 1);
+        assert_eq!(
+            poll_once(Pin::new(&mut timeout), &waker).poll,
+            Poll::Ready(Err(Elapsed {}))
+        );
+        assert_eq!(log.take(), ["poll work", "poll work", "drop work"]);
+    }
+}
@@ -1 +1,11 @@
+pub mod future;
 pub mod mpmc;
+#[cfg(target_os = "linux")]
+pub mod net;
+pub mod pool;
+pub mod runtime;
+pub mod scope;
+pub mod stream;
+pub mod sync;
+pub mod testing;
+pub mod time;
@@ -1,9 +1,32 @@
+mod coop;
+pub mod durable;
+pub mod fault;
+pub mod ipc;
+pub mod keyed;
+pub mod priority;
+mod select;
+mod shared;
+pub mod timed;
+
+pub use coop::DEFAULT_BUDGET;
+pub use select::{recv_any, RecvAny, Select};
+
+use coop::Budget;
+
 use core::fmt;
+use mpmc_core::{Lock, RecvError as ChannelRecvError, SendError as ChannelSendError};
 use std::error::Error;
-use std::future::Future;
+use std::future::{poll_fn, Future};
 use std::sync::{Arc, Mutex};
-use std::task::Waker;
-use std::{collections::VecDeque, task::Poll};
+use std::task::Poll;
+
+/// The synchronous channel shared by the senders and receivers, see
+/// [`mpmc_core::Channel`].
+pub type Channel = mpmc_core::Channel<String>;
+
+/// The lock protecting the [`Channel`] unless another one is chosen with
+/// [`channel_with_lock`].
+pub type DefaultLock = Mutex<Channel>;
 
 /// Creates a new asynchronous bounded multi-producer multi-consumer channel,
 /// returning the sender/receiver halves.
@@ -17,10 +40,68 @@ use std::{collections::VecDeque, task::Poll};
 /// closed. Subsequent attempts to send a message will return a
 /// [`ChannelClosedError`]. Subsequent attempts to receive a message will drain
 /// the channel and once it is empty, will also return a [`ChannelClosedError`].
+///
+/// The channel is protected by a [`std::sync::Mutex`], see
+/// [`channel_with_lock`] to use a different lock.
 pub fn channel(capacity: usize) -> (Sender, Receiver) {
-    let inner = Arc::new(Mutex::new(Channel::new(capacity)));
+    channel_with_lock(capacity, false)
+}
 
-    (Sender::new(inner.clone()), Receiver::new(inner))
+/// Creates a new mpmc channel which hands off capacity and messages to
+/// waiting senders and receivers in strict FIFO order.
+///
+/// In a channel created with [`mpmc::channel`], a waiting sender is woken when
+/// a message is received, but a sender which calls `send` before the woken
+/// sender is polled again can take the free slot first. The woken sender then
+/// goes back to the end of the queue. Under contention, a waiting sender can
+/// be overtaken like this indefinitely. The same applies to waiting receivers
+/// and newly sent messages.
+///
+/// In a fair channel, a freed slot is reserved for the sender which is woken,
+/// and a new message is reserved for the receiver which is woken. Other
+/// senders and receivers can't take them, and have to join the back of the
+/// queue. This bounds how long any sender or receiver has to wait, at the cost
+/// of some throughput: a reserved slot or message stays unused until the
+/// woken task is polled.
+///
+/// Closing and draining the channel follow the same rules as
+/// [`mpmc::channel`]. Once the channel is closed, messages are no longer
+/// reserved for specific receivers.
+///
+/// [`mpmc::channel`]: fn@super::mpmc::channel
+pub fn fair_channel(capacity: usize) -> (Sender, Receiver) {
+    channel_with_lock(capacity, true)
+}
+
+/// Creates a new mpmc channel protected by the lock `L`.
+///
+/// Any [`Lock`] can be used, for example the [`SpinLock`] from `mpmc_core`,
+/// which is what a `no_std` user of [`Channel`] would have instead of the
+/// [`std::sync::Mutex`] used by [`mpmc::channel`]:
+///
+/// ```
+/// use mpmc_core::SpinLock;
+/// use understanding_async_await::mpmc::{self, Channel};
+///
+/// let (tx, rx) = mpmc::channel_with_lock::<SpinLock<Channel>>(10, false);
+/// ```
+///
+/// [`SpinLock`]: mpmc_core::SpinLock
+///
+/// If `fair` is true, the channel behaves like [`fair_channel`], otherwise
+/// like [`mpmc::channel`].
+///
+/// [`mpmc::channel`]: fn@super::mpmc::channel
+pub fn channel_with_lock<L>(capacity: usize, fair: bool) -> (Sender<L>, Receiver<L>)
+where
+    L: Lock<Channel>,
+{
+    let inner = Arc::new(L::new(Channel::new(capacity, fair)));
+
+    (
+        Sender::new(inner.clone(), Some(DEFAULT_BUDGET)),
+        Receiver::new(inner, Some(DEFAULT_BUDGET)),
+    )
 }
 
 /// Error returned when the underlying channel is closed.
@@ -44,21 +125,46 @@ impl Error for ChannelClosedError {}
 /// This half can be cloned to send from multiple tasks. Dropping all senders
 /// will cause the channel to be closed.
 ///
+/// Each sender has a cooperative scheduling budget, see [`set_budget`].
+///
+/// The channel is protected by the lock `L`, see [`channel_with_lock`].
+///
 /// [`mpmc::channel`]: fn@super::mpmc::channel
 /// [`send`]: fn@Self::send
-pub struct Sender {
-    inner: Arc<Mutex<Channel>>,
+/// [`set_budget`]: fn@Self::set_budget
+pub struct Sender<L = DefaultLock>
+where
+    L: Lock<Channel>,
+{
+    inner: Arc<L>,
+    budget: Arc<Budget>,
 }
 
-impl Sender {
-    fn new(inner: Arc<Mutex<Channel>>) -> Self {
-        {
-            match inner.lock() {
-                Ok(mut guard) => guard.inc_senders(),
-                Err(_) => panic!("MPMC Channel has become corrupted."),
-            }
+impl<L> Sender<L>
+where
+    L: Lock<Channel>,
+{
+    fn new(inner: Arc<L>, budget: Option<u32>) -> Self {
+        inner.lock().inc_senders();
+        Self {
+            inner,
+            budget: Arc::new(Budget::new(budget)),
         }
-        Self { inner }
+    }
+
+    /// Sets the number of sends which can complete in a row before this
+    /// sender yields to the runtime.
+    ///
+    /// If a channel always has free capacity, a task which sends in a loop
+    /// would never yield, starving other tasks on the same thread. Once the
+    /// budget is used up, the next call to `send` will yield once before
+    /// sending. The budget is refilled whenever a send has to wait.
+    ///
+    /// Pass `None` to disable the budget. The default is
+    /// [`DEFAULT_BUDGET`]. Cloning a sender gives the clone a full budget with
+    /// the same limit.
+    pub fn set_budget(&mut self, budget: Option<u32>) {
+        self.budget = Arc::new(Budget::new(budget));
     }
 
     /// Sends a value, waiting until there is capacity.
@@ -68,50 +174,116 @@ impl Sender {
     /// never be received, however an `Ok` result doesn't guarantee that the
     /// value will be received as all receivers may disconnect immediately
     /// after this method returns `Ok`.
+    ///
+    /// This method is cancel safe. If the returned future is dropped before
+    /// it completes, any free capacity that it had already been woken for will
+    /// be passed on to the next waiting sender.
     pub async fn send(&self, value: String) -> Result<(), ChannelClosedError> {
         Send {
             value,
             inner: self.inner.clone(),
+            budget: self.budget.clone(),
+            waiter_id: None,
         }
         .await
     }
+
+    /// Waits until the channel is closed.
+    ///
+    /// The channel is closed once every [`Receiver`] has been dropped, or
+    /// when [`Receiver::close`] is called. After that, every call to
+    /// [`send`] will fail. This lets a task which sends only occasionally
+    /// find out that nobody is listening any more, without having to send a
+    /// value first.
+    ///
+    /// This method is cancel safe.
+    ///
+    /// [`send`]: fn@Self::send
+    pub async fn closed(&self) {
+        poll_fn(|cx| {
+            let mut guard = self.inner.lock();
+            if guard.is_closed() {
+                Poll::Ready(())
+            } else {
+                guard.register_closed_waker(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
 }
 
-impl Clone for Sender {
+impl<L> Clone for Sender<L>
+where
+    L: Lock<Channel>,
+{
     fn clone(&self) -> Self {
-        Self::new(self.inner.clone())
+        Self::new(self.inner.clone(), self.budget.limit())
     }
 }
 
-impl Drop for Sender {
+impl<L> Drop for Sender<L>
+where
+    L: Lock<Channel>,
+{
     fn drop(&mut self) {
-        match self.inner.lock() {
-            Ok(mut guard) => guard.dec_senders(),
-            Err(_) => panic!("MPMC Channel has become corrupted."),
-        }
+        self.inner.lock().dec_senders();
     }
 }
 
-struct Send {
+struct Send<L>
+where
+    L: Lock<Channel>,
+{
     value: String,
-    inner: Arc<Mutex<Channel>>,
+    inner: Arc<L>,
+    budget: Arc<Budget>,
+    /// The id given to this future when it registered its waker.
+    waiter_id: Option<u64>,
 }
 
-impl Future for Send {
+impl<L> Future for Send<L>
+where
+    L: Lock<Channel>,
+{
     type Output = Result<(), ChannelClosedError>;
 
-    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
-        let Ok(mut guard) = self.inner.lock() else {
-            panic!("MPMC Channel has become corrupted.");
-        };
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if this.budget.poll_proceed(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let mut guard = this.inner.lock();
 
-        match guard.send(self.value.clone()) {
-            Ok(_) => Poll::Ready(Ok(())),
+        let poll = match guard.send(this.waiter_id, this.value.clone()) {
+            Ok(_) => {
+                if let Some(id) = this.waiter_id.take() {
+                    guard.remove_sender_waker(id);
+                }
+                Poll::Ready(Ok(()))
+            }
             Err(ChannelSendError::Closed) => Poll::Ready(Err(ChannelClosedError {})),
             Err(ChannelSendError::Full) => {
-                guard.register_sender_waker(cx.waker().clone());
+                this.waiter_id =
+                    Some(guard.register_sender_waker(this.waiter_id, cx.waker().clone()));
                 Poll::Pending
             }
+        };
+        this.budget.record(&poll);
+        poll
+    }
+}
+
+impl<L> Drop for Send<L>
+where
+    L: Lock<Channel>,
+{
+    fn drop(&mut self) {
+        if let Some(id) = self.waiter_id {
+            self.inner.lock().deregister_sender_waker(id);
         }
     }
 }
@@ -124,21 +296,46 @@ impl Future for Send {
 /// only be received by a single receiver. Dropping all receivers will cause
 /// the channel to be closed.
 ///
+/// Each receiver has a cooperative scheduling budget, see [`set_budget`].
+///
+/// The channel is protected by the lock `L`, see [`channel_with_lock`].
+///
 /// [`mpmc::channel`]: fn@super::mpmc::channel
 /// [`recv`]: fn@Self::recv
-pub struct Receiver {
-    inner: Arc<Mutex<Channel>>,
+/// [`set_budget`]: fn@Self::set_budget
+pub struct Receiver<L = DefaultLock>
+where
+    L: Lock<Channel>,
+{
+    inner: Arc<L>,
+    budget: Arc<Budget>,
 }
 
-impl Receiver {
-    fn new(inner: Arc<Mutex<Channel>>) -> Self {
-        {
-            match inner.lock() {
-                Ok(mut guard) => guard.inc_receivers(),
-                Err(_) => panic!("MPMC Channel has become corrupted."),
-            }
+impl<L> Receiver<L>
+where
+    L: Lock<Channel>,
+{
+    fn new(inner: Arc<L>, budget: Option<u32>) -> Self {
+        inner.lock().inc_receivers();
+        Self {
+            inner,
+            budget: Arc::new(Budget::new(budget)),
         }
-        Self { inner }
+    }
+
+    /// Sets the number of receives which can complete in a row before this
+    /// receiver yields to the runtime.
+    ///
+    /// If a channel always has messages available, a task which receives in a
+    /// loop would never yield, starving other tasks on the same thread. Once
+    /// the budget is used up, the next call to `recv` will yield once before
+    /// receiving. The budget is refilled whenever a receive has to wait.
+    ///
+    /// Pass `None` to disable the budget. The default is
+    /// [`DEFAULT_BUDGET`]. Cloning a receiver gives the clone a full budget
+    /// with the same limit.
+    pub fn set_budget(&mut self, budget: Option<u32>) {
+        self.budget = Arc::new(Budget::new(budget));
     }
 
     /// Receives a value, waiting until one is available.
@@ -147,233 +344,220 @@ impl Receiver {
     /// continue to return the remaining values stored in the channel buffer.
     /// Once the channel is empty, this method will return
     /// [`ChannelClosedError`].
+    ///
+    /// This method is cancel safe. If the returned future is dropped before
+    /// it completes, no message will be lost, and any wake up that it had
+    /// already been given will be passed on to the next waiting receiver.
     pub async fn recv(&self) -> Result<String, ChannelClosedError> {
         Recv {
             inner: self.inner.clone(),
+            budget: self.budget.clone(),
+            waiter_id: None,
         }
         .await
     }
+
+    /// Closes the channel without dropping the receiver.
+    ///
+    /// Subsequent attempts to send a message will return a
+    /// [`ChannelClosedError`]. Messages which are already in the channel
+    /// buffer can still be received, once it is empty [`recv`] will also
+    /// return a `ChannelClosedError`. This allows receivers to stop accepting
+    /// new messages and then drain the channel.
+    ///
+    /// [`recv`]: fn@Self::recv
+    pub fn close(&self) {
+        self.inner.lock().close();
+    }
+
+    /// Puts a value which was received but couldn't be delivered back at the
+    /// front of the channel, see [`Channel::requeue`].
+    fn requeue(&self, value: String) {
+        self.inner.lock().requeue(value);
+    }
 }
 
-impl Clone for Receiver {
+impl<L> Clone for Receiver<L>
+where
+    L: Lock<Channel>,
+{
     fn clone(&self) -> Self {
-        Self::new(self.inner.clone())
+        Self::new(self.inner.clone(), self.budget.limit())
     }
 }
 
-impl Drop for Receiver {
+impl<L> Drop for Receiver<L>
+where
+    L: Lock<Channel>,
+{
     fn drop(&mut self) {
-        match self.inner.lock() {
-            Ok(mut guard) => guard.dec_receivers(),
-            Err(_) => panic!("MPMC Channel has become corrupted."),
-        }
+        self.inner.lock().dec_receivers();
     }
 }
 
-struct Recv {
-    inner: Arc<Mutex<Channel>>,
+struct Recv<L>
+where
+    L: Lock<Channel>,
+{
+    inner: Arc<L>,
+    budget: Arc<Budget>,
+    /// The id given to this future when it registered its waker.
+    waiter_id: Option<u64>,
 }
 
-impl Future for Recv {
+impl<L> Future for Recv<L>
+where
+    L: Lock<Channel>,
+{
     type Output = Result<String, ChannelClosedError>;
 
-    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
-        let Ok(mut guard) = self.inner.lock() else {
-            panic!("MPMC Channel has become corrupted.");
-        };
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if this.budget.poll_proceed(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let mut guard = this.inner.lock();
 
-        match guard.recv() {
-            Ok(value) => Poll::Ready(Ok(value)),
+        let poll = match guard.recv(this.waiter_id) {
+            Ok(value) => {
+                if let Some(id) = this.waiter_id.take() {
+                    guard.remove_receiver_waker(id);
+                }
+                Poll::Ready(Ok(value))
+            }
             Err(ChannelRecvError::Closed) => Poll::Ready(Err(ChannelClosedError {})),
             Err(ChannelRecvError::Empty) => {
-                guard.register_receiver_waker(cx.waker().clone());
+                this.waiter_id =
+                    Some(guard.register_receiver_waker(this.waiter_id, cx.waker().clone()));
                 Poll::Pending
             }
-        }
+        };
+        this.budget.record(&poll);
+        poll
     }
 }
 
-/// The inner mpmc channel implementation.
-///
-/// This is a sync object. All methods return immediately.
-struct Channel {
-    /// The message buffer
-    buffer: VecDeque<String>,
-    /// The capacity of the channel, this many messages can be buffered before
-    /// sending will error.
-    capacity: usize,
-    /// Indicates when the channel has been closed.
-    closed: bool,
-
-    /// The number of connected `Sender`s.
-    senders: usize,
-    /// The number of active `Receiver`s.
-    receivers: usize,
-
-    /// A queue of wakers for senders awaiting free capacity in the channel.
-    sender_wakers: VecDeque<Waker>,
-    /// A queue of wakers for receivers awaiting a new message in the channel.
-    receiver_wakers: VecDeque<Waker>,
-}
-
-impl Channel {
-    fn new(capacity: usize) -> Self {
-        Self {
-            buffer: VecDeque::with_capacity(capacity),
-            capacity,
-            closed: false,
-
-            senders: 0,
-            receivers: 0,
-
-            sender_wakers: VecDeque::new(),
-            receiver_wakers: VecDeque::new(),
+impl<L> Drop for Recv<L>
+where
+    L: Lock<Channel>,
+{
+    fn drop(&mut self) {
+        if let Some(id) = self.waiter_id {
+            self.inner.lock().deregister_receiver_waker(id);
         }
     }
+}
 
-    /// Sends a message across the channel.
-    ///
-    /// If the message can be sent, the next receiver waker in the queue (if
-    /// any) will be woken as there is now an additional message which can be
-    /// received.
-    ///
-    /// An error will be returned if the channel is full or closed.
-    fn send(&mut self, value: String) -> Result<(), ChannelSendError> {
-        if self.closed {
-            return Err(ChannelSendError::Closed);
-        }
-
-        if self.buffer.len() < self.capacity {
-            self.buffer.push_back(value);
-            self.wake_next_receiver();
-            Ok(())
-        } else {
-            Err(ChannelSendError::Full)
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::{pin, Pin};
 
-    /// Receives a message from the channel.
-    ///
-    /// If a message can be received, then the next sender waker in the queue
-    /// (if any) will be woken as there is now additional free capacity to send
-    /// another message.
-    ///
-    /// An error will be returned if the channel is empty. The error will
-    /// depend on whether the channel is also closed.
-    fn recv(&mut self) -> Result<String, ChannelRecvError> {
-        match self.buffer.pop_front() {
-            Some(value) => {
-                self.wake_next_sender();
-                Ok(value)
-            }
-            None => {
-                if !self.closed {
-                    Err(ChannelRecvError::Empty)
-                } else {
-                    Err(ChannelRecvError::Closed)
-                }
-            }
-        }
-    }
+    use super::{channel, fair_channel, ChannelClosedError, Receiver, Sender};
+    use crate::testing::{poll_once, MockWaker};
 
-    /// Registers a waker to be woken when capacity is available.
-    ///
-    /// Senders are woken in FIFO order.
-    fn register_sender_waker(&mut self, waker: Waker) {
-        self.sender_wakers.push_back(waker);
-    }
+    /// The number of senders waiting for capacity at any time.
+    const WAITERS: usize = 8;
 
-    /// Registers a waker to be woken when a message is available.
-    ///
-    /// Receivers are woken in FIFO order.
-    fn register_receiver_waker(&mut self, waker: Waker) {
-        self.receiver_wakers.push_back(waker);
-    }
+    type SendFuture<'a> = Pin<Box<dyn Future<Output = Result<(), ChannelClosedError>> + 'a>>;
 
-    /// Wakes the sender at the front of the queue.
-    ///
-    /// If no sender wakers are registered, this method does nothing.
-    fn wake_next_sender(&mut self) {
-        if let Some(waker) = self.sender_wakers.pop_front() {
-            waker.wake();
-        }
+    /// A send which is waiting, with the number of sends which had completed
+    /// when it started.
+    struct Waiter<'a> {
+        send: SendFuture<'a>,
+        waker: MockWaker,
+        wakes: usize,
+        started_at: usize,
     }
 
-    /// Wakes the receiver at the front of the queue.
+    /// Runs a contended channel of capacity 1 by hand and returns the largest
+    /// number of other sends which completed while a single send waited,
+    /// whether or not it has completed itself.
     ///
-    /// If no receiver wakers are registered, this method does nothing.
-    fn wake_next_receiver(&mut self) {
-        if let Some(waker) = self.receiver_wakers.pop_front() {
-            waker.wake();
+    /// `WAITERS` senders wait for capacity. Each round, one message is
+    /// received, freeing a slot and waking the first waiting sender. Before
+    /// that sender is polled again, a new sender arrives and tries to take
+    /// the slot, then waits too if it can't.
+    fn worst_wait(mut tx: Sender, mut rx: Receiver, rounds: usize) -> usize {
+        tx.set_budget(None);
+        rx.set_budget(None);
+        let waker = MockWaker::new();
+        poll_once(pin!(tx.send("fill".into())), &waker)
+            .assert_ready()
+            .unwrap();
+
+        let mut completed = 0;
+        let mut worst = 0;
+        let mut waiters = Vec::new();
+        for _ in 0..WAITERS {
+            send(&tx, &mut waiters, &mut completed);
         }
-    }
-
-    /// Increment the sender count.
-    fn inc_senders(&mut self) {
-        self.senders += 1;
-    }
 
-    /// Decrement the sender count.
-    ///
-    /// If the count reaches zero, close the channel.
-    fn dec_senders(&mut self) {
-        self.senders -= 1;
-        if self.senders == 0 {
-            self.close();
+        for _ in 0..rounds {
+            poll_once(pin!(rx.recv()), &waker).assert_ready().unwrap();
+            send(&tx, &mut waiters, &mut completed);
+
+            let mut idx = 0;
+            while idx < waiters.len() {
+                let waiter = &mut waiters[idx];
+                let wakes = waiter.waker.counts().total_wakes();
+                if wakes == waiter.wakes {
+                    idx += 1;
+                    continue;
+                }
+                waiter.wakes = wakes;
+                if poll_once(waiter.send.as_mut(), &waiter.waker)
+                    .poll
+                    .is_ready()
+                {
+                    worst = worst.max(completed - waiter.started_at);
+                    completed += 1;
+                    waiters.remove(idx);
+                } else {
+                    idx += 1;
+                }
+            }
         }
-    }
 
-    /// Increment the receiver count.
-    fn inc_receivers(&mut self) {
-        self.receivers += 1;
+        // Senders which are still waiting have been overtaken too.
+        waiters
+            .iter()
+            .map(|waiter| completed - waiter.started_at)
+            .fold(worst, usize::max)
     }
 
-    /// Decrement the receiver count.
-    ///
-    /// If the count reaches zero, close the channel.
-    fn dec_receivers(&mut self) {
-        self.receivers -= 1;
-        if self.receivers == 0 {
-            self.close();
+    /// Starts a send, counting it as completed if it didn't have to wait.
+    fn send<'a>(tx: &'a Sender, waiters: &mut Vec<Waiter<'a>>, completed: &mut usize) {
+        let mut send: SendFuture<'a> = Box::pin(tx.send("value".into()));
+        let waker = MockWaker::new();
+        if poll_once(send.as_mut(), &waker).poll.is_ready() {
+            *completed += 1;
+        } else {
+            waiters.push(Waiter {
+                send,
+                waker,
+                wakes: 0,
+                started_at: *completed,
+            });
         }
     }
 
-    /// Close the channel.
-    ///
-    /// All sender and receiver wakers which have been registered, but not yet
-    /// woken will get woken now.
-    fn close(&mut self) {
-        self.closed = true;
-
-        while let Some(waker) = self.sender_wakers.pop_front() {
-            waker.wake();
-        }
-        while let Some(waker) = self.receiver_wakers.pop_front() {
-            waker.wake();
-        }
+    #[test]
+    fn fair_channel_bounds_how_often_a_sender_is_overtaken() {
+        let (tx, rx) = fair_channel(1);
+        let worst = worst_wait(tx, rx, 200);
+        assert!(worst <= WAITERS, "a sender was overtaken {worst} times");
     }
-}
 
-#[derive(Debug)]
-enum ChannelSendError {
-    Full,
-    Closed,
-}
-impl fmt::Display for ChannelSendError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{self:?}")
-    }
-}
-impl Error for ChannelSendError {}
-
-#[derive(Debug)]
-enum ChannelRecvError {
-    Empty,
-    Closed,
-}
-impl fmt::Display for ChannelRecvError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{self:?}")
+    #[test]
+    fn channel_lets_new_senders_overtake() {
+        // Shows that the scenario above really is unfair without the
+        // reservations, so the bound isn't met by accident.
+        let (tx, rx) = channel(1);
+        let worst = worst_wait(tx, rx, 200);
+        assert!(worst > WAITERS, "a sender was only overtaken {worst} times");
     }
 }
-impl Error for ChannelRecvError {}
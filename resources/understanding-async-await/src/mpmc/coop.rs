@@ -0,0 +1,161 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::task::{Context, Poll};
+
+/// The number of operations a [`Sender`] or [`Receiver`] can complete in a
+/// row before it yields, unless configured otherwise.
+///
+/// This is the same budget that tokio gives each task.
+///
+/// [`Sender`]: struct@super::Sender
+/// [`Receiver`]: struct@super::Receiver
+pub const DEFAULT_BUDGET: u32 = 128;
+
+/// An operation budget for cooperative scheduling.
+///
+/// A task which sends to or receives from a channel that always has capacity
+/// or messages available will never return `Poll::Pending`, so it never gives
+/// the runtime a chance to run other tasks on the same thread. To avoid this,
+/// each operation which completes immediately uses up one unit of budget.
+/// Once the budget has been used up, the next operation wakes its task and
+/// returns `Poll::Pending` instead, yielding back to the runtime. The budget
+/// is then refilled.
+///
+/// Whenever an operation returns `Poll::Pending` because it has to wait, the
+/// task is yielding anyway, so the budget is refilled too.
+///
+/// Tokio keeps a single budget per task which its runtime refills each time
+/// the task is polled. The channel can't rely on running on a particular
+/// runtime, so each sender and receiver keeps its own budget instead. Since a
+/// sender or receiver handle is usually owned by a single task, this works
+/// out to much the same thing, with two limitations:
+///
+/// - A task which uses several handles gets a budget for each of them, so it
+///   can complete up to the sum of their budgets before it yields. It isn't
+///   made to yield at all if it alternates between channels and other
+///   futures which are always ready.
+/// - Tasks which share a handle by reference also share its budget, so one
+///   of them may be made to yield early because of the others' operations.
+#[derive(Debug)]
+pub(super) struct Budget {
+    /// The budget to refill to, `None` means that the budget is unconstrained.
+    limit: Option<u32>,
+    /// The operations remaining before the next forced yield.
+    remaining: AtomicU32,
+}
+
+impl Budget {
+    /// Creates a new full budget. A limit of 0 is treated as 1, otherwise
+    /// no operation could ever complete.
+    pub(super) fn new(limit: Option<u32>) -> Self {
+        let limit = limit.map(|limit| limit.max(1));
+        Self {
+            limit,
+            remaining: AtomicU32::new(limit.unwrap_or(0)),
+        }
+    }
+
+    /// The budget that this one refills to.
+    pub(super) fn limit(&self) -> Option<u32> {
+        self.limit
+    }
+
+    /// Checks whether there is budget left to perform an operation.
+    ///
+    /// If the budget has been used up, it is refilled, the task is woken, and
+    /// `Poll::Pending` is returned. The caller must then return
+    /// `Poll::Pending` without performing the operation.
+    pub(super) fn poll_proceed(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let Some(limit) = self.limit else {
+            return Poll::Ready(());
+        };
+
+        if self.remaining.load(Ordering::Relaxed) == 0 {
+            self.remaining.store(limit, Ordering::Relaxed);
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+
+    /// Records the outcome of an operation.
+    ///
+    /// An operation which completed uses up one unit of budget. An operation
+    /// which is waiting will yield, so the budget is refilled.
+    pub(super) fn record<T>(&self, poll: &Poll<T>) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+
+        match poll {
+            Poll::Ready(_) => {
+                _ = self.remaining.fetch_update(
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                    |remaining| remaining.checked_sub(1),
+                );
+            }
+            Poll::Pending => self.remaining.store(limit, Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use crate::mpmc::{self, DEFAULT_BUDGET};
+    use crate::runtime::{self, Runtime};
+
+    const MESSAGES: usize = 1_000;
+
+    /// Receives every message from a full channel on a current-thread
+    /// runtime, while a sibling task tries to make progress.
+    ///
+    /// Returns the number of times the sibling ran before all the messages
+    /// had been received.
+    fn sibling_ticks(budget: Option<u32>) -> u64 {
+        Runtime::current_thread().block_on(async move {
+            let (tx, mut rx) = mpmc::channel(MESSAGES);
+            rx.set_budget(budget);
+            for val in 0..MESSAGES {
+                tx.send(format!("{val}")).await.unwrap();
+            }
+            drop(tx);
+
+            let ticks = Arc::new(AtomicU64::new(0));
+            let sibling = runtime::spawn({
+                let ticks = Arc::clone(&ticks);
+                async move {
+                    loop {
+                        ticks.fetch_add(1, Ordering::SeqCst);
+                        runtime::yield_now().await;
+                    }
+                }
+            });
+
+            let mut received = 0;
+            while rx.recv().await.is_ok() {
+                received += 1;
+            }
+            assert_eq!(received, MESSAGES);
+            sibling.abort();
+
+            ticks.load(Ordering::SeqCst)
+        })
+    }
+
+    #[test]
+    fn sibling_task_starves_without_a_budget() {
+        assert_eq!(sibling_ticks(None), 0);
+    }
+
+    #[test]
+    fn sibling_task_progresses_with_a_budget() {
+        let ticks = sibling_ticks(Some(DEFAULT_BUDGET));
+        // The receiver yields once each time its budget is used up.
+        let yields = (MESSAGES as u64) / u64::from(DEFAULT_BUDGET);
+        assert!(ticks >= yields, "sibling only ticked {ticks} times");
+    }
+}
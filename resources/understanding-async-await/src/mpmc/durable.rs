@@ -0,0 +1,884 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::{error::Error, fmt};
+
+use super::shared::{self, ReceiverHandle, SenderHandle, Shared};
+use super::{ChannelClosedError, ChannelRecvError, ChannelSendError};
+
+/// When the log is flushed to disk with `fsync`.
+///
+/// Syncing more often reduces the number of acknowledged messages which can be
+/// lost if the machine (not just the process) crashes, at the cost of slower
+/// sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// Sync after every message, before `send` returns.
+    Always,
+    /// Sync after every `n` messages.
+    EveryN(usize),
+    /// Never sync explicitly, leave it to the operating system.
+    Never,
+}
+
+/// Options for a durable channel.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// When to sync the log to disk.
+    pub fsync: FsyncPolicy,
+    /// The size in bytes after which a new segment file is started.
+    ///
+    /// Segments are only deleted once every message in them has been
+    /// committed, so smaller segments free up disk space sooner.
+    pub segment_size: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            fsync: FsyncPolicy::Always,
+            segment_size: 1024 * 1024,
+        }
+    }
+}
+
+/// Opens a durable asynchronous bounded multi-producer multi-consumer
+/// channel stored in the directory `path`, returning the sender/receiver
+/// halves.
+///
+/// Uses the default [`Options`]. See [`open_with_options`] for details.
+pub fn open(path: impl AsRef<Path>, capacity: usize) -> io::Result<(Sender, Receiver)> {
+    open_with_options(path, capacity, Options::default())
+}
+
+/// Opens a durable channel with the given options.
+///
+/// This channel behaves like [`mpmc::channel`], except that every message is
+/// appended to a log on disk before [`Sender::send`] returns. The log is made
+/// up of segment files in the directory `path`, which will be created if it
+/// doesn't exist.
+///
+/// Each message received has an offset, which the receiver must
+/// [`commit`] once it has been processed. When the channel is opened again,
+/// for example after the process has crashed, every message which was sent but
+/// not committed is loaded back into the channel to be received again. This
+/// gives at-least-once delivery: a message which was processed but not
+/// committed before a crash will be received a second time.
+///
+/// If the end of the log was only partially written when the process stopped,
+/// the incomplete message is discarded and the segment file truncated.
+///
+/// The capacity of the channel is the number of messages which have been sent
+/// but not yet received. Messages loaded from the log when the channel is
+/// opened may exceed the capacity, in which case senders will wait until the
+/// extra messages have been received.
+///
+/// Only a single channel may have the directory open at a time. An
+/// exclusive lock is taken on a `lock` file in the directory, and opening the
+/// directory again, from this or any other process, fails until the channel
+/// holding the lock has been dropped.
+///
+/// Writes to the log are performed while the channel is locked, blocking the
+/// task which is sending. This keeps the implementation simple, but a
+/// production implementation would hand the writes off to a dedicated thread.
+///
+/// [`mpmc::channel`]: fn@super::channel
+/// [`commit`]: fn@Receiver::commit
+pub fn open_with_options(
+    path: impl AsRef<Path>,
+    capacity: usize,
+    options: Options,
+) -> io::Result<(Sender, Receiver)> {
+    let (log, buffer) = Log::open(path.as_ref(), options)?;
+    let (tx, rx) = shared::handles(Buffer {
+        messages: buffer,
+        received: BTreeSet::new(),
+        log,
+        capacity,
+    });
+
+    Ok((Sender { channel: tx }, Receiver { channel: rx }))
+}
+
+/// Error returned from a durable channel.
+#[derive(Debug)]
+pub enum DurableError {
+    /// The channel is closed.
+    Closed,
+    /// Writing to the log on disk failed.
+    Io(io::Error),
+    /// The offset passed to [`Receiver::commit`] isn't one which has been
+    /// received and not yet committed.
+    InvalidOffset(u64),
+}
+impl fmt::Display for DurableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Closed => write!(f, "channel closed"),
+            Self::Io(err) => write!(f, "durable channel log error: {err}"),
+            Self::InvalidOffset(offset) => {
+                write!(
+                    f,
+                    "offset {offset} hasn't been received or is already committed"
+                )
+            }
+        }
+    }
+}
+impl Error for DurableError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Closed | Self::InvalidOffset(_) => None,
+            Self::Io(err) => Some(err),
+        }
+    }
+}
+impl From<io::Error> for DurableError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// A message received from a durable channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// The position of the message in the log, pass this to
+    /// [`Receiver::commit`] once the message has been processed.
+    pub offset: u64,
+    /// The value which was sent.
+    pub value: String,
+}
+
+/// The sending-half of the [`durable::open`] type.
+///
+/// Messages can be sent through the channel with [`send`].
+///
+/// This half can be cloned to send from multiple tasks. Dropping all senders
+/// will cause the channel to be closed.
+///
+/// [`durable::open`]: fn@open
+/// [`send`]: fn@Self::send
+#[derive(Clone)]
+pub struct Sender {
+    channel: SenderHandle<Buffer>,
+}
+
+impl Sender {
+    /// Sends a value, waiting until there is capacity.
+    ///
+    /// The value is written to the log (and synced, depending on the
+    /// [`FsyncPolicy`]) before this method returns `Ok`. Once it has returned
+    /// `Ok`, the value will be received even if the process crashes.
+    ///
+    /// An error is returned if the channel is closed or if writing to the log
+    /// fails. In either case, the value will never be received.
+    pub async fn send(&self, value: String) -> Result<(), DurableError> {
+        match self.channel.send(|channel| channel.send(&value)).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(err)) => Err(DurableError::Io(err)),
+            Err(ChannelClosedError {}) => Err(DurableError::Closed),
+        }
+    }
+}
+
+/// The receiving-half of the [`durable::open`] type.
+///
+/// Messages can be received from the channel with [`recv`] and must be
+/// committed with [`commit`] once they have been processed.
+///
+/// This half can be cloned to receive from multiple tasks. Each message will
+/// only be received by a single receiver (until the channel is opened again).
+/// Dropping all receivers will cause the channel to be closed.
+///
+/// [`durable::open`]: fn@open
+/// [`recv`]: fn@Self::recv
+/// [`commit`]: fn@Self::commit
+#[derive(Clone)]
+pub struct Receiver {
+    channel: ReceiverHandle<Buffer>,
+}
+
+impl Receiver {
+    /// Receives a message, waiting until one is available.
+    ///
+    /// Once the channel is closed (by dropping all senders), this method will
+    /// continue to return the remaining messages stored in the channel buffer.
+    /// Once the channel is empty, this method will return
+    /// [`ChannelClosedError`].
+    pub async fn recv(&self) -> Result<Message, ChannelClosedError> {
+        self.channel.recv(Shared::recv).await
+    }
+
+    /// Commits a received message, so that it won't be received again when
+    /// the channel is next opened.
+    ///
+    /// Messages may be committed in any order. Only the offset below which
+    /// every message has been committed is stored on disk, so after a crash,
+    /// messages committed out of order may be received again. Segment files
+    /// which only contain committed messages are deleted.
+    ///
+    /// Only a message which has been received can be committed, and only
+    /// once. Any other offset returns [`DurableError::InvalidOffset`],
+    /// otherwise a message which nobody has processed could be skipped the
+    /// next time the channel is opened.
+    pub fn commit(&self, offset: u64) -> Result<(), DurableError> {
+        let mut guard = self.channel.lock();
+        let buffer = &mut guard.buffer;
+        if !buffer.received.remove(&offset) {
+            return Err(DurableError::InvalidOffset(offset));
+        }
+        Ok(buffer.log.commit(offset)?)
+    }
+}
+
+/// The durable channel's message buffer.
+struct Buffer {
+    /// The messages which have been written to the log, but not yet received.
+    messages: VecDeque<Message>,
+    /// The offsets of the messages which have been received, but not yet
+    /// committed.
+    received: BTreeSet<u64>,
+    /// The log on disk.
+    log: Log,
+    /// The capacity of the channel, this many messages can be buffered before
+    /// sending will error.
+    capacity: usize,
+}
+
+/// The inner durable channel implementation.
+///
+/// All methods return immediately (after any file system operations have
+/// completed).
+impl Shared<Buffer> {
+    /// Sends a message across the channel.
+    ///
+    /// The message is appended to the log before being added to the buffer.
+    /// If the message can be sent, the next receiver waker in the queue (if
+    /// any) will be woken as there is now an additional message which can be
+    /// received.
+    ///
+    /// An error will be returned if the channel is full. If the message
+    /// couldn't be written to the log, the inner error is returned instead.
+    fn send(&mut self, value: &str) -> Result<io::Result<()>, ChannelSendError> {
+        let buffer = &mut self.buffer;
+        if buffer.messages.len() < buffer.capacity {
+            let offset = match buffer.log.append(value) {
+                Ok(offset) => offset,
+                Err(err) => return Ok(Err(err)),
+            };
+            buffer.messages.push_back(Message {
+                offset,
+                value: value.to_owned(),
+            });
+            self.wake_next_receiver();
+            Ok(Ok(()))
+        } else {
+            Err(ChannelSendError::Full)
+        }
+    }
+
+    /// Receives a message from the channel.
+    ///
+    /// If a message can be received, then the next sender waker in the queue
+    /// (if any) will be woken as there is now additional free capacity to send
+    /// another message.
+    ///
+    /// An error will be returned if the channel is empty. The error will
+    /// depend on whether the channel is also closed.
+    fn recv(&mut self) -> Result<Message, ChannelRecvError> {
+        match self.buffer.messages.pop_front() {
+            Some(message) => {
+                self.buffer.received.insert(message.offset);
+                self.wake_next_sender();
+                Ok(message)
+            }
+            None => {
+                if !self.is_closed() {
+                    Err(ChannelRecvError::Empty)
+                } else {
+                    Err(ChannelRecvError::Closed)
+                }
+            }
+        }
+    }
+}
+
+/// The name of the file which stores the committed offset.
+const COMMIT_FILE: &str = "commit";
+
+/// The name of the file which is locked while the channel is open.
+const LOCK_FILE: &str = "lock";
+
+/// The size of a record header: the payload length followed by its checksum.
+const HEADER_LEN: usize = 8;
+
+/// A segment file in the log.
+struct Segment {
+    /// The offset of the first message in the segment.
+    base: u64,
+    path: PathBuf,
+}
+
+/// The append-only log which backs a durable channel.
+///
+/// The log is a sequence of segment files named after the offset of their
+/// first message. Each message is stored as a record made up of the length of
+/// the value, a CRC-32 checksum of the value, and then the value itself. The
+/// offset of a message is not stored, it is the segment's base offset plus
+/// the record's position in the segment.
+struct Log {
+    dir: PathBuf,
+    options: Options,
+    /// The lock file, holding the exclusive lock on the directory until the
+    /// log is dropped.
+    _lock: File,
+
+    /// All the segments in the log, ordered by base offset. The last one is
+    /// the active segment which is being appended to.
+    segments: Vec<Segment>,
+    /// The active segment file.
+    active: File,
+    /// The size of the active segment file in bytes.
+    active_len: u64,
+    /// The offset which will be given to the next message appended.
+    next_offset: u64,
+    /// The number of messages appended since the last sync.
+    unsynced: usize,
+
+    /// Every message below this offset has been committed.
+    committed: u64,
+    /// Offsets above `committed` which have been committed out of order.
+    committed_ahead: BTreeSet<u64>,
+}
+
+impl Log {
+    /// Opens the log in `dir`, recovering from any partially written records.
+    ///
+    /// Returns the log and the messages which haven't been committed.
+    fn open(dir: &Path, options: Options) -> io::Result<(Self, VecDeque<Message>)> {
+        fs::create_dir_all(dir)?;
+        let lock = lock_dir(dir)?;
+
+        let committed = match fs::read_to_string(dir.join(COMMIT_FILE)) {
+            // The offset is followed by a newline. Without it, the commit
+            // file was only partially written, so nothing is known to be
+            // committed and every message left in the log is received again.
+            Ok(contents) => match contents.strip_suffix('\n') {
+                Some(offset) => offset.parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "invalid commit file")
+                })?,
+                None => 0,
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let base = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".log"))
+                .and_then(|base| base.parse::<u64>().ok());
+            if let Some(base) = base {
+                segments.push(Segment { base, path });
+            }
+        }
+        segments.sort_by_key(|segment| segment.base);
+
+        // Segments are only deleted once all their messages have been
+        // committed, even if the commit file doesn't say so.
+        let mut committed = segments
+            .first()
+            .map_or(committed, |first| committed.max(first.base));
+
+        let mut uncommitted = VecDeque::new();
+        let mut missing = BTreeSet::new();
+        let mut next_offset = committed;
+        let mut active_len = 0;
+        for (idx, segment) in segments.iter().enumerate() {
+            let values = Self::recover_segment(&segment.path)?;
+            active_len = fs::metadata(&segment.path)?.len();
+            next_offset = segment.base;
+            for value in values {
+                if next_offset >= committed {
+                    uncommitted.push_back(Message {
+                        offset: next_offset,
+                        value,
+                    });
+                }
+                next_offset += 1;
+            }
+            // A segment which was cut short leaves a gap before the next one.
+            // The missing messages can never be received, so they count as
+            // committed, otherwise the committed offset could never move past
+            // them.
+            if let Some(next) = segments.get(idx + 1) {
+                missing.extend(next_offset.max(committed)..next.base);
+            }
+        }
+        while missing.remove(&committed) {
+            committed += 1;
+        }
+        // Messages may have been committed and their segments deleted.
+        let next_offset = next_offset.max(committed);
+
+        let created = segments.is_empty();
+        if created {
+            segments.push(Segment {
+                base: next_offset,
+                path: Self::segment_path(dir, next_offset),
+            });
+        }
+        let active = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&segments[segments.len() - 1].path)?;
+        if created {
+            sync_dir(dir)?;
+        }
+
+        let log = Self {
+            dir: dir.to_owned(),
+            options,
+            _lock: lock,
+
+            segments,
+            active,
+            active_len,
+            next_offset,
+            unsynced: 0,
+
+            committed,
+            committed_ahead: missing,
+        };
+        Ok((log, uncommitted))
+    }
+
+    /// Reads all the complete records in a segment file.
+    ///
+    /// If the file ends with a partially written or corrupted record, the
+    /// file is truncated to remove it.
+    fn recover_segment(path: &Path) -> io::Result<Vec<String>> {
+        let mut contents = Vec::new();
+        File::open(path)?.read_to_end(&mut contents)?;
+
+        let mut values = Vec::new();
+        let mut pos = 0;
+        while let Some(value) = Self::read_record(&contents[pos..]) {
+            pos += HEADER_LEN + value.len();
+            values.push(value);
+        }
+
+        if pos < contents.len() {
+            let file = OpenOptions::new().write(true).open(path)?;
+            file.set_len(pos as u64)?;
+            file.sync_all()?;
+        }
+
+        Ok(values)
+    }
+
+    /// Reads the record at the start of `bytes`.
+    ///
+    /// Returns `None` if the record is incomplete or its checksum doesn't
+    /// match.
+    fn read_record(bytes: &[u8]) -> Option<String> {
+        let header = bytes.get(..HEADER_LEN)?;
+        let len = u32::from_le_bytes(header[..4].try_into().ok()?) as usize;
+        let checksum = u32::from_le_bytes(header[4..].try_into().ok()?);
+
+        let payload = bytes.get(HEADER_LEN..HEADER_LEN + len)?;
+        if crc32(payload) != checksum {
+            return None;
+        }
+
+        String::from_utf8(payload.to_vec()).ok()
+    }
+
+    fn segment_path(dir: &Path, base: u64) -> PathBuf {
+        dir.join(format!("{base:020}.log"))
+    }
+
+    /// Appends a value to the log, returning its offset.
+    ///
+    /// A new segment is started first if the active one has reached the
+    /// segment size. The log is synced according to the [`FsyncPolicy`].
+    fn append(&mut self, value: &str) -> io::Result<u64> {
+        if self.active_len >= self.options.segment_size {
+            self.roll()?;
+        }
+
+        let payload = value.as_bytes();
+        let len = u32::try_from(payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message too large"))?;
+        let mut record = Vec::with_capacity(HEADER_LEN + payload.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&crc32(payload).to_le_bytes());
+        record.extend_from_slice(payload);
+
+        self.active.write_all(&record)?;
+        self.active_len += record.len() as u64;
+        self.unsynced += 1;
+
+        let sync = match self.options.fsync {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::EveryN(n) => self.unsynced >= n,
+            FsyncPolicy::Never => false,
+        };
+        if sync {
+            self.active.sync_data()?;
+            self.unsynced = 0;
+        }
+
+        let offset = self.next_offset;
+        self.next_offset += 1;
+        Ok(offset)
+    }
+
+    /// Starts a new active segment.
+    fn roll(&mut self) -> io::Result<()> {
+        self.active.sync_data()?;
+        self.unsynced = 0;
+
+        let base = self.next_offset;
+        let path = Self::segment_path(&self.dir, base);
+        self.active = OpenOptions::new().create(true).append(true).open(&path)?;
+        sync_dir(&self.dir)?;
+        self.active_len = 0;
+        self.segments.push(Segment { base, path });
+
+        Ok(())
+    }
+
+    /// Commits the message at `offset`, which must be in the log and not
+    /// already committed.
+    ///
+    /// If this moves the committed offset forward, the new value is written
+    /// to the commit file and fully committed segments are deleted.
+    fn commit(&mut self, offset: u64) -> io::Result<()> {
+        debug_assert!((self.committed..self.next_offset).contains(&offset));
+        self.committed_ahead.insert(offset);
+        let before = self.committed;
+        while self.committed_ahead.remove(&self.committed) {
+            self.committed += 1;
+        }
+        if self.committed == before {
+            return Ok(());
+        }
+
+        // Write the new offset to a temporary file and rename it, so that the
+        // commit file is never partially written. The rename itself is only
+        // durable once the directory has been synced.
+        let tmp = self.dir.join(format!("{COMMIT_FILE}.tmp"));
+        let mut file = File::create(&tmp)?;
+        file.write_all(format!("{}\n", self.committed).as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join(COMMIT_FILE))?;
+        sync_dir(&self.dir)?;
+
+        self.compact()
+    }
+
+    /// Deletes segments which only contain committed messages.
+    ///
+    /// The active segment is never deleted.
+    fn compact(&mut self) -> io::Result<()> {
+        while self.segments.len() > 1 && self.segments[1].base <= self.committed {
+            let segment = self.segments.remove(0);
+            fs::remove_file(&segment.path)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Log {
+    fn drop(&mut self) {
+        if self.unsynced > 0 {
+            _ = self.active.sync_data();
+        }
+    }
+}
+
+/// Takes an exclusive lock on the lock file in `dir`, without waiting.
+///
+/// The lock is held until the returned file is closed. It is released by the
+/// operating system if the process exits, so a crash never leaves the
+/// directory locked.
+fn lock_dir(dir: &Path) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(LOCK_FILE))?;
+    // SAFETY: `file` is an open file descriptor for the duration of the call.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::WouldBlock {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "durable channel directory is already open",
+            ));
+        }
+        return Err(err);
+    }
+    Ok(file)
+}
+
+/// Syncs the directory `dir`, so that files which have been created, renamed
+/// or deleted in it survive a crash.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// Calculates the CRC-32 (IEEE) checksum of `bytes`.
+///
+/// This is the simple bit-by-bit version of the algorithm. It's slower than
+/// the table driven version, but it's short.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::path::{Path, PathBuf};
+    use std::process;
+
+    use super::{
+        open, open_with_options, DurableError, FsyncPolicy, Message, Options, COMMIT_FILE,
+        HEADER_LEN,
+    };
+    use crate::runtime;
+
+    /// The number of messages sent before each simulated crash.
+    const MESSAGES: u64 = 24;
+
+    /// Small segments, so that the log is spread over several of them.
+    fn options() -> Options {
+        Options {
+            fsync: FsyncPolicy::Always,
+            segment_size: 64,
+        }
+    }
+
+    /// A directory which is removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str, seed: u64) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "understanding-async-await-{name}-{}-{seed}",
+                process::id()
+            ));
+            _ = fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A xorshift generator, good enough to pick truncation offsets.
+    struct Rng(u64);
+
+    impl Rng {
+        fn new(seed: u64) -> Self {
+            Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+        }
+
+        /// Returns a number in `0..bound`.
+        fn below(&mut self, bound: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % bound
+        }
+    }
+
+    fn value(offset: u64, rng: &mut Rng) -> String {
+        format!("message-{offset}-{}", "x".repeat(rng.below(12) as usize))
+    }
+
+    /// Sends `MESSAGES` values and commits a random number of them, in
+    /// order. Returns the values and the number committed.
+    fn fill(dir: &Path, rng: &mut Rng) -> (Vec<String>, u64) {
+        let values: Vec<String> = (0..MESSAGES).map(|offset| value(offset, rng)).collect();
+        let committed = rng.below(MESSAGES + 1);
+
+        let (tx, rx) = open_with_options(dir, MESSAGES as usize, options()).unwrap();
+        runtime::block_on(async {
+            for value in &values {
+                tx.send(value.clone()).await.unwrap();
+            }
+            for _ in 0..committed {
+                let message = rx.recv().await.unwrap();
+                rx.commit(message.offset).unwrap();
+            }
+        });
+
+        (values, committed)
+    }
+
+    /// Opens the channel and receives everything in it, committing each
+    /// message.
+    fn drain(dir: &Path) -> Vec<Message> {
+        let (tx, rx) = open_with_options(dir, MESSAGES as usize, options()).unwrap();
+        drop(tx);
+        runtime::block_on(async {
+            let mut received = Vec::new();
+            while let Ok(message) = rx.recv().await {
+                rx.commit(message.offset).unwrap();
+                received.push(message);
+            }
+            received
+        })
+    }
+
+    /// Returns the segment files in `dir` with their base offsets, in order.
+    fn segments(dir: &Path) -> Vec<(u64, PathBuf)> {
+        let mut segments: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter_map(|path| {
+                let base = path.file_stem()?.to_str()?.parse().ok()?;
+                (path.extension()? == "log").then_some((base, path))
+            })
+            .collect();
+        segments.sort();
+        segments
+    }
+
+    fn truncate(path: &Path, len: u64) {
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+        file.set_len(len).unwrap();
+    }
+
+    #[test]
+    fn segment_truncated_at_random_offset() {
+        for seed in 0..64 {
+            let dir = TempDir::new("durable-segment", seed);
+            let mut rng = Rng::new(seed);
+            let (values, committed) = fill(&dir.0, &mut rng);
+
+            let segments = segments(&dir.0);
+            let (base, path) = &segments[rng.below(segments.len() as u64) as usize];
+            let len = fs::metadata(path).unwrap().len();
+            let cut = rng.below(len + 1);
+            truncate(path, cut);
+
+            // Only the records which were cut off are lost, everything else
+            // which wasn't committed is received exactly once.
+            let end = segments
+                .iter()
+                .find(|(next, _)| next > base)
+                .map_or(MESSAGES, |(next, _)| *next);
+            let mut expected = Vec::new();
+            for offset in committed..MESSAGES {
+                let value = &values[offset as usize];
+                if (*base..end).contains(&offset) {
+                    let pos = (*base..offset)
+                        .map(|offset| (HEADER_LEN + values[offset as usize].len()) as u64)
+                        .sum::<u64>()
+                        + (HEADER_LEN + value.len()) as u64;
+                    if pos > cut {
+                        continue;
+                    }
+                }
+                expected.push(Message {
+                    offset,
+                    value: value.clone(),
+                });
+            }
+
+            assert_eq!(drain(&dir.0), expected, "seed {seed}, cut {cut} of {len}");
+            assert_eq!(drain(&dir.0), Vec::new(), "seed {seed}, received again");
+        }
+    }
+
+    #[test]
+    fn commit_file_truncated_at_random_offset() {
+        for seed in 0..64 {
+            let dir = TempDir::new("durable-commit", seed);
+            let mut rng = Rng::new(seed);
+            let (values, committed) = fill(&dir.0, &mut rng);
+
+            let path = dir.0.join(COMMIT_FILE);
+            let len = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+            let cut = rng.below(len + 1);
+            if len > 0 {
+                truncate(&path, cut);
+            }
+
+            // A torn commit file means that nothing is known to be committed,
+            // every message which is still in the log is received again, but
+            // only once, and none are lost.
+            let first = if cut == len {
+                committed
+            } else {
+                segments(&dir.0)[0].0
+            };
+            assert!(first <= committed);
+            let expected: Vec<Message> = (first..MESSAGES)
+                .map(|offset| Message {
+                    offset,
+                    value: values[offset as usize].clone(),
+                })
+                .collect();
+
+            assert_eq!(drain(&dir.0), expected, "seed {seed}, cut {cut} of {len}");
+            assert_eq!(drain(&dir.0), Vec::new(), "seed {seed}, received again");
+        }
+    }
+
+    #[test]
+    fn only_received_offsets_can_be_committed() {
+        let dir = TempDir::new("durable-invalid-commit", 0);
+        let (tx, rx) = open(&dir.0, 4).unwrap();
+        let message = runtime::block_on(async {
+            for value in ["a", "b", "c"] {
+                tx.send(value.into()).await.unwrap();
+            }
+            rx.recv().await.unwrap()
+        });
+        assert_eq!(message.offset, 0);
+
+        // Written, but not received yet.
+        assert!(matches!(rx.commit(1), Err(DurableError::InvalidOffset(1))));
+        // Never written.
+        assert!(matches!(rx.commit(9), Err(DurableError::InvalidOffset(9))));
+        rx.commit(0).unwrap();
+        // Already committed.
+        assert!(matches!(rx.commit(0), Err(DurableError::InvalidOffset(0))));
+        drop((tx, rx));
+
+        let offsets: Vec<u64> = drain(&dir.0).iter().map(|message| message.offset).collect();
+        assert_eq!(offsets, [1, 2]);
+    }
+
+    #[test]
+    fn directory_can_only_be_opened_once() {
+        let dir = TempDir::new("durable-lock", 0);
+        let channel = open(&dir.0, 4).unwrap();
+        let err = open(&dir.0, 4).err().expect("second open should fail");
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+
+        drop(channel);
+        open(&dir.0, 4).unwrap();
+    }
+}
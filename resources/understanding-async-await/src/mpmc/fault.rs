@@ -0,0 +1,822 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+
+use mpmc_core::Lock;
+
+use super::{ChannelClosedError, DefaultLock};
+
+/// Which faults to inject and how often.
+///
+/// Each probability is a number between 0.0 and 1.0 which is checked
+/// independently every time a wrapped send or receive future is polled (or,
+/// for `delayed_wake`, every time the channel wakes a wrapped future). A
+/// probability of 0.0 disables that fault, which is the default for all of
+/// them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Faults {
+    /// Wake the task straight away when a poll returns `Poll::Pending`, even
+    /// though nothing has changed. The task will be polled again and must
+    /// cope with finding the channel in the same state.
+    pub spurious_wakeup: f64,
+    /// Hold on to a wake up from the channel instead of passing it on to the
+    /// task. See [`max_wake_delay`] for when it is delivered.
+    ///
+    /// [`max_wake_delay`]: Self::max_wake_delay
+    pub delayed_wake: f64,
+    /// The maximum number of polls of wrapped futures that a delayed wake, or
+    /// the wake for an injected `full` or `empty`, is held for. The exact
+    /// number is chosen at random between 1 and this value.
+    ///
+    /// Held wakes are only counted down when a wrapped future is polled. Once
+    /// every wrapped future is waiting, nothing would poll them, so all the
+    /// held wakes are delivered straight away.
+    pub max_wake_delay: u32,
+    /// Return `Poll::Pending` from a send or a receive without touching the
+    /// channel.
+    pub pending: f64,
+    /// Make a send behave as though the channel were full.
+    ///
+    /// The sender waits in the channel's queue like a real waiting sender, so
+    /// it is woken by the next receive. If that doesn't happen first, it is
+    /// woken after a delay, see [`max_wake_delay`].
+    ///
+    /// [`max_wake_delay`]: Self::max_wake_delay
+    pub full: f64,
+    /// Make a receive behave as though the channel were empty.
+    ///
+    /// The receiver waits in the same way as for `full`, and is woken by the
+    /// next send or after a delay.
+    pub empty: f64,
+    /// Close the channel, as if [`Receiver::close`] had been called.
+    ///
+    /// [`Receiver::close`]: fn@super::Receiver::close
+    pub close: f64,
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Self {
+            spurious_wakeup: 0.0,
+            delayed_wake: 0.0,
+            max_wake_delay: 4,
+            pending: 0.0,
+            full: 0.0,
+            empty: 0.0,
+            close: 0.0,
+        }
+    }
+}
+
+/// The number of faults of each kind which have been injected so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    /// Polls which returned `Poll::Pending` and woke the task anyway.
+    pub spurious_wakeups: u64,
+    /// Wakes from the channel which were held back.
+    pub delayed_wakes: u64,
+    /// Polls which returned `Poll::Pending` without polling the channel.
+    pub pendings: u64,
+    /// Sends which were told the channel was full.
+    pub fulls: u64,
+    /// Receives which were told the channel was empty.
+    pub empties: u64,
+    /// Times the channel was closed, including by [`Injector::close`].
+    ///
+    /// [`Injector::close`]: fn@Injector::close
+    pub closes: u64,
+}
+
+/// Creates a new [`mpmc::channel`] with fault injection.
+///
+/// The same `seed` and [`Faults`] will inject the same faults, so long as the
+/// wrapped futures are polled in the same order. With a single-threaded
+/// runtime this makes a failure found with fault injection reproducible.
+///
+/// The returned [`Injector`] can be used to change the faults, inspect what
+/// has been injected, and release delayed wakes.
+///
+/// [`mpmc::channel`]: fn@super::channel
+pub fn channel(capacity: usize, seed: u64, faults: Faults) -> (Sender, Receiver, Injector) {
+    let (tx, rx) = super::channel(capacity);
+    wrap(tx, rx, seed, faults)
+}
+
+/// Wraps both halves of an existing mpmc channel with fault injection.
+///
+/// This can be used to inject faults into a [`fair_channel`], or into a
+/// channel whose handles have had their budget changed. See [`channel`] for
+/// details.
+///
+/// Other handles to the same channel which aren't wrapped are not affected,
+/// except that an injected close will close the channel for them too.
+///
+/// [`fair_channel`]: fn@super::fair_channel
+/// [`channel`]: fn@channel
+pub fn wrap(
+    tx: super::Sender,
+    rx: super::Receiver,
+    seed: u64,
+    faults: Faults,
+) -> (Sender, Receiver, Injector) {
+    let state = Arc::new(Mutex::new(State {
+        faults,
+        rng: Rng::new(seed),
+        held: Vec::new(),
+        live: 0,
+        parked: 0,
+        stats: FaultStats::default(),
+        channel: Arc::clone(&tx.inner),
+    }));
+
+    (
+        Sender {
+            inner: Some(tx),
+            state: Arc::clone(&state),
+        },
+        Receiver {
+            inner: Some(rx),
+            state: Arc::clone(&state),
+        },
+        Injector { state },
+    )
+}
+
+/// A handle to control the faults injected into a channel.
+///
+/// Returned by [`fault::channel`] and [`fault::wrap`].
+///
+/// [`fault::channel`]: fn@channel
+/// [`fault::wrap`]: fn@wrap
+#[derive(Clone)]
+pub struct Injector {
+    state: Arc<Mutex<State>>,
+}
+
+impl Injector {
+    /// Replaces the faults to inject from now on.
+    ///
+    /// This doesn't reseed the random number generator, so a test which
+    /// changes the faults at the same points will still be reproducible.
+    pub fn set_faults(&self, faults: Faults) {
+        lock(&self.state).faults = faults;
+    }
+
+    /// Returns the number of faults injected so far.
+    pub fn stats(&self) -> FaultStats {
+        lock(&self.state).stats.clone()
+    }
+
+    /// Delivers all the wakes which are currently being held.
+    pub fn flush(&self) {
+        let held = lock(&self.state).take_held();
+        wake_all(held);
+    }
+
+    /// Closes the channel now, rather than waiting for the close fault.
+    pub fn close(&self) {
+        let channel = {
+            let mut state = lock(&self.state);
+            state.stats.closes += 1;
+            Arc::clone(&state.channel)
+        };
+        close_channel(&channel);
+        self.flush();
+    }
+}
+
+/// The sending-half of a channel with fault injection.
+///
+/// Works just like [`mpmc::Sender`], see [`fault::channel`] for the faults
+/// which can be injected.
+///
+/// [`mpmc::Sender`]: struct@super::Sender
+/// [`fault::channel`]: fn@channel
+pub struct Sender {
+    /// Always `Some` until the sender is dropped.
+    inner: Option<super::Sender>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Sender {
+    /// Sends a value, waiting until there is capacity.
+    ///
+    /// See [`mpmc::Sender::send`].
+    ///
+    /// [`mpmc::Sender::send`]: fn@super::Sender::send
+    pub async fn send(&self, value: String) -> Result<(), ChannelClosedError> {
+        let Some(inner) = &self.inner else {
+            unreachable!("sender is only taken on drop");
+        };
+        Faulty::new(inner.send(value), &self.state, Op::Send).await
+    }
+}
+
+impl Clone for Sender {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        // Dropping the last sender closes the channel, which wakes any waiting
+        // receivers. Those wakes may be held, so release them afterwards.
+        drop(self.inner.take());
+        let held = lock(&self.state).take_held();
+        wake_all(held);
+    }
+}
+
+/// The receiving-half of a channel with fault injection.
+///
+/// Works just like [`mpmc::Receiver`], see [`fault::channel`] for the faults
+/// which can be injected.
+///
+/// [`mpmc::Receiver`]: struct@super::Receiver
+/// [`fault::channel`]: fn@channel
+pub struct Receiver {
+    /// Always `Some` until the receiver is dropped.
+    inner: Option<super::Receiver>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Receiver {
+    /// Receives a value, waiting until one is available.
+    ///
+    /// See [`mpmc::Receiver::recv`].
+    ///
+    /// [`mpmc::Receiver::recv`]: fn@super::Receiver::recv
+    pub async fn recv(&self) -> Result<String, ChannelClosedError> {
+        let Some(inner) = &self.inner else {
+            unreachable!("receiver is only taken on drop");
+        };
+        Faulty::new(inner.recv(), &self.state, Op::Recv).await
+    }
+
+    /// Closes the channel without dropping the receiver.
+    ///
+    /// See [`mpmc::Receiver::close`].
+    ///
+    /// [`mpmc::Receiver::close`]: fn@super::Receiver::close
+    pub fn close(&self) {
+        if let Some(inner) = &self.inner {
+            inner.close();
+        }
+        let held = lock(&self.state).take_held();
+        wake_all(held);
+    }
+}
+
+impl Clone for Receiver {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        drop(self.inner.take());
+        let held = lock(&self.state).take_held();
+        wake_all(held);
+    }
+}
+
+/// The shared fault injection state for a channel.
+struct State {
+    faults: Faults,
+    rng: Rng,
+    /// Delayed wakes, together with the number of polls left until they are
+    /// delivered.
+    held: Vec<(u32, Waker)>,
+    /// The number of wrapped futures which exist.
+    live: usize,
+    /// The number of wrapped futures which have returned `Poll::Pending` and
+    /// haven't been polled since.
+    parked: usize,
+    stats: FaultStats,
+    /// Used to inject a close.
+    channel: Arc<DefaultLock>,
+}
+
+impl State {
+    /// Counts down the held wakes by one poll, returning those which are due.
+    fn tick(&mut self) -> Vec<Waker> {
+        let mut due = Vec::new();
+        self.held.retain_mut(|(remaining, waker)| {
+            *remaining -= 1;
+            if *remaining == 0 {
+                due.push(waker.clone());
+                false
+            } else {
+                true
+            }
+        });
+
+        due
+    }
+
+    fn take_held(&mut self) -> Vec<Waker> {
+        self.held.drain(..).map(|(_, waker)| waker).collect()
+    }
+
+    /// Decides which fault, if any, to inject into this poll.
+    fn choose(&mut self, op: Op) -> Option<Fault> {
+        if self.rng.chance(self.faults.close) {
+            self.stats.closes += 1;
+            return Some(Fault::Close);
+        }
+
+        match op {
+            Op::Send if self.rng.chance(self.faults.full) => {
+                self.stats.fulls += 1;
+                return Some(Fault::Unavailable);
+            }
+            Op::Recv if self.rng.chance(self.faults.empty) => {
+                self.stats.empties += 1;
+                return Some(Fault::Unavailable);
+            }
+            _ => {}
+        }
+
+        if self.rng.chance(self.faults.pending) {
+            self.stats.pendings += 1;
+            return Some(Fault::Pending);
+        }
+
+        None
+    }
+
+    /// Holds `waker` for a random number of polls.
+    fn hold(&mut self, waker: Waker) {
+        let max = self.faults.max_wake_delay.max(1);
+        let delay = 1 + self.rng.below(max);
+        self.held.push((delay, waker));
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Send,
+    Recv,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Fault {
+    /// Return `Poll::Pending` without polling the channel.
+    Pending,
+    /// Wait as though the channel were full, for a send, or empty, for a
+    /// receive.
+    Unavailable,
+    /// Close the channel before polling it.
+    Close,
+}
+
+/// A send or receive future with fault injection.
+struct Faulty<'a, F> {
+    /// `Some` until the future completes or is dropped.
+    future: Option<Pin<Box<F>>>,
+    state: &'a Arc<Mutex<State>>,
+    op: Op,
+    /// The waiter id registered with the channel by an injected `full` or
+    /// `empty`.
+    waiter_id: Option<u64>,
+    /// Whether this future is counted in `State::parked`.
+    parked: bool,
+}
+
+impl<'a, F> Faulty<'a, F> {
+    fn new(future: F, state: &'a Arc<Mutex<State>>, op: Op) -> Self {
+        lock(state).live += 1;
+        Self {
+            future: Some(Box::pin(future)),
+            state,
+            op,
+            waiter_id: None,
+            parked: false,
+        }
+    }
+
+    /// Wraps the task's waker, so that wakes from the channel can be delayed.
+    fn fault_waker(&self, cx: &Context<'_>) -> Waker {
+        Waker::from(Arc::new(FaultWaker {
+            state: Arc::downgrade(self.state),
+            waker: cx.waker().clone(),
+        }))
+    }
+
+    /// Waits as though the channel were full or empty.
+    ///
+    /// The task joins the channel's queue of waiting senders or receivers, so
+    /// the next real send or receive wakes it. Its waker is also held, so
+    /// that it is woken after a delay if nothing happens on the channel.
+    fn wait_unavailable(&mut self, cx: &Context<'_>) {
+        let waker = self.fault_waker(cx);
+        let channel = Arc::clone(&lock(self.state).channel);
+        let id = {
+            let mut channel = Lock::lock(&*channel);
+            match self.op {
+                Op::Send => channel.register_sender_waker(None, waker),
+                Op::Recv => channel.register_receiver_waker(None, waker),
+            }
+        };
+        self.waiter_id = Some(id);
+        lock(self.state).hold(cx.waker().clone());
+    }
+
+    /// Removes the waiter registered by `wait_unavailable`, if any.
+    ///
+    /// If `pass_on` is set and the waiter has already been woken, the wake is
+    /// passed on to the next waiter, as it won't be used.
+    fn leave_queue(&mut self, pass_on: bool) {
+        let Some(id) = self.waiter_id.take() else {
+            return;
+        };
+        let channel = Arc::clone(&lock(self.state).channel);
+        let mut channel = Lock::lock(&*channel);
+        match (self.op, pass_on) {
+            (Op::Send, true) => channel.deregister_sender_waker(id),
+            (Op::Send, false) => _ = channel.remove_sender_waker(id),
+            (Op::Recv, true) => channel.deregister_receiver_waker(id),
+            (Op::Recv, false) => _ = channel.remove_receiver_waker(id),
+        }
+    }
+
+    /// Counts this future as waiting and returns `Poll::Pending`.
+    ///
+    /// If every wrapped future is now waiting, no more polls will count down
+    /// the held wakes, so they are all delivered.
+    fn park<T>(&mut self) -> Poll<T> {
+        let held = {
+            let mut state = lock(self.state);
+            if !self.parked {
+                self.parked = true;
+                state.parked += 1;
+            }
+            if state.parked == state.live {
+                state.take_held()
+            } else {
+                Vec::new()
+            }
+        };
+        wake_all(held);
+        Poll::Pending
+    }
+
+    fn unpark(&mut self, state: &mut State) {
+        if self.parked {
+            self.parked = false;
+            state.parked -= 1;
+        }
+    }
+}
+
+impl<F> Future for Faulty<'_, F>
+where
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        // The channel is polled below, which registers the task again if it
+        // still has to wait.
+        this.leave_queue(false);
+        // The channel is never locked while the state is. It's the other way
+        // round: the channel wakes our `FaultWaker`s while it holds its own
+        // lock, and they lock the state.
+        let (due, fault) = {
+            let mut state = lock(this.state);
+            this.unpark(&mut state);
+            let due = state.tick();
+            let fault = state.choose(this.op);
+            (due, fault)
+        };
+        wake_all(due);
+
+        match fault {
+            Some(Fault::Pending) => {
+                // The channel hasn't been polled, so nothing else will wake us.
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Some(Fault::Unavailable) => {
+                this.wait_unavailable(cx);
+                return this.park();
+            }
+            Some(Fault::Close) => {
+                let channel = Arc::clone(&lock(this.state).channel);
+                close_channel(&channel);
+                // Every waiting task has just been woken, don't leave any of
+                // them waiting on a held wake.
+                let held = lock(this.state).take_held();
+                wake_all(held);
+            }
+            None => {}
+        }
+
+        let waker = this.fault_waker(cx);
+        let Some(future) = &mut this.future else {
+            panic!("`Faulty` polled after completion");
+        };
+        let poll = future.as_mut().poll(&mut Context::from_waker(&waker));
+        if poll.is_ready() {
+            this.future = None;
+            return poll;
+        }
+
+        let spurious = {
+            let mut state = lock(this.state);
+            let probability = state.faults.spurious_wakeup;
+            let spurious = state.rng.chance(probability);
+            if spurious {
+                state.stats.spurious_wakeups += 1;
+            }
+            spurious
+        };
+        if spurious {
+            cx.waker().wake_by_ref();
+        }
+        this.park()
+    }
+}
+
+impl<F> Drop for Faulty<'_, F> {
+    fn drop(&mut self) {
+        // A cancelled future passes on any wake it was given, which may then
+        // be held.
+        let cancelled = self.future.take().is_some();
+        self.leave_queue(true);
+        let held = {
+            let mut state = lock(self.state);
+            self.unpark(&mut state);
+            state.live -= 1;
+            // The futures which are left may all be waiting.
+            if cancelled || state.parked == state.live {
+                state.take_held()
+            } else {
+                Vec::new()
+            }
+        };
+        wake_all(held);
+    }
+}
+
+/// Wraps the waker given to the channel, so that wakes can be delayed.
+struct FaultWaker {
+    state: Weak<Mutex<State>>,
+    waker: Waker,
+}
+
+impl Wake for FaultWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if let Some(state) = self.state.upgrade() {
+            let mut state = lock(&state);
+            let probability = state.faults.delayed_wake;
+            if state.rng.chance(probability) {
+                state.stats.delayed_wakes += 1;
+                let max = state.faults.max_wake_delay.max(1);
+                let delay = 1 + state.rng.below(max);
+                state.held.push((delay, self.waker.clone()));
+                return;
+            }
+        }
+
+        self.waker.wake_by_ref();
+    }
+}
+
+/// A small, seedable pseudo-random number generator (SplitMix64).
+///
+/// We only need reproducible sequences, not good randomness, so there's no
+/// point pulling in a dependency for this.
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns `true` with the given probability.
+    ///
+    /// A probability of 0.0 or less doesn't use up a random number, so
+    /// disabled faults don't change the sequence for the others.
+    fn chance(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        // The top 53 bits give a uniformly distributed f64 in [0, 1).
+        let sample = (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64;
+        sample < probability
+    }
+
+    /// Returns a number in `0..bound`.
+    fn below(&mut self, bound: u32) -> u32 {
+        (self.next_u64() % u64::from(bound)) as u32
+    }
+}
+
+fn lock(state: &Mutex<State>) -> std::sync::MutexGuard<'_, State> {
+    match state.lock() {
+        Ok(guard) => guard,
+        Err(_) => panic!("Fault injection state has become corrupted."),
+    }
+}
+
+fn close_channel(channel: &DefaultLock) {
+    Lock::lock(channel).close();
+}
+
+fn wake_all(wakers: Vec<Waker>) {
+    for waker in wakers {
+        waker.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use super::{channel, FaultStats, Faults};
+    use crate::runtime::{self, Runtime};
+    use crate::testing::{poll_once, MockWaker};
+
+    const MESSAGES: usize = 200;
+
+    #[derive(Debug, PartialEq)]
+    struct Outcome {
+        sent: usize,
+        /// The messages received by each consumer, in order.
+        received: Vec<Vec<String>>,
+        stats: FaultStats,
+    }
+
+    fn faults() -> Faults {
+        Faults {
+            spurious_wakeup: 0.2,
+            delayed_wake: 0.3,
+            max_wake_delay: 8,
+            pending: 0.1,
+            full: 0.1,
+            empty: 0.1,
+            close: 0.002,
+        }
+    }
+
+    /// Sends messages from two producers to two consumers on a current-thread
+    /// runtime, which polls the tasks in the same order each time.
+    fn run(seed: u64, faults: Faults) -> Outcome {
+        Runtime::current_thread().block_on(async move {
+            let (tx, rx, injector) = channel(4, seed, faults);
+
+            let producers: Vec<_> = (0..2)
+                .map(|id| {
+                    let tx = tx.clone();
+                    runtime::spawn(async move {
+                        let mut sent = 0;
+                        for val in 0..MESSAGES / 2 {
+                            if tx.send(format!("{id}-{val}")).await.is_err() {
+                                break;
+                            }
+                            sent += 1;
+                        }
+                        sent
+                    })
+                })
+                .collect();
+            drop(tx);
+
+            let consumers: Vec<_> = (0..2)
+                .map(|_| {
+                    let rx = rx.clone();
+                    runtime::spawn(async move {
+                        let mut received = Vec::new();
+                        while let Ok(value) = rx.recv().await {
+                            received.push(value);
+                        }
+                        received
+                    })
+                })
+                .collect();
+            drop(rx);
+
+            let mut sent = 0;
+            for producer in producers {
+                sent += producer.await.unwrap();
+            }
+            let mut received = Vec::new();
+            for consumer in consumers {
+                received.push(consumer.await.unwrap());
+            }
+
+            Outcome {
+                sent,
+                received,
+                stats: injector.stats(),
+            }
+        })
+    }
+
+    #[test]
+    fn every_sent_message_is_received() {
+        for seed in 0..8 {
+            let outcome = run(seed, faults());
+            let received: usize = outcome.received.iter().map(Vec::len).sum();
+            assert_eq!(outcome.sent, received, "seed {seed}: {outcome:?}");
+        }
+    }
+
+    #[test]
+    fn same_seed_replays_same_faults() {
+        let first = run(3, faults());
+        assert_ne!(first.stats, FaultStats::default());
+        assert_eq!(first, run(3, faults()));
+    }
+
+    #[test]
+    fn different_seeds_inject_different_faults() {
+        assert_ne!(run(3, faults()).stats, run(4, faults()).stats);
+    }
+
+    /// Faults which make every receive find the channel empty, and every send
+    /// return `Poll::Pending` without being parked, so that it can count down
+    /// held wakes.
+    fn empty_and_pending(max_wake_delay: u32) -> Faults {
+        Faults {
+            max_wake_delay,
+            pending: 1.0,
+            empty: 1.0,
+            ..Faults::default()
+        }
+    }
+
+    #[test]
+    fn empty_fault_waits_for_the_next_send() {
+        let (tx, rx, injector) = channel(4, 0, empty_and_pending(100));
+        let (send_waker, recv_waker) = (MockWaker::new(), MockWaker::new());
+        let mut send = pin!(tx.send("a".to_owned()));
+        // Keeps a future which isn't waiting once the first send completes.
+        let mut other_send = pin!(tx.send("b".to_owned()));
+        let mut recv = pin!(rx.recv());
+
+        poll_once(send.as_mut(), &send_waker).assert_woke_itself();
+        poll_once(other_send.as_mut(), &send_waker).assert_woke_itself();
+        poll_once(recv.as_mut(), &recv_waker).assert_registered_waker();
+        assert_eq!(recv_waker.counts().total_wakes(), 0);
+
+        injector.set_faults(Faults::default());
+        poll_once(send.as_mut(), &send_waker)
+            .assert_ready()
+            .unwrap();
+        assert_eq!(recv_waker.counts().total_wakes(), 1);
+        let value = poll_once(recv.as_mut(), &recv_waker).assert_ready();
+        assert_eq!(value.unwrap(), "a");
+    }
+
+    #[test]
+    fn empty_fault_is_woken_after_the_delay() {
+        let (tx, rx, _injector) = channel(4, 0, empty_and_pending(1));
+        let (send_waker, recv_waker) = (MockWaker::new(), MockWaker::new());
+        let mut send = pin!(tx.send("a".to_owned()));
+        let mut recv = pin!(rx.recv());
+
+        poll_once(send.as_mut(), &send_waker).assert_woke_itself();
+        poll_once(recv.as_mut(), &recv_waker).assert_registered_waker();
+        assert_eq!(recv_waker.counts().total_wakes(), 0);
+
+        // Still a pending fault, but the poll counts down the held wake.
+        poll_once(send.as_mut(), &send_waker).assert_woke_itself();
+        assert_eq!(recv_waker.counts().total_wakes(), 1);
+    }
+
+    #[test]
+    fn held_wakes_are_delivered_once_every_future_is_waiting() {
+        let (_tx, rx, _injector) = channel(4, 0, empty_and_pending(100));
+        let waker = MockWaker::new();
+        let mut recv = pin!(rx.recv());
+
+        // Nothing else would ever count down the held wake.
+        poll_once(recv.as_mut(), &waker).assert_woke_itself();
+    }
+}
@@ -0,0 +1,797 @@
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::{error::Error, fmt};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::oneshot;
+
+use super::{DefaultLock, DEFAULT_BUDGET};
+
+/// Keeps the listener accepting connections.
+///
+/// Every local handle and every served connection holds a clone. Once the
+/// last one is dropped, the listener is told to shut down straight away.
+type ListenerGuard = Arc<oneshot::Sender<()>>;
+
+/// Binds a new mpmc channel to the Unix domain socket at `path`, returning
+/// the local sender/receiver halves.
+///
+/// The channel itself lives in this process and behaves like
+/// [`mpmc::channel`]. Other processes on the same host can [`connect_sender`]
+/// or [`connect_receiver`] to the socket to send to or receive from the
+/// channel. Values are serialized as JSON with serde, so `T` must be
+/// serializable.
+///
+/// Each connected remote sender or receiver counts towards the number of
+/// connected senders or receivers of the channel. When a remote process
+/// disconnects (for example because it dropped its last handle or exited),
+/// its sender or receiver is dropped. If it was the last one, the channel
+/// closes, exactly as when the last local sender or receiver is dropped. Keep
+/// the local halves until the remote processes have connected, otherwise the
+/// channel may close before they get the chance.
+///
+/// When the channel closes because every receiver has been dropped, remote
+/// senders are told straight away, so their next send fails without waiting
+/// for credit.
+///
+/// Remote senders are subject to the same capacity as local senders. Each
+/// connection is given one credit, which allows it to send a single value.
+/// The credit is returned once that value has been added to the channel
+/// buffer, which will wait until the channel has capacity. This is the same
+/// as a local sender waiting in [`Sender::send`].
+///
+/// The socket is accepting connections until every handle to the channel,
+/// local or remote, has been dropped. The listener then shuts down and the
+/// socket file is removed.
+///
+/// This function must be called from within a tokio runtime.
+///
+/// [`mpmc::channel`]: fn@super::channel
+pub fn bind<T>(path: impl AsRef<Path>, capacity: usize) -> io::Result<(Sender<T>, Receiver<T>)>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let path = path.as_ref().to_owned();
+    let listener = UnixListener::bind(&path)?;
+    let (tx, rx) = super::channel(capacity);
+    let (guard, shutdown) = oneshot::channel();
+    let guard = Arc::new(guard);
+
+    tokio::spawn(listen(
+        listener,
+        path,
+        Arc::downgrade(&tx.inner),
+        Arc::downgrade(&guard),
+        shutdown,
+    ));
+
+    Ok((
+        Sender {
+            inner: SenderInner::Local(tx, Arc::clone(&guard)),
+            _marker: PhantomData,
+        },
+        Receiver {
+            inner: ReceiverInner::Local(rx, guard),
+            _marker: PhantomData,
+        },
+    ))
+}
+
+/// Connects to a channel bound to the Unix domain socket at `path` as a
+/// sender.
+///
+/// Clones of the returned sender share a single connection, which is closed
+/// when the last clone is dropped.
+pub async fn connect_sender<T>(path: impl AsRef<Path>) -> io::Result<Sender<T>>
+where
+    T: Serialize,
+{
+    let connection = Connection::connect(path.as_ref(), Role::Sender).await?;
+
+    Ok(Sender {
+        inner: SenderInner::Remote(Arc::new(tokio::sync::Mutex::new(connection))),
+        _marker: PhantomData,
+    })
+}
+
+/// Connects to a channel bound to the Unix domain socket at `path` as a
+/// receiver.
+///
+/// Clones of the returned receiver share a single connection, which is
+/// closed when the last clone is dropped.
+pub async fn connect_receiver<T>(path: impl AsRef<Path>) -> io::Result<Receiver<T>>
+where
+    T: DeserializeOwned,
+{
+    let connection = Connection::connect(path.as_ref(), Role::Receiver).await?;
+
+    Ok(Receiver {
+        inner: ReceiverInner::Remote(Arc::new(tokio::sync::Mutex::new(connection))),
+        _marker: PhantomData,
+    })
+}
+
+/// Error returned from an IPC channel.
+#[derive(Debug)]
+pub enum IpcError {
+    /// The channel is closed.
+    Closed,
+    /// The connection to the channel failed.
+    Io(io::Error),
+    /// A value couldn't be serialized or deserialized.
+    Codec(serde_json::Error),
+}
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Closed => write!(f, "channel closed"),
+            Self::Io(err) => write!(f, "ipc channel connection error: {err}"),
+            Self::Codec(err) => write!(f, "ipc channel codec error: {err}"),
+        }
+    }
+}
+impl Error for IpcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Closed => None,
+            Self::Io(err) => Some(err),
+            Self::Codec(err) => Some(err),
+        }
+    }
+}
+impl From<io::Error> for IpcError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl From<serde_json::Error> for IpcError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Codec(err)
+    }
+}
+
+/// The sending-half of an IPC channel.
+///
+/// A sender is either local, returned from [`bind`], or remote, returned from
+/// [`connect_sender`]. Both are used in the same way.
+pub struct Sender<T> {
+    inner: SenderInner,
+    _marker: PhantomData<fn(T)>,
+}
+
+enum SenderInner {
+    Local(super::Sender, ListenerGuard),
+    Remote(Arc<tokio::sync::Mutex<Connection>>),
+}
+
+impl<T> Sender<T>
+where
+    T: Serialize,
+{
+    /// Sends a value, waiting until there is capacity.
+    ///
+    /// A successful send occurs when the channel was open at the time the
+    /// value was sent. As with [`mpmc::Sender::send`], an `Ok` result doesn't
+    /// guarantee that the value will be received. For a remote sender, the
+    /// channel may also close before the value reaches it.
+    ///
+    /// This method is cancel safe in the sense that the connection is never
+    /// left with part of a value on it. If a remote send is cancelled part
+    /// way through writing the value, the rest is written before anything
+    /// else on the connection, so the value may still be received.
+    ///
+    /// [`mpmc::Sender::send`]: fn@super::Sender::send
+    pub async fn send(&self, value: T) -> Result<(), IpcError> {
+        let value = serde_json::to_string(&value)?;
+
+        match &self.inner {
+            SenderInner::Local(tx, _) => tx.send(value).await.map_err(|_| IpcError::Closed),
+            SenderInner::Remote(connection) => connection.lock().await.send(value).await,
+        }
+    }
+
+    /// Waits until the channel is closed.
+    ///
+    /// See [`mpmc::Sender::closed`]. A remote sender returns once the channel
+    /// has told it that it's closed, or the connection is lost. While it
+    /// waits, it holds the connection, so clones of the same remote sender
+    /// can't send until it returns or is cancelled.
+    ///
+    /// This method is cancel safe.
+    ///
+    /// [`mpmc::Sender::closed`]: fn@super::Sender::closed
+    pub async fn closed(&self) {
+        match &self.inner {
+            SenderInner::Local(tx, _) => tx.closed().await,
+            SenderInner::Remote(connection) => connection.lock().await.closed().await,
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        let inner = match &self.inner {
+            SenderInner::Local(tx, guard) => SenderInner::Local(tx.clone(), Arc::clone(guard)),
+            SenderInner::Remote(connection) => SenderInner::Remote(Arc::clone(connection)),
+        };
+
+        Self {
+            inner,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// The receiving-half of an IPC channel.
+///
+/// A receiver is either local, returned from [`bind`], or remote, returned
+/// from [`connect_receiver`]. Both are used in the same way.
+pub struct Receiver<T> {
+    inner: ReceiverInner,
+    _marker: PhantomData<fn() -> T>,
+}
+
+enum ReceiverInner {
+    Local(super::Receiver, ListenerGuard),
+    Remote(Arc<tokio::sync::Mutex<Connection>>),
+}
+
+impl<T> Receiver<T>
+where
+    T: DeserializeOwned,
+{
+    /// Receives a value, waiting until one is available.
+    ///
+    /// Once the channel is closed, this method will continue to return the
+    /// remaining values stored in the channel buffer. Once the channel is
+    /// empty, this method will return [`IpcError::Closed`].
+    pub async fn recv(&self) -> Result<T, IpcError> {
+        let value = match &self.inner {
+            ReceiverInner::Local(rx, _) => rx.recv().await.map_err(|_| IpcError::Closed)?,
+            ReceiverInner::Remote(connection) => connection.lock().await.recv().await?,
+        };
+
+        Ok(serde_json::from_str(&value)?)
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        let inner = match &self.inner {
+            ReceiverInner::Local(rx, guard) => ReceiverInner::Local(rx.clone(), Arc::clone(guard)),
+            ReceiverInner::Remote(connection) => ReceiverInner::Remote(Arc::clone(connection)),
+        };
+
+        Self {
+            inner,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Which half of the channel a connection acts as.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum Role {
+    Sender,
+    Receiver,
+}
+
+/// A frame sent over a connection.
+///
+/// Frames are serialized as JSON, one frame per line.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum Frame {
+    /// The first frame sent by a client, saying what it will do.
+    Hello { role: Role },
+    /// Permission to send `count` more values. Sent by the channel to remote
+    /// senders, and by remote receivers to the channel to ask for values.
+    Credit { count: usize },
+    /// A serialized value.
+    Value { value: String },
+    /// The channel is closed. Sent by the channel to remote senders and
+    /// receivers.
+    Closed,
+}
+
+/// Reads and writes frames on a Unix socket.
+struct Framed {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    /// The bytes of frames which haven't been written to the socket yet.
+    ///
+    /// A frame is only left here if the write was cancelled part way through.
+    /// The rest of it must be written before anything else, otherwise the
+    /// other end would see a corrupted frame.
+    unsent: Vec<u8>,
+}
+
+impl Framed {
+    fn new(stream: UnixStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+            unsent: Vec::new(),
+        }
+    }
+
+    /// Reads the next frame, returning `None` if the connection was closed.
+    ///
+    /// This method is cancel safe, no partially read frame will be lost.
+    async fn read(&mut self) -> Option<Frame> {
+        let line = self.lines.next_line().await.ok()??;
+        serde_json::from_str(&line).ok()
+    }
+
+    /// Reads the next frame if one has already arrived, without waiting.
+    ///
+    /// Returns `Some(None)` if the connection has been closed.
+    async fn try_read(&mut self) -> Option<Option<Frame>> {
+        tokio::select! {
+            biased;
+            frame = self.read() => Some(frame),
+            _ = std::future::ready(()) => None,
+        }
+    }
+
+    /// Writes a frame, after whatever is left of a cancelled write.
+    ///
+    /// This method is cancel safe, if it is cancelled, the rest of the frame
+    /// is written by the next call to `write` or [`flush`].
+    ///
+    /// [`flush`]: fn@Self::flush
+    async fn write(&mut self, frame: &Frame) -> io::Result<()> {
+        serde_json::to_writer(&mut self.unsent, frame)?;
+        self.unsent.push(b'\n');
+        self.flush().await
+    }
+
+    /// Finishes writing any frames left over from cancelled writes.
+    ///
+    /// This method is cancel safe, bytes are only removed from `unsent` once
+    /// they have been written.
+    async fn flush(&mut self) -> io::Result<()> {
+        while !self.unsent.is_empty() {
+            let written = self.writer.write(&self.unsent).await?;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.unsent.drain(..written);
+        }
+        Ok(())
+    }
+}
+
+/// A client connection to a channel, used by remote senders and receivers.
+struct Connection {
+    framed: Framed,
+    /// The number of values a remote sender may send.
+    credit: usize,
+    /// Whether a remote receiver has asked for a value which it hasn't
+    /// received yet.
+    requested: bool,
+    /// Whether the channel has told us that it is closed.
+    closed: bool,
+}
+
+impl Connection {
+    async fn connect(path: &Path, role: Role) -> io::Result<Self> {
+        let mut framed = Framed::new(UnixStream::connect(path).await?);
+        framed.write(&Frame::Hello { role }).await?;
+
+        Ok(Self {
+            framed,
+            credit: 0,
+            requested: false,
+            closed: false,
+        })
+    }
+
+    /// Handles a frame from the channel which isn't the one we're waiting for.
+    fn handle(&mut self, frame: Option<Frame>) {
+        match frame {
+            Some(Frame::Credit { count }) => self.credit += count,
+            Some(Frame::Closed) | None => self.closed = true,
+            Some(_) => {}
+        }
+    }
+
+    async fn send(&mut self, value: String) -> Result<(), IpcError> {
+        // A previous value may have been cut short, the channel won't return
+        // our credit until it has all of it.
+        self.framed.flush().await?;
+
+        // Catch up on anything the channel has already told us, so that we
+        // don't send to a channel which we know is closed.
+        while let Some(frame) = self.framed.try_read().await {
+            self.handle(frame);
+            if self.closed {
+                break;
+            }
+        }
+
+        while self.credit == 0 && !self.closed {
+            let frame = self.framed.read().await;
+            self.handle(frame);
+        }
+        if self.closed {
+            return Err(IpcError::Closed);
+        }
+
+        // Use the credit before writing, the value is on its way once the
+        // write has started, even if it is cancelled.
+        self.credit -= 1;
+        self.framed.write(&Frame::Value { value }).await?;
+        Ok(())
+    }
+
+    async fn closed(&mut self) {
+        while !self.closed {
+            let frame = self.framed.read().await;
+            self.handle(frame);
+        }
+    }
+
+    async fn recv(&mut self) -> Result<String, IpcError> {
+        if self.closed {
+            return Err(IpcError::Closed);
+        }
+
+        // If a previous call was cancelled after asking for a value, the
+        // value may already be on its way, so don't ask for another. The
+        // request itself may have been cut short, so finish writing it.
+        if !self.requested {
+            self.requested = true;
+            self.framed.write(&Frame::Credit { count: 1 }).await?;
+        } else {
+            self.framed.flush().await?;
+        }
+
+        loop {
+            match self.framed.read().await {
+                Some(Frame::Value { value }) => {
+                    self.requested = false;
+                    return Ok(value);
+                }
+                frame => {
+                    self.handle(frame);
+                    if self.closed {
+                        return Err(IpcError::Closed);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Accepts connections to the channel until every handle to it has been
+/// dropped.
+///
+/// The listener only holds weak references, so that it doesn't keep the
+/// channel alive itself. It shuts down when the last [`ListenerGuard`] is
+/// dropped, which closes `shutdown`.
+async fn listen(
+    listener: UnixListener,
+    path: PathBuf,
+    channel: Weak<DefaultLock>,
+    guard: Weak<oneshot::Sender<()>>,
+    mut shutdown: oneshot::Receiver<()>,
+) {
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break,
+        };
+
+        let (Some(inner), Some(guard)) = (channel.upgrade(), guard.upgrade()) else {
+            break;
+        };
+        if let Ok((stream, _)) = accepted {
+            tokio::spawn(serve(Framed::new(stream), inner, guard));
+        }
+    }
+
+    _ = std::fs::remove_file(path);
+}
+
+/// Serves a single client connection.
+///
+/// The listener keeps accepting connections until this returns and drops
+/// `_guard`.
+async fn serve(mut framed: Framed, inner: Arc<DefaultLock>, _guard: ListenerGuard) {
+    match framed.read().await {
+        Some(Frame::Hello { role: Role::Sender }) => {
+            serve_sender(framed, super::Sender::new(inner, Some(DEFAULT_BUDGET))).await
+        }
+        Some(Frame::Hello {
+            role: Role::Receiver,
+        }) => serve_receiver(framed, super::Receiver::new(inner, Some(DEFAULT_BUDGET))).await,
+        _ => {}
+    }
+}
+
+/// Forwards values from a remote sender into the channel.
+///
+/// A credit is returned to the remote sender each time one of its values has
+/// been added to the channel. If the channel closes, the remote sender is
+/// told so, even if it isn't sending at the time. The local sender is dropped
+/// when the remote sender disconnects.
+async fn serve_sender(mut framed: Framed, tx: super::Sender) {
+    if framed.write(&Frame::Credit { count: 1 }).await.is_err() {
+        return;
+    }
+
+    loop {
+        // Both `read` and `closed` are cancel safe, so nothing is lost.
+        let frame = tokio::select! {
+            frame = framed.read() => frame,
+            _ = tx.closed() => {
+                _ = framed.write(&Frame::Closed).await;
+                return;
+            }
+        };
+        let Some(frame) = frame else {
+            return;
+        };
+        let Frame::Value { value } = frame else {
+            continue;
+        };
+
+        let reply = match tx.send(value).await {
+            Ok(_) => Frame::Credit { count: 1 },
+            Err(_) => Frame::Closed,
+        };
+        if framed.write(&reply).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Forwards values from the channel to a remote receiver as it asks for them.
+///
+/// The local receiver is dropped when the remote receiver disconnects. If a
+/// value can't be written because the remote receiver has gone, it's put back
+/// in the channel for another receiver.
+async fn serve_receiver(mut framed: Framed, rx: super::Receiver) {
+    let mut requested = 0;
+    loop {
+        if requested == 0 {
+            match framed.read().await {
+                Some(Frame::Credit { count }) => requested += count,
+                Some(_) => {}
+                None => return,
+            }
+            continue;
+        }
+
+        // Wait for a value, but stop if the remote receiver disconnects. Both
+        // `recv` and `read` are cancel safe, so nothing is lost.
+        let frame = tokio::select! {
+            value = rx.recv() => match value {
+                Ok(value) => Frame::Value { value },
+                Err(_) => Frame::Closed,
+            },
+            frame = framed.read() => {
+                match frame {
+                    Some(Frame::Credit { count }) => requested += count,
+                    Some(_) => {}
+                    None => return,
+                }
+                continue;
+            }
+        };
+
+        // Writes aren't raced against anything, so a frame is always either
+        // written in full or the connection has failed.
+        if framed.write(&frame).await.is_err() {
+            if let Frame::Value { value } = frame {
+                rx.requeue(value);
+            }
+            return;
+        }
+        if matches!(frame, Frame::Closed) {
+            return;
+        }
+        requested -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::{Path, PathBuf};
+    use std::process::{self, Stdio};
+    use std::time::Duration;
+
+    use tokio::net::UnixStream;
+
+    use super::{bind, connect_receiver, connect_sender, Frame, Framed, IpcError};
+
+    /// Tells the test binary which side of a test to run in a child process.
+    const CHILD_ROLE: &str = "UAA_IPC_CHILD_ROLE";
+    /// The socket path for the child process to connect to.
+    const CHILD_PATH: &str = "UAA_IPC_CHILD_PATH";
+    const MESSAGES: u32 = 20;
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("uaa-ipc-{}-{name}.sock", process::id()));
+        _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Runs the `child_process` test in a new process of this test binary,
+    /// playing `role` on the channel at `path`.
+    fn spawn_child(role: &str, path: &Path) -> tokio::process::Child {
+        tokio::process::Command::new(env::current_exe().unwrap())
+            .args(["--exact", "mpmc::ipc::tests::child_process", "--nocapture"])
+            .env(CHILD_ROLE, role)
+            .env(CHILD_PATH, path)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap()
+    }
+
+    /// The side of the tests below which runs in a child process. When this
+    /// test is run normally, without a role, it does nothing.
+    #[tokio::test]
+    async fn child_process() {
+        let (Ok(role), Some(path)) = (env::var(CHILD_ROLE), env::var_os(CHILD_PATH)) else {
+            return;
+        };
+
+        match role.as_str() {
+            "sender" => {
+                let tx = connect_sender::<u32>(&path).await.unwrap();
+                for val in 0..MESSAGES {
+                    tx.send(val).await.unwrap();
+                }
+            }
+            "receiver" => {
+                let rx = connect_receiver::<u32>(&path).await.unwrap();
+                for val in 0..MESSAGES {
+                    assert_eq!(rx.recv().await.unwrap(), val);
+                }
+                assert!(matches!(rx.recv().await, Err(IpcError::Closed)));
+            }
+            role => panic!("unknown child role {role}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn receives_from_sender_in_child_process() {
+        let path = socket_path("child-sender");
+        let (tx, rx) = bind::<u32>(&path, 2).unwrap();
+        let mut child = spawn_child("sender", &path);
+
+        for val in 0..MESSAGES {
+            assert_eq!(rx.recv().await.unwrap(), val);
+        }
+        assert!(child.wait().await.unwrap().success());
+
+        // The child's sender disconnected when it exited, so dropping the
+        // local sender closes the channel.
+        drop(tx);
+        assert!(matches!(rx.recv().await, Err(IpcError::Closed)));
+    }
+
+    #[tokio::test]
+    async fn sends_to_receiver_in_child_process() {
+        let path = socket_path("child-receiver");
+        let (tx, rx) = bind::<u32>(&path, 2).unwrap();
+        let mut child = spawn_child("receiver", &path);
+
+        // The local receiver never receives, so every value goes to the
+        // child. It's kept until the child exits, otherwise the channel
+        // would close before the child has connected.
+        for val in 0..MESSAGES {
+            tx.send(val).await.unwrap();
+        }
+        drop(tx);
+
+        assert!(child.wait().await.unwrap().success());
+        drop(rx);
+    }
+
+    #[tokio::test]
+    async fn remote_sender_is_told_when_channel_closes() {
+        let path = socket_path("closed");
+        let (_tx, rx) = bind::<u32>(&path, 2).unwrap();
+        let remote = connect_sender::<u32>(&path).await.unwrap();
+        remote.send(1).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), 1);
+
+        // The remote sender has been given its credit back, it only finds
+        // out about the close from the channel.
+        drop(rx);
+        tokio::time::timeout(Duration::from_secs(5), remote.closed())
+            .await
+            .expect("remote sender wasn't told that the channel closed");
+        assert!(matches!(remote.send(2).await, Err(IpcError::Closed)));
+    }
+
+    #[tokio::test]
+    async fn listener_shuts_down_once_every_handle_is_dropped() {
+        let path = socket_path("shutdown");
+        let (tx, rx) = bind::<u32>(&path, 2).unwrap();
+        let remote_tx = connect_sender::<u32>(&path).await.unwrap();
+        let remote_rx = connect_receiver::<u32>(&path).await.unwrap();
+        // The value can only reach the remote receiver once both remote
+        // handles are being served.
+        remote_tx.send(1).await.unwrap();
+        assert_eq!(remote_rx.recv().await.unwrap(), 1);
+        drop((tx, rx));
+
+        // The served connections still hold the channel.
+        tokio::task::yield_now().await;
+        assert!(path.exists());
+
+        drop((remote_tx, remote_rx));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while path.exists() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("socket file wasn't removed");
+        assert!(connect_sender::<u32>(&path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn cancelled_write_leaves_no_partial_frame() {
+        let (local, peer) = UnixStream::pair().unwrap();
+        let mut framed = Framed::new(local);
+        let mut peer = Framed::new(peer);
+
+        // Far larger than the socket buffer, and nothing is reading yet, so
+        // the write is cut short by the timeout.
+        let large = "x".repeat(4 * 1024 * 1024);
+        let frame = Frame::Value {
+            value: large.clone(),
+        };
+        let cancelled = tokio::time::timeout(Duration::ZERO, framed.write(&frame)).await;
+        assert!(cancelled.is_err(), "write should have been cancelled");
+        assert!(!framed.unsent.is_empty());
+
+        let reader = tokio::spawn(async move {
+            let mut values = Vec::new();
+            for _ in 0..2 {
+                match peer.read().await {
+                    Some(Frame::Value { value }) => values.push(value),
+                    frame => panic!("expected a value, got {frame:?}"),
+                }
+            }
+            values
+        });
+        let frame = Frame::Value {
+            value: "after".into(),
+        };
+        framed.write(&frame).await.unwrap();
+        assert_eq!(reader.await.unwrap(), [large, "after".into()]);
+    }
+
+    #[tokio::test]
+    async fn value_is_kept_when_remote_receiver_disconnects() {
+        let path = socket_path("receiver-gone");
+        let (tx, rx) = bind::<u32>(&path, 2).unwrap();
+        let remote = connect_receiver::<u32>(&path).await.unwrap();
+        tx.send(1).await.unwrap();
+        assert_eq!(remote.recv().await.unwrap(), 1);
+
+        // Ask for another value, then disconnect before it arrives.
+        let cancelled = tokio::time::timeout(Duration::from_millis(10), remote.recv()).await;
+        assert!(cancelled.is_err(), "nothing has been sent yet");
+        drop(remote);
+
+        tx.send(2).await.unwrap();
+        let value = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("the value was lost");
+        assert_eq!(value.unwrap(), 2);
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+use super::shared::{self, ReceiverHandle, SenderHandle, Shared};
+use super::{ChannelClosedError, ChannelRecvError, ChannelSendError};
+
+/// Creates a new asynchronous bounded multi-producer multi-consumer channel
+/// which keeps only the latest value for each key, returning the
+/// sender/receiver halves.
+///
+/// Each message sent on this channel has a key. If a message is sent for a key
+/// which already has a message waiting in the buffer, the waiting message's
+/// value is replaced by the new one. The message keeps its original position
+/// in the buffer, so frequently updated keys don't keep moving to the back of
+/// the queue. Replacing a value never waits, even when the channel is full.
+///
+/// The capacity of the channel is the number of distinct keys which can be
+/// buffered. Since a key is only ever in the buffer once, receivers will see
+/// each key at most once while draining the messages currently buffered.
+///
+/// Closing and draining the channel follow the same rules as
+/// [`mpmc::channel`].
+///
+/// [`mpmc::channel`]: fn@super::channel
+pub fn channel<K>(capacity: usize) -> (Sender<K>, Receiver<K>)
+where
+    K: Eq + Hash + Clone,
+{
+    let (tx, rx) = shared::handles(Buffer::new(capacity));
+
+    (Sender { channel: tx }, Receiver { channel: rx })
+}
+
+/// The sending-half of the [`keyed::channel`] type.
+///
+/// Messages can be sent through the channel with [`send`].
+///
+/// This half can be cloned to send from multiple tasks. Dropping all senders
+/// will cause the channel to be closed.
+///
+/// [`keyed::channel`]: fn@channel
+/// [`send`]: fn@Self::send
+pub struct Sender<K> {
+    channel: SenderHandle<Buffer<K>>,
+}
+
+impl<K> Sender<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Sends a value for a key, replacing any value for that key which is
+    /// still in the buffer.
+    ///
+    /// If there is no value for the key in the buffer, this method waits until
+    /// there is capacity.
+    ///
+    /// A successful send occurs when there is at least one [`Receiver`] still
+    /// connected to the channel. An `Err` result means that the value will
+    /// never be received, however an `Ok` result doesn't guarantee that the
+    /// value will be received as all receivers may disconnect immediately
+    /// after this method returns `Ok`. Nor does it guarantee that this value
+    /// will be received, it may be replaced by a later value for the same key.
+    pub async fn send(&self, key: K, value: String) -> Result<(), ChannelClosedError> {
+        self.channel
+            .send(|channel| channel.send(key.clone(), value.clone()))
+            .await
+    }
+}
+
+impl<K> Clone for Sender<K> {
+    fn clone(&self) -> Self {
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+/// The receiving-half of the [`keyed::channel`] type.
+///
+/// Messages can be received from the channel with [`recv`].
+///
+/// This half can be cloned to receive from multiple tasks. Each message will
+/// only be received by a single receiver. Dropping all receivers will cause
+/// the channel to be closed.
+///
+/// [`keyed::channel`]: fn@channel
+/// [`recv`]: fn@Self::recv
+pub struct Receiver<K> {
+    channel: ReceiverHandle<Buffer<K>>,
+}
+
+impl<K> Receiver<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Receives a key and its latest value, waiting until one is available.
+    ///
+    /// Once the channel is closed (by dropping all senders), this method will
+    /// continue to return the remaining values stored in the channel buffer.
+    /// Once the channel is empty, this method will return
+    /// [`ChannelClosedError`].
+    pub async fn recv(&self) -> Result<(K, String), ChannelClosedError> {
+        self.channel.recv(Shared::recv).await
+    }
+}
+
+impl<K> Clone for Receiver<K> {
+    fn clone(&self) -> Self {
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+/// The keyed channel's message buffer.
+struct Buffer<K> {
+    /// The order in which keys will be received. Each key appears at most
+    /// once.
+    order: VecDeque<K>,
+    /// The latest value for each key in `order`.
+    values: HashMap<K, String>,
+    /// The capacity of the channel, this many distinct keys can be buffered
+    /// before sending a new key will error.
+    capacity: usize,
+}
+
+impl<K> Buffer<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn new(capacity: usize) -> Self {
+        Self {
+            order: VecDeque::with_capacity(capacity),
+            values: HashMap::with_capacity(capacity),
+            capacity,
+        }
+    }
+}
+
+/// The inner keyed channel implementation.
+impl<K> Shared<Buffer<K>>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Sends a message for a key across the channel.
+    ///
+    /// If the key is already in the buffer, its value is replaced. No
+    /// receiver is woken, as there is no additional message to receive.
+    ///
+    /// Otherwise, if the message can be sent, the next receiver waker in the
+    /// queue (if any) will be woken as there is now an additional message
+    /// which can be received.
+    ///
+    /// An error will be returned if the channel is full and doesn't already
+    /// contain the key.
+    fn send(&mut self, key: K, value: String) -> Result<(), ChannelSendError> {
+        let buffer = &mut self.buffer;
+        if let Some(existing) = buffer.values.get_mut(&key) {
+            *existing = value;
+            return Ok(());
+        }
+
+        if buffer.order.len() < buffer.capacity {
+            buffer.order.push_back(key.clone());
+            buffer.values.insert(key, value);
+            self.wake_next_receiver();
+            Ok(())
+        } else {
+            Err(ChannelSendError::Full)
+        }
+    }
+
+    /// Receives the oldest key and its latest value from the channel.
+    ///
+    /// If a message can be received, then the next sender waker in the queue
+    /// (if any) will be woken as there is now additional free capacity to send
+    /// another key.
+    ///
+    /// An error will be returned if the channel is empty. The error will
+    /// depend on whether the channel is also closed.
+    fn recv(&mut self) -> Result<(K, String), ChannelRecvError> {
+        match self.buffer.order.pop_front() {
+            Some(key) => {
+                let value = self
+                    .buffer
+                    .values
+                    .remove(&key)
+                    .expect("every key in the buffer has a value");
+                self.wake_next_sender();
+                Ok((key, value))
+            }
+            None => {
+                if !self.is_closed() {
+                    Err(ChannelRecvError::Empty)
+                } else {
+                    Err(ChannelRecvError::Closed)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use super::{channel, Receiver, Sender};
+    use crate::testing::{poll_once, MockWaker};
+
+    fn send(tx: &Sender<&'static str>, key: &'static str, value: &str) {
+        poll_once(pin!(tx.send(key, value.into())), &MockWaker::new())
+            .assert_ready()
+            .unwrap();
+    }
+
+    fn recv(rx: &Receiver<&'static str>) -> (&'static str, String) {
+        poll_once(pin!(rx.recv()), &MockWaker::new())
+            .assert_ready()
+            .unwrap()
+    }
+
+    #[test]
+    fn latest_value_wins_and_keeps_its_place() {
+        let (tx, rx) = channel(4);
+        send(&tx, "a", "a1");
+        send(&tx, "b", "b1");
+        send(&tx, "a", "a2");
+        send(&tx, "c", "c1");
+        send(&tx, "a", "a3");
+
+        assert_eq!(recv(&rx), ("a", "a3".into()));
+        assert_eq!(recv(&rx), ("b", "b1".into()));
+        assert_eq!(recv(&rx), ("c", "c1".into()));
+
+        // Once received, the key goes to the back of the queue again.
+        send(&tx, "b", "b2");
+        send(&tx, "a", "a4");
+        assert_eq!(recv(&rx), ("b", "b2".into()));
+        assert_eq!(recv(&rx), ("a", "a4".into()));
+    }
+
+    #[test]
+    fn capacity_counts_distinct_keys() {
+        let (tx, rx) = channel(2);
+        send(&tx, "a", "a1");
+        send(&tx, "b", "b1");
+        // Replacing a value never waits, even when the channel is full.
+        send(&tx, "a", "a2");
+
+        let waker = MockWaker::new();
+        let mut new_key = pin!(tx.send("c", "c1".into()));
+        poll_once(new_key.as_mut(), &waker).assert_registered_waker();
+
+        assert_eq!(recv(&rx), ("a", "a2".into()));
+        assert_eq!(waker.counts().total_wakes(), 1);
+        poll_once(new_key, &waker).assert_ready().unwrap();
+        assert_eq!(recv(&rx), ("b", "b1".into()));
+        assert_eq!(recv(&rx), ("c", "c1".into()));
+    }
+
+    #[test]
+    fn buffered_keys_are_drained_after_close() {
+        let (tx, rx) = channel(4);
+        send(&tx, "a", "a1");
+        send(&tx, "b", "b1");
+        drop(tx);
+
+        assert_eq!(recv(&rx), ("a", "a1".into()));
+        assert_eq!(recv(&rx), ("b", "b1".into()));
+        poll_once(pin!(rx.recv()), &MockWaker::new())
+            .assert_ready()
+            .unwrap_err();
+    }
+
+    #[test]
+    fn send_fails_once_receivers_are_dropped() {
+        let (tx, rx) = channel(4);
+        drop(rx);
+        poll_once(pin!(tx.send("a", "a1".into())), &MockWaker::new())
+            .assert_ready()
+            .unwrap_err();
+    }
+
+    #[test]
+    fn waiting_receiver_is_woken_when_channel_closes() {
+        let (tx, rx) = channel::<&str>(4);
+        let waker = MockWaker::new();
+        let mut recv = pin!(rx.recv());
+        poll_once(recv.as_mut(), &waker).assert_registered_waker();
+
+        drop(tx);
+        assert_eq!(waker.counts().total_wakes(), 1);
+        poll_once(recv, &waker).assert_ready().unwrap_err();
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use super::shared::{self, ReceiverHandle, SenderHandle, Shared};
+use super::{ChannelClosedError, ChannelRecvError, ChannelSendError};
+
+/// The priority of a message sent through a [`priority::channel`].
+///
+/// Messages with a higher priority are received before messages with a lower
+/// priority. Messages with the same priority are received in the order that
+/// they were sent.
+///
+/// [`priority::channel`]: fn@channel
+pub type Priority = u8;
+
+/// The priority given to messages sent with [`Sender::send`].
+pub const DEFAULT_PRIORITY: Priority = 0;
+
+/// Creates a new asynchronous bounded multi-producer multi-consumer channel
+/// which orders messages by priority, returning the sender/receiver halves.
+///
+/// This channel behaves like [`mpmc::channel`], except that instead of
+/// receiving messages strictly in the order that they were sent, the message
+/// with the highest [`Priority`] is received first. Messages with the same
+/// priority are received in FIFO order.
+///
+/// Capacity, closing and draining the channel follow the same rules as
+/// [`mpmc::channel`]: once the buffer is full, senders wait regardless of the
+/// priority of the message they are sending, and once the channel is closed
+/// the remaining messages are still received (highest priority first) before
+/// [`ChannelClosedError`] is returned.
+///
+/// A steady stream of high priority messages will starve lower priority
+/// messages. Use [`channel_with_starvation_limit`] to bound how long a low
+/// priority message can wait.
+///
+/// [`mpmc::channel`]: fn@super::channel
+pub fn channel(capacity: usize) -> (Sender, Receiver) {
+    let (tx, rx) = shared::handles(Buffer::new(capacity, None));
+
+    (Sender { channel: tx }, Receiver { channel: rx })
+}
+
+/// Creates a new priority channel which protects low priority messages from
+/// starvation.
+///
+/// This channel behaves like [`priority::channel`], except that the oldest
+/// message in the buffer can only be overtaken by `limit` newer messages of
+/// higher priority. After that, it will be the next message received,
+/// regardless of the priority of the other messages in the buffer.
+///
+/// A `limit` of 0 means that messages are never overtaken, so the channel
+/// behaves like a plain FIFO channel.
+///
+/// [`priority::channel`]: fn@channel
+pub fn channel_with_starvation_limit(capacity: usize, limit: usize) -> (Sender, Receiver) {
+    let (tx, rx) = shared::handles(Buffer::new(capacity, Some(limit)));
+
+    (Sender { channel: tx }, Receiver { channel: rx })
+}
+
+/// The sending-half of the [`priority::channel`] type.
+///
+/// Messages can be sent through the channel with [`send`] or
+/// [`send_with_priority`].
+///
+/// This half can be cloned to send from multiple tasks. Dropping all senders
+/// will cause the channel to be closed.
+///
+/// [`priority::channel`]: fn@channel
+/// [`send`]: fn@Self::send
+/// [`send_with_priority`]: fn@Self::send_with_priority
+#[derive(Clone)]
+pub struct Sender {
+    channel: SenderHandle<Buffer>,
+}
+
+impl Sender {
+    /// Sends a value with the [`DEFAULT_PRIORITY`], waiting until there is
+    /// capacity.
+    ///
+    /// See [`send_with_priority`] for details.
+    ///
+    /// [`send_with_priority`]: fn@Self::send_with_priority
+    pub async fn send(&self, value: String) -> Result<(), ChannelClosedError> {
+        self.send_with_priority(value, DEFAULT_PRIORITY).await
+    }
+
+    /// Sends a value with the given priority, waiting until there is capacity.
+    ///
+    /// A successful send occurs when there is at least one [`Receiver`] still
+    /// connected to the channel. An `Err` result means that the value will
+    /// never be received, however an `Ok` result doesn't guarantee that the
+    /// value will be received as all receivers may disconnect immediately
+    /// after this method returns `Ok`.
+    pub async fn send_with_priority(
+        &self,
+        value: String,
+        priority: Priority,
+    ) -> Result<(), ChannelClosedError> {
+        self.channel
+            .send(|channel| channel.send(value.clone(), priority))
+            .await
+    }
+}
+
+/// The receiving-half of the [`priority::channel`] type.
+///
+/// Messages can be received from the channel with [`recv`].
+///
+/// This half can be cloned to receive from multiple tasks. Each message will
+/// only be received by a single receiver. Dropping all receivers will cause
+/// the channel to be closed.
+///
+/// [`priority::channel`]: fn@channel
+/// [`recv`]: fn@Self::recv
+#[derive(Clone)]
+pub struct Receiver {
+    channel: ReceiverHandle<Buffer>,
+}
+
+impl Receiver {
+    /// Receives the value with the highest priority, waiting until one is
+    /// available.
+    ///
+    /// Once the channel is closed (by dropping all senders), this method will
+    /// continue to return the remaining values stored in the channel buffer.
+    /// Once the channel is empty, this method will return
+    /// [`ChannelClosedError`].
+    pub async fn recv(&self) -> Result<String, ChannelClosedError> {
+        self.channel.recv(Shared::recv).await
+    }
+}
+
+/// A message stored in the priority channel buffer.
+struct Entry {
+    /// The order in which this message was sent, used to find the oldest
+    /// message in the buffer.
+    seq: u64,
+    value: String,
+}
+
+/// The priority channel's message buffer.
+struct Buffer {
+    /// One FIFO queue per priority. Queues are removed once they are empty,
+    /// so the last entry always holds the highest priority message.
+    queues: BTreeMap<Priority, VecDeque<Entry>>,
+    /// The number of messages in all the queues.
+    len: usize,
+    /// The capacity of the channel, this many messages can be buffered before
+    /// sending will error.
+    capacity: usize,
+
+    /// The sequence number which will be given to the next message sent.
+    next_seq: u64,
+    /// The number of newer messages which may be received before the oldest
+    /// message, if starvation protection is enabled.
+    starvation_limit: Option<usize>,
+    /// The number of newer messages which have been received before the
+    /// current oldest message.
+    oldest_overtaken: usize,
+}
+
+impl Buffer {
+    fn new(capacity: usize, starvation_limit: Option<usize>) -> Self {
+        Self {
+            queues: BTreeMap::new(),
+            len: 0,
+            capacity,
+
+            next_seq: 0,
+            starvation_limit,
+            oldest_overtaken: 0,
+        }
+    }
+
+    /// Returns the priority of the highest priority non-empty queue.
+    fn highest_priority(&self) -> Option<Priority> {
+        self.queues.keys().next_back().copied()
+    }
+
+    /// Returns the priority of the queue containing the oldest message.
+    ///
+    /// The oldest message in each queue is at the front, so only the front of
+    /// each queue needs to be checked.
+    fn oldest_priority(&self) -> Option<Priority> {
+        self.queues
+            .iter()
+            .filter_map(|(priority, queue)| queue.front().map(|entry| (entry.seq, *priority)))
+            .min()
+            .map(|(_, priority)| priority)
+    }
+
+    /// Removes the message at the front of the queue for `priority`.
+    ///
+    /// The queue must exist and will be removed if it becomes empty.
+    fn pop_front(&mut self, priority: Priority) -> String {
+        let queue = self
+            .queues
+            .get_mut(&priority)
+            .expect("queue for priority must exist");
+        let entry = queue
+            .pop_front()
+            .expect("queues in the buffer are never empty");
+        if queue.is_empty() {
+            self.queues.remove(&priority);
+        }
+        self.len -= 1;
+
+        entry.value
+    }
+}
+
+/// The inner priority channel implementation.
+impl Shared<Buffer> {
+    /// Sends a message across the channel with the given priority.
+    ///
+    /// If the message can be sent, the next receiver waker in the queue (if
+    /// any) will be woken as there is now an additional message which can be
+    /// received.
+    ///
+    /// An error will be returned if the channel is full.
+    fn send(&mut self, value: String, priority: Priority) -> Result<(), ChannelSendError> {
+        let buffer = &mut self.buffer;
+        if buffer.len < buffer.capacity {
+            let seq = buffer.next_seq;
+            buffer.next_seq += 1;
+            buffer
+                .queues
+                .entry(priority)
+                .or_default()
+                .push_back(Entry { seq, value });
+            buffer.len += 1;
+            self.wake_next_receiver();
+            Ok(())
+        } else {
+            Err(ChannelSendError::Full)
+        }
+    }
+
+    /// Receives the highest priority message from the channel.
+    ///
+    /// If starvation protection is enabled and the oldest message has been
+    /// overtaken too many times, the oldest message is received instead.
+    ///
+    /// If a message can be received, then the next sender waker in the queue
+    /// (if any) will be woken as there is now additional free capacity to send
+    /// another message.
+    ///
+    /// An error will be returned if the channel is empty. The error will
+    /// depend on whether the channel is also closed.
+    fn recv(&mut self) -> Result<String, ChannelRecvError> {
+        let buffer = &mut self.buffer;
+        let Some(oldest) = buffer.oldest_priority() else {
+            if !self.is_closed() {
+                return Err(ChannelRecvError::Empty);
+            } else {
+                return Err(ChannelRecvError::Closed);
+            }
+        };
+
+        let priority = match buffer.starvation_limit {
+            Some(limit) if buffer.oldest_overtaken >= limit => oldest,
+            _ => buffer.highest_priority().unwrap_or(oldest),
+        };
+
+        if priority == oldest {
+            buffer.oldest_overtaken = 0;
+        } else {
+            buffer.oldest_overtaken += 1;
+        }
+
+        let value = buffer.pop_front(priority);
+        self.wake_next_sender();
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use super::{channel, channel_with_starvation_limit, Priority, Receiver, Sender};
+    use crate::runtime;
+    use crate::testing::{poll_once, MockWaker};
+
+    async fn send_all(tx: &Sender, messages: &[(&str, Priority)]) {
+        for (value, priority) in messages {
+            tx.send_with_priority(value.to_string(), *priority)
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn recv_all(rx: &Receiver) -> Vec<String> {
+        let mut received = Vec::new();
+        while let Ok(value) = rx.recv().await {
+            received.push(value);
+        }
+        received
+    }
+
+    #[test]
+    fn highest_priority_is_received_first() {
+        runtime::block_on(async {
+            let (tx, rx) = channel(8);
+            send_all(&tx, &[("low", 1), ("high", 9), ("default", 0), ("mid", 5)]).await;
+            drop(tx);
+            assert_eq!(recv_all(&rx).await, ["high", "mid", "low", "default"]);
+        });
+    }
+
+    #[test]
+    fn same_priority_is_received_in_send_order() {
+        runtime::block_on(async {
+            let (tx, rx) = channel(8);
+            send_all(
+                &tx,
+                &[("a1", 1), ("b1", 2), ("a2", 1), ("b2", 2), ("a3", 1)],
+            )
+            .await;
+            drop(tx);
+            assert_eq!(recv_all(&rx).await, ["b1", "b2", "a1", "a2", "a3"]);
+        });
+    }
+
+    #[test]
+    fn starvation_limit_bounds_how_often_the_oldest_is_overtaken() {
+        runtime::block_on(async {
+            let (tx, rx) = channel_with_starvation_limit(8, 2);
+            send_all(
+                &tx,
+                &[("old", 0), ("h1", 9), ("h2", 9), ("h3", 9), ("h4", 9)],
+            )
+            .await;
+            drop(tx);
+            // `old` is overtaken twice, then received ahead of the rest.
+            assert_eq!(recv_all(&rx).await, ["h1", "h2", "old", "h3", "h4"]);
+        });
+    }
+
+    #[test]
+    fn starvation_limit_of_zero_is_fifo() {
+        runtime::block_on(async {
+            let (tx, rx) = channel_with_starvation_limit(8, 0);
+            send_all(&tx, &[("a", 0), ("b", 9), ("c", 5)]).await;
+            drop(tx);
+            assert_eq!(recv_all(&rx).await, ["a", "b", "c"]);
+        });
+    }
+
+    #[test]
+    fn full_channel_waits_whatever_the_priority() {
+        let (tx, rx) = channel(1);
+        let waker = MockWaker::new();
+        poll_once(pin!(tx.send_with_priority("low".into(), 0)), &waker)
+            .assert_ready()
+            .unwrap();
+
+        let mut high = pin!(tx.send_with_priority("high".into(), 9));
+        poll_once(high.as_mut(), &waker).assert_registered_waker();
+        let value = poll_once(pin!(rx.recv()), &waker).assert_ready().unwrap();
+        assert_eq!(value, "low");
+        poll_once(high, &waker).assert_ready().unwrap();
+    }
+}
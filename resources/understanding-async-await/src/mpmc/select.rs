@@ -0,0 +1,313 @@
+use std::borrow::Cow;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use mpmc_core::Lock;
+
+use super::{Channel, ChannelClosedError, ChannelRecvError, DefaultLock, Receiver};
+
+/// Receives a value from whichever of the `receivers` has one available first.
+///
+/// This is a convenience for a one-off, biased [`Select`]. The receivers are
+/// always checked in the order they are given, so if the first receiver
+/// always has values available, the others will never be received from. To
+/// receive fairly from several receivers in a loop, create a [`Select`] once
+/// and call [`Select::recv`] repeatedly.
+///
+/// # Panics
+///
+/// The returned future will panic when polled if `receivers` is empty.
+pub fn recv_any<'a, L>(receivers: &[&'a Receiver<L>]) -> RecvAny<'a, 'a, L>
+where
+    L: Lock<Channel>,
+{
+    RecvAny::new(receivers.to_vec().into(), 0, None)
+}
+
+/// Receives from several [`Receiver`]s at once.
+///
+/// Calling [`recv`] returns a future which registers with every channel and
+/// completes with the first value available from any of them, together with
+/// the index of the receiver it came from. Only a single value is ever taken
+/// from a channel, the others are left untouched.
+///
+/// By default, receivers are checked in round-robin order: each call to
+/// `recv` starts checking at the receiver after the one which last returned a
+/// value. Use [`biased`] to always check the receivers in the order they were
+/// added.
+///
+/// The future returned by `recv` is cancel safe, unlike racing multiple calls
+/// to [`Receiver::recv`] with `tokio::select!`: if it is dropped before it
+/// completes, no message is lost and any wake up it had been given is passed
+/// on to another waiting receiver.
+///
+/// [`recv`]: fn@Self::recv
+/// [`biased`]: fn@Self::biased
+pub struct Select<'a, L = DefaultLock>
+where
+    L: Lock<Channel>,
+{
+    receivers: Vec<&'a Receiver<L>>,
+    biased: bool,
+    /// The index of the receiver to check first on the next call to `recv`.
+    next: usize,
+}
+
+impl<'a, L> Select<'a, L>
+where
+    L: Lock<Channel>,
+{
+    /// Creates a new `Select` with no receivers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a receiver to select from.
+    ///
+    /// The receiver's index, which is returned alongside each value received
+    /// from it, is the number of receivers added before it.
+    pub fn receiver(mut self, receiver: &'a Receiver<L>) -> Self {
+        self.receivers.push(receiver);
+        self
+    }
+
+    /// Always check receivers in the order they were added.
+    ///
+    /// A biased select may starve later receivers if earlier ones always have
+    /// values available, but it can be used to give some channels (such as
+    /// one carrying control messages) precedence over others.
+    pub fn biased(mut self) -> Self {
+        self.biased = true;
+        self
+    }
+
+    /// Receives a value from the first receiver that has one available.
+    ///
+    /// Returns the index of the receiver and the value received. Channels
+    /// which are closed and empty are skipped. Once all of the channels are
+    /// closed and empty, this method will return [`ChannelClosedError`].
+    ///
+    /// # Panics
+    ///
+    /// The returned future will panic when polled if no receivers have been
+    /// added.
+    pub fn recv<'s>(&'s mut self) -> RecvAny<'a, 's, L> {
+        let start = if self.biased { 0 } else { self.next };
+        let receivers = self.receivers.as_slice().into();
+        RecvAny::new(receivers, start, Some(&mut self.next))
+    }
+}
+
+impl<'a, L> Default for Select<'a, L>
+where
+    L: Lock<Channel>,
+{
+    fn default() -> Self {
+        Self {
+            receivers: Vec::new(),
+            biased: false,
+            next: 0,
+        }
+    }
+}
+
+/// Future returned by [`recv_any`] and [`Select::recv`].
+pub struct RecvAny<'a, 's, L = DefaultLock>
+where
+    L: Lock<Channel>,
+{
+    receivers: Cow<'s, [&'a Receiver<L>]>,
+    /// The index of the first receiver to check.
+    start: usize,
+    /// Where to store the index of the receiver to check first next time, for
+    /// round-robin selects.
+    next: Option<&'s mut usize>,
+    /// The waiter id registered with each receiver's channel, if any.
+    waiter_ids: Vec<Option<u64>>,
+}
+
+impl<'a, 's, L> RecvAny<'a, 's, L>
+where
+    L: Lock<Channel>,
+{
+    fn new(
+        receivers: Cow<'s, [&'a Receiver<L>]>,
+        start: usize,
+        next: Option<&'s mut usize>,
+    ) -> Self {
+        let waiter_ids = vec![None; receivers.len()];
+        Self {
+            receivers,
+            start,
+            next,
+            waiter_ids,
+        }
+    }
+}
+
+impl<'a, 's, L> Future for RecvAny<'a, 's, L>
+where
+    L: Lock<Channel>,
+{
+    type Output = Result<(usize, String), ChannelClosedError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let count = this.receivers.len();
+        assert!(count > 0, "RecvAny polled with no receivers");
+
+        // Yield if any of the receivers has used up its budget. Only the
+        // budget of the receiver which a value is received from is used up.
+        for receiver in this.receivers.iter() {
+            if receiver.budget.poll_proceed(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+
+        let mut all_closed = true;
+        for offset in 0..count {
+            let idx = (this.start + offset) % count;
+            let mut guard = this.receivers[idx].inner.lock();
+
+            match guard.recv(this.waiter_ids[idx]) {
+                Ok(value) => {
+                    if let Some(id) = this.waiter_ids[idx].take() {
+                        guard.remove_receiver_waker(id);
+                    }
+                    if let Some(next) = this.next.as_deref_mut() {
+                        *next = (idx + 1) % count;
+                    }
+                    let poll = Poll::Ready(Ok((idx, value)));
+                    this.receivers[idx].budget.record(&poll);
+                    return poll;
+                }
+                Err(ChannelRecvError::Closed) => {}
+                Err(ChannelRecvError::Empty) => {
+                    all_closed = false;
+                    let id =
+                        guard.register_receiver_waker(this.waiter_ids[idx], cx.waker().clone());
+                    this.waiter_ids[idx] = Some(id);
+                }
+            }
+        }
+
+        if all_closed {
+            Poll::Ready(Err(ChannelClosedError {}))
+        } else {
+            for receiver in this.receivers.iter() {
+                receiver.budget.record(&Poll::<()>::Pending);
+            }
+            Poll::Pending
+        }
+    }
+}
+
+impl<'a, 's, L> Drop for RecvAny<'a, 's, L>
+where
+    L: Lock<Channel>,
+{
+    fn drop(&mut self) {
+        // Whether we completed or not, all our registrations must be removed.
+        // If another channel already woke us, `deregister_receiver_waker`
+        // will pass that wake up on to another receiver.
+        for (receiver, waiter_id) in self.receivers.iter().zip(&self.waiter_ids) {
+            if let Some(id) = waiter_id {
+                receiver.inner.lock().deregister_receiver_waker(*id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use super::{recv_any, Select};
+    use crate::mpmc;
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn dropped_recv_any_loses_no_message() {
+        let (tx_a, rx_a) = mpmc::channel(4);
+        let (_tx_b, rx_b) = mpmc::channel(4);
+        let waker = MockWaker::new();
+
+        let mut recv = Box::pin(recv_any(&[&rx_a, &rx_b]));
+        poll_once(recv.as_mut(), &waker).assert_pending();
+        assert_eq!(waker.live(), 2, "registered with both channels");
+
+        // Woken for the message, but dropped before taking it.
+        poll_once(pin!(tx_a.send("value".into())), &MockWaker::new())
+            .assert_ready()
+            .unwrap();
+        assert_eq!(waker.counts().total_wakes(), 1);
+        drop(recv);
+        assert_eq!(waker.live(), 0, "a receiver waker was left registered");
+
+        let value = poll_once(pin!(rx_a.recv()), &waker).assert_ready().unwrap();
+        assert_eq!(value, "value");
+    }
+
+    #[test]
+    fn dropped_recv_any_passes_on_its_wake() {
+        let (tx_a, rx_a) = mpmc::channel(4);
+        let (_tx_b, rx_b) = mpmc::channel(4);
+        let mut select = Select::new().receiver(&rx_a).receiver(&rx_b);
+        let select_waker = MockWaker::new();
+        let waker = MockWaker::new();
+
+        let mut any = Box::pin(select.recv());
+        poll_once(any.as_mut(), &select_waker).assert_pending();
+        let mut recv = pin!(rx_a.recv());
+        poll_once(recv.as_mut(), &waker).assert_registered_waker();
+
+        // The select is first in the queue, so it is woken for the message.
+        poll_once(pin!(tx_a.send("value".into())), &MockWaker::new())
+            .assert_ready()
+            .unwrap();
+        assert_eq!(select_waker.counts().total_wakes(), 1);
+        assert_eq!(waker.counts().total_wakes(), 0);
+
+        drop(any);
+        assert_eq!(select_waker.live(), 0);
+        assert_eq!(waker.counts().total_wakes(), 1);
+        assert_eq!(poll_once(recv, &waker).assert_ready().unwrap(), "value");
+    }
+
+    #[test]
+    fn select_receives_round_robin() {
+        let (tx_a, rx_a) = mpmc::channel(4);
+        let (tx_b, rx_b) = mpmc::channel(4);
+        let waker = MockWaker::new();
+        for value in ["a1", "a2"] {
+            poll_once(pin!(tx_a.send(value.into())), &waker)
+                .assert_ready()
+                .unwrap();
+        }
+        for value in ["b1", "b2"] {
+            poll_once(pin!(tx_b.send(value.into())), &waker)
+                .assert_ready()
+                .unwrap();
+        }
+
+        let mut select = Select::new().receiver(&rx_a).receiver(&rx_b);
+        let mut received = Vec::new();
+        for _ in 0..4 {
+            received.push(
+                poll_once(pin!(select.recv()), &waker)
+                    .assert_ready()
+                    .unwrap(),
+            );
+        }
+        assert_eq!(
+            received,
+            [
+                (0, "a1".to_string()),
+                (1, "b1".to_string()),
+                (0, "a2".to_string()),
+                (1, "b2".to_string()),
+            ]
+        );
+    }
+}
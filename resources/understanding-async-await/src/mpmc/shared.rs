@@ -0,0 +1,541 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use mpmc_core::Lock;
+
+use super::{ChannelClosedError, ChannelRecvError, ChannelSendError};
+
+/// The state shared by the senders and receivers of a channel variant.
+///
+/// The variants (priority, keyed, timed and durable) only differ in how they
+/// store messages, which is kept in `buffer`. The sender and receiver counts,
+/// closing the channel and the queues of waiting senders and receivers work
+/// the same way for all of them, and live here.
+///
+/// This is a sync object. All methods return immediately.
+pub(super) struct Shared<B> {
+    /// The variant's message buffer.
+    pub(super) buffer: B,
+    /// Indicates when the channel has been closed.
+    closed: bool,
+
+    /// The number of connected `Sender`s.
+    senders: usize,
+    /// The number of active `Receiver`s.
+    receivers: usize,
+
+    /// A queue of wakers for senders awaiting free capacity in the channel,
+    /// together with the id of the waiter which registered them.
+    sender_wakers: VecDeque<(u64, Waker)>,
+    /// The id which will be given to the next registered sender waiter.
+    next_sender_id: u64,
+    /// A queue of wakers for receivers awaiting a new message in the channel,
+    /// together with the id of the waiter which registered them.
+    receiver_wakers: VecDeque<(u64, Waker)>,
+    /// The id which will be given to the next registered receiver waiter.
+    next_receiver_id: u64,
+}
+
+impl<B> Shared<B> {
+    pub(super) fn new(buffer: B) -> Self {
+        Self {
+            buffer,
+            closed: false,
+
+            senders: 0,
+            receivers: 0,
+
+            sender_wakers: VecDeque::new(),
+            next_sender_id: 0,
+            receiver_wakers: VecDeque::new(),
+            next_receiver_id: 0,
+        }
+    }
+
+    /// Returns whether the channel has been closed.
+    pub(super) fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Wakes the sender at the front of the queue.
+    ///
+    /// If no sender wakers are registered, this method does nothing.
+    pub(super) fn wake_next_sender(&mut self) {
+        if let Some((_, waker)) = self.sender_wakers.pop_front() {
+            waker.wake();
+        }
+    }
+
+    /// Wakes the receiver at the front of the queue.
+    ///
+    /// If no receiver wakers are registered, this method does nothing.
+    pub(super) fn wake_next_receiver(&mut self) {
+        if let Some((_, waker)) = self.receiver_wakers.pop_front() {
+            waker.wake();
+        }
+    }
+
+    /// Wakes every receiver in the queue.
+    pub(super) fn wake_all_receivers(&mut self) {
+        while let Some((_, waker)) = self.receiver_wakers.pop_front() {
+            waker.wake();
+        }
+    }
+
+    /// Registers a waker to be woken when capacity is available.
+    ///
+    /// Senders are woken in FIFO order. A waiter which is still in the queue
+    /// from a previous registration keeps its place, only its waker is
+    /// replaced. Otherwise it is added to the back of the queue.
+    ///
+    /// Returns the id which identifies the waiter in the queue.
+    fn register_sender_waker(&mut self, id: Option<u64>, waker: Waker) -> u64 {
+        register(&mut self.sender_wakers, &mut self.next_sender_id, id, waker)
+    }
+
+    /// Removes a sender waiter from the queue.
+    ///
+    /// This is called when a sender stops waiting without having sent its
+    /// message. If the waiter is no longer in the queue, then it has already
+    /// been woken for capacity which it won't use, so the next sender is woken
+    /// in its place. The buffer is opaque here, so that sender may find the
+    /// channel full after all and go back to waiting.
+    fn deregister_sender_waker(&mut self, id: u64) {
+        if !remove(&mut self.sender_wakers, id) {
+            self.wake_next_sender();
+        }
+    }
+
+    /// Registers a waker to be woken when a message is available.
+    ///
+    /// Receivers are woken in FIFO order, a waiter keeps its place in the
+    /// queue in the same way as for [`register_sender_waker`].
+    ///
+    /// Returns the id which identifies the waiter in the queue.
+    ///
+    /// [`register_sender_waker`]: fn@Self::register_sender_waker
+    fn register_receiver_waker(&mut self, id: Option<u64>, waker: Waker) -> u64 {
+        register(
+            &mut self.receiver_wakers,
+            &mut self.next_receiver_id,
+            id,
+            waker,
+        )
+    }
+
+    /// Removes a receiver waiter from the queue.
+    ///
+    /// This is called when a receiver stops waiting without having received a
+    /// message. If the waiter is no longer in the queue, then it has already
+    /// been woken for a message which it won't receive, so the next receiver
+    /// is woken in its place.
+    fn deregister_receiver_waker(&mut self, id: u64) {
+        if !remove(&mut self.receiver_wakers, id) {
+            self.wake_next_receiver();
+        }
+    }
+
+    /// Close the channel.
+    ///
+    /// All sender and receiver wakers which have been registered, but not yet
+    /// woken will get woken now.
+    fn close(&mut self) {
+        self.closed = true;
+
+        while let Some((_, waker)) = self.sender_wakers.pop_front() {
+            waker.wake();
+        }
+        self.wake_all_receivers();
+    }
+}
+
+/// A sender's handle on a [`Shared`] channel.
+///
+/// The channel counts its handles: creating or cloning one increments the
+/// sender count, dropping one decrements it, closing the channel when the
+/// last sender is dropped.
+pub(super) struct SenderHandle<B> {
+    channel: Arc<Mutex<Shared<B>>>,
+}
+
+impl<B> SenderHandle<B> {
+    pub(super) fn new(channel: Arc<Mutex<Shared<B>>>) -> Self {
+        Lock::lock(&*channel).senders += 1;
+        Self { channel }
+    }
+
+    /// Locks the channel.
+    pub(super) fn lock(&self) -> MutexGuard<'_, Shared<B>> {
+        Lock::lock(&*self.channel)
+    }
+
+    /// Waits until `try_send` succeeds.
+    ///
+    /// `try_send` is called with the channel locked each time the sender is
+    /// polled. While it returns [`ChannelSendError::Full`], the sender waits
+    /// in FIFO order for another sender's turn to free up capacity. If the
+    /// channel is closed, `try_send` isn't called at all.
+    ///
+    /// This method is cancel safe. If the returned future is dropped while it
+    /// is waiting, it leaves the queue, and any wake up that it had already
+    /// been given is passed on to the next waiting sender.
+    pub(super) async fn send<T, F>(&self, try_send: F) -> Result<T, ChannelClosedError>
+    where
+        F: FnMut(&mut Shared<B>) -> Result<T, ChannelSendError> + Unpin,
+    {
+        Send {
+            handle: self,
+            try_send,
+            waiter_id: None,
+        }
+        .await
+    }
+}
+
+impl<B> Clone for SenderHandle<B> {
+    fn clone(&self) -> Self {
+        Self::new(self.channel.clone())
+    }
+}
+
+impl<B> Drop for SenderHandle<B> {
+    fn drop(&mut self) {
+        let mut guard = self.lock();
+        guard.senders -= 1;
+        if guard.senders == 0 {
+            guard.close();
+        }
+    }
+}
+
+struct Send<'a, B, F> {
+    handle: &'a SenderHandle<B>,
+    try_send: F,
+    /// The id given to this future when it registered its waker.
+    waiter_id: Option<u64>,
+}
+
+impl<B, T, F> Future for Send<'_, B, F>
+where
+    F: FnMut(&mut Shared<B>) -> Result<T, ChannelSendError> + Unpin,
+{
+    type Output = Result<T, ChannelClosedError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut guard = this.handle.lock();
+        if guard.closed {
+            return Poll::Ready(Err(ChannelClosedError {}));
+        }
+
+        match (this.try_send)(&mut guard) {
+            Ok(sent) => {
+                if let Some(id) = this.waiter_id.take() {
+                    remove(&mut guard.sender_wakers, id);
+                }
+                Poll::Ready(Ok(sent))
+            }
+            Err(ChannelSendError::Closed) => Poll::Ready(Err(ChannelClosedError {})),
+            Err(ChannelSendError::Full) => {
+                this.waiter_id =
+                    Some(guard.register_sender_waker(this.waiter_id, cx.waker().clone()));
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<B, F> Drop for Send<'_, B, F> {
+    fn drop(&mut self) {
+        if let Some(id) = self.waiter_id {
+            self.handle.lock().deregister_sender_waker(id);
+        }
+    }
+}
+
+/// A receiver's handle on a [`Shared`] channel.
+///
+/// Counted in the same way as [`SenderHandle`], closing the channel when the
+/// last receiver is dropped.
+pub(super) struct ReceiverHandle<B> {
+    channel: Arc<Mutex<Shared<B>>>,
+}
+
+impl<B> ReceiverHandle<B> {
+    pub(super) fn new(channel: Arc<Mutex<Shared<B>>>) -> Self {
+        Lock::lock(&*channel).receivers += 1;
+        Self { channel }
+    }
+
+    /// Locks the channel.
+    pub(super) fn lock(&self) -> MutexGuard<'_, Shared<B>> {
+        Lock::lock(&*self.channel)
+    }
+
+    /// Waits until `try_recv` succeeds.
+    ///
+    /// `try_recv` is called with the channel locked each time the receiver is
+    /// polled. While it returns [`ChannelRecvError::Empty`], the receiver
+    /// waits in FIFO order to be woken when a message is available.
+    ///
+    /// This method is cancel safe. If the returned future is dropped while it
+    /// is waiting, it leaves the queue, and any wake up that it had already
+    /// been given is passed on to the next waiting receiver.
+    pub(super) async fn recv<T, F>(&self, try_recv: F) -> Result<T, ChannelClosedError>
+    where
+        F: FnMut(&mut Shared<B>) -> Result<T, ChannelRecvError> + Unpin,
+    {
+        Recv {
+            handle: self,
+            try_recv,
+            waiter_id: None,
+        }
+        .await
+    }
+}
+
+impl<B> Clone for ReceiverHandle<B> {
+    fn clone(&self) -> Self {
+        Self::new(self.channel.clone())
+    }
+}
+
+impl<B> Drop for ReceiverHandle<B> {
+    fn drop(&mut self) {
+        let mut guard = self.lock();
+        guard.receivers -= 1;
+        if guard.receivers == 0 {
+            guard.close();
+        }
+    }
+}
+
+struct Recv<'a, B, F> {
+    handle: &'a ReceiverHandle<B>,
+    try_recv: F,
+    /// The id given to this future when it registered its waker.
+    waiter_id: Option<u64>,
+}
+
+impl<B, T, F> Future for Recv<'_, B, F>
+where
+    F: FnMut(&mut Shared<B>) -> Result<T, ChannelRecvError> + Unpin,
+{
+    type Output = Result<T, ChannelClosedError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut guard = this.handle.lock();
+        match (this.try_recv)(&mut guard) {
+            Ok(received) => {
+                if let Some(id) = this.waiter_id.take() {
+                    remove(&mut guard.receiver_wakers, id);
+                }
+                Poll::Ready(Ok(received))
+            }
+            Err(ChannelRecvError::Closed) => Poll::Ready(Err(ChannelClosedError {})),
+            Err(ChannelRecvError::Empty) => {
+                this.waiter_id =
+                    Some(guard.register_receiver_waker(this.waiter_id, cx.waker().clone()));
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<B, F> Drop for Recv<'_, B, F> {
+    fn drop(&mut self) {
+        if let Some(id) = self.waiter_id {
+            self.handle.lock().deregister_receiver_waker(id);
+        }
+    }
+}
+
+/// Creates the sender and receiver handles for a new channel.
+pub(super) fn handles<B>(buffer: B) -> (SenderHandle<B>, ReceiverHandle<B>) {
+    from_shared(Arc::new(Mutex::new(Shared::new(buffer))))
+}
+
+/// Creates the sender and receiver handles for an existing channel.
+pub(super) fn from_shared<B>(
+    channel: Arc<Mutex<Shared<B>>>,
+) -> (SenderHandle<B>, ReceiverHandle<B>) {
+    (
+        SenderHandle::new(channel.clone()),
+        ReceiverHandle::new(channel),
+    )
+}
+
+/// Adds a waiter to a waker queue, or replaces its waker if it's already
+/// there.
+///
+/// Returns the id of the waiter.
+fn register(
+    queue: &mut VecDeque<(u64, Waker)>,
+    next_id: &mut u64,
+    id: Option<u64>,
+    waker: Waker,
+) -> u64 {
+    if let Some(id) = id {
+        if let Some((_, registered)) = queue.iter_mut().find(|(waiter_id, _)| *waiter_id == id) {
+            *registered = waker;
+            return id;
+        }
+    }
+
+    let id = *next_id;
+    *next_id += 1;
+    queue.push_back((id, waker));
+    id
+}
+
+/// Removes a waiter from a waker queue.
+///
+/// Returns whether the waiter was found in the queue.
+fn remove(queue: &mut VecDeque<(u64, Waker)>, id: u64) -> bool {
+    match queue.iter().position(|(waiter_id, _)| *waiter_id == id) {
+        Some(position) => {
+            queue.remove(position);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::pin::pin;
+
+    use super::{handles, Shared};
+    use crate::mpmc::{ChannelRecvError, ChannelSendError};
+    use crate::testing::{poll_once, MockWaker};
+
+    /// The number of messages the test buffer can hold.
+    const CAPACITY: usize = 1;
+
+    type Buffer = VecDeque<&'static str>;
+
+    fn push(
+        value: &'static str,
+    ) -> impl FnMut(&mut Shared<Buffer>) -> Result<(), ChannelSendError> + Unpin {
+        move |channel| {
+            if channel.buffer.len() < CAPACITY {
+                channel.buffer.push_back(value);
+                channel.wake_next_receiver();
+                Ok(())
+            } else {
+                Err(ChannelSendError::Full)
+            }
+        }
+    }
+
+    fn pop(channel: &mut Shared<Buffer>) -> Result<&'static str, ChannelRecvError> {
+        match channel.buffer.pop_front() {
+            Some(value) => {
+                channel.wake_next_sender();
+                Ok(value)
+            }
+            None if channel.is_closed() => Err(ChannelRecvError::Closed),
+            None => Err(ChannelRecvError::Empty),
+        }
+    }
+
+    #[test]
+    fn repolled_recv_keeps_a_single_place_in_the_queue() {
+        let (_tx, rx) = handles(Buffer::new());
+        let waker = MockWaker::new();
+        let mut recv = pin!(rx.recv(pop));
+        for _ in 0..3 {
+            poll_once(recv.as_mut(), &waker).assert_pending();
+        }
+        assert_eq!(rx.lock().receiver_wakers.len(), 1);
+    }
+
+    #[test]
+    fn cancelled_recv_leaves_the_queue() {
+        let (tx, rx) = handles(Buffer::new());
+        let first_waker = MockWaker::new();
+        let second_waker = MockWaker::new();
+        let mut first = Box::pin(rx.recv(pop));
+        let mut second = pin!(rx.recv(pop));
+        poll_once(first.as_mut(), &first_waker).assert_registered_waker();
+        poll_once(second.as_mut(), &second_waker).assert_registered_waker();
+
+        drop(first);
+        assert_eq!(first_waker.live(), 0);
+        poll_once(pin!(tx.send(push("value"))), &MockWaker::new())
+            .assert_ready()
+            .unwrap();
+
+        // The wake goes to the receiver which is still waiting, not to the
+        // stale waker of the cancelled one.
+        assert_eq!(first_waker.counts().total_wakes(), 0);
+        assert_eq!(second_waker.counts().total_wakes(), 1);
+        assert_eq!(
+            poll_once(second, &second_waker).assert_ready().unwrap(),
+            "value"
+        );
+    }
+
+    #[test]
+    fn cancelled_recv_passes_on_its_wake() {
+        let (tx, rx) = handles(Buffer::new());
+        let first_waker = MockWaker::new();
+        let second_waker = MockWaker::new();
+        let mut first = Box::pin(rx.recv(pop));
+        let mut second = pin!(rx.recv(pop));
+        poll_once(first.as_mut(), &first_waker).assert_registered_waker();
+        poll_once(second.as_mut(), &second_waker).assert_registered_waker();
+
+        poll_once(pin!(tx.send(push("value"))), &MockWaker::new())
+            .assert_ready()
+            .unwrap();
+        assert_eq!(first_waker.counts().total_wakes(), 1);
+        assert_eq!(second_waker.counts().total_wakes(), 0);
+
+        // Dropped before it could take the message it was woken for.
+        drop(first);
+        assert_eq!(second_waker.counts().total_wakes(), 1);
+        assert_eq!(
+            poll_once(second, &second_waker).assert_ready().unwrap(),
+            "value"
+        );
+    }
+
+    #[test]
+    fn cancelled_send_passes_on_its_wake() {
+        let (tx, rx) = handles(Buffer::new());
+        let waker = MockWaker::new();
+        poll_once(pin!(tx.send(push("full"))), &waker)
+            .assert_ready()
+            .unwrap();
+
+        let first_waker = MockWaker::new();
+        let second_waker = MockWaker::new();
+        let mut first = Box::pin(tx.send(push("first")));
+        let mut second = pin!(tx.send(push("second")));
+        poll_once(first.as_mut(), &first_waker).assert_registered_waker();
+        poll_once(second.as_mut(), &second_waker).assert_registered_waker();
+
+        assert_eq!(
+            poll_once(pin!(rx.recv(pop)), &waker)
+                .assert_ready()
+                .unwrap(),
+            "full"
+        );
+        assert_eq!(first_waker.counts().total_wakes(), 1);
+        drop(first);
+        assert_eq!(second_waker.counts().total_wakes(), 1);
+        poll_once(second, &second_waker).assert_ready().unwrap();
+        assert_eq!(
+            poll_once(pin!(rx.recv(pop)), &waker)
+                .assert_ready()
+                .unwrap(),
+            "second"
+        );
+    }
+}
@@ -0,0 +1,630 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Wake, Waker};
+use std::time::{Duration, Instant};
+
+use mpmc_core::Lock;
+
+use super::shared::{self, ReceiverHandle, SenderHandle, Shared};
+use super::{ChannelClosedError, ChannelRecvError, ChannelSendError};
+use crate::time;
+
+/// A source of time for a [`timed::channel`].
+///
+/// The clock tells the channel what time it is and wakes the channel when a
+/// deadline has passed, so that delayed messages can be delivered and expired
+/// messages can be removed.
+///
+/// [`timed::channel`]: fn@channel
+pub trait Clock: std::marker::Send + Sync + 'static {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+
+    /// Wakes `waker` once the current time has reached `deadline`.
+    ///
+    /// This method is called while the channel is locked, so the waker must
+    /// not be woken from within this call, even if the deadline has already
+    /// passed.
+    fn wake_at(&self, deadline: Instant, waker: Waker);
+}
+
+/// A [`Clock`] that uses the system's monotonic clock.
+///
+/// Deadlines are registered with the global [`Timer`], so every channel
+/// shares its driver thread. Like the timer, deadlines have a resolution of
+/// one millisecond.
+///
+/// [`Timer`]: struct@crate::time::Timer
+#[derive(Clone, Default)]
+pub struct SystemClock {}
+
+impl SystemClock {
+    /// Creates a new system clock.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wake_at(&self, deadline: Instant, waker: Waker) {
+        time::global().wake_at(deadline, waker);
+    }
+}
+
+/// A [`Clock`] which only moves when it is told to.
+///
+/// The mock clock starts at the time it was created and stays there until
+/// [`advance`] is called. Any wakers registered for deadlines which have been
+/// reached are woken during the call to `advance`. This makes it possible to
+/// test time dependent behaviour without waiting.
+///
+/// Clones of a mock clock share the same time.
+///
+/// [`advance`]: fn@Self::advance
+#[derive(Clone)]
+pub struct MockClock {
+    inner: Arc<Mutex<MockClockInner>>,
+}
+
+struct MockClockInner {
+    now: Instant,
+    pending: Vec<(Instant, Waker)>,
+}
+
+impl MockClock {
+    /// Creates a new mock clock set to the current time.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(MockClockInner {
+                now: Instant::now(),
+                pending: Vec::new(),
+            })),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    ///
+    /// All wakers registered for a deadline up to and including the new time
+    /// will be woken before this method returns. Advancing by
+    /// `Duration::ZERO` wakes any wakers registered for a time which has
+    /// already been reached.
+    pub fn advance(&self, duration: Duration) {
+        let due = {
+            let Ok(mut guard) = self.inner.lock() else {
+                panic!("MockClock has become corrupted.");
+            };
+            guard.now += duration;
+
+            let now = guard.now;
+            let (due, pending) = guard
+                .pending
+                .drain(..)
+                .partition::<Vec<_>, _>(|(deadline, _)| *deadline <= now);
+            guard.pending = pending;
+            due
+        };
+
+        // Wake outside of the lock, the woken channel may call `now()`.
+        for (_, waker) in due {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        match self.inner.lock() {
+            Ok(guard) => guard.now,
+            Err(_) => panic!("MockClock has become corrupted."),
+        }
+    }
+
+    fn wake_at(&self, deadline: Instant, waker: Waker) {
+        let Ok(mut guard) = self.inner.lock() else {
+            panic!("MockClock has become corrupted.");
+        };
+
+        // Even if the deadline has already passed, the waker will only be
+        // woken on the next call to `advance`.
+        guard.pending.push((deadline, waker));
+    }
+}
+
+/// Creates a new asynchronous bounded multi-producer multi-consumer channel
+/// with support for message expiry and delayed delivery, returning the
+/// sender/receiver halves.
+///
+/// This channel behaves like [`mpmc::channel`], and additionally allows
+/// messages to be sent with a time to live ([`Sender::send_with_ttl`]) or a
+/// delay ([`Sender::send_delayed`]).
+///
+/// A message which isn't received before its time to live has passed expires.
+/// Expired messages are removed from the channel and are never received, the
+/// number of expired messages can be retrieved with
+/// [`Receiver::expired_count`].
+///
+/// A delayed message can't be received until its delay has passed. Until
+/// then, receivers will skip over it. Once it is due, a waiting receiver will
+/// be woken to receive it.
+///
+/// Both expiring and delayed messages take up capacity in the channel until
+/// they are removed.
+///
+/// The channel uses the [`SystemClock`]. Use [`channel_with_clock`] to provide
+/// a different [`Clock`], for example a [`MockClock`] in tests.
+///
+/// [`mpmc::channel`]: fn@super::channel
+pub fn channel(capacity: usize) -> (Sender, Receiver) {
+    channel_with_clock(capacity, SystemClock::new())
+}
+
+/// Creates a new timed channel which uses the given clock.
+///
+/// See [`timed::channel`] for details.
+///
+/// [`timed::channel`]: fn@channel
+pub fn channel_with_clock(capacity: usize, clock: impl Clock) -> (Sender, Receiver) {
+    let clock = Arc::new(clock);
+    let channel = Arc::new_cyclic(|this| {
+        Mutex::new(Shared::new(Buffer {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+
+            clock,
+            this: this.clone(),
+            armed: None,
+            expired: 0,
+        }))
+    });
+    let (tx, rx) = shared::from_shared(channel);
+
+    (Sender { channel: tx }, Receiver { channel: rx })
+}
+
+/// The sending-half of the [`timed::channel`] type.
+///
+/// Messages can be sent through the channel with [`send`], [`send_with_ttl`]
+/// or [`send_delayed`].
+///
+/// This half can be cloned to send from multiple tasks. Dropping all senders
+/// will cause the channel to be closed.
+///
+/// [`timed::channel`]: fn@channel
+/// [`send`]: fn@Self::send
+/// [`send_with_ttl`]: fn@Self::send_with_ttl
+/// [`send_delayed`]: fn@Self::send_delayed
+#[derive(Clone)]
+pub struct Sender {
+    channel: SenderHandle<Buffer>,
+}
+
+impl Sender {
+    /// Sends a value which never expires, waiting until there is capacity.
+    ///
+    /// A successful send occurs when there is at least one [`Receiver`] still
+    /// connected to the channel. An `Err` result means that the value will
+    /// never be received, however an `Ok` result doesn't guarantee that the
+    /// value will be received as all receivers may disconnect immediately
+    /// after this method returns `Ok`.
+    pub async fn send(&self, value: String) -> Result<(), ChannelClosedError> {
+        self.send_timed(value, None, None).await
+    }
+
+    /// Sends a value which expires if it isn't received within `ttl`, waiting
+    /// until there is capacity.
+    ///
+    /// The time to live starts once the value has been added to the channel
+    /// buffer, time spent waiting for capacity is not counted.
+    pub async fn send_with_ttl(
+        &self,
+        value: String,
+        ttl: Duration,
+    ) -> Result<(), ChannelClosedError> {
+        self.send_timed(value, None, Some(ttl)).await
+    }
+
+    /// Sends a value which can't be received until `delay` has passed,
+    /// waiting until there is capacity.
+    ///
+    /// The delay starts once the value has been added to the channel buffer,
+    /// time spent waiting for capacity is not counted. A delayed value takes
+    /// up capacity in the channel while it waits.
+    pub async fn send_delayed(
+        &self,
+        value: String,
+        delay: Duration,
+    ) -> Result<(), ChannelClosedError> {
+        self.send_timed(value, Some(delay), None).await
+    }
+
+    async fn send_timed(
+        &self,
+        value: String,
+        delay: Option<Duration>,
+        ttl: Option<Duration>,
+    ) -> Result<(), ChannelClosedError> {
+        self.channel
+            .send(|channel| {
+                let result = channel.send(value.clone(), delay, ttl);
+                // Sending may have delivered or expired messages, which could
+                // change when the channel next needs to wake up.
+                channel.arm_timer();
+                result
+            })
+            .await
+    }
+}
+
+/// The receiving-half of the [`timed::channel`] type.
+///
+/// Messages can be received from the channel with [`recv`].
+///
+/// This half can be cloned to receive from multiple tasks. Each message will
+/// only be received by a single receiver. Dropping all receivers will cause
+/// the channel to be closed.
+///
+/// [`timed::channel`]: fn@channel
+/// [`recv`]: fn@Self::recv
+#[derive(Clone)]
+pub struct Receiver {
+    channel: ReceiverHandle<Buffer>,
+}
+
+impl Receiver {
+    /// Receives a value, waiting until one is available.
+    ///
+    /// Expired values are skipped and delayed values are only returned once
+    /// they are due.
+    ///
+    /// Once the channel is closed (by dropping all senders), this method will
+    /// continue to return the remaining values stored in the channel buffer,
+    /// including waiting for delayed values to become due. Once the channel is
+    /// empty, this method will return [`ChannelClosedError`].
+    pub async fn recv(&self) -> Result<String, ChannelClosedError> {
+        self.channel
+            .recv(|channel| {
+                let result = channel.recv();
+                channel.arm_timer();
+                result
+            })
+            .await
+    }
+
+    /// Returns the number of messages which have expired in this channel.
+    ///
+    /// Messages are counted when they are removed from the channel, which
+    /// happens whenever the channel is accessed after they expire.
+    pub fn expired_count(&self) -> u64 {
+        let mut guard = self.channel.lock();
+        guard.remove_expired();
+        guard.buffer.expired
+    }
+}
+
+/// Wakes the channel when its timer fires.
+///
+/// A single timer is registered with the clock for the earliest time at which
+/// a delayed message becomes due or a message expires. When it fires, the
+/// channel wakes the waiters which can now make progress and arms the timer
+/// again for the next deadline.
+struct TimerWaker {
+    channel: Weak<Mutex<Shared<Buffer>>>,
+}
+
+impl Wake for TimerWaker {
+    fn wake(self: Arc<Self>) {
+        let Some(channel) = self.channel.upgrade() else {
+            // All senders and receivers are gone, nobody to wake.
+            return;
+        };
+        let mut guard = Lock::lock(&*channel);
+
+        guard.buffer.armed = None;
+        guard.on_timer();
+        guard.arm_timer();
+    }
+}
+
+/// A message stored in the timed channel buffer.
+struct Entry {
+    value: String,
+    /// The message can't be received before this time.
+    due_at: Option<Instant>,
+    /// The message expires at this time.
+    expires_at: Option<Instant>,
+}
+
+/// The timed channel's message buffer.
+struct Buffer {
+    /// The messages, in the order they were sent.
+    entries: VecDeque<Entry>,
+    /// The capacity of the channel, this many messages can be buffered before
+    /// sending will error.
+    capacity: usize,
+
+    /// The source of time for delays and expiry.
+    clock: Arc<dyn Clock>,
+    /// The channel this buffer belongs to, for the timer to wake.
+    this: Weak<Mutex<Shared<Buffer>>>,
+    /// The deadline for which a timer has been registered with the clock.
+    armed: Option<Instant>,
+    /// The number of messages which have expired.
+    expired: u64,
+}
+
+/// The inner timed channel implementation.
+impl Shared<Buffer> {
+    /// Sends a message across the channel.
+    ///
+    /// Expired messages are removed first, so that they don't take up
+    /// capacity. If the message can be sent and is not delayed, the next
+    /// receiver waker in the queue (if any) will be woken as there is now an
+    /// additional message which can be received.
+    ///
+    /// An error will be returned if the channel is full.
+    fn send(
+        &mut self,
+        value: String,
+        delay: Option<Duration>,
+        ttl: Option<Duration>,
+    ) -> Result<(), ChannelSendError> {
+        self.remove_expired();
+        let buffer = &mut self.buffer;
+        if buffer.entries.len() < buffer.capacity {
+            let now = buffer.clock.now();
+            buffer.entries.push_back(Entry {
+                value,
+                due_at: delay.map(|delay| now + delay),
+                expires_at: ttl.map(|ttl| now + ttl),
+            });
+            if delay.is_none() {
+                self.wake_next_receiver();
+            }
+            Ok(())
+        } else {
+            Err(ChannelSendError::Full)
+        }
+    }
+
+    /// Receives the oldest message which is due from the channel.
+    ///
+    /// Expired messages are removed first and delayed messages which aren't
+    /// yet due are skipped. If a message can be received, then the next sender
+    /// waker in the queue (if any) will be woken as there is now additional
+    /// free capacity to send another message.
+    ///
+    /// An error will be returned if there is no message due. The error will
+    /// be `Closed` only if the channel is both closed and empty, a closed
+    /// channel which still contains delayed messages will return `Empty`.
+    fn recv(&mut self) -> Result<String, ChannelRecvError> {
+        self.remove_expired();
+
+        let closed = self.is_closed();
+        let buffer = &mut self.buffer;
+        let now = buffer.clock.now();
+        let position = buffer
+            .entries
+            .iter()
+            .position(|entry| entry.due_at.is_none_or(|due_at| due_at <= now));
+
+        match position.and_then(|position| buffer.entries.remove(position)) {
+            Some(entry) => {
+                self.wake_next_sender();
+                Ok(entry.value)
+            }
+            None => {
+                if !closed || !buffer.entries.is_empty() {
+                    Err(ChannelRecvError::Empty)
+                } else {
+                    Err(ChannelRecvError::Closed)
+                }
+            }
+        }
+    }
+
+    /// Removes all expired messages from the buffer.
+    ///
+    /// A sender is woken for each message removed, as capacity has been freed.
+    fn remove_expired(&mut self) {
+        let buffer = &mut self.buffer;
+        let now = buffer.clock.now();
+        let before = buffer.entries.len();
+        buffer
+            .entries
+            .retain(|entry| entry.expires_at.is_none_or(|expires_at| expires_at > now));
+
+        let removed = before - buffer.entries.len();
+        buffer.expired += removed as u64;
+        for _ in 0..removed {
+            self.wake_next_sender();
+        }
+    }
+
+    /// Handles the channel timer firing.
+    ///
+    /// Expired messages are removed (waking senders) and a receiver is woken
+    /// for each message which is now due. If the channel is closed and the
+    /// last messages have expired, all receivers are woken so that they can
+    /// see that the channel is closed.
+    fn on_timer(&mut self) {
+        self.remove_expired();
+        if self.is_closed() && self.buffer.entries.is_empty() {
+            self.wake_all_receivers();
+            return;
+        }
+
+        let now = self.buffer.clock.now();
+        let due = self
+            .buffer
+            .entries
+            .iter()
+            .filter(|entry| entry.due_at.is_none_or(|due_at| due_at <= now))
+            .count();
+        for _ in 0..due {
+            self.wake_next_receiver();
+        }
+    }
+
+    /// Registers a timer with the clock for the next deadline, if needed.
+    ///
+    /// The next deadline is the earliest time at which a delayed message will
+    /// become due or a message will expire. A new timer is only registered if
+    /// there isn't already one armed for that time or earlier.
+    fn arm_timer(&mut self) {
+        let buffer = &mut self.buffer;
+        let now = buffer.clock.now();
+        let next = buffer
+            .entries
+            .iter()
+            .flat_map(|entry| [entry.due_at, entry.expires_at])
+            .flatten()
+            .filter(|deadline| *deadline > now)
+            .min();
+
+        let Some(next) = next else {
+            return;
+        };
+        if buffer.armed.is_some_and(|armed| armed <= next) {
+            return;
+        }
+
+        buffer.armed = Some(next);
+        let waker = Waker::from(Arc::new(TimerWaker {
+            channel: buffer.this.clone(),
+        }));
+        buffer.clock.wake_at(next, waker);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+    use std::time::{Duration, Instant};
+
+    use super::{channel, channel_with_clock, MockClock};
+    use crate::runtime;
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn delayed_message_is_received_once_due() {
+        let clock = MockClock::new();
+        let (tx, rx) = channel_with_clock(4, clock.clone());
+        let waker = MockWaker::new();
+
+        let send = pin!(tx.send_delayed("later".into(), Duration::from_secs(1)));
+        poll_once(send, &waker).assert_ready().unwrap();
+
+        let mut recv = pin!(rx.recv());
+        poll_once(recv.as_mut(), &waker).assert_registered_waker();
+
+        clock.advance(Duration::from_millis(999));
+        assert_eq!(waker.counts().total_wakes(), 0);
+
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(waker.counts().total_wakes(), 1);
+        assert_eq!(poll_once(recv, &waker).assert_ready().unwrap(), "later");
+    }
+
+    #[test]
+    fn delayed_message_is_skipped_until_due() {
+        let clock = MockClock::new();
+        let (tx, rx) = channel_with_clock(4, clock.clone());
+        let waker = MockWaker::new();
+
+        poll_once(
+            pin!(tx.send_delayed("later".into(), Duration::from_secs(1))),
+            &waker,
+        )
+        .assert_ready()
+        .unwrap();
+        poll_once(pin!(tx.send("now".into())), &waker)
+            .assert_ready()
+            .unwrap();
+
+        let value = poll_once(pin!(rx.recv()), &waker).assert_ready().unwrap();
+        assert_eq!(value, "now");
+
+        clock.advance(Duration::from_secs(1));
+        let value = poll_once(pin!(rx.recv()), &waker).assert_ready().unwrap();
+        assert_eq!(value, "later");
+    }
+
+    #[test]
+    fn expired_message_is_never_received() {
+        let clock = MockClock::new();
+        let (tx, rx) = channel_with_clock(4, clock.clone());
+        let waker = MockWaker::new();
+
+        let send = pin!(tx.send_with_ttl("stale".into(), Duration::from_secs(1)));
+        poll_once(send, &waker).assert_ready().unwrap();
+        clock.advance(Duration::from_secs(1));
+
+        poll_once(pin!(rx.recv()), &waker).assert_pending();
+        assert_eq!(rx.expired_count(), 1);
+    }
+
+    #[test]
+    fn expiry_frees_capacity_for_a_waiting_sender() {
+        let clock = MockClock::new();
+        let (tx, rx) = channel_with_clock(1, clock.clone());
+        let waker = MockWaker::new();
+
+        let send = pin!(tx.send_with_ttl("stale".into(), Duration::from_secs(1)));
+        poll_once(send, &waker).assert_ready().unwrap();
+        let mut send = pin!(tx.send("fresh".into()));
+        poll_once(send.as_mut(), &waker).assert_registered_waker();
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(waker.counts().total_wakes(), 1);
+        poll_once(send, &waker).assert_ready().unwrap();
+
+        let value = poll_once(pin!(rx.recv()), &waker).assert_ready().unwrap();
+        assert_eq!(value, "fresh");
+        assert_eq!(rx.expired_count(), 1);
+    }
+
+    #[test]
+    fn closed_channel_waits_for_delayed_messages() {
+        let clock = MockClock::new();
+        let (tx, rx) = channel_with_clock(4, clock.clone());
+        let waker = MockWaker::new();
+
+        {
+            let send = pin!(tx.send_delayed("last".into(), Duration::from_secs(1)));
+            poll_once(send, &waker).assert_ready().unwrap();
+        }
+        drop(tx);
+
+        let mut recv = pin!(rx.recv());
+        poll_once(recv.as_mut(), &waker).assert_registered_waker();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(poll_once(recv, &waker).assert_ready().unwrap(), "last");
+
+        poll_once(pin!(rx.recv()), &waker)
+            .assert_ready()
+            .unwrap_err();
+    }
+
+    #[test]
+    fn system_clock_wakes_receiver() {
+        let (tx, rx) = channel(4);
+        let start = Instant::now();
+        runtime::block_on(async {
+            tx.send_delayed("later".into(), Duration::from_millis(20))
+                .await
+                .unwrap();
+            assert_eq!(rx.recv().await.unwrap(), "later");
+        });
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
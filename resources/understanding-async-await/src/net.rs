@@ -0,0 +1,19 @@
+//! TCP and UDP sockets driven by an `epoll` reactor, for Linux.
+//!
+//! Each socket is registered with a single, process wide reactor, which waits
+//! for readiness events on a driver thread and wakes the task waiting to read
+//! or write. The sockets don't depend on any particular executor, they work
+//! with our own [`runtime`] as well as with tokio.
+//!
+//! Sockets are registered as edge-triggered, `epoll` only tells us when a
+//! socket goes from not ready to ready. So the reactor remembers that a socket
+//! is ready until an operation on it returns `WouldBlock`, and only then waits
+//! for the next event.
+//!
+//! [`runtime`]: mod@crate::runtime
+mod reactor;
+mod tcp;
+mod udp;
+
+pub use tcp::{TcpListener, TcpStream};
+pub use udp::UdpSocket;
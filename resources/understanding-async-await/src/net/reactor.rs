@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// The maximum number of events returned by a single call to `epoll_wait`.
+const MAX_EVENTS: usize = 64;
+
+/// Whether a task is waiting to read or to write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Interest {
+    Read,
+    Write,
+}
+
+/// Waits for readiness events on file descriptors and wakes the tasks which
+/// are interested in them.
+///
+/// There is a single reactor per process. Its driver thread blocks in
+/// `epoll_wait` and wakes tasks from there, so the sockets work with any
+/// executor.
+struct Reactor {
+    epoll: OwnedFd,
+    registrations: Mutex<Registrations>,
+}
+
+struct Registrations {
+    /// The readiness of each registered file descriptor, by token.
+    io: HashMap<u64, Arc<ScheduledIo>>,
+    /// The token which will be given to the next registration.
+    next_token: u64,
+}
+
+impl Reactor {
+    fn get() -> &'static Reactor {
+        static REACTOR: OnceLock<Reactor> = OnceLock::new();
+        REACTOR.get_or_init(|| {
+            let reactor = Reactor::new().expect("failed to create epoll instance");
+            thread::Builder::new()
+                .name("io-driver".into())
+                .spawn(|| Reactor::get().drive())
+                .expect("failed to spawn I/O driver thread");
+            reactor
+        })
+    }
+
+    fn new() -> io::Result<Self> {
+        // SAFETY: `epoll_create1` has no preconditions, the returned file
+        // descriptor is checked before it is owned.
+        let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epoll < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            // SAFETY: `epoll` is a new, valid file descriptor which nothing
+            // else owns.
+            epoll: unsafe { OwnedFd::from_raw_fd(epoll) },
+            registrations: Mutex::new(Registrations {
+                io: HashMap::new(),
+                next_token: 0,
+            }),
+        })
+    }
+
+    /// The body of the driver thread.
+    fn drive(&self) {
+        let mut events = vec![libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        let mut wakers = Vec::new();
+        loop {
+            // SAFETY: `events` has room for `MAX_EVENTS` events.
+            let n = unsafe {
+                libc::epoll_wait(
+                    self.epoll.as_raw_fd(),
+                    events.as_mut_ptr(),
+                    MAX_EVENTS as i32,
+                    -1,
+                )
+            };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                panic!("epoll_wait failed: {err}");
+            }
+
+            {
+                let registrations = self.lock_registrations();
+                for event in &events[..n as usize] {
+                    // The file descriptor may have been deregistered since
+                    // the event was returned.
+                    if let Some(io) = registrations.io.get(&{ event.u64 }) {
+                        io.set_readiness(event.events, &mut wakers);
+                    }
+                }
+            }
+            // Wake outside of the lock, the woken task may be polled straight
+            // away on another thread and deregister its socket.
+            for waker in wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+
+    fn lock_registrations(&self) -> MutexGuard<'_, Registrations> {
+        match self.registrations.lock() {
+            Ok(guard) => guard,
+            Err(_) => panic!("Reactor state has become corrupted."),
+        }
+    }
+}
+
+/// The readiness of a registered file descriptor.
+struct ScheduledIo {
+    state: Mutex<IoState>,
+}
+
+#[derive(Default)]
+struct IoState {
+    read: Direction,
+    write: Direction,
+}
+
+/// The readiness in one direction, read or write.
+///
+/// The file descriptor is registered as edge-triggered, so `epoll` only
+/// reports a change from not ready to ready. We therefore remember that it's
+/// ready until an operation returns `WouldBlock`.
+#[derive(Default)]
+struct Direction {
+    ready: bool,
+    /// Incremented on every readiness event.
+    ///
+    /// An event may arrive between an operation returning `WouldBlock` and
+    /// the readiness being cleared. Clearing only happens if no event has
+    /// arrived since the readiness was observed, otherwise the event would
+    /// be lost and the task would never be woken.
+    tick: u64,
+    waker: Option<Waker>,
+}
+
+impl ScheduledIo {
+    fn set_readiness(&self, events: u32, wakers: &mut Vec<Waker>) {
+        let events = events as i32;
+        let mut state = self.lock_state();
+        if events & (libc::EPOLLIN | libc::EPOLLRDHUP | libc::EPOLLHUP | libc::EPOLLERR) != 0 {
+            state.read.set_ready(wakers);
+        }
+        if events & (libc::EPOLLOUT | libc::EPOLLHUP | libc::EPOLLERR) != 0 {
+            state.write.set_ready(wakers);
+        }
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, IoState> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(_) => panic!("Reactor state has become corrupted."),
+        }
+    }
+}
+
+impl IoState {
+    fn direction(&mut self, interest: Interest) -> &mut Direction {
+        match interest {
+            Interest::Read => &mut self.read,
+            Interest::Write => &mut self.write,
+        }
+    }
+}
+
+impl Direction {
+    fn set_ready(&mut self, wakers: &mut Vec<Waker>) {
+        self.ready = true;
+        self.tick = self.tick.wrapping_add(1);
+        wakers.extend(self.waker.take());
+    }
+}
+
+/// A file descriptor registered with the reactor.
+///
+/// Only one task can wait for each interest at a time, if two tasks read from
+/// the same socket concurrently, only the last one to poll will be woken.
+pub(super) struct Registration {
+    fd: RawFd,
+    token: u64,
+    io: Arc<ScheduledIo>,
+}
+
+impl Registration {
+    /// Registers `fd` for both read and write readiness.
+    ///
+    /// The file descriptor must stay open until the registration has been
+    /// dropped.
+    pub(super) fn new(fd: RawFd) -> io::Result<Self> {
+        let reactor = Reactor::get();
+        let io = Arc::new(ScheduledIo {
+            state: Mutex::new(IoState::default()),
+        });
+        let token = {
+            let mut registrations = reactor.lock_registrations();
+            let token = registrations.next_token;
+            registrations.next_token += 1;
+            registrations.io.insert(token, Arc::clone(&io));
+            token
+        };
+
+        let mut event = libc::epoll_event {
+            events: (libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLRDHUP | libc::EPOLLET) as u32,
+            u64: token,
+        };
+        // SAFETY: `event` is a valid epoll event and both file descriptors
+        // are open.
+        let result = unsafe {
+            libc::epoll_ctl(
+                reactor.epoll.as_raw_fd(),
+                libc::EPOLL_CTL_ADD,
+                fd,
+                &mut event,
+            )
+        };
+        if result < 0 {
+            let err = io::Error::last_os_error();
+            reactor.lock_registrations().io.remove(&token);
+            return Err(err);
+        }
+
+        Ok(Self { fd, token, io })
+    }
+
+    /// Polls for readiness.
+    ///
+    /// Returns the tick of the readiness, to pass to [`clear_readiness`] if
+    /// the operation would block.
+    ///
+    /// [`clear_readiness`]: fn@Self::clear_readiness
+    pub(super) fn poll_ready(&self, interest: Interest, cx: &mut Context<'_>) -> Poll<u64> {
+        let mut state = self.io.lock_state();
+        let direction = state.direction(interest);
+        if direction.ready {
+            return Poll::Ready(direction.tick);
+        }
+
+        match &mut direction.waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            waker => *waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+
+    /// Clears the readiness after an operation returned `WouldBlock`, unless
+    /// a new readiness event has arrived since `tick`.
+    pub(super) fn clear_readiness(&self, interest: Interest, tick: u64) {
+        let mut state = self.io.lock_state();
+        let direction = state.direction(interest);
+        if direction.tick == tick {
+            direction.ready = false;
+        }
+    }
+
+    /// Performs a non-blocking operation once the file descriptor is ready,
+    /// retrying whenever it would block.
+    pub(super) fn poll_io<R>(
+        &self,
+        interest: Interest,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut() -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        loop {
+            let Poll::Ready(tick) = self.poll_ready(interest, cx) else {
+                return Poll::Pending;
+            };
+            match op() {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    // Go round again, to either pick up a new event or
+                    // register our waker.
+                    self.clear_readiness(interest, tick);
+                }
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let reactor = Reactor::get();
+        // SAFETY: The file descriptor is still open, a null event is allowed
+        // for `EPOLL_CTL_DEL`. Any error is ignored, there is nothing we
+        // could do about it.
+        unsafe {
+            libc::epoll_ctl(
+                reactor.epoll.as_raw_fd(),
+                libc::EPOLL_CTL_DEL,
+                self.fd,
+                std::ptr::null_mut(),
+            );
+        }
+        reactor.lock_registrations().io.remove(&self.token);
+    }
+}
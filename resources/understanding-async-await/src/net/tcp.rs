@@ -0,0 +1,388 @@
+use std::fmt;
+use std::future::poll_fn;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::{self, Shutdown, SocketAddr, ToSocketAddrs};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::task::{Context, Poll};
+
+use super::reactor::{Interest, Registration};
+
+/// A TCP socket server, listening for connections.
+pub struct TcpListener {
+    // Declared before the socket, so that it's deregistered before the file
+    // descriptor is closed.
+    registration: Registration,
+    inner: net::TcpListener,
+}
+
+impl TcpListener {
+    /// Creates a new listener bound to `addr`.
+    ///
+    /// Binding doesn't block, so this isn't an async function. Resolving
+    /// `addr` may block though, so pass an IP address rather than a host
+    /// name.
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::from_std(net::TcpListener::bind(addr)?)
+    }
+
+    /// Creates a new listener from a standard library listener.
+    ///
+    /// The listener is put into non-blocking mode.
+    pub fn from_std(listener: net::TcpListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            registration: Registration::new(listener.as_raw_fd())?,
+            inner: listener,
+        })
+    }
+
+    /// Returns the address that this listener is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Accepts a new incoming connection.
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    /// Polls to accept a new incoming connection.
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(TcpStream, SocketAddr)>> {
+        let (stream, addr) = match self
+            .registration
+            .poll_io(Interest::Read, cx, || self.inner.accept())
+        {
+            Poll::Ready(Ok(accepted)) => accepted,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+        Poll::Ready(TcpStream::from_std(stream).map(|stream| (stream, addr)))
+    }
+}
+
+impl fmt::Debug for TcpListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpListener")
+            .field("local_addr", &self.inner.local_addr().ok())
+            .finish()
+    }
+}
+
+/// A TCP stream between a local and a remote socket.
+///
+/// Reading and writing take `&self`, in the style of `AsyncRead` and
+/// `AsyncWrite`, so one task can read from a stream while another writes to
+/// it.
+pub struct TcpStream {
+    // Declared before the socket, so that it's deregistered before the file
+    // descriptor is closed.
+    registration: Registration,
+    inner: net::TcpStream,
+}
+
+impl TcpStream {
+    /// Opens a TCP connection to `addr`.
+    ///
+    /// The connection is started without blocking, the returned future
+    /// completes once the socket becomes writable, which is when the
+    /// connection has been established (or has failed).
+    pub async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let stream = Self::from_std(connect_nonblocking(addr)?)?;
+        poll_fn(|cx| stream.registration.poll_ready(Interest::Write, cx)).await;
+        match stream.inner.take_error()? {
+            Some(err) => Err(err),
+            None => Ok(stream),
+        }
+    }
+
+    /// Creates a new stream from a standard library stream.
+    ///
+    /// The stream is put into non-blocking mode.
+    pub fn from_std(stream: net::TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            registration: Registration::new(stream.as_raw_fd())?,
+            inner: stream,
+        })
+    }
+
+    /// Returns the local address of this stream.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Returns the remote address of this stream.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    /// Reads some bytes into `buf`, returning how many were read.
+    ///
+    /// Returns `Ok(0)` once the remote end has shut down writing.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_fn(|cx| self.poll_read(cx, buf)).await
+    }
+
+    /// Polls to read some bytes into `buf`.
+    pub fn poll_read(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.registration
+            .poll_io(Interest::Read, cx, || (&self.inner).read(buf))
+    }
+
+    /// Writes some bytes from `buf`, returning how many were written.
+    pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        poll_fn(|cx| self.poll_write(cx, buf)).await
+    }
+
+    /// Polls to write some bytes from `buf`.
+    pub fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.registration
+            .poll_io(Interest::Write, cx, || (&self.inner).write(buf))
+    }
+
+    /// Writes all of `buf`.
+    pub async fn write_all(&self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Shuts down the read half, the write half or both halves of the
+    /// connection.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+}
+
+impl fmt::Debug for TcpStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpStream")
+            .field("local_addr", &self.inner.local_addr().ok())
+            .field("peer_addr", &self.inner.peer_addr().ok())
+            .finish()
+    }
+}
+
+/// Creates a non-blocking socket and starts connecting it to `addr`.
+///
+/// The standard library can only connect a socket in blocking mode, so we
+/// create the socket ourselves.
+fn connect_nonblocking(addr: SocketAddr) -> io::Result<net::TcpStream> {
+    let domain = match addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    // SAFETY: `socket` has no preconditions, the returned file descriptor is
+    // checked before it is owned.
+    let fd = unsafe {
+        libc::socket(
+            domain,
+            libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            0,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `fd` is a new, valid file descriptor which nothing else owns.
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let (storage, len) = sockaddr(addr);
+    // SAFETY: `storage` holds a socket address of length `len`.
+    let result = unsafe {
+        libc::connect(
+            socket.as_raw_fd(),
+            (&storage as *const libc::sockaddr_storage).cast(),
+            len,
+        )
+    };
+    if result < 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EINPROGRESS) {
+            return Err(err);
+        }
+    }
+
+    Ok(net::TcpStream::from(socket))
+}
+
+/// Converts a socket address to its C representation.
+fn sockaddr(addr: SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    // SAFETY: All zeros is a valid `sockaddr_storage`.
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let sin = libc::sockaddr_in {
+                sin_family: libc::AF_INET as libc::sa_family_t,
+                sin_port: addr.port().to_be(),
+                sin_addr: libc::in_addr {
+                    s_addr: u32::from_ne_bytes(addr.ip().octets()),
+                },
+                sin_zero: [0; 8],
+            };
+            // SAFETY: `sockaddr_storage` is large enough and suitably aligned
+            // for any socket address.
+            unsafe {
+                (&mut storage as *mut libc::sockaddr_storage)
+                    .cast::<libc::sockaddr_in>()
+                    .write(sin)
+            };
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = libc::sockaddr_in6 {
+                sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                sin6_port: addr.port().to_be(),
+                sin6_flowinfo: addr.flowinfo(),
+                sin6_addr: libc::in6_addr {
+                    s6_addr: addr.ip().octets(),
+                },
+                sin6_scope_id: addr.scope_id(),
+            };
+            // SAFETY: As above.
+            unsafe {
+                (&mut storage as *mut libc::sockaddr_storage)
+                    .cast::<libc::sockaddr_in6>()
+                    .write(sin6)
+            };
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+
+    (storage, len as libc::socklen_t)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Shutdown;
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{TcpListener, TcpStream};
+    use crate::runtime::{self, Runtime};
+    use crate::testing::MockWaker;
+
+    /// Connects a client to a new listener on a free loopback port, returning
+    /// the client and the accepted server side.
+    async fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = TcpStream::connect(addr).await.unwrap();
+        let (server, peer) = listener.accept().await.unwrap();
+        assert_eq!(peer, client.local_addr().unwrap());
+        (client, server)
+    }
+
+    #[test]
+    fn round_trip() {
+        Runtime::multi_thread(2).block_on(async {
+            let (client, server) = connected_pair().await;
+            let echo = runtime::spawn(async move {
+                let mut buf = [0; 64];
+                loop {
+                    let n = server.read(&mut buf).await?;
+                    if n == 0 {
+                        return Ok::<_, std::io::Error>(());
+                    }
+                    server.write_all(&buf[..n]).await?;
+                }
+            });
+
+            client.write_all(b"hello").await.unwrap();
+            let mut buf = [0; 5];
+            let mut read = 0;
+            while read < buf.len() {
+                read += client.read(&mut buf[read..]).await.unwrap();
+            }
+            assert_eq!(&buf, b"hello");
+
+            client.shutdown(Shutdown::Write).unwrap();
+            echo.await.unwrap().unwrap();
+            assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn large_message_round_trip() {
+        Runtime::multi_thread(2).block_on(async {
+            let (client, server) = connected_pair().await;
+            let client = Arc::new(client);
+            let message: Vec<u8> = (0..4_000_000).map(|i| (i % 251) as u8).collect();
+
+            // Neither side reads until the other has started writing, so both
+            // have to wait for readiness along the way.
+            let writer = runtime::spawn({
+                let client = Arc::clone(&client);
+                let message = message.clone();
+                async move {
+                    client.write_all(&message).await?;
+                    client.shutdown(Shutdown::Write)
+                }
+            });
+
+            let mut received = Vec::new();
+            let mut buf = [0; 4096];
+            loop {
+                let n = server.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n]);
+            }
+            writer.await.unwrap().unwrap();
+            assert!(received == message);
+        });
+    }
+
+    #[test]
+    fn write_waits_for_readiness_after_would_block() {
+        let runtime = Runtime::current_thread();
+        let (client, server) = runtime.block_on(connected_pair());
+
+        // Write without reading on the other side until the socket buffers
+        // are full and the write returns `WouldBlock`.
+        let waker = MockWaker::new();
+        let chunk = vec![7; 64 * 1024];
+        let mut written = 0;
+        loop {
+            let waker = waker.waker();
+            match client.poll_write(&mut Context::from_waker(&waker), &chunk) {
+                Poll::Ready(n) => written += n.unwrap(),
+                Poll::Pending => break,
+            }
+        }
+        assert!(written > 0);
+        assert_eq!(waker.live(), 1, "the waker wasn't registered");
+
+        // Reading on the other side frees up space, which is a new readiness
+        // event for the writer.
+        let mut buf = vec![0; 64 * 1024];
+        let mut read = 0;
+        runtime.block_on(async {
+            while read < written {
+                read += server.read(&mut buf).await.unwrap();
+            }
+        });
+        let start = Instant::now();
+        while waker.counts().total_wakes() == 0 {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "writer wasn't woken"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let waker = waker.waker();
+        match client.poll_write(&mut Context::from_waker(&waker), &chunk) {
+            Poll::Ready(n) => assert!(n.unwrap() > 0),
+            Poll::Pending => panic!("write still pending after being woken"),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+use std::future::poll_fn;
+use std::io;
+use std::net::{self, SocketAddr, ToSocketAddrs};
+use std::os::fd::AsRawFd;
+use std::task::{Context, Poll};
+
+use super::reactor::{Interest, Registration};
+
+/// A UDP socket.
+///
+/// Sending and receiving take `&self`, so the socket can be shared between
+/// tasks, for example in an `Arc`.
+pub struct UdpSocket {
+    // Declared before the socket, so that it's deregistered before the file
+    // descriptor is closed.
+    registration: Registration,
+    inner: net::UdpSocket,
+}
+
+impl UdpSocket {
+    /// Creates a new socket bound to `addr`.
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::from_std(net::UdpSocket::bind(addr)?)
+    }
+
+    /// Creates a new socket from a standard library socket.
+    ///
+    /// The socket is put into non-blocking mode.
+    pub fn from_std(socket: net::UdpSocket) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            registration: Registration::new(socket.as_raw_fd())?,
+            inner: socket,
+        })
+    }
+
+    /// Returns the address that this socket is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Sets the remote address which [`send`] sends to and which [`recv`]
+    /// receives from.
+    ///
+    /// [`send`]: fn@Self::send
+    /// [`recv`]: fn@Self::recv
+    pub fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        self.inner.connect(addr)
+    }
+
+    /// Sends a datagram to `target`, returning the number of bytes sent.
+    pub async fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        poll_fn(|cx| self.poll_send_to(cx, buf, target)).await
+    }
+
+    /// Polls to send a datagram to `target`.
+    pub fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        self.registration
+            .poll_io(Interest::Write, cx, || self.inner.send_to(buf, target))
+    }
+
+    /// Receives a datagram, returning the number of bytes read and the
+    /// address it came from.
+    ///
+    /// If `buf` is too small for the datagram, the rest is discarded.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        poll_fn(|cx| self.poll_recv_from(cx, buf)).await
+    }
+
+    /// Polls to receive a datagram.
+    pub fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        self.registration
+            .poll_io(Interest::Read, cx, || self.inner.recv_from(buf))
+    }
+
+    /// Sends a datagram to the connected address.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        poll_fn(|cx| {
+            self.registration
+                .poll_io(Interest::Write, cx, || self.inner.send(buf))
+        })
+        .await
+    }
+
+    /// Receives a datagram from the connected address.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_fn(|cx| {
+            self.registration
+                .poll_io(Interest::Read, cx, || self.inner.recv(buf))
+        })
+        .await
+    }
+}
+
+impl fmt::Debug for UdpSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpSocket")
+            .field("local_addr", &self.inner.local_addr().ok())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UdpSocket;
+    use crate::runtime::{self, Runtime};
+
+    #[test]
+    fn round_trip() {
+        Runtime::multi_thread(2).block_on(async {
+            let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let server_addr = server.local_addr().unwrap();
+            let echo = runtime::spawn(async move {
+                let mut buf = [0; 64];
+                for _ in 0..3 {
+                    let (n, peer) = server.recv_from(&mut buf).await?;
+                    server.send_to(&buf[..n], peer).await?;
+                }
+                Ok::<_, std::io::Error>(())
+            });
+
+            let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+            client.connect(server_addr).unwrap();
+            let mut buf = [0; 64];
+            for message in ["ping", "pong", "bye"] {
+                client.send(message.as_bytes()).await.unwrap();
+                let n = client.recv(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], message.as_bytes());
+            }
+            echo.await.unwrap().unwrap();
+        });
+    }
+}
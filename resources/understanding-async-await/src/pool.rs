@@ -0,0 +1,392 @@
+use std::any::Any;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::mpmc;
+
+/// A job handler which can be shared between workers.
+type Handler = Arc<dyn Fn(String) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// A pool of worker tasks which process jobs received from an
+/// [`mpmc::Receiver`].
+///
+/// Each worker is a tokio task which receives a job from the channel, runs
+/// the handler on it, and then goes back for the next job. Jobs are sent to
+/// the pool with the [`mpmc::Sender`] half of the channel.
+///
+/// A job which panics doesn't take its worker down with it. The panic is
+/// caught, counted in the worker's [`WorkerStats`], and the worker carries on
+/// with the next job.
+///
+/// The number of workers can be changed with [`resize`]. To stop the pool,
+/// call [`shutdown`], which closes the channel so that no more jobs are
+/// accepted, waits for the jobs already in the channel to be processed, and
+/// then joins the workers. Jobs are processed even if the pool has no
+/// workers left at that point.
+///
+/// [`resize`]: fn@Self::resize
+/// [`shutdown`]: fn@Self::shutdown
+pub struct WorkerPool {
+    /// Each worker receives with a clone of this receiver.
+    rx: mpmc::Receiver,
+    handler: Handler,
+    workers: Vec<Worker>,
+    /// The final statistics of the workers which have been removed by
+    /// `resize`.
+    removed: Vec<WorkerStats>,
+    /// The id which will be given to the next worker spawned.
+    next_id: usize,
+}
+
+struct Worker {
+    /// Notified when this worker should stop.
+    stop: Arc<Notify>,
+    stats: Arc<Counters>,
+    handle: JoinHandle<()>,
+}
+
+/// Statistics for a single worker in a [`WorkerPool`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerStats {
+    /// The id of the worker, unique within its pool.
+    pub id: usize,
+    /// The number of jobs which completed successfully.
+    pub completed: u64,
+    /// The number of jobs which panicked.
+    pub panicked: u64,
+    /// Whether the worker is currently running a job.
+    pub busy: bool,
+}
+
+/// The live counters behind a [`WorkerStats`].
+#[derive(Debug)]
+struct Counters {
+    id: usize,
+    completed: AtomicU64,
+    panicked: AtomicU64,
+    busy: AtomicBool,
+}
+
+impl Counters {
+    fn snapshot(&self) -> WorkerStats {
+        WorkerStats {
+            id: self.id,
+            completed: self.completed.load(Ordering::Relaxed),
+            panicked: self.panicked.load(Ordering::Relaxed),
+            busy: self.busy.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl WorkerPool {
+    /// Creates a new pool and spawns `workers` workers which receive jobs from
+    /// `rx` and pass them to `handler`.
+    ///
+    /// This function must be called from within a tokio runtime.
+    pub fn new<F, Fut>(rx: mpmc::Receiver, workers: usize, handler: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |job| Box::pin(handler(job)));
+        let mut pool = Self {
+            rx,
+            handler,
+            workers: Vec::new(),
+            removed: Vec::new(),
+            next_id: 0,
+        };
+        for _ in 0..workers {
+            pool.spawn_worker();
+        }
+
+        pool
+    }
+
+    /// Returns the number of workers in the pool.
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Changes the number of workers in the pool.
+    ///
+    /// New workers are spawned straight away. When shrinking the pool, the
+    /// most recently spawned workers are stopped. A worker which is running a
+    /// job finishes it before stopping, this method waits until all the
+    /// removed workers have stopped.
+    pub async fn resize(&mut self, workers: usize) {
+        while self.workers.len() < workers {
+            self.spawn_worker();
+        }
+
+        if self.workers.len() > workers {
+            let removed = self.workers.split_off(workers);
+            for worker in &removed {
+                worker.stop.notify_one();
+            }
+            for worker in removed {
+                _ = worker.handle.await;
+                self.removed.push(worker.stats.snapshot());
+            }
+        }
+    }
+
+    /// Returns statistics for each worker which has been in the pool, ordered
+    /// by id.
+    ///
+    /// Workers which have been removed by [`resize`] keep their final
+    /// statistics, so that no completed or panicked jobs go missing from the
+    /// totals.
+    ///
+    /// [`resize`]: fn@Self::resize
+    pub fn stats(&self) -> Vec<WorkerStats> {
+        let mut stats = self.removed.clone();
+        stats.extend(self.workers.iter().map(|worker| worker.stats.snapshot()));
+        stats.sort_by_key(|stats| stats.id);
+        stats
+    }
+
+    /// Shuts the pool down gracefully.
+    ///
+    /// The channel is closed, so any further attempts to send jobs will fail.
+    /// The workers carry on until every job already in the channel has been
+    /// processed and are then joined. If the pool has been resized to no
+    /// workers, a worker is spawned to process the remaining jobs.
+    ///
+    /// Returns the final statistics for each worker, as for [`stats`].
+    ///
+    /// [`stats`]: fn@Self::stats
+    pub async fn shutdown(mut self) -> Vec<WorkerStats> {
+        self.rx.close();
+        if self.workers.is_empty() {
+            self.spawn_worker();
+        }
+
+        for worker in std::mem::take(&mut self.workers) {
+            _ = worker.handle.await;
+            self.removed.push(worker.stats.snapshot());
+        }
+
+        self.stats()
+    }
+
+    fn spawn_worker(&mut self) {
+        let stop = Arc::new(Notify::new());
+        let stats = Arc::new(Counters {
+            id: self.next_id,
+            completed: AtomicU64::new(0),
+            panicked: AtomicU64::new(0),
+            busy: AtomicBool::new(false),
+        });
+        self.next_id += 1;
+
+        let handle = tokio::spawn(run_worker(
+            self.rx.clone(),
+            Arc::clone(&self.handler),
+            Arc::clone(&stop),
+            Arc::clone(&stats),
+        ));
+
+        self.workers.push(Worker {
+            stop,
+            stats,
+            handle,
+        });
+    }
+}
+
+/// The body of a worker task.
+///
+/// Receives and runs jobs until the channel is closed and empty, or until the
+/// worker is told to stop.
+async fn run_worker(rx: mpmc::Receiver, handler: Handler, stop: Arc<Notify>, stats: Arc<Counters>) {
+    loop {
+        // Receiving is cancel safe, so if we're told to stop while waiting
+        // for a job, no job will be lost.
+        let job = tokio::select! {
+            biased;
+            _ = stop.notified() => break,
+            job = rx.recv() => job,
+        };
+        let Ok(job) = job else {
+            break;
+        };
+
+        stats.busy.store(true, Ordering::Relaxed);
+        // The handler is called inside the future, so that a panic while
+        // creating the job's future is caught as well.
+        let handler = Arc::clone(&handler);
+        match CatchUnwind::new(async move { handler(job).await }).await {
+            Ok(()) => stats.completed.fetch_add(1, Ordering::Relaxed),
+            Err(_) => stats.panicked.fetch_add(1, Ordering::Relaxed),
+        };
+        stats.busy.store(false, Ordering::Relaxed);
+    }
+}
+
+/// A future which catches a panic in the future it wraps.
+///
+/// Each call to the inner future's `poll` is made inside
+/// [`std::panic::catch_unwind`]. If it panics, this future returns the panic
+/// payload as an error, instead of the panic unwinding through the worker.
+struct CatchUnwind<F> {
+    future: Pin<Box<F>>,
+}
+
+impl<F> CatchUnwind<F> {
+    fn new(future: F) -> Self {
+        Self {
+            future: Box::pin(future),
+        }
+    }
+}
+
+impl<F> Future for CatchUnwind<F>
+where
+    F: Future,
+{
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The future is never polled again after it panics, so we don't need
+        // to worry about it being left in an inconsistent state.
+        let future = &mut self.future;
+        match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::sync::oneshot;
+
+    use super::{WorkerPool, WorkerStats};
+    use crate::mpmc;
+
+    /// A handler which records each job, and panics on a job named "panic".
+    fn recording_pool(rx: mpmc::Receiver, workers: usize) -> (WorkerPool, Arc<Mutex<Vec<String>>>) {
+        let done = Arc::new(Mutex::new(Vec::new()));
+        let record = Arc::clone(&done);
+        let pool = WorkerPool::new(rx, workers, move |job: String| {
+            let record = Arc::clone(&record);
+            async move {
+                tokio::task::yield_now().await;
+                if job == "panic" {
+                    panic!("job panicked");
+                }
+                record.lock().unwrap().push(job);
+            }
+        });
+        (pool, done)
+    }
+
+    fn totals(stats: &[WorkerStats]) -> (u64, u64) {
+        stats.iter().fold((0, 0), |(completed, panicked), stats| {
+            (completed + stats.completed, panicked + stats.panicked)
+        })
+    }
+
+    async fn send_jobs(tx: &mpmc::Sender, jobs: &[&str]) {
+        for job in jobs {
+            tx.send(job.to_string()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn panicking_job_doesnt_stop_its_worker() {
+        let (tx, rx) = mpmc::channel(8);
+        let (pool, done) = recording_pool(rx, 1);
+        send_jobs(&tx, &["a", "panic", "b", "panic", "c"]).await;
+
+        let stats = pool.shutdown().await;
+        assert_eq!(stats.len(), 1);
+        assert_eq!(totals(&stats), (3, 2));
+        assert_eq!(*done.lock().unwrap(), ["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn resize_grows_and_shrinks_the_pool() {
+        let (tx, rx) = mpmc::channel(8);
+        let (mut pool, done) = recording_pool(rx, 1);
+        pool.resize(4).await;
+        assert_eq!(pool.size(), 4);
+        send_jobs(&tx, &["a", "b", "c", "d"]).await;
+
+        pool.resize(1).await;
+        assert_eq!(pool.size(), 1);
+        // The removed workers keep their statistics.
+        let ids: Vec<usize> = pool.stats().iter().map(|stats| stats.id).collect();
+        assert_eq!(ids, [0, 1, 2, 3]);
+
+        let stats = pool.shutdown().await;
+        assert_eq!(totals(&stats), (4, 0));
+        assert_eq!(done.lock().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn shrinking_waits_for_a_running_job() {
+        let (tx, rx) = mpmc::channel(8);
+        let (started_tx, started_rx) = oneshot::channel();
+        let (finish_tx, finish_rx) = oneshot::channel::<()>();
+        let gates = Arc::new(Mutex::new(Some((started_tx, finish_rx))));
+        let mut pool = WorkerPool::new(rx, 1, move |_job| {
+            let gates = gates.lock().unwrap().take();
+            async move {
+                if let Some((started, finish)) = gates {
+                    _ = started.send(());
+                    _ = finish.await;
+                }
+            }
+        });
+        send_jobs(&tx, &["slow"]).await;
+        started_rx.await.unwrap();
+        assert!(pool.stats()[0].busy);
+
+        let resize = tokio::spawn(async move {
+            pool.resize(0).await;
+            pool
+        });
+        tokio::task::yield_now().await;
+        assert!(!resize.is_finished());
+
+        finish_tx.send(()).unwrap();
+        let pool = resize.await.unwrap();
+        assert_eq!(pool.size(), 0);
+        assert_eq!(totals(&pool.stats()), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn shutdown_drains_jobs_without_workers() {
+        let (tx, rx) = mpmc::channel(8);
+        let (mut pool, done) = recording_pool(rx, 2);
+        pool.resize(0).await;
+        send_jobs(&tx, &["a", "b", "c"]).await;
+
+        let stats = pool.shutdown().await;
+        assert_eq!(totals(&stats), (3, 0));
+        assert_eq!(*done.lock().unwrap(), ["a", "b", "c"]);
+        assert!(tx.send("late".into()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn shutdown_drains_a_pool_created_empty() {
+        let (tx, rx) = mpmc::channel(8);
+        let (pool, done) = recording_pool(rx, 0);
+        send_jobs(&tx, &["a", "b"]).await;
+
+        pool.shutdown().await;
+        assert_eq!(*done.lock().unwrap(), ["a", "b"]);
+    }
+}
@@ -0,0 +1,431 @@
+//! A minimal async runtime, so that the examples can be run without tokio.
+//!
+//! This runtime is built for reading, not for speed. It has a single run
+//! queue behind a mutex, no I/O or timers, and wakers which are built from a
+//! hand-written [`RawWakerVTable`].
+//!
+//! There are two flavours of runtime:
+//!
+//! - [`Runtime::current_thread`] polls spawned tasks on the thread which
+//!   calls [`Runtime::block_on`], in between polls of the future it's
+//!   blocking on. A spawned task won't be polled until the task which
+//!   spawned it yields.
+//! - [`Runtime::multi_thread`] starts a number of worker threads which poll
+//!   spawned tasks in parallel, while the thread calling `block_on` only
+//!   polls the future it's blocking on.
+//!
+//! For exploring how tasks are polled and woken, a [`Stepper`] runs tasks
+//! one poll at a time, printing their `Debug` state and the waker activity
+//! in between.
+//!
+//! [`RawWakerVTable`]: std::task::RawWakerVTable
+mod step;
+mod task;
+mod waker;
+
+pub use step::{Command, ParseCommandError, Stepper};
+pub use task::{JoinError, JoinHandle};
+
+use std::any;
+use std::cell::RefCell;
+use std::future::Future;
+use std::panic::Location;
+use std::pin::{pin, Pin};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+
+use crate::future::DetectLostWakeups;
+use task::{Flavor, Shared};
+use waker::ThreadWaker;
+
+/// Runs a future to completion on a new current-thread runtime.
+///
+/// The calling thread is parked whenever there is nothing to do, and
+/// unparked by the waker when the future (or a spawned task) is woken.
+#[track_caller]
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    Runtime::current_thread().block_on(future)
+}
+
+/// Spawns a task on the runtime which is running on this thread.
+///
+/// # Panics
+///
+/// Panics if called from outside of a runtime, that is, not from within
+/// [`block_on`] or a task.
+#[track_caller]
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    // Spawn outside of the closure, so that the task records our caller as
+    // the location it was spawned from.
+    match CONTEXT.with(|context| context.borrow().clone()) {
+        Some(shared) => shared.spawn(future),
+        None => panic!("spawn must be called from within an understanding_async_await runtime"),
+    }
+}
+
+/// Yields back to the runtime once.
+///
+/// The returned future wakes its own task and returns `Poll::Pending` the
+/// first time it's polled, so other tasks get a chance to run before it is
+/// polled again and completes.
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+/// The future returned by [`yield_now`].
+#[derive(Debug)]
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+thread_local! {
+    /// The runtime which [`spawn`] spawns onto from this thread.
+    static CONTEXT: RefCell<Option<Arc<Shared>>> = const { RefCell::new(None) };
+}
+
+/// Sets the runtime for [`spawn`] on this thread, restoring the previous one
+/// when dropped.
+struct EnterGuard {
+    previous: Option<Arc<Shared>>,
+}
+
+impl EnterGuard {
+    fn enter(shared: &Arc<Shared>) -> Self {
+        let previous = CONTEXT.with(|context| context.replace(Some(Arc::clone(shared))));
+        Self { previous }
+    }
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        CONTEXT.with(|context| *context.borrow_mut() = self.previous.take());
+    }
+}
+
+/// An async runtime.
+///
+/// Create one with [`current_thread`] or [`multi_thread`], then run a future
+/// on it with [`block_on`]. Dropping the runtime shuts it down: worker threads
+/// are stopped and tasks which haven't completed are dropped.
+///
+/// [`current_thread`]: fn@Self::current_thread
+/// [`multi_thread`]: fn@Self::multi_thread
+/// [`block_on`]: fn@Self::block_on
+pub struct Runtime {
+    shared: Arc<Shared>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl Runtime {
+    /// Creates a runtime which polls all tasks on the thread which calls
+    /// [`block_on`].
+    ///
+    /// Spawned tasks are only polled while `block_on` is running.
+    ///
+    /// [`block_on`]: fn@Self::block_on
+    pub fn current_thread() -> Self {
+        Self {
+            shared: Arc::new(Shared::new(Flavor::CurrentThread)),
+            workers: Vec::new(),
+        }
+    }
+
+    /// Creates a runtime with `workers` worker threads which poll spawned
+    /// tasks.
+    ///
+    /// A runtime with 0 workers gets 1.
+    pub fn multi_thread(workers: usize) -> Self {
+        let shared = Arc::new(Shared::new(Flavor::MultiThread));
+        let workers = (0..workers.max(1))
+            .map(|index| {
+                let shared = Arc::clone(&shared);
+                thread::Builder::new()
+                    .name(format!("runtime-worker-{index}"))
+                    .spawn(move || {
+                        let _guard = EnterGuard::enter(&shared);
+                        while let Some(task) = shared.wait_for_task() {
+                            task.run();
+                        }
+                    })
+                    .expect("failed to spawn worker thread")
+            })
+            .collect();
+
+        Self { shared, workers }
+    }
+
+    /// Reports futures which can never be woken again.
+    ///
+    /// The future passed to [`block_on`] and every task spawned from now on
+    /// are wrapped in [`DetectLostWakeups`]. A warning naming the future's
+    /// type and where it was spawned is logged with `tracing` when it
+    /// returns `Pending` without keeping or waking its waker, or when every
+    /// clone of its waker is dropped while it's waiting. Without this, such a
+    /// task just never runs again.
+    ///
+    /// [`block_on`]: fn@Self::block_on
+    pub fn detect_lost_wakeups(self) -> Self {
+        self.shared.set_detect_lost_wakeups();
+        self
+    }
+
+    /// Spawns a task onto this runtime.
+    ///
+    /// On a current-thread runtime, the task won't be polled until
+    /// [`block_on`] is called.
+    ///
+    /// [`block_on`]: fn@Self::block_on
+    #[track_caller]
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.shared.spawn(future)
+    }
+
+    /// Runs a future to completion, blocking the current thread.
+    ///
+    /// The thread is parked while there is nothing to do. On a current-thread
+    /// runtime it also polls spawned tasks while the future is waiting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread is already in `block_on` on the same
+    /// current-thread runtime.
+    #[track_caller]
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        if self.shared.detects_lost_wakeups() {
+            let type_name = any::type_name::<F>();
+            let future = DetectLostWakeups::with_location(future, type_name, Location::caller());
+            self.run(pin!(future))
+        } else {
+            self.run(pin!(future))
+        }
+    }
+
+    fn run<F: Future>(&self, mut future: Pin<&mut F>) -> F::Output {
+        let _guard = EnterGuard::enter(&self.shared);
+        let thread_waker = ThreadWaker::current();
+        let waker = waker::waker(Arc::clone(&thread_waker));
+        let mut cx = Context::from_waker(&waker);
+
+        if self.workers.is_empty() {
+            if !self.shared.set_driver(Some(thread::current())) {
+                panic!("a current-thread runtime can only be driven by one thread at a time");
+            }
+            let _driver = DriverGuard {
+                shared: &self.shared,
+            };
+
+            loop {
+                if thread_waker.take_woken() {
+                    if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                        return output;
+                    }
+                }
+
+                // Poll the tasks which are queued now, but not tasks which
+                // are queued while we do, so that the future we're blocking
+                // on gets a turn in between.
+                let mut budget = self.shared.queue_len();
+                while budget > 0 {
+                    let Some(task) = self.shared.next_task() else {
+                        break;
+                    };
+                    task.run();
+                    budget -= 1;
+                }
+
+                if !thread_waker.is_woken() && self.shared.is_idle() {
+                    thread::park();
+                }
+            }
+        } else {
+            loop {
+                if thread_waker.take_woken() {
+                    if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                        return output;
+                    }
+                }
+                if !thread_waker.is_woken() {
+                    thread::park();
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        self.shared.shutdown();
+        for worker in self.workers.drain(..) {
+            // A worker only panics if the runtime itself is broken, tasks'
+            // panics are caught.
+            _ = worker.join();
+        }
+        self.shared.cancel_all();
+    }
+}
+
+/// Clears the driver thread when `block_on` returns (or unwinds).
+struct DriverGuard<'a> {
+    shared: &'a Shared,
+}
+
+impl Drop for DriverGuard<'_> {
+    fn drop(&mut self) {
+        self.shared.set_driver(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+
+    use super::{spawn, yield_now, JoinError, JoinHandle, Runtime};
+    use crate::testing::{poll_once, MockWaker};
+
+    /// Sets its flag when dropped.
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// A task which never completes, and sets the returned flag once its
+    /// future has been dropped.
+    fn never_completes() -> (impl future::Future<Output = ()> + Send, Arc<AtomicBool>) {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = DropFlag(Arc::clone(&dropped));
+        let future = async move {
+            let _flag = flag;
+            future::pending::<()>().await;
+        };
+        (future, dropped)
+    }
+
+    #[test]
+    fn spawned_tasks_return_their_output() {
+        for runtime in [Runtime::current_thread(), Runtime::multi_thread(2)] {
+            let sum = runtime.block_on(async {
+                let handles: Vec<_> = (0..10).map(|n| spawn(async move { n * 2 })).collect();
+                let mut sum = 0;
+                for handle in handles {
+                    sum += handle.await.unwrap();
+                }
+                sum
+            });
+            assert_eq!(sum, 90);
+        }
+    }
+
+    #[test]
+    fn current_thread_polls_spawned_tasks_once_main_task_yields() {
+        let runtime = Runtime::current_thread();
+        runtime.block_on(async {
+            let ran = Arc::new(AtomicBool::new(false));
+            let handle = spawn({
+                let ran = Arc::clone(&ran);
+                async move { ran.store(true, Ordering::SeqCst) }
+            });
+            assert!(!ran.load(Ordering::SeqCst));
+
+            yield_now().await;
+            assert!(ran.load(Ordering::SeqCst));
+            assert!(handle.is_finished());
+        });
+    }
+
+    #[test]
+    fn aborted_task_is_dropped_and_cancelled() {
+        let runtime = Runtime::current_thread();
+        let (future, dropped) = never_completes();
+        let handle = runtime.spawn(future);
+
+        handle.abort();
+        let result = runtime.block_on(handle);
+        assert!(matches!(result, Err(JoinError::Cancelled)));
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn panic_is_returned_to_join_handle() {
+        let runtime = Runtime::multi_thread(1);
+        let handle = runtime.spawn(async { panic!("task failed") });
+
+        match runtime.block_on(handle) {
+            Err(JoinError::Panicked(payload)) => {
+                assert_eq!(payload.downcast_ref::<&str>(), Some(&"task failed"));
+            }
+            result => panic!("expected a panic, got {result:?}"),
+        }
+        // The worker thread survived the panic.
+        assert_eq!(runtime.block_on(runtime.spawn(async { 1 })).unwrap(), 1);
+    }
+
+    #[test]
+    fn shutdown_cancels_unfinished_tasks() {
+        for runtime in [Runtime::current_thread(), Runtime::multi_thread(2)] {
+            let (future, dropped) = never_completes();
+            let handle = runtime.spawn(future);
+            runtime.block_on(yield_now());
+            assert!(!handle.is_finished());
+
+            drop(runtime);
+            assert!(dropped.load(Ordering::SeqCst));
+            assert!(handle.is_finished());
+            let waker = MockWaker::new();
+            let result = poll_once(pin!(handle), &waker).assert_ready();
+            assert!(matches!(result, Err(JoinError::Cancelled)));
+        }
+    }
+
+    #[test]
+    fn task_can_check_whether_it_has_finished() {
+        let runtime = Runtime::current_thread();
+        let own_handle = Arc::new(Mutex::new(None::<JoinHandle<()>>));
+        let (tx, rx) = mpsc::channel();
+        let handle = runtime.spawn({
+            let own_handle = Arc::clone(&own_handle);
+            async move {
+                let finished = own_handle
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map(JoinHandle::is_finished);
+                tx.send(finished).unwrap();
+            }
+        });
+        *own_handle.lock().unwrap() = Some(handle);
+
+        runtime.block_on(yield_now());
+        assert_eq!(rx.try_recv(), Ok(Some(false)));
+        let handle = own_handle.lock().unwrap().take().unwrap();
+        assert!(handle.is_finished());
+    }
+}
@@ -0,0 +1,664 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io::{self, BufRead, Write};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use super::waker::{self, Wakeable};
+
+/// How often a stepper with nothing to poll checks whether its tasks can
+/// still be woken.
+const WAIT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// What a [`Stepper`] does when it pauses before a poll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Poll the next task, then pause again.
+    Step,
+    /// Poll the remaining tasks without pausing, still printing each step.
+    Continue,
+    /// Stop without polling any more tasks.
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = ParseCommandError;
+
+    /// Parses `s`, `step` or an empty line as [`Step`], `c` or `continue` as
+    /// [`Continue`] and `q` or `quit` as [`Quit`].
+    ///
+    /// [`Step`]: Command::Step
+    /// [`Continue`]: Command::Continue
+    /// [`Quit`]: Command::Quit
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" | "s" | "step" => Ok(Self::Step),
+            "c" | "continue" => Ok(Self::Continue),
+            "q" | "quit" => Ok(Self::Quit),
+            _ => Err(ParseCommandError {}),
+        }
+    }
+}
+
+/// Error returned when a [`Command`] can't be parsed.
+#[derive(Debug)]
+pub struct ParseCommandError {}
+
+impl fmt::Display for ParseCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown command, expected step, continue or quit")
+    }
+}
+impl Error for ParseCommandError {}
+
+/// A single-threaded executor which pauses before each poll, to step through
+/// what the tasks do.
+///
+/// Before each poll, the stepper prints the run queue, the `Debug` state of
+/// every task, which shows where each hand-written state machine is up to,
+/// and the waker activity since the previous step: which tasks were woken,
+/// and how many wakers each task has handed out and not yet dropped. It then
+/// waits for a [`Command`], either from standard input, see
+/// [`interactive`], or from a script, see [`scripted`]. The trace goes to
+/// standard output unless another writer is given with [`with_output`].
+///
+/// Tasks must implement `Debug`, so are usually hand-written futures. They
+/// are polled on the thread calling [`run`], but may be woken from any
+/// thread, for example by a timer. Tasks can't spawn other tasks.
+///
+/// [`interactive`]: fn@Self::interactive
+/// [`scripted`]: fn@Self::scripted
+/// [`with_output`]: fn@Self::with_output
+/// [`run`]: fn@Self::run
+pub struct Stepper {
+    shared: Arc<StepShared>,
+    tasks: BTreeMap<u64, StepTask>,
+    next_id: u64,
+    /// The number of polls so far.
+    steps: u64,
+    /// Whether to pause before the next poll, cleared by `Continue`.
+    paused: bool,
+    /// The remaining commands, `None` to read them from standard input.
+    script: Option<VecDeque<Command>>,
+    /// Where the trace is written.
+    output: Box<dyn Write>,
+}
+
+struct StepShared {
+    state: Mutex<StepState>,
+    /// Notified when a task is queued, `run` waits on this when there is
+    /// nothing to poll.
+    condvar: Condvar,
+}
+
+struct StepState {
+    /// The ids of the tasks which have been woken and are waiting to be
+    /// polled.
+    queue: VecDeque<u64>,
+    /// What has happened to the wakers since the last step.
+    activity: Vec<String>,
+    /// The task being polled, if any.
+    polling: Option<u64>,
+}
+
+struct StepTask {
+    future: Pin<Box<dyn StepFuture>>,
+    type_name: String,
+    waker: Arc<TaskWaker>,
+    /// The number of wakers the task held at the previous step.
+    wakers: usize,
+}
+
+/// A task's future, which reports its output with `Debug`.
+trait StepFuture: Future<Output = String> + fmt::Debug {}
+
+impl<F: Future<Output = String> + fmt::Debug> StepFuture for F {}
+
+/// Wraps a spawned future, formatting its output and forwarding `Debug`.
+struct Spawned<F> {
+    future: Pin<Box<F>>,
+}
+
+impl<F> Future for Spawned<F>
+where
+    F: Future,
+    F::Output: fmt::Debug,
+{
+    type Output = String;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.future
+            .as_mut()
+            .poll(cx)
+            .map(|output| format!("{output:?}"))
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for Spawned<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.future.fmt(f)
+    }
+}
+
+impl Stepper {
+    /// Creates a stepper which reads a command from standard input each
+    /// time it pauses.
+    ///
+    /// An empty line steps, `c` continues and `q` quits. If standard input
+    /// is closed, the stepper continues.
+    pub fn interactive() -> Self {
+        Self::new(None)
+    }
+
+    /// Creates a stepper which takes its commands from `commands`.
+    ///
+    /// The commands are echoed as they are used. Once they run out, the
+    /// stepper continues.
+    pub fn scripted(commands: impl IntoIterator<Item = Command>) -> Self {
+        Self::new(Some(commands.into_iter().collect()))
+    }
+
+    fn new(script: Option<VecDeque<Command>>) -> Self {
+        Self {
+            shared: Arc::new(StepShared {
+                state: Mutex::new(StepState {
+                    queue: VecDeque::new(),
+                    activity: Vec::new(),
+                    polling: None,
+                }),
+                condvar: Condvar::new(),
+            }),
+            tasks: BTreeMap::new(),
+            next_id: 1,
+            steps: 0,
+            paused: true,
+            script,
+            output: Box::new(io::stdout()),
+        }
+    }
+
+    /// Writes the trace, and the prompt of an interactive stepper, to
+    /// `output` instead of standard output.
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Adds a task, which is queued to be polled. Returns its id, which is
+    /// used to refer to it in the trace.
+    pub fn spawn<F>(&mut self, future: F) -> u64
+    where
+        F: Future + fmt::Debug + 'static,
+        F::Output: fmt::Debug,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        let waker = Arc::new(TaskWaker {
+            id,
+            scheduled: AtomicBool::new(true),
+            shared: Arc::clone(&self.shared),
+        });
+        self.tasks.insert(
+            id,
+            StepTask {
+                future: Box::pin(Spawned {
+                    future: Box::pin(future),
+                }),
+                type_name: short_type_name(std::any::type_name::<F>()),
+                waker,
+                wakers: 0,
+            },
+        );
+        self.shared.lock_state().queue.push_back(id);
+        id
+    }
+
+    /// Polls the tasks one step at a time until they have all completed.
+    ///
+    /// Returns `false` if it stopped early, because of [`Command::Quit`] or
+    /// because none of the remaining tasks could ever be woken.
+    pub fn run(&mut self) -> bool {
+        loop {
+            let Some(id) = self.next_task() else {
+                return self.tasks.is_empty();
+            };
+
+            self.steps += 1;
+            self.print_step(id);
+            if self.paused {
+                match self.next_command() {
+                    Command::Step => {}
+                    Command::Continue => self.paused = false,
+                    Command::Quit => {
+                        _ = writeln!(self.output, "quit with {} task(s) left", self.tasks.len());
+                        return false;
+                    }
+                }
+            }
+            self.poll(id);
+        }
+    }
+
+    /// Takes the next task from the run queue, waiting for one to be woken
+    /// if it's empty.
+    ///
+    /// Returns `None` once there are no tasks left, or when none of them can
+    /// ever be woken.
+    fn next_task(&mut self) -> Option<u64> {
+        let mut state = self.shared.lock_state();
+        loop {
+            if let Some(id) = state.queue.pop_front() {
+                // A task woken during the poll in which it completed is still
+                // queued, skip it rather than counting a step.
+                if self.tasks.contains_key(&id) {
+                    return Some(id);
+                }
+                continue;
+            }
+            if self.tasks.is_empty() {
+                _ = writeln!(
+                    self.output,
+                    "all tasks completed after {} step(s)",
+                    self.steps
+                );
+                return None;
+            }
+            // A waker is woken with the lock held, so if no wakers are left
+            // now, nothing can queue a task any more.
+            if self.tasks.values().all(|task| task.live_wakers() == 0) {
+                _ = writeln!(
+                    self.output,
+                    "no task is queued and no task holds a waker, so none can be woken:"
+                );
+                for (id, task) in &self.tasks {
+                    _ = writeln!(self.output, "  #{id} {}: {:?}", task.type_name, task.future);
+                }
+                return None;
+            }
+
+            _ = writeln!(self.output, "waiting for a task to be woken...");
+            state = match self.shared.condvar.wait_timeout(state, WAIT_TIMEOUT) {
+                Ok((state, _)) => state,
+                Err(_) => panic!("Stepper state has become corrupted."),
+            };
+        }
+    }
+
+    fn print_step(&mut self, id: u64) {
+        let (queue, activity) = {
+            let mut state = self.shared.lock_state();
+            (
+                state.queue.iter().copied().collect::<Vec<_>>(),
+                std::mem::take(&mut state.activity),
+            )
+        };
+
+        _ = writeln!(self.output, "── step {}: poll task #{id} ──", self.steps);
+        let queue: Vec<_> = queue.iter().map(|id| format!("#{id}")).collect();
+        _ = writeln!(self.output, "run queue after it: [{}]", queue.join(", "));
+
+        _ = writeln!(self.output, "tasks:");
+        for (task_id, task) in &self.tasks {
+            let marker = if *task_id == id { ">" } else { " " };
+            _ = writeln!(
+                self.output,
+                "{marker} #{task_id} {}: {:?} (wakers held: {})",
+                task.type_name,
+                task.future,
+                task.live_wakers()
+            );
+        }
+
+        _ = writeln!(self.output, "waker activity since the last step:");
+        let mut quiet = activity.is_empty();
+        for line in &activity {
+            _ = writeln!(self.output, "  {line}");
+        }
+        for (task_id, task) in &mut self.tasks {
+            let wakers = task.live_wakers();
+            if wakers != task.wakers {
+                _ = writeln!(
+                    self.output,
+                    "  task #{task_id} holds {wakers} waker(s), {} before",
+                    task.wakers
+                );
+                task.wakers = wakers;
+                quiet = false;
+            }
+        }
+        if quiet {
+            _ = writeln!(self.output, "  none");
+        }
+    }
+
+    fn next_command(&mut self) -> Command {
+        if let Some(script) = &mut self.script {
+            let command = script.pop_front().unwrap_or(Command::Continue);
+            _ = writeln!(self.output, "> {command:?}");
+            return command;
+        }
+
+        let stdin = io::stdin();
+        loop {
+            _ = write!(self.output, "[enter] step, [c]ontinue, [q]uit > ");
+            _ = self.output.flush();
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    _ = writeln!(self.output);
+                    return Command::Continue;
+                }
+                Ok(_) => {}
+            }
+            match line.parse() {
+                Ok(command) => return command,
+                Err(err) => _ = writeln!(self.output, "{err}"),
+            }
+        }
+    }
+
+    fn poll(&mut self, id: u64) {
+        let Some(task) = self.tasks.get_mut(&id) else {
+            return;
+        };
+
+        // Clear the flag before polling, so that a wake during the poll queues
+        // the task again.
+        task.waker.scheduled.store(false, Ordering::Release);
+        self.shared.lock_state().polling = Some(id);
+        let poll = {
+            let waker = waker::waker(Arc::clone(&task.waker));
+            let mut cx = Context::from_waker(&waker);
+            task.future.as_mut().poll(&mut cx)
+        };
+        self.shared.lock_state().polling = None;
+
+        match poll {
+            Poll::Ready(output) => {
+                _ = writeln!(self.output, "task #{id} completed with {output}\n");
+                self.tasks.remove(&id);
+            }
+            Poll::Pending => {
+                if !task.waker.scheduled.load(Ordering::Acquire) && task.live_wakers() == 0 {
+                    _ = writeln!(
+                        self.output,
+                        "task #{id} returned Pending without keeping or waking its waker, \
+                         it will never be polled again\n"
+                    );
+                } else {
+                    _ = writeln!(self.output, "task #{id} returned Pending\n");
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Stepper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stepper")
+            .field("tasks", &self.tasks.len())
+            .field("steps", &self.steps)
+            .field("paused", &self.paused)
+            .finish()
+    }
+}
+
+impl StepShared {
+    fn lock_state(&self) -> MutexGuard<'_, StepState> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(_) => panic!("Stepper state has become corrupted."),
+        }
+    }
+}
+
+impl StepTask {
+    /// Returns the number of wakers for this task which exist, not counting
+    /// the stepper's own reference.
+    fn live_wakers(&self) -> usize {
+        Arc::strong_count(&self.waker) - 1
+    }
+}
+
+/// The waker of a task on a [`Stepper`], which records each wake.
+struct TaskWaker {
+    id: u64,
+    /// Whether the task is in the run queue, so that it isn't queued twice.
+    scheduled: AtomicBool,
+    shared: Arc<StepShared>,
+}
+
+impl Wakeable for TaskWaker {
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut state = self.shared.lock_state();
+        let by = match state.polling {
+            Some(id) if id == self.id => "by itself".to_string(),
+            Some(id) => format!("while polling task #{id}"),
+            None => "from outside of a poll".to_string(),
+        };
+        if self.scheduled.swap(true, Ordering::AcqRel) {
+            state
+                .activity
+                .push(format!("task #{} woken {by}, already queued", self.id));
+        } else {
+            state.activity.push(format!("task #{} woken {by}", self.id));
+            state.queue.push_back(self.id);
+            self.shared.condvar.notify_one();
+        }
+    }
+}
+
+/// Shortens a type name by removing the module paths, so that
+/// `two_step_async::manual_future::TripleAdd` becomes `TripleAdd`.
+fn short_type_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    // Where the current path segment starts in `short`.
+    let mut segment_start = 0;
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            short.truncate(segment_start);
+            continue;
+        }
+        short.push(c);
+        if !(c.is_alphanumeric() || c == '_') {
+            segment_start = short.len();
+        }
+    }
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{self, Future};
+    use std::io::{self, Write};
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+
+    use super::{Command, Stepper};
+    use crate::testing::fixtures::Log;
+
+    /// Returns `Pending` for its first `pending` polls, and wakes itself on
+    /// every poll, including the one in which it completes.
+    #[derive(Debug)]
+    struct WakesEveryPoll {
+        pending: usize,
+    }
+
+    impl Future for WakesEveryPoll {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            cx.waker().wake_by_ref();
+            if self.pending == 0 {
+                return Poll::Ready(());
+            }
+            self.pending -= 1;
+            Poll::Pending
+        }
+    }
+
+    /// Collects a stepper's trace.
+    #[derive(Clone, Default)]
+    struct Trace(Arc<Mutex<Vec<u8>>>);
+
+    impl Trace {
+        /// Returns the lines written since the last call.
+        fn take(&self) -> Vec<String> {
+            let bytes = std::mem::take(&mut *self.0.lock().unwrap());
+            String::from_utf8(bytes)
+                .unwrap()
+                .lines()
+                .map(str::to_owned)
+                .collect()
+        }
+    }
+
+    impl Write for Trace {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn remaining(stepper: &Stepper) -> Vec<Command> {
+        stepper.script.iter().flatten().copied().collect()
+    }
+
+    #[test]
+    fn step_polls_once_per_command_until_quit() {
+        let log = Log::default();
+        let mut stepper = Stepper::scripted([Command::Step, Command::Step, Command::Quit]);
+        stepper.spawn(log.probe("a", 5));
+
+        assert!(!stepper.run());
+        assert_eq!(log.take(), ["poll a", "poll a"]);
+        assert_eq!(stepper.tasks.len(), 1);
+        assert_eq!(remaining(&stepper), []);
+    }
+
+    #[test]
+    fn continue_polls_remaining_tasks_without_pausing() {
+        let log = Log::default();
+        let mut stepper = Stepper::scripted([Command::Continue, Command::Quit]);
+        stepper.spawn(log.probe("a", 1));
+        stepper.spawn(log.probe("b", 0));
+
+        assert!(stepper.run());
+        assert_eq!(
+            log.take(),
+            ["poll a", "poll b", "drop b", "poll a", "drop a"]
+        );
+        assert_eq!(stepper.steps, 3);
+        assert_eq!(remaining(&stepper), [Command::Quit]);
+    }
+
+    #[test]
+    fn continues_once_the_script_runs_out() {
+        let log = Log::default();
+        let mut stepper = Stepper::scripted([Command::Step]);
+        stepper.spawn(log.probe("a", 2));
+
+        assert!(stepper.run());
+        assert_eq!(log.take(), ["poll a", "poll a", "poll a", "drop a"]);
+        assert!(stepper.tasks.is_empty());
+    }
+
+    #[test]
+    fn task_woken_as_it_completes_is_not_stepped_again() {
+        let log = Log::default();
+        let mut stepper =
+            Stepper::scripted([Command::Step, Command::Step, Command::Step, Command::Quit]);
+        stepper.spawn(log.wrap("a", WakesEveryPoll { pending: 0 }));
+        stepper.spawn(log.probe("b", 1));
+
+        // `a` is queued again as it completes, the third step must poll `b`
+        // rather than taking `a`'s stale id and its `Step`.
+        assert!(stepper.run());
+        assert_eq!(
+            log.take(),
+            ["poll a", "drop a", "poll b", "poll b", "drop b"]
+        );
+        assert_eq!(stepper.steps, 3);
+        assert_eq!(remaining(&stepper), [Command::Quit]);
+    }
+
+    #[test]
+    fn stops_when_no_task_can_be_woken() {
+        let mut stepper = Stepper::scripted([]);
+        stepper.spawn(future::pending::<()>());
+
+        assert!(!stepper.run());
+        assert_eq!(stepper.steps, 1);
+        assert_eq!(stepper.tasks.len(), 1);
+    }
+
+    #[test]
+    fn trace_shows_each_step_and_its_outcome() {
+        let log = Log::default();
+        let trace = Trace::default();
+        let mut stepper =
+            Stepper::scripted([Command::Step, Command::Quit]).with_output(trace.clone());
+        stepper.spawn(log.probe("a", 1));
+        stepper.spawn(log.probe("b", 0));
+
+        assert!(!stepper.run());
+        let trace = trace.take();
+        assert_eq!(
+            trace,
+            [
+                "── step 1: poll task #1 ──",
+                "run queue after it: [#2]",
+                "tasks:",
+                "> #1 Logged<Probe>: Probe { name: \"a\", pending: 1 } (wakers held: 0)",
+                "  #2 Logged<Probe>: Probe { name: \"b\", pending: 0 } (wakers held: 0)",
+                "waker activity since the last step:",
+                "  none",
+                "> Step",
+                "task #1 returned Pending",
+                "",
+                "── step 2: poll task #2 ──",
+                "run queue after it: [#1]",
+                "tasks:",
+                "  #1 Logged<Probe>: Probe { name: \"a\", pending: 0 } (wakers held: 0)",
+                "> #2 Logged<Probe>: Probe { name: \"b\", pending: 0 } (wakers held: 0)",
+                "waker activity since the last step:",
+                "  task #1 woken by itself",
+                "> Quit",
+                "quit with 2 task(s) left",
+            ]
+        );
+    }
+
+    #[test]
+    fn trace_lists_the_tasks_which_can_never_be_woken() {
+        let trace = Trace::default();
+        let mut stepper = Stepper::scripted([]).with_output(trace.clone());
+        stepper.spawn(future::pending::<()>());
+
+        assert!(!stepper.run());
+        let trace = trace.take();
+        assert_eq!(
+            trace[trace.len() - 4..],
+            [
+                "task #1 returned Pending without keeping or waking its waker, \
+                 it will never be polled again",
+                "",
+                "no task is queued and no task holds a waker, so none can be woken:",
+                "  #1 Pending<()>: Pending",
+            ]
+        );
+    }
+}
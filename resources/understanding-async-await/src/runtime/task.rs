@@ -0,0 +1,410 @@
+use std::any;
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe, Location};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread::Thread;
+
+use super::waker::{self, Wakeable};
+use crate::future::DetectLostWakeups;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// The scheduler flavour of a runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Flavor {
+    /// Tasks are run by the thread calling `block_on`, in between polls of
+    /// the future it's blocking on.
+    CurrentThread,
+    /// Tasks are run by a pool of worker threads.
+    MultiThread,
+}
+
+/// The state shared by a runtime, its tasks and their wakers.
+pub(super) struct Shared {
+    flavor: Flavor,
+    /// Tasks which have been woken and are waiting to be polled.
+    queue: Mutex<VecDeque<Arc<Task>>>,
+    /// Notified when a task is queued, worker threads wait on this.
+    ///
+    /// Only used by the multi-thread flavour.
+    condvar: Condvar,
+    /// The thread running `block_on`, which is unparked when a task is
+    /// queued.
+    ///
+    /// Only used by the current-thread flavour.
+    driver: Mutex<Option<Thread>>,
+    /// Every task which hasn't completed yet, so that they can be dropped
+    /// when the runtime shuts down.
+    tasks: Mutex<HashMap<u64, Arc<Task>>>,
+    next_task_id: AtomicU64,
+    shutdown: AtomicBool,
+    /// Whether futures are wrapped in [`DetectLostWakeups`].
+    detect_lost_wakeups: AtomicBool,
+}
+
+impl Shared {
+    pub(super) fn new(flavor: Flavor) -> Self {
+        Self {
+            flavor,
+            queue: Mutex::new(VecDeque::new()),
+            condvar: Condvar::new(),
+            driver: Mutex::new(None),
+            tasks: Mutex::new(HashMap::new()),
+            next_task_id: AtomicU64::new(0),
+            shutdown: AtomicBool::new(false),
+            detect_lost_wakeups: AtomicBool::new(false),
+        }
+    }
+
+    /// Spawns a new task which will be polled by this runtime.
+    #[track_caller]
+    pub(super) fn spawn<F>(self: &Arc<Self>, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let join = Arc::new(JoinState {
+            inner: Mutex::new(JoinInner {
+                result: None,
+                waker: None,
+            }),
+        });
+
+        // The task's future completes the join state with the output. If the
+        // task panics or is aborted, the task completes it with an error.
+        let future = {
+            let join = Arc::clone(&join);
+            async move {
+                let output = future.await;
+                join.complete(Ok(output));
+            }
+        };
+        let future: BoxFuture = if self.detects_lost_wakeups() {
+            Box::pin(DetectLostWakeups::with_location(
+                future,
+                any::type_name::<F>(),
+                Location::caller(),
+            ))
+        } else {
+            Box::pin(future)
+        };
+
+        let task = Arc::new(Task {
+            id: self.next_task_id.fetch_add(1, Ordering::Relaxed),
+            future: Mutex::new(Some(future)),
+            scheduled: AtomicBool::new(true),
+            aborted: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            join: Arc::clone(&join) as Arc<dyn Complete>,
+            shared: Arc::clone(self),
+        });
+
+        lock(&self.tasks).insert(task.id, Arc::clone(&task));
+        self.schedule(Arc::clone(&task));
+
+        JoinHandle { join, task }
+    }
+
+    /// Wraps futures in [`DetectLostWakeups`] from now on.
+    pub(super) fn set_detect_lost_wakeups(&self) {
+        self.detect_lost_wakeups.store(true, Ordering::Release);
+    }
+
+    /// Returns whether futures are wrapped in [`DetectLostWakeups`].
+    pub(super) fn detects_lost_wakeups(&self) -> bool {
+        self.detect_lost_wakeups.load(Ordering::Acquire)
+    }
+
+    /// Adds a task to the back of the run queue.
+    fn schedule(&self, task: Arc<Task>) {
+        if self.shutdown.load(Ordering::Acquire) {
+            return;
+        }
+
+        lock(&self.queue).push_back(task);
+        match self.flavor {
+            Flavor::CurrentThread => {
+                if let Some(driver) = &*lock(&self.driver) {
+                    driver.unpark();
+                }
+            }
+            Flavor::MultiThread => self.condvar.notify_one(),
+        }
+    }
+
+    /// Takes the next task from the run queue, if there is one.
+    pub(super) fn next_task(&self) -> Option<Arc<Task>> {
+        lock(&self.queue).pop_front()
+    }
+
+    /// Returns the number of tasks in the run queue.
+    pub(super) fn queue_len(&self) -> usize {
+        lock(&self.queue).len()
+    }
+
+    /// Returns whether the run queue is empty.
+    pub(super) fn is_idle(&self) -> bool {
+        lock(&self.queue).is_empty()
+    }
+
+    /// Waits for the next task from the run queue.
+    ///
+    /// Returns `None` once the runtime is shutting down.
+    pub(super) fn wait_for_task(&self) -> Option<Arc<Task>> {
+        let mut queue = lock(&self.queue);
+        loop {
+            if self.shutdown.load(Ordering::Acquire) {
+                return None;
+            }
+            if let Some(task) = queue.pop_front() {
+                return Some(task);
+            }
+            queue = match self.condvar.wait(queue) {
+                Ok(queue) => queue,
+                Err(_) => panic!("Runtime state has become corrupted."),
+            };
+        }
+    }
+
+    /// Sets the thread to unpark when a task is scheduled.
+    ///
+    /// Returns `false` if another thread is already driving the runtime.
+    pub(super) fn set_driver(&self, driver: Option<Thread>) -> bool {
+        let mut current = lock(&self.driver);
+        if driver.is_some() && current.is_some() {
+            return false;
+        }
+        *current = driver;
+        true
+    }
+
+    /// Tells the worker threads to stop.
+    ///
+    /// From now on, woken tasks are no longer queued.
+    pub(super) fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Release);
+        // Hold the queue lock, so that a worker can't miss the notification
+        // between checking the flag and waiting.
+        let _queue = lock(&self.queue);
+        self.condvar.notify_all();
+    }
+
+    /// Drops every task which hasn't completed.
+    ///
+    /// Their join handles will return [`JoinError::Cancelled`]. This must only
+    /// be called once the worker threads have stopped.
+    pub(super) fn cancel_all(&self) {
+        // Drop the futures outside of the locks, they may try to wake other
+        // tasks as they are dropped.
+        let queued: Vec<_> = lock(&self.queue).drain(..).collect();
+        let tasks: Vec<_> = lock(&self.tasks).drain().map(|(_, task)| task).collect();
+        drop(queued);
+        for task in tasks {
+            task.cancel();
+        }
+    }
+
+    fn remove(&self, id: u64) {
+        lock(&self.tasks).remove(&id);
+    }
+}
+
+/// A spawned task.
+pub(super) struct Task {
+    id: u64,
+    /// `None` once the task has completed.
+    future: Mutex<Option<BoxFuture>>,
+    /// Whether the task is in the run queue, so that it isn't queued twice.
+    scheduled: AtomicBool,
+    aborted: AtomicBool,
+    /// Set once the future has been dropped, after completing or not.
+    ///
+    /// This is separate from `future`, which is locked for the whole of a
+    /// poll, so that a task can check whether it has finished itself.
+    finished: AtomicBool,
+    join: Arc<dyn Complete>,
+    shared: Arc<Shared>,
+}
+
+impl Task {
+    /// Polls the task's future once.
+    ///
+    /// A panic in the future is caught and passed to the join handle, it
+    /// doesn't bring down the thread running the task.
+    pub(super) fn run(self: &Arc<Self>) {
+        // Clear the flag before polling, so that a wake during the poll queues
+        // the task again.
+        self.scheduled.store(false, Ordering::Release);
+        if self.aborted.load(Ordering::Acquire) {
+            self.cancel();
+            return;
+        }
+
+        let mut slot = lock(&self.future);
+        let Some(future) = slot.as_mut() else {
+            // The task has already completed.
+            return;
+        };
+
+        let waker = waker::waker(Arc::clone(self));
+        let mut cx = Context::from_waker(&waker);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(&mut cx)));
+        match result {
+            Ok(Poll::Pending) => return,
+            Ok(Poll::Ready(())) => {}
+            Err(payload) => self.join.fail(JoinError::Panicked(payload)),
+        }
+        let future = slot.take();
+        drop(slot);
+        drop(future);
+        self.finished.store(true, Ordering::Release);
+        self.shared.remove(self.id);
+    }
+
+    /// Drops the task's future and completes its join handle with a cancelled
+    /// error.
+    fn cancel(&self) {
+        let future = lock(&self.future).take();
+        if future.is_some() {
+            drop(future);
+            self.finished.store(true, Ordering::Release);
+            self.join.fail(JoinError::Cancelled);
+        }
+        self.shared.remove(self.id);
+    }
+
+    fn abort(self: &Arc<Self>) {
+        self.aborted.store(true, Ordering::Release);
+        // The task is cancelled the next time it is run.
+        self.wake_by_ref();
+    }
+}
+
+impl Wakeable for Task {
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            self.shared.schedule(Arc::clone(self));
+        }
+    }
+}
+
+/// Completes a join state with an error, without knowing the output type.
+trait Complete: Send + Sync {
+    fn fail(&self, err: JoinError);
+}
+
+struct JoinState<T> {
+    inner: Mutex<JoinInner<T>>,
+}
+
+struct JoinInner<T> {
+    result: Option<Result<T, JoinError>>,
+    /// The waker of the task awaiting the join handle.
+    waker: Option<Waker>,
+}
+
+impl<T> JoinState<T> {
+    /// Stores the task's result and wakes the task awaiting it.
+    ///
+    /// Only the first result is kept.
+    fn complete(&self, result: Result<T, JoinError>) {
+        let waker = {
+            let mut inner = lock(&self.inner);
+            if inner.result.is_some() {
+                return;
+            }
+            inner.result = Some(result);
+            inner.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: Send> Complete for JoinState<T> {
+    fn fail(&self, err: JoinError) {
+        self.complete(Err(err));
+    }
+}
+
+/// A handle to a spawned task, which can be awaited to get its output.
+///
+/// Dropping the handle detaches the task, it carries on running but its
+/// output can no longer be retrieved.
+pub struct JoinHandle<T> {
+    join: Arc<JoinState<T>>,
+    task: Arc<Task>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Aborts the task.
+    ///
+    /// The task's future is dropped the next time the runtime would have
+    /// polled it, and awaiting the handle returns [`JoinError::Cancelled`].
+    /// If the task has already completed, this does nothing.
+    pub fn abort(&self) {
+        self.task.abort();
+    }
+
+    /// Returns whether the task has completed, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        lock(&self.join.inner).result.is_some() || self.task.finished.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut inner = lock(&self.join.inner);
+        match inner.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                inner.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("id", &self.task.id)
+            .finish()
+    }
+}
+
+/// Error returned by a [`JoinHandle`] when its task didn't complete.
+#[derive(Debug)]
+pub enum JoinError {
+    /// The task was aborted, or the runtime shut down before it completed.
+    Cancelled,
+    /// The task panicked. Contains the panic payload.
+    Panicked(Box<dyn Any + Send>),
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cancelled => write!(f, "task was cancelled"),
+            Self::Panicked(_) => write!(f, "task panicked"),
+        }
+    }
+}
+impl Error for JoinError {}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(_) => panic!("Runtime state has become corrupted."),
+    }
+}
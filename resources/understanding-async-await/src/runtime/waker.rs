@@ -0,0 +1,111 @@
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{RawWaker, RawWakerVTable, Waker};
+use std::thread::{self, Thread};
+
+/// Something which can be woken, shared behind an `Arc`.
+///
+/// This is our version of [`std::task::Wake`]. The standard library can turn
+/// an `Arc<impl Wake>` into a [`Waker`] for us, but here we build the
+/// [`RawWakerVTable`] by hand to see what that involves.
+pub(super) trait Wakeable: Send + Sync + 'static {
+    fn wake_by_ref(self: &Arc<Self>);
+}
+
+/// Creates a waker which calls [`Wakeable::wake_by_ref`] on `wakeable`.
+///
+/// The waker's data pointer is the pointer inside the `Arc`. Each clone of the
+/// waker owns one strong reference to it.
+pub(super) fn waker<W: Wakeable>(wakeable: Arc<W>) -> Waker {
+    let raw = RawWaker::new(Arc::into_raw(wakeable).cast(), &VTable::<W>::VTABLE);
+    // SAFETY: The vtable functions below uphold the `RawWaker` contract: the
+    // data pointer came from `Arc::into_raw` and each function accounts for
+    // the strong reference it is given.
+    unsafe { Waker::from_raw(raw) }
+}
+
+/// Holds the vtable for a `Wakeable` type.
+///
+/// A `static` can't be generic, but an associated const can. Taking a
+/// reference to it gives us a `&'static RawWakerVTable` for each `W`.
+struct VTable<W>(PhantomData<W>);
+
+impl<W: Wakeable> VTable<W> {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(
+        Self::clone_waker,
+        Self::wake,
+        Self::wake_by_ref,
+        Self::drop_waker,
+    );
+
+    /// Clones the waker by adding a strong reference for the new waker.
+    unsafe fn clone_waker(data: *const ()) -> RawWaker {
+        // SAFETY: `data` came from `Arc::into_raw` and the waker being cloned
+        // still holds its strong reference.
+        unsafe { Arc::increment_strong_count(data.cast::<W>()) };
+        RawWaker::new(data, &Self::VTABLE)
+    }
+
+    /// Wakes and consumes the waker, so its strong reference is dropped.
+    unsafe fn wake(data: *const ()) {
+        // SAFETY: The waker owns one strong reference, which we take back.
+        let wakeable = unsafe { Arc::from_raw(data.cast::<W>()) };
+        wakeable.wake_by_ref();
+    }
+
+    /// Wakes without consuming the waker.
+    unsafe fn wake_by_ref(data: *const ()) {
+        // SAFETY: The waker still owns its strong reference, so we borrow it
+        // without changing the count.
+        let wakeable = std::mem::ManuallyDrop::new(unsafe { Arc::from_raw(data.cast::<W>()) });
+        wakeable.wake_by_ref();
+    }
+
+    /// Drops the waker's strong reference.
+    unsafe fn drop_waker(data: *const ()) {
+        // SAFETY: The waker owns one strong reference, which we drop.
+        drop(unsafe { Arc::from_raw(data.cast::<W>()) });
+    }
+}
+
+/// Wakes the thread which is blocked in `block_on`.
+///
+/// The thread parks itself when the future it's blocking on returns
+/// `Poll::Pending`. Waking sets a flag, so the thread knows to poll the future
+/// again, and unparks it.
+pub(super) struct ThreadWaker {
+    thread: Thread,
+    woken: AtomicBool,
+}
+
+impl ThreadWaker {
+    /// Creates a waker for the current thread.
+    ///
+    /// It starts off woken, so that the future is polled the first time.
+    pub(super) fn current() -> Arc<Self> {
+        Arc::new(Self {
+            thread: thread::current(),
+            woken: AtomicBool::new(true),
+        })
+    }
+
+    /// Returns whether the waker has been woken, clearing the flag.
+    pub(super) fn take_woken(&self) -> bool {
+        self.woken.swap(false, Ordering::AcqRel)
+    }
+
+    /// Returns whether the waker has been woken, without clearing the flag.
+    pub(super) fn is_woken(&self) -> bool {
+        self.woken.load(Ordering::Acquire)
+    }
+}
+
+impl Wakeable for ThreadWaker {
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        // If the thread isn't parked yet, the next call to `park` will return
+        // straight away.
+        self.thread.unpark();
+    }
+}
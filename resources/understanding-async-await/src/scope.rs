@@ -0,0 +1,524 @@
+//! Structured concurrency: a scope which owns the tasks spawned in it.
+//!
+//! A [`TaskScope`] spawns child tasks onto an executor through a [`Spawner`]
+//! and keeps track of them. Results are returned in the order the children
+//! complete. If a child fails or panics, the remaining children are
+//! cancelled.
+//!
+//! The scope doesn't rely on the executor's join handles. Each child future is
+//! wrapped in a future which checks whether the scope has been cancelled
+//! before it polls the child, and which reports back to the scope when the
+//! child completes or is dropped. That's what lets the scope know when every
+//! child is gone, whichever executor runs them.
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+/// A boxed task, ready to be spawned.
+pub type BoxTask = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Spawns tasks for a [`TaskScope`].
+///
+/// This is implemented for a tokio runtime [`Handle`] and for any function
+/// which takes a [`BoxTask`]. To use our own [`runtime`], pass a closure
+/// which calls `runtime::spawn(task)`.
+///
+/// [`Handle`]: tokio::runtime::Handle
+/// [`runtime`]: mod@crate::runtime
+pub trait Spawner: Send + Sync + 'static {
+    /// Spawns `task`, which must be polled until it completes.
+    fn spawn(&self, task: BoxTask);
+}
+
+impl Spawner for tokio::runtime::Handle {
+    fn spawn(&self, task: BoxTask) {
+        // The scope tracks the task itself, the join handle isn't needed.
+        drop(tokio::runtime::Handle::spawn(self, task));
+    }
+}
+
+impl<F> Spawner for F
+where
+    F: Fn(BoxTask) + Send + Sync + 'static,
+{
+    fn spawn(&self, task: BoxTask) {
+        self(task)
+    }
+}
+
+/// A scope which owns the tasks spawned in it.
+///
+/// Child tasks return `Result<T, E>`. Their results are returned by
+/// [`join_next`] in the order the tasks complete. The first child which
+/// returns an error or panics cancels all the others, see [`cancel`].
+///
+/// Once [`join_next`] has returned `None`, or [`join_all`] or [`shutdown`]
+/// has completed, every child has been dropped.
+///
+/// # Dropping the scope
+///
+/// Dropping a scope doesn't wait for its children. It only flags them as
+/// cancelled and wakes them, each child is dropped the next time its
+/// executor polls it, which may be after the scope is gone, or never if the
+/// executor has shut down. Until then, a child can still be running on
+/// another thread.
+///
+/// To be sure that no child outlives the scope, always finish with
+/// [`join_all`], with [`join_next`] until it returns `None`, or with
+/// [`shutdown`], rather than dropping the scope.
+///
+/// [`join_next`]: fn@Self::join_next
+/// [`join_all`]: fn@Self::join_all
+/// [`shutdown`]: fn@Self::shutdown
+/// [`cancel`]: fn@Self::cancel
+pub struct TaskScope<T, E> {
+    spawner: Box<dyn Spawner>,
+    shared: Arc<Shared<T, E>>,
+}
+
+struct Shared<T, E> {
+    state: Mutex<State<T, E>>,
+}
+
+struct State<T, E> {
+    /// The number of children which haven't been dropped yet.
+    running: usize,
+    /// The results of children which have finished, in completion order.
+    finished: VecDeque<Outcome<T, E>>,
+    cancelled: bool,
+    /// The wakers of the children which are waiting, so that they can be
+    /// woken to drop their futures when the scope is cancelled.
+    children: HashMap<u64, Waker>,
+    /// The id which will be given to the next child.
+    next_id: u64,
+    /// The waker of the task waiting in `join_next`.
+    waker: Option<Waker>,
+}
+
+enum Outcome<T, E> {
+    Ok(T),
+    Err(E),
+    Panicked(Box<dyn Any + Send>),
+    Cancelled,
+}
+
+impl<T, E> TaskScope<T, E>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    /// Creates a new, empty scope which spawns its children with `spawner`.
+    pub fn new(spawner: impl Spawner) -> Self {
+        Self {
+            spawner: Box::new(spawner),
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    running: 0,
+                    finished: VecDeque::new(),
+                    cancelled: false,
+                    children: HashMap::new(),
+                    next_id: 0,
+                    waker: None,
+                }),
+            }),
+        }
+    }
+
+    /// Spawns a child task in this scope.
+    ///
+    /// If the scope has already been cancelled, the child is dropped without
+    /// being polled.
+    pub fn spawn<F>(&mut self, future: F)
+    where
+        F: Future<Output = Result<T, E>> + Send + 'static,
+    {
+        let id = {
+            let mut state = self.shared.lock_state();
+            state.running += 1;
+            let id = state.next_id;
+            state.next_id += 1;
+            id
+        };
+
+        self.spawner.spawn(Box::pin(Child {
+            id,
+            future: Some(Box::pin(future)),
+            shared: Arc::clone(&self.shared),
+        }));
+    }
+
+    /// Returns the number of children which haven't been dropped yet.
+    pub fn len(&self) -> usize {
+        self.shared.lock_state().running
+    }
+
+    /// Returns whether every child has been dropped.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Waits for the next child to complete and returns its result.
+    ///
+    /// Returns `None` once every child has been dropped. Children which were
+    /// cancelled aren't returned.
+    ///
+    /// If the child returned an error or panicked, the remaining children are
+    /// cancelled before the error is returned.
+    pub async fn join_next(&mut self) -> Option<Result<T, ScopeError<E>>> {
+        poll_fn(|cx| self.poll_join_next(cx)).await
+    }
+
+    /// Polls for the next child to complete.
+    pub fn poll_join_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<T, ScopeError<E>>>> {
+        let (result, wakers) = {
+            let mut state = self.shared.lock_state();
+            loop {
+                let result = match state.finished.pop_front() {
+                    Some(Outcome::Ok(value)) => Ok(value),
+                    Some(Outcome::Err(err)) => Err(ScopeError::Failed(err)),
+                    Some(Outcome::Panicked(payload)) => Err(ScopeError::Panicked(payload)),
+                    Some(Outcome::Cancelled) => continue,
+                    None if state.running == 0 => return Poll::Ready(None),
+                    None => {
+                        state.waker = Some(cx.waker().clone());
+                        return Poll::Pending;
+                    }
+                };
+
+                let wakers = if result.is_err() {
+                    state.cancel()
+                } else {
+                    Vec::new()
+                };
+                break (result, wakers);
+            }
+        };
+        // Wake outside of the lock, the children may be polled straight away
+        // on other threads.
+        for waker in wakers {
+            waker.wake();
+        }
+
+        Poll::Ready(Some(result))
+    }
+
+    /// Waits for every child to complete, returning their results in
+    /// completion order.
+    ///
+    /// Returns the first error or panic instead, once the remaining children
+    /// have been cancelled and dropped.
+    pub async fn join_all(mut self) -> Result<Vec<T>, ScopeError<E>> {
+        let mut values = Vec::new();
+        while let Some(result) = self.join_next().await {
+            match result {
+                Ok(value) => values.push(value),
+                Err(err) => {
+                    self.shutdown().await;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Cancels the children which are still running.
+    ///
+    /// Each child is woken, and its future is dropped the next time it is
+    /// polled instead of being polled itself. Children spawned after this
+    /// are dropped without being polled.
+    pub fn cancel(&self) {
+        let wakers = self.shared.lock_state().cancel();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Cancels the children which are still running and waits until every
+    /// child has been dropped.
+    ///
+    /// Results which haven't been returned by [`join_next`] yet are
+    /// discarded.
+    ///
+    /// [`join_next`]: fn@Self::join_next
+    pub async fn shutdown(&mut self) {
+        self.cancel();
+        while self.join_next().await.is_some() {}
+    }
+}
+
+impl<T, E> Drop for TaskScope<T, E> {
+    /// Flags the children as cancelled, without waiting for them to be
+    /// dropped.
+    fn drop(&mut self) {
+        let wakers = self.shared.lock_state().cancel();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl<T, E> fmt::Debug for TaskScope<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.shared.lock_state();
+        f.debug_struct("TaskScope")
+            .field("running", &state.running)
+            .field("finished", &state.finished.len())
+            .field("cancelled", &state.cancelled)
+            .finish()
+    }
+}
+
+impl<T, E> Shared<T, E> {
+    /// Records the outcome of a child and wakes the task waiting for it.
+    fn finish(&self, id: u64, outcome: Outcome<T, E>) {
+        let waker = {
+            let mut state = self.lock_state();
+            state.running -= 1;
+            state.children.remove(&id);
+            state.finished.push_back(outcome);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, State<T, E>> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(_) => panic!("TaskScope state has become corrupted."),
+        }
+    }
+}
+
+impl<T, E> State<T, E> {
+    /// Marks the scope as cancelled, returning the wakers of the children to
+    /// wake once the lock has been released.
+    fn cancel(&mut self) -> Vec<Waker> {
+        self.cancelled = true;
+        self.children.drain().map(|(_, waker)| waker).collect()
+    }
+}
+
+type ChildFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+/// The task spawned for each child, which wraps the child's future.
+struct Child<T, E> {
+    id: u64,
+    /// `None` once the child has finished.
+    future: Option<ChildFuture<T, E>>,
+    shared: Arc<Shared<T, E>>,
+}
+
+impl<T, E> Future for Child<T, E> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let Some(future) = &mut this.future else {
+            return Poll::Ready(());
+        };
+
+        {
+            let mut state = this.shared.lock_state();
+            if state.cancelled {
+                drop(state);
+                this.future = None;
+                this.shared.finish(this.id, Outcome::Cancelled);
+                return Poll::Ready(());
+            }
+            // Register before polling, so that a cancel during the poll wakes
+            // us again.
+            state.children.insert(this.id, cx.waker().clone());
+        }
+
+        let outcome = match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Pending) => return Poll::Pending,
+            Ok(Poll::Ready(Ok(value))) => Outcome::Ok(value),
+            Ok(Poll::Ready(Err(err))) => Outcome::Err(err),
+            Err(payload) => Outcome::Panicked(payload),
+        };
+        this.future = None;
+        this.shared.finish(this.id, outcome);
+        Poll::Ready(())
+    }
+}
+
+impl<T, E> Drop for Child<T, E> {
+    fn drop(&mut self) {
+        // The executor dropped the child before it finished, for example
+        // because the runtime is shutting down.
+        if self.future.take().is_some() {
+            self.shared.finish(self.id, Outcome::Cancelled);
+        }
+    }
+}
+
+/// Error returned by a [`TaskScope`] when a child didn't succeed.
+pub enum ScopeError<E> {
+    /// The child returned an error.
+    Failed(E),
+    /// The child panicked. Contains the panic payload, which can be passed to
+    /// [`std::panic::resume_unwind`] to carry on unwinding.
+    Panicked(Box<dyn Any + Send>),
+}
+
+impl<E: fmt::Debug> fmt::Debug for ScopeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed(err) => f.debug_tuple("Failed").field(err).finish(),
+            Self::Panicked(_) => f.debug_tuple("Panicked").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for ScopeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed(err) => write!(f, "task failed: {err}"),
+            Self::Panicked(_) => write!(f, "task panicked"),
+        }
+    }
+}
+impl<E: fmt::Debug + fmt::Display> Error for ScopeError<E> {}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+    use std::sync::{Arc, Mutex};
+
+    use super::{BoxTask, ScopeError, TaskScope};
+    use crate::runtime::{self, Runtime};
+    use crate::testing::fixtures::{gate, Log};
+    use crate::testing::{poll_once, MockWaker};
+
+    fn runtime_scope<T: Send + 'static>() -> TaskScope<T, String> {
+        TaskScope::new(|task| {
+            runtime::spawn(task);
+        })
+    }
+
+    #[test]
+    fn results_are_returned_in_completion_order() {
+        let values = Runtime::current_thread().block_on(async {
+            let mut scope = runtime_scope();
+            let mut openers = Vec::new();
+            for idx in 0..3 {
+                let (gate, opener) = gate();
+                openers.push(opener);
+                scope.spawn(async move {
+                    gate.await;
+                    Ok(idx)
+                });
+            }
+
+            let mut values = Vec::new();
+            for idx in [2, 0, 1] {
+                openers[idx].open();
+                values.push(scope.join_next().await.unwrap().unwrap());
+            }
+            assert!(scope.join_next().await.is_none());
+            values
+        });
+
+        assert_eq!(values, [2, 0, 1]);
+    }
+
+    #[test]
+    fn first_error_cancels_siblings() {
+        let log = Log::default();
+        let (result, dropped) = Runtime::current_thread().block_on({
+            let log = log.clone();
+            async move {
+                let mut scope = runtime_scope::<u64>();
+                scope.spawn(log.wrap("sibling", pending()));
+                scope.spawn(async {
+                    runtime::yield_now().await;
+                    Err("failed".to_owned())
+                });
+                scope.spawn(log.wrap("other sibling", pending()));
+
+                let result = scope.join_all().await;
+                (result, log.take())
+            }
+        });
+
+        assert!(matches!(result, Err(ScopeError::Failed(err)) if err == "failed"));
+        assert!(dropped.contains(&"drop sibling".to_owned()), "{dropped:?}");
+        assert!(
+            dropped.contains(&"drop other sibling".to_owned()),
+            "{dropped:?}"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn first_panic_is_returned_and_cancels_siblings() {
+        let log = Log::default();
+        let mut scope = TaskScope::<u64, String>::new(tokio::runtime::Handle::current());
+        scope.spawn(log.wrap("sibling", pending()));
+        scope.spawn(async { panic!("boom") });
+
+        let Some(Err(ScopeError::Panicked(payload))) = scope.join_next().await else {
+            panic!("expected the child's panic");
+        };
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+
+        scope.shutdown().await;
+        assert!(scope.is_empty());
+        assert!(log.take().contains(&"drop sibling".to_owned()));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn shutdown_waits_for_every_child() {
+        let log = Log::default();
+        let mut scope = TaskScope::<u64, String>::new(tokio::runtime::Handle::current());
+        scope.spawn(async { Ok(1) });
+        for name in ["first", "second", "third"] {
+            scope.spawn(log.wrap(name, pending()));
+        }
+
+        scope.shutdown().await;
+
+        assert!(scope.is_empty());
+        let mut dropped: Vec<_> = log
+            .take()
+            .into_iter()
+            .filter(|entry| entry.starts_with("drop"))
+            .collect();
+        dropped.sort();
+        assert_eq!(dropped, ["drop first", "drop second", "drop third"]);
+        // The finished child's result was discarded.
+        assert!(scope.join_next().await.is_none());
+    }
+
+    #[test]
+    fn dropping_the_scope_only_flags_cancellation() {
+        let tasks = Arc::new(Mutex::new(Vec::<BoxTask>::new()));
+        let log = Log::default();
+        let mut scope = TaskScope::<u64, String>::new({
+            let tasks = Arc::clone(&tasks);
+            move |task| tasks.lock().unwrap().push(task)
+        });
+        scope.spawn(log.wrap("child", pending()));
+        let mut task = tasks.lock().unwrap().pop().unwrap();
+        let waker = MockWaker::new();
+
+        poll_once(task.as_mut(), &waker).assert_registered_waker();
+        drop(scope);
+        assert_eq!(waker.counts().total_wakes(), 1);
+        assert_eq!(log.take(), ["poll child"]);
+
+        // The child is only dropped once its executor polls it again.
+        poll_once(task.as_mut(), &waker).assert_ready();
+        assert_eq!(log.take(), ["drop child"]);
+    }
+}
@@ -0,0 +1,146 @@
+//! Asynchronous iteration with the [`Stream`] trait.
+//!
+//! A stream is to an iterator what a future is to a function call. Instead of
+//! `next` returning the next item, [`poll_next`] returns `Poll::Ready` with
+//! the next item (or `None` at the end) or `Poll::Pending` if the next item
+//! isn't available yet, in which case the task is woken when it is.
+//!
+//! Like the combinators in [`future`], each stream here is a hand-written
+//! state machine and the streams it wraps are boxed, so they are all `Unpin`.
+//!
+//! [`poll_next`]: fn@Stream::poll_next
+//! [`future`]: mod@crate::future
+mod buffered;
+mod chunks_timeout;
+mod filter;
+mod interval;
+mod iter;
+mod map;
+mod merge;
+mod take;
+mod unfold;
+
+pub use buffered::Buffered;
+pub use chunks_timeout::ChunksTimeout;
+pub use filter::Filter;
+pub use interval::{interval, IntervalStream};
+pub use iter::{iter, Iter};
+pub use map::Map;
+pub use merge::{merge, Merge};
+pub use take::Take;
+pub use unfold::{unfold, Unfold};
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// A sequence of values produced asynchronously.
+pub trait Stream {
+    /// The type of the values produced by the stream.
+    type Item;
+
+    /// Attempts to get the next value of the stream.
+    ///
+    /// Returns `Poll::Ready(Some(item))` with the next value,
+    /// `Poll::Ready(None)` once the stream has ended, or `Poll::Pending` if
+    /// the next value isn't available yet. In that case the waker from `cx`
+    /// is woken when the stream should be polled again.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>>;
+}
+
+impl<S: Stream + Unpin + ?Sized> Stream for &mut S {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut **self).poll_next(cx)
+    }
+}
+
+/// Adapters for any [`Stream`].
+pub trait StreamExt: Stream {
+    /// Returns a future which resolves to the next item of the stream.
+    fn next(&mut self) -> Next<'_, Self>
+    where
+        Self: Unpin,
+    {
+        Next { stream: self }
+    }
+
+    /// Transforms each item with `f`.
+    fn map<T, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> T,
+    {
+        Map::new(self, f)
+    }
+
+    /// Only yields the items for which `predicate` returns `true`.
+    fn filter<F>(self, predicate: F) -> Filter<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> bool,
+    {
+        Filter::new(self, predicate)
+    }
+
+    /// Yields at most `n` items, then ends.
+    ///
+    /// The inner stream is dropped as soon as the last item has been yielded.
+    fn take(self, n: usize) -> Take<Self>
+    where
+        Self: Sized,
+    {
+        Take::new(self, n)
+    }
+
+    /// Runs up to `n` of the futures from this stream at once, yielding their
+    /// outputs in the order the futures came from the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    fn buffered(self, n: usize) -> Buffered<Self>
+    where
+        Self: Sized,
+        Self::Item: Future,
+    {
+        Buffered::new(self, n)
+    }
+
+    /// Groups items into chunks of up to `capacity` items.
+    ///
+    /// A chunk is yielded once it's full, or once `duration` has elapsed
+    /// since its first item arrived, whichever comes first. When the stream
+    /// ends, the items left over are yielded as a final chunk.
+    ///
+    /// The timeouts use the global timer, see [`ChunksTimeout::with_timer`]
+    /// to use a different one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    fn chunks_timeout(self, capacity: usize, duration: Duration) -> ChunksTimeout<Self>
+    where
+        Self: Sized,
+    {
+        ChunksTimeout::new(self, capacity, duration)
+    }
+}
+
+impl<S: Stream + ?Sized> StreamExt for S {}
+
+/// The future returned by [`StreamExt::next`].
+#[derive(Debug)]
+pub struct Next<'a, S: ?Sized> {
+    stream: &'a mut S,
+}
+
+impl<S: Stream + Unpin + ?Sized> Future for Next<'_, S> {
+    type Output = Option<S::Item>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.stream).poll_next(cx)
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::Stream;
+use crate::future::MaybeDone;
+
+/// The stream returned by [`StreamExt::buffered`].
+///
+/// [`StreamExt::buffered`]: fn@super::StreamExt::buffered
+#[derive(Debug)]
+pub struct Buffered<S: Stream>
+where
+    S::Item: Future,
+{
+    /// `None` once the inner stream has ended.
+    stream: Option<Pin<Box<S>>>,
+    /// The futures which are running, in the order they came from the
+    /// stream.
+    in_progress: VecDeque<MaybeDone<S::Item>>,
+    max: usize,
+}
+
+impl<S: Stream> Buffered<S>
+where
+    S::Item: Future,
+{
+    pub(super) fn new(stream: S, max: usize) -> Self {
+        if max == 0 {
+            panic!("buffered stream must allow at least one future");
+        }
+        Self {
+            stream: Some(Box::pin(stream)),
+            in_progress: VecDeque::new(),
+            max,
+        }
+    }
+}
+
+impl<S: Stream> Stream for Buffered<S>
+where
+    S::Item: Future,
+{
+    type Item = <S::Item as Future>::Output;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        // Fill up the buffer with futures from the stream.
+        while this.in_progress.len() < this.max {
+            let Some(stream) = &mut this.stream else {
+                break;
+            };
+            match stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(future)) => this.in_progress.push_back(MaybeDone::new(future)),
+                Poll::Ready(None) => this.stream = None,
+                Poll::Pending => break,
+            }
+        }
+
+        // Poll every future, so they all make progress, but only the oldest
+        // one can be yielded.
+        let mut front_ready = false;
+        for (index, future) in this.in_progress.iter_mut().enumerate() {
+            let ready = future.poll(cx);
+            if index == 0 {
+                front_ready = ready;
+            }
+        }
+        if front_ready {
+            let mut future = this
+                .in_progress
+                .pop_front()
+                .expect("front future is missing");
+            return Poll::Ready(Some(future.take()));
+        }
+
+        if this.in_progress.is_empty() && this.stream.is_none() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::stream::{self, StreamExt};
+    use crate::testing::fixtures::gate;
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn buffered_limits_concurrency_and_keeps_order() {
+        let waker = MockWaker::new();
+        let started = Arc::new(AtomicUsize::new(0));
+        let (gates, openers): (Vec<_>, Vec<_>) = (0..3).map(|_| gate()).unzip();
+        let futures = gates.into_iter().enumerate().map(|(idx, gate)| {
+            let started = Arc::clone(&started);
+            async move {
+                started.fetch_add(1, Ordering::SeqCst);
+                gate.await;
+                idx
+            }
+        });
+        let mut buffered = stream::iter(futures).buffered(2);
+
+        poll_once(pin!(buffered.next()), &waker).assert_registered_waker();
+        assert_eq!(started.load(Ordering::SeqCst), 2);
+
+        // The second future completes first, but it has to wait for the first.
+        openers[1].open();
+        assert_eq!(waker.counts().total_wakes(), 1);
+        poll_once(pin!(buffered.next()), &waker).assert_pending();
+        openers[0].open();
+        assert_eq!(
+            poll_once(pin!(buffered.next()), &waker).assert_ready(),
+            Some(0)
+        );
+        assert_eq!(
+            poll_once(pin!(buffered.next()), &waker).assert_ready(),
+            Some(1)
+        );
+
+        // Only now has there been room for the third future to start.
+        poll_once(pin!(buffered.next()), &waker).assert_pending();
+        assert_eq!(started.load(Ordering::SeqCst), 3);
+        openers[2].open();
+        assert_eq!(
+            poll_once(pin!(buffered.next()), &waker).assert_ready(),
+            Some(2)
+        );
+        assert_eq!(
+            poll_once(pin!(buffered.next()), &waker).assert_ready(),
+            None
+        );
+    }
+}
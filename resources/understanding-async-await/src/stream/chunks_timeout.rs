@@ -0,0 +1,146 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use super::Stream;
+use crate::time::{self, Sleep, Timer};
+
+/// The stream returned by [`StreamExt::chunks_timeout`].
+///
+/// [`StreamExt::chunks_timeout`]: fn@super::StreamExt::chunks_timeout
+#[derive(Debug)]
+pub struct ChunksTimeout<S: Stream> {
+    /// `None` once the inner stream has ended.
+    stream: Option<Pin<Box<S>>>,
+    items: Vec<S::Item>,
+    capacity: usize,
+    duration: Duration,
+    /// The timer to use instead of the global one.
+    timer: Option<Timer>,
+    /// Started when the first item of a chunk arrives.
+    sleep: Option<Sleep>,
+}
+
+// The items are never pinned, and the inner stream is pinned in its box, so
+// moving a `ChunksTimeout` is fine even if the items aren't `Unpin`.
+impl<S: Stream> Unpin for ChunksTimeout<S> {}
+
+impl<S: Stream> ChunksTimeout<S> {
+    pub(super) fn new(stream: S, capacity: usize, duration: Duration) -> Self {
+        if capacity == 0 {
+            panic!("chunk capacity must be greater than zero");
+        }
+        Self {
+            stream: Some(Box::pin(stream)),
+            items: Vec::with_capacity(capacity),
+            capacity,
+            duration,
+            timer: None,
+            sleep: None,
+        }
+    }
+
+    /// Uses `timer` for the timeouts, instead of the global timer.
+    ///
+    /// This allows a paused [`Timer`] to be used in tests.
+    pub fn with_timer(mut self, timer: &Timer) -> Self {
+        self.timer = Some(timer.clone());
+        self
+    }
+
+    fn take_chunk(&mut self) -> Vec<S::Item> {
+        self.sleep = None;
+        std::mem::replace(&mut self.items, Vec::with_capacity(self.capacity))
+    }
+}
+
+impl<S: Stream> Stream for ChunksTimeout<S> {
+    type Item = Vec<S::Item>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        while let Some(stream) = &mut this.stream {
+            match stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    if this.items.is_empty() {
+                        // The timeout runs from the first item of each chunk.
+                        this.sleep = Some(match &this.timer {
+                            Some(timer) => timer.sleep(this.duration),
+                            None => time::sleep(this.duration),
+                        });
+                    }
+                    this.items.push(item);
+                    if this.items.len() == this.capacity {
+                        return Poll::Ready(Some(this.take_chunk()));
+                    }
+                }
+                Poll::Ready(None) => this.stream = None,
+                Poll::Pending => break,
+            }
+        }
+
+        if this.stream.is_none() {
+            // Return what's left as a final, shorter chunk.
+            return if this.items.is_empty() {
+                Poll::Ready(None)
+            } else {
+                Poll::Ready(Some(this.take_chunk()))
+            };
+        }
+
+        let Some(sleep) = &mut this.sleep else {
+            return Poll::Pending;
+        };
+        match Pin::new(sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Some(this.take_chunk())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+    use std::time::Duration;
+
+    use crate::stream::StreamExt;
+    use crate::testing::fixtures::queue;
+    use crate::testing::{poll_once, MockWaker};
+    use crate::time::Timer;
+
+    #[test]
+    fn chunks_timeout_yields_full_or_late_chunks() {
+        let timer = Timer::paused();
+        let waker = MockWaker::new();
+        let (queue, pusher) = queue();
+        let mut chunks = queue
+            .chunks_timeout(3, Duration::from_millis(50))
+            .with_timer(&timer);
+
+        pusher.push(1);
+        pusher.push(2);
+        poll_once(pin!(chunks.next()), &waker).assert_registered_waker();
+        timer.advance(Duration::from_millis(50));
+        assert_eq!(waker.counts().total_wakes(), 1);
+        assert_eq!(
+            poll_once(pin!(chunks.next()), &waker).assert_ready(),
+            Some(vec![1, 2])
+        );
+
+        for value in [3, 4, 5, 6] {
+            pusher.push(value);
+        }
+        assert_eq!(
+            poll_once(pin!(chunks.next()), &waker).assert_ready(),
+            Some(vec![3, 4, 5])
+        );
+
+        pusher.close();
+        assert_eq!(
+            poll_once(pin!(chunks.next()), &waker).assert_ready(),
+            Some(vec![6])
+        );
+        assert_eq!(poll_once(pin!(chunks.next()), &waker).assert_ready(), None);
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::Stream;
+
+/// The stream returned by [`StreamExt::filter`].
+///
+/// [`StreamExt::filter`]: fn@super::StreamExt::filter
+pub struct Filter<S, F> {
+    stream: Pin<Box<S>>,
+    predicate: F,
+}
+
+impl<S, F> Filter<S, F> {
+    pub(super) fn new(stream: S, predicate: F) -> Self {
+        Self {
+            stream: Box::pin(stream),
+            predicate,
+        }
+    }
+}
+
+// Closures aren't `Debug`, so `predicate` is left out.
+impl<S: fmt::Debug, F> fmt::Debug for Filter<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Filter")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, F> Stream for Filter<S, F>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> bool + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        // Keep polling until an item passes, or the inner stream is pending.
+        // A stream which is always ready and never passes would keep us here,
+        // just like an iterator would.
+        loop {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) if (this.predicate)(&item) => {
+                    return Poll::Ready(Some(item))
+                }
+                Poll::Ready(Some(_)) => {}
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use crate::stream::{self, StreamExt};
+    use crate::testing::fixtures::queue;
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn filter_skips_items_until_one_passes() {
+        let waker = MockWaker::new();
+        let mut squares = stream::iter(1..)
+            .map(|x: u64| x * x)
+            .filter(|x| x % 2 == 0)
+            .take(3);
+
+        for expected in [4, 16, 36] {
+            let item = poll_once(pin!(squares.next()), &waker).assert_ready();
+            assert_eq!(item, Some(expected));
+        }
+        assert_eq!(poll_once(pin!(squares.next()), &waker).assert_ready(), None);
+        assert_eq!(waker.counts().total_wakes(), 0);
+    }
+
+    #[test]
+    fn filter_is_pending_when_stream_is() {
+        let waker = MockWaker::new();
+        let (queue, pusher) = queue();
+        let mut odd = queue.filter(|x| x % 2 == 1);
+
+        pusher.push(2);
+        poll_once(pin!(odd.next()), &waker).assert_registered_waker();
+        pusher.push(3);
+        assert_eq!(waker.counts().total_wakes(), 1);
+        assert_eq!(poll_once(pin!(odd.next()), &waker).assert_ready(), Some(3));
+    }
+
+    #[test]
+    fn filter_with_closure_is_debug() {
+        let limit = 2;
+        let filter = stream::iter(1..4).filter(move |x: &u64| *x < limit);
+        assert!(format!("{filter:?}").starts_with("Filter { stream: "));
+    }
+}
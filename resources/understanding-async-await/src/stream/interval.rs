@@ -0,0 +1,81 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use super::Stream;
+use crate::time::{self, Interval};
+
+/// Creates a stream which yields an instant every `period`, using the global
+/// timer.
+///
+/// The first item is yielded straight away. See [`time::interval`] for how
+/// late ticks are handled.
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+///
+/// [`time::interval`]: fn@crate::time::interval
+pub fn interval(period: Duration) -> IntervalStream {
+    IntervalStream::new(time::interval(period))
+}
+
+/// A stream which yields the ticks of an [`Interval`].
+///
+/// Use [`new`] with an interval from a paused [`Timer`] to control time in
+/// tests.
+///
+/// [`new`]: fn@Self::new
+/// [`Timer`]: struct@crate::time::Timer
+#[derive(Debug)]
+pub struct IntervalStream {
+    interval: Interval,
+}
+
+impl IntervalStream {
+    /// Creates a stream which yields the ticks of `interval`.
+    pub fn new(interval: Interval) -> Self {
+        Self { interval }
+    }
+}
+
+impl Stream for IntervalStream {
+    type Item = Instant;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.interval.poll_tick(cx).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+    use std::time::Duration;
+
+    use super::IntervalStream;
+    use crate::stream::StreamExt;
+    use crate::testing::{poll_once, MockWaker};
+    use crate::time::Timer;
+
+    #[test]
+    fn interval_ticks_when_the_timer_advances() {
+        let timer = Timer::paused();
+        let start = timer.now();
+        let waker = MockWaker::new();
+        let mut ticks = IntervalStream::new(timer.interval(Duration::from_millis(10)));
+
+        assert_eq!(
+            poll_once(pin!(ticks.next()), &waker).assert_ready(),
+            Some(start)
+        );
+        poll_once(pin!(ticks.next()), &waker).assert_registered_waker();
+        timer.advance(Duration::from_millis(5));
+        assert_eq!(waker.counts().total_wakes(), 0);
+        timer.advance(Duration::from_millis(5));
+        assert_eq!(waker.counts().total_wakes(), 1);
+        assert_eq!(
+            poll_once(pin!(ticks.next()), &waker).assert_ready(),
+            Some(start + Duration::from_millis(10))
+        );
+    }
+}
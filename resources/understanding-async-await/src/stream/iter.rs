@@ -0,0 +1,27 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::Stream;
+
+/// Creates a stream which yields the items of an iterator.
+///
+/// The stream is always ready, each poll returns the next item straight away.
+pub fn iter<I: IntoIterator>(iter: I) -> Iter<I::IntoIter> {
+    Iter {
+        iter: iter.into_iter(),
+    }
+}
+
+/// The stream returned by [`iter`].
+#[derive(Debug)]
+pub struct Iter<I> {
+    iter: I,
+}
+
+impl<I: Iterator + Unpin> Stream for Iter<I> {
+    type Item = I::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.iter.next())
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::Stream;
+
+/// The stream returned by [`StreamExt::map`].
+///
+/// [`StreamExt::map`]: fn@super::StreamExt::map
+pub struct Map<S, F> {
+    stream: Pin<Box<S>>,
+    f: F,
+}
+
+impl<S, F> Map<S, F> {
+    pub(super) fn new(stream: S, f: F) -> Self {
+        Self {
+            stream: Box::pin(stream),
+            f,
+        }
+    }
+}
+
+// Closures aren't `Debug`, so `f` is left out.
+impl<S: fmt::Debug, F> fmt::Debug for Map<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Map")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, F, T> Stream for Map<S, F>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> T + Unpin,
+{
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        match this.stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(item)) => Poll::Ready(Some((this.f)(item))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use crate::stream::{self, StreamExt};
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn map_applies_function_to_each_item() {
+        let waker = MockWaker::new();
+        let mut squares = stream::iter(1..4).map(|x: u64| x * x);
+
+        for expected in [1, 4, 9] {
+            let item = poll_once(pin!(squares.next()), &waker).assert_ready();
+            assert_eq!(item, Some(expected));
+        }
+        assert_eq!(poll_once(pin!(squares.next()), &waker).assert_ready(), None);
+        // Nothing was ever pending, so nothing needed waking.
+        assert_eq!(waker.counts().total_wakes(), 0);
+    }
+
+    #[test]
+    fn map_with_closure_is_debug() {
+        let offset = 1;
+        let map = stream::iter(1..4).map(move |x: u64| x + offset);
+        assert!(format!("{map:?}").starts_with("Map { stream: "));
+    }
+}
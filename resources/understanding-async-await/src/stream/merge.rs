@@ -0,0 +1,117 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::Stream;
+
+/// Merges two streams with the same item type into one.
+///
+/// Items are yielded as soon as either stream has one. The stream which is
+/// polled first alternates on each poll, so a stream which is always ready
+/// can't starve the other one. The merged stream ends once both streams have
+/// ended.
+pub fn merge<A, B>(a: A, b: B) -> Merge<A, B>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+{
+    Merge {
+        a: Some(Box::pin(a)),
+        b: Some(Box::pin(b)),
+        b_first: false,
+    }
+}
+
+/// The stream returned by [`merge`].
+#[derive(Debug)]
+pub struct Merge<A, B> {
+    /// Each stream is `None` once it has ended.
+    a: Option<Pin<Box<A>>>,
+    b: Option<Pin<Box<B>>>,
+    /// Whether `b` is polled first on the next poll.
+    b_first: bool,
+}
+
+impl<A, B> Stream for Merge<A, B>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+{
+    type Item = A::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let b_first = this.b_first;
+        this.b_first = !b_first;
+
+        for poll_b in [b_first, !b_first] {
+            let item = if poll_b {
+                poll_side(&mut this.b, cx)
+            } else {
+                poll_side(&mut this.a, cx)
+            };
+            if let Some(item) = item {
+                return Poll::Ready(Some(item));
+            }
+        }
+
+        if this.a.is_none() && this.b.is_none() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Polls one of the merged streams, dropping it once it has ended.
+fn poll_side<S: Stream>(side: &mut Option<Pin<Box<S>>>, cx: &mut Context<'_>) -> Option<S::Item> {
+    let stream = side.as_mut()?;
+    match stream.as_mut().poll_next(cx) {
+        Poll::Ready(Some(item)) => Some(item),
+        Poll::Ready(None) => {
+            *side = None;
+            None
+        }
+        Poll::Pending => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use crate::stream::{self, StreamExt};
+    use crate::testing::fixtures::queue;
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn merge_alternates_between_streams() {
+        let waker = MockWaker::new();
+        let mut merged = stream::merge(stream::iter([1, 3, 5]), stream::iter([2, 4]));
+
+        for expected in [1, 2, 3, 4, 5] {
+            let item = poll_once(pin!(merged.next()), &waker).assert_ready();
+            assert_eq!(item, Some(expected));
+        }
+        assert_eq!(poll_once(pin!(merged.next()), &waker).assert_ready(), None);
+    }
+
+    #[test]
+    fn merge_waits_for_either_stream() {
+        let waker = MockWaker::new();
+        let (a, push_a) = queue();
+        let (b, push_b) = queue();
+        let mut merged = stream::merge(a, b);
+
+        poll_once(pin!(merged.next()), &waker).assert_registered_waker();
+        push_b.push(2);
+        assert_eq!(waker.counts().total_wakes(), 1);
+        assert_eq!(
+            poll_once(pin!(merged.next()), &waker).assert_ready(),
+            Some(2)
+        );
+
+        push_a.close();
+        push_b.close();
+        assert_eq!(poll_once(pin!(merged.next()), &waker).assert_ready(), None);
+    }
+}
@@ -0,0 +1,74 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::Stream;
+
+/// The stream returned by [`StreamExt::take`].
+///
+/// [`StreamExt::take`]: fn@super::StreamExt::take
+#[derive(Debug)]
+pub struct Take<S> {
+    /// `None` once the stream has ended, so that it's dropped straight away.
+    stream: Option<Pin<Box<S>>>,
+    remaining: usize,
+}
+
+impl<S> Take<S> {
+    pub(super) fn new(stream: S, n: usize) -> Self {
+        Self {
+            stream: (n > 0).then(|| Box::pin(stream)),
+            remaining: n,
+        }
+    }
+}
+
+impl<S: Stream> Stream for Take<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let Some(stream) = &mut this.stream else {
+            return Poll::Ready(None);
+        };
+
+        match stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                this.remaining -= 1;
+                if this.remaining == 0 {
+                    this.stream = None;
+                }
+                Poll::Ready(Some(item))
+            }
+            Poll::Ready(None) => {
+                this.stream = None;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use crate::stream::{self, StreamExt};
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn take_ends_after_n_items() {
+        let waker = MockWaker::new();
+        let mut taken = stream::iter(1..).take(2);
+
+        assert_eq!(
+            poll_once(pin!(taken.next()), &waker).assert_ready(),
+            Some(1)
+        );
+        assert_eq!(
+            poll_once(pin!(taken.next()), &waker).assert_ready(),
+            Some(2)
+        );
+        assert_eq!(poll_once(pin!(taken.next()), &waker).assert_ready(), None);
+        assert_eq!(poll_once(pin!(taken.next()), &waker).assert_ready(), None);
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::Stream;
+
+/// Creates a stream from a seed value and an async function.
+///
+/// The function is called with the current value and returns a future. If
+/// the future returns `Some((item, next))`, the stream yields `item` and the
+/// function is called with `next` for the following item. If it returns
+/// `None`, the stream ends.
+pub fn unfold<T, F, Fut, Item>(init: T, f: F) -> Unfold<T, F, Fut>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = Option<(Item, T)>>,
+{
+    Unfold {
+        f,
+        state: UnfoldState::Value { value: init },
+    }
+}
+
+/// The stream returned by [`unfold`].
+pub struct Unfold<T, F, Fut> {
+    f: F,
+    state: UnfoldState<T, Fut>,
+}
+
+enum UnfoldState<T, Fut> {
+    /// Waiting for the next poll, to call the function with this value.
+    Value {
+        value: T,
+    },
+    /// Waiting for the function's future to complete.
+    Future {
+        future: Pin<Box<Fut>>,
+    },
+    Done,
+}
+
+// Neither the closure nor its future are usually `Debug`, so only the value
+// is shown.
+impl<T: fmt::Debug, F, Fut> fmt::Debug for Unfold<T, F, Fut> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Unfold");
+        match &self.state {
+            UnfoldState::Value { value } => debug.field("value", value),
+            UnfoldState::Future { .. } => debug.field("state", &"Future"),
+            UnfoldState::Done => debug.field("state", &"Done"),
+        };
+        debug.finish_non_exhaustive()
+    }
+}
+
+impl<T, F, Fut, Item> Stream for Unfold<T, F, Fut>
+where
+    T: Unpin,
+    F: FnMut(T) -> Fut + Unpin,
+    Fut: Future<Output = Option<(Item, T)>>,
+{
+    type Item = Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            match std::mem::replace(&mut this.state, UnfoldState::Done) {
+                UnfoldState::Value { value } => {
+                    this.state = UnfoldState::Future {
+                        future: Box::pin((this.f)(value)),
+                    };
+                }
+                UnfoldState::Future { mut future } => match future.as_mut().poll(cx) {
+                    Poll::Ready(Some((item, value))) => {
+                        this.state = UnfoldState::Value { value };
+                        return Poll::Ready(Some(item));
+                    }
+                    Poll::Ready(None) => return Poll::Ready(None),
+                    Poll::Pending => {
+                        this.state = UnfoldState::Future { future };
+                        return Poll::Pending;
+                    }
+                },
+                UnfoldState::Done => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use crate::stream::{self, StreamExt};
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn unfold_threads_state_through() {
+        let waker = MockWaker::new();
+        let mut countdown = stream::unfold(3, |n| async move {
+            if n == 0 {
+                None
+            } else {
+                Some((n, n - 1))
+            }
+        });
+        assert_eq!(format!("{countdown:?}"), "Unfold { value: 3, .. }");
+
+        for expected in [3, 2, 1] {
+            let item = poll_once(pin!(countdown.next()), &waker).assert_ready();
+            assert_eq!(item, Some(expected));
+        }
+        assert_eq!(
+            poll_once(pin!(countdown.next()), &waker).assert_ready(),
+            None
+        );
+        // Once it has ended, it stays ended.
+        assert_eq!(
+            poll_once(pin!(countdown.next()), &waker).assert_ready(),
+            None
+        );
+    }
+}
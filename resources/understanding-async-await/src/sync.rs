@@ -0,0 +1,34 @@
+//! Synchronization primitives for use in async code.
+//!
+//! Unlike their counterparts in `std::sync`, these never block the thread.
+//! Waiting is done by returning `Poll::Pending` and waking the task once it
+//! can make progress.
+mod barrier;
+mod mutex;
+mod notify;
+mod semaphore;
+
+pub use barrier::{Barrier, BarrierWaitResult, Wait};
+pub use mutex::{Lock, LockOwned, Mutex, MutexGuard, OwnedMutexGuard};
+pub use notify::{Notified, Notify};
+pub use semaphore::{
+    Acquire, AcquireError, AcquireOwned, OwnedSemaphorePermit, Semaphore, SemaphorePermit,
+    TryAcquireError,
+};
+
+/// Emits a trace event for an operation on a waker held by one of our
+/// primitives.
+///
+/// The events mirror tokio's `tokio::task::waker` events, which have an `op`
+/// field such as `waker.clone` or `waker.wake`. We don't know the id of the
+/// task which the waker belongs to, so instead we record the primitive, the
+/// method which performed the operation and the id of the waiter.
+fn trace_waker(kind: &'static str, method: &'static str, op: &'static str, waiter_id: u64) {
+    tracing::trace!(
+        target: "understanding_async_await::sync::waker",
+        op,
+        resource.kind = kind,
+        resource.method = method,
+        waiter.id = waiter_id,
+    );
+}
@@ -0,0 +1,242 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use super::trace_waker;
+
+/// A barrier which lets a number of tasks wait until they have all reached
+/// the same point.
+///
+/// Each task calls [`wait`]. The first `n - 1` tasks to do so wait, when the
+/// `n`th task arrives, all of them are released. One of the tasks, the last to
+/// arrive, is told that it is the leader, see [`BarrierWaitResult`].
+///
+/// The barrier can be reused, once a group of tasks has been released, the
+/// next `n` calls to `wait` form the next group.
+///
+/// Every waker operation emits a trace event with the target
+/// `understanding_async_await::sync::waker`, in the same style as tokio's
+/// `tokio::task::waker` events.
+///
+/// [`wait`]: fn@Self::wait
+pub struct Barrier {
+    state: std::sync::Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// The number of tasks in each group.
+    n: usize,
+    /// The tasks in the current group which are waiting, together with the
+    /// id of the waiter.
+    waiters: Vec<(u64, Waker)>,
+    /// The id which will be given to the next waiter.
+    next_id: u64,
+    /// Incremented each time a group is released.
+    generation: u64,
+}
+
+impl Barrier {
+    /// Creates a new barrier which releases tasks in groups of `n`.
+    ///
+    /// A barrier for 0 tasks behaves like a barrier for 1, every call to
+    /// `wait` completes straight away.
+    pub fn new(n: usize) -> Self {
+        Self {
+            state: std::sync::Mutex::new(State {
+                n: n.max(1),
+                waiters: Vec::new(),
+                next_id: 0,
+                generation: 0,
+            }),
+        }
+    }
+
+    /// Waits until `n` tasks have called `wait`.
+    ///
+    /// A task which arrives counts as waiting from the first time the
+    /// returned future is polled. If the future is dropped before the group
+    /// is released, the task leaves the group again and another task will be
+    /// needed to take its place.
+    pub fn wait(&self) -> Wait<'_> {
+        Wait {
+            barrier: self,
+            waiting: None,
+        }
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(_) => panic!("Barrier state has become corrupted."),
+        }
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock_state();
+        f.debug_struct("Barrier")
+            .field("n", &state.n)
+            .field("waiting", &state.waiters.len())
+            .field("generation", &state.generation)
+            .finish()
+    }
+}
+
+/// The result of waiting on a [`Barrier`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    is_leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// Returns whether this task was the leader of its group.
+    ///
+    /// Exactly one task in each group is the leader, the one which arrived
+    /// last and released the others.
+    pub fn is_leader(&self) -> bool {
+        self.is_leader
+    }
+}
+
+/// The future returned by [`Barrier::wait`].
+pub struct Wait<'a> {
+    barrier: &'a Barrier,
+    /// The waiter id and the generation of the group this task joined, once
+    /// it has arrived at the barrier.
+    waiting: Option<(u64, u64)>,
+}
+
+impl Future for Wait<'_> {
+    type Output = BarrierWaitResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut state = this.barrier.lock_state();
+        let Some((id, generation)) = this.waiting else {
+            if state.waiters.len() + 1 < state.n {
+                let id = state.next_id;
+                state.next_id += 1;
+                trace_waker("Barrier", "wait", "waker.clone", id);
+                state.waiters.push((id, cx.waker().clone()));
+                this.waiting = Some((id, state.generation));
+                return Poll::Pending;
+            }
+
+            // This is the last task of the group, release everyone.
+            state.generation += 1;
+            let waiters = std::mem::take(&mut state.waiters);
+            drop(state);
+            for (id, waker) in waiters {
+                trace_waker("Barrier", "wait", "waker.wake", id);
+                waker.wake();
+            }
+            return Poll::Ready(BarrierWaitResult { is_leader: true });
+        };
+
+        if state.generation != generation {
+            this.waiting = None;
+            return Poll::Ready(BarrierWaitResult { is_leader: false });
+        }
+        if let Some((_, waker)) = state.waiters.iter_mut().find(|(w, _)| *w == id) {
+            if !waker.will_wake(cx.waker()) {
+                trace_waker("Barrier", "wait", "waker.drop", id);
+                trace_waker("Barrier", "wait", "waker.clone", id);
+                waker.clone_from(cx.waker());
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Wait<'_> {
+    fn drop(&mut self) {
+        let Some((id, generation)) = self.waiting else {
+            return;
+        };
+
+        let mut state = self.barrier.lock_state();
+        // If the group has already been released, there is nothing to undo.
+        if state.generation == generation {
+            if let Some(position) = state.waiters.iter().position(|(w, _)| *w == id) {
+                state.waiters.swap_remove(position);
+                trace_waker("Barrier", "wait", "waker.drop", id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use super::Barrier;
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn last_task_to_arrive_is_the_leader() {
+        let barrier = Barrier::new(3);
+        let wakers = [MockWaker::new(), MockWaker::new(), MockWaker::new()];
+        let mut first = pin!(barrier.wait());
+        let mut second = pin!(barrier.wait());
+
+        poll_once(first.as_mut(), &wakers[0]).assert_registered_waker();
+        poll_once(second.as_mut(), &wakers[1]).assert_registered_waker();
+        let last = poll_once(pin!(barrier.wait()), &wakers[2]).assert_ready();
+
+        assert!(last.is_leader());
+        assert_eq!(wakers[0].counts().total_wakes(), 1);
+        assert_eq!(wakers[1].counts().total_wakes(), 1);
+        assert!(!poll_once(first.as_mut(), &wakers[0])
+            .assert_ready()
+            .is_leader());
+        assert!(!poll_once(second.as_mut(), &wakers[1])
+            .assert_ready()
+            .is_leader());
+    }
+
+    #[test]
+    fn barrier_can_be_reused() {
+        let barrier = Barrier::new(2);
+        let waker = MockWaker::new();
+
+        for _ in 0..3 {
+            let mut first = pin!(barrier.wait());
+            poll_once(first.as_mut(), &waker).assert_registered_waker();
+            assert!(poll_once(pin!(barrier.wait()), &waker)
+                .assert_ready()
+                .is_leader());
+            assert!(!poll_once(first.as_mut(), &waker).assert_ready().is_leader());
+        }
+    }
+
+    #[test]
+    fn dropped_waiter_leaves_the_group() {
+        let barrier = Barrier::new(2);
+        let (dropped_waker, waker) = (MockWaker::new(), MockWaker::new());
+        let mut dropped = Box::pin(barrier.wait());
+
+        poll_once(dropped.as_mut(), &dropped_waker).assert_registered_waker();
+        drop(dropped);
+        assert_eq!(dropped_waker.live(), 0);
+
+        let mut first = pin!(barrier.wait());
+        poll_once(first.as_mut(), &waker).assert_registered_waker();
+        assert!(poll_once(pin!(barrier.wait()), &waker)
+            .assert_ready()
+            .is_leader());
+    }
+
+    #[test]
+    fn barrier_for_zero_tasks_never_waits() {
+        let barrier = Barrier::new(0);
+        let waker = MockWaker::new();
+
+        assert!(poll_once(pin!(barrier.wait()), &waker)
+            .assert_ready()
+            .is_leader());
+    }
+}
@@ -0,0 +1,523 @@
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+/// An async mutual exclusion lock.
+///
+/// Locking the mutex returns a future which waits for the lock, instead of
+/// blocking the thread like [`std::sync::Mutex`] does. This means that the
+/// [`MutexGuard`] can be held across an `.await`. If another task on the same
+/// thread tries to lock the mutex while the guard is held, it will wait its
+/// turn instead of blocking the thread (and with it, the task holding the
+/// guard).
+///
+/// Tasks acquire the lock in the order in which they started waiting for it.
+/// When the lock is released, it is handed directly to the task at the front
+/// of the queue, so a task which calls [`lock`] later can't take it first.
+///
+/// Unlike `std::sync::Mutex`, this mutex isn't poisoned if a task panics
+/// while holding the lock.
+///
+/// [`lock`]: fn@Self::lock
+pub struct Mutex<T: ?Sized> {
+    state: std::sync::Mutex<State>,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: The mutex only gives out access to the value to one guard at a time,
+// so it can be sent and shared between threads as long as the value can be
+// sent between them.
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+/// The state of the lock, protected by a (synchronous) std mutex.
+///
+/// The std mutex is only held while the state is being checked or modified,
+/// never while the async lock is held.
+#[derive(Debug)]
+struct State {
+    /// Whether the lock is held, or has been handed to a waiter which hasn't
+    /// taken it yet.
+    locked: bool,
+    /// Tasks waiting for the lock in FIFO order, together with the id of the
+    /// waiter which registered them.
+    waiters: VecDeque<(u64, Waker)>,
+    /// The id which will be given to the next waiter.
+    next_id: u64,
+    /// The waiter which the lock has been handed to, but which hasn't been
+    /// polled since.
+    granted: Option<u64>,
+}
+
+impl<T> Mutex<T> {
+    /// Creates a new unlocked mutex containing `value`.
+    pub fn new(value: T) -> Self {
+        Self {
+            state: std::sync::Mutex::new(State {
+                locked: false,
+                waiters: VecDeque::new(),
+                next_id: 0,
+                granted: None,
+            }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Consumes the mutex, returning the value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Locks the mutex, waiting until the lock is available.
+    ///
+    /// The lock is released when the returned guard is dropped.
+    ///
+    /// This method is cancel safe. If the returned future is dropped before
+    /// it completes, it gives up its place in the queue. If the lock had
+    /// already been handed to it, the lock is passed on to the next waiter.
+    pub fn lock(&self) -> Lock<'_, T> {
+        Lock {
+            mutex: self,
+            waiter_id: None,
+        }
+    }
+
+    /// Locks the mutex, returning a guard which owns a reference to it.
+    ///
+    /// This works like [`lock`], but the guard keeps the mutex alive rather
+    /// than borrowing it. This is useful when the guard has to be stored in a
+    /// struct, such as a hand-written future, which can't borrow from the
+    /// mutex.
+    ///
+    /// [`lock`]: fn@Self::lock
+    pub fn lock_owned(self: Arc<Self>) -> LockOwned<T> {
+        LockOwned {
+            mutex: Some(self),
+            waiter_id: None,
+        }
+    }
+
+    /// Locks the mutex if the lock is available right now.
+    ///
+    /// Returns `None` if the lock is held or other tasks are already waiting
+    /// for it.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let mut state = self.lock_state();
+        if state.locked {
+            return None;
+        }
+        state.locked = true;
+
+        Some(MutexGuard { mutex: self })
+    }
+
+    /// Returns a mutable reference to the value.
+    ///
+    /// Since this borrows the mutex mutably, no locking is needed.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Tries to acquire the lock on behalf of a waiter.
+    ///
+    /// On the first call, `waiter_id` is `None`. If the lock is free and
+    /// nobody is waiting, it is taken straight away. Otherwise the waiter
+    /// joins the back of the queue and is given an id. On later calls, the
+    /// waiter takes the lock if it has been handed to it, otherwise its waker
+    /// is updated.
+    fn poll_acquire(&self, waiter_id: &mut Option<u64>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.lock_state();
+        match *waiter_id {
+            None => {
+                if !state.locked {
+                    state.locked = true;
+                    return Poll::Ready(());
+                }
+                let id = state.next_id;
+                state.next_id += 1;
+                state.waiters.push_back((id, cx.waker().clone()));
+                *waiter_id = Some(id);
+                Poll::Pending
+            }
+            Some(id) => {
+                if state.granted == Some(id) {
+                    state.granted = None;
+                    *waiter_id = None;
+                    return Poll::Ready(());
+                }
+                if let Some((_, waker)) = state.waiters.iter_mut().find(|(w, _)| *w == id) {
+                    waker.clone_from(cx.waker());
+                }
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Stops waiting for the lock.
+    ///
+    /// If the lock was already handed to this waiter, it is released again so
+    /// that the next waiter gets it.
+    fn cancel_acquire(&self, id: u64) {
+        let mut state = self.lock_state();
+        if state.granted == Some(id) {
+            state.granted = None;
+            let waker = Self::release(&mut state);
+            drop(state);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        } else if let Some(position) = state.waiters.iter().position(|(w, _)| *w == id) {
+            state.waiters.remove(position);
+        }
+    }
+
+    /// Releases the lock, handing it to the next waiter if there is one.
+    fn unlock(&self) {
+        let waker = Self::release(&mut self.lock_state());
+        // Wake outside of the state lock, in case the waker polls the task
+        // straight away.
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Hands the lock to the next waiter, or marks it as free.
+    ///
+    /// Returns the waker of the waiter which was given the lock.
+    fn release(state: &mut State) -> Option<Waker> {
+        match state.waiters.pop_front() {
+            Some((id, waker)) => {
+                state.granted = Some(id);
+                Some(waker)
+            }
+            None => {
+                state.locked = false;
+                None
+            }
+        }
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(_) => panic!("Mutex state has become corrupted."),
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for Mutex<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        match self.try_lock() {
+            Some(guard) => d.field("value", &&*guard),
+            None => d.field("value", &format_args!("<locked>")),
+        };
+        d.finish()
+    }
+}
+
+/// The future returned by [`Mutex::lock`].
+pub struct Lock<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+    /// The id given to this future when it joined the queue.
+    waiter_id: Option<u64>,
+}
+
+impl<'a, T: ?Sized> Future for Lock<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        match this.mutex.poll_acquire(&mut this.waiter_id, cx) {
+            Poll::Ready(()) => Poll::Ready(MutexGuard { mutex: this.mutex }),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for Lock<'_, T> {
+    fn drop(&mut self) {
+        if let Some(id) = self.waiter_id {
+            self.mutex.cancel_acquire(id);
+        }
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Lock<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lock")
+            .field("waiter_id", &self.waiter_id)
+            .finish()
+    }
+}
+
+/// The future returned by [`Mutex::lock_owned`].
+pub struct LockOwned<T: ?Sized> {
+    /// Taken when the lock is acquired and moved into the guard.
+    mutex: Option<Arc<Mutex<T>>>,
+    /// The id given to this future when it joined the queue.
+    waiter_id: Option<u64>,
+}
+
+impl<T: ?Sized> Future for LockOwned<T> {
+    type Output = OwnedMutexGuard<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let Some(mutex) = &this.mutex else {
+            panic!("LockOwned polled after completion");
+        };
+        match mutex.poll_acquire(&mut this.waiter_id, cx) {
+            Poll::Ready(()) => Poll::Ready(OwnedMutexGuard {
+                mutex: this.mutex.take().expect("checked above"),
+            }),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for LockOwned<T> {
+    fn drop(&mut self) {
+        if let (Some(mutex), Some(id)) = (&self.mutex, self.waiter_id) {
+            mutex.cancel_acquire(id);
+        }
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for LockOwned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockOwned")
+            .field("acquired", &self.mutex.is_none())
+            .field("waiter_id", &self.waiter_id)
+            .finish()
+    }
+}
+
+/// A guard which gives access to the value in a [`Mutex`].
+///
+/// The lock is released when the guard is dropped. The guard can be held
+/// across an `.await`.
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+// SAFETY: The guard gives out `&T`, so it can only be shared between threads if
+// `T` can be.
+unsafe impl<T: ?Sized + Send + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The guard holds the lock, so there is no other access.
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The guard holds the lock, so there is no other access.
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// A guard returned by [`Mutex::lock_owned`].
+///
+/// Works like [`MutexGuard`], but holds an `Arc` of the mutex instead of
+/// borrowing it, so it has no lifetime.
+pub struct OwnedMutexGuard<T: ?Sized> {
+    mutex: Arc<Mutex<T>>,
+}
+
+// SAFETY: The guard gives out `&T`, so it can only be shared between threads if
+// `T` can be.
+unsafe impl<T: ?Sized + Send + Sync> Sync for OwnedMutexGuard<T> {}
+
+impl<T: ?Sized> OwnedMutexGuard<T> {
+    /// Returns the mutex which this guard has locked.
+    pub fn mutex(this: &Self) -> &Arc<Mutex<T>> {
+        &this.mutex
+    }
+}
+
+impl<T: ?Sized> Deref for OwnedMutexGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The guard holds the lock, so there is no other access.
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for OwnedMutexGuard<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The guard holds the lock, so there is no other access.
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedMutexGuard<T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for OwnedMutexGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+    use std::sync::Arc;
+
+    use super::{Mutex, OwnedMutexGuard};
+    use crate::runtime::{self, Runtime};
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn lock_is_handed_to_waiters_in_fifo_order() {
+        let mutex = Mutex::new(0);
+        let (first_waker, second_waker) = (MockWaker::new(), MockWaker::new());
+        let guard = mutex.try_lock().unwrap();
+        let mut first = pin!(mutex.lock());
+        let mut second = pin!(mutex.lock());
+
+        poll_once(first.as_mut(), &first_waker).assert_registered_waker();
+        poll_once(second.as_mut(), &second_waker).assert_registered_waker();
+
+        drop(guard);
+        assert_eq!(first_waker.counts().total_wakes(), 1);
+        assert_eq!(second_waker.counts().total_wakes(), 0);
+        // A new lock call joins the back of the queue.
+        poll_once(pin!(mutex.lock()), &MockWaker::new()).assert_pending();
+
+        let guard = poll_once(first.as_mut(), &first_waker).assert_ready();
+        poll_once(second.as_mut(), &second_waker).assert_pending();
+        drop(guard);
+        assert_eq!(second_waker.counts().total_wakes(), 1);
+        poll_once(second.as_mut(), &second_waker).assert_ready();
+    }
+
+    #[test]
+    fn cancelled_lock_passes_on_a_granted_lock() {
+        let mutex = Mutex::new(0);
+        let (first_waker, second_waker) = (MockWaker::new(), MockWaker::new());
+        let guard = mutex.try_lock().unwrap();
+        let mut first = Box::pin(mutex.lock());
+        let mut second = pin!(mutex.lock());
+
+        poll_once(first.as_mut(), &first_waker).assert_registered_waker();
+        poll_once(second.as_mut(), &second_waker).assert_registered_waker();
+        drop(guard);
+        assert_eq!(first_waker.counts().total_wakes(), 1);
+
+        drop(first);
+        assert_eq!(second_waker.counts().total_wakes(), 1);
+        let mut guard = poll_once(second.as_mut(), &second_waker).assert_ready();
+        *guard += 1;
+        drop(guard);
+        assert_eq!(*mutex.try_lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn cancelled_waiter_leaves_the_queue() {
+        let mutex = Mutex::new(0);
+        let (first_waker, second_waker) = (MockWaker::new(), MockWaker::new());
+        let guard = mutex.try_lock().unwrap();
+        let mut first = Box::pin(mutex.lock());
+        let mut second = pin!(mutex.lock());
+
+        poll_once(first.as_mut(), &first_waker).assert_registered_waker();
+        poll_once(second.as_mut(), &second_waker).assert_registered_waker();
+        drop(first);
+        assert_eq!(first_waker.live(), 0);
+
+        drop(guard);
+        assert_eq!(second_waker.counts().total_wakes(), 1);
+        poll_once(second.as_mut(), &second_waker).assert_ready();
+    }
+
+    #[test]
+    fn try_lock_fails_while_waiters_are_queued() {
+        let mutex = Mutex::new(0);
+        let waker = MockWaker::new();
+        let guard = mutex.try_lock().unwrap();
+        assert!(mutex.try_lock().is_none());
+
+        let mut lock = pin!(mutex.lock());
+        poll_once(lock.as_mut(), &waker).assert_registered_waker();
+        drop(guard);
+        // The lock has been handed to the waiter, even though it hasn't been
+        // polled yet.
+        assert!(mutex.try_lock().is_none());
+
+        drop(poll_once(lock.as_mut(), &waker).assert_ready());
+        assert!(mutex.try_lock().is_some());
+    }
+
+    #[test]
+    fn owned_guards_are_held_across_awaits_in_other_tasks() {
+        let mutex = Arc::new(Mutex::new(Vec::new()));
+
+        let log = Runtime::multi_thread(2).block_on({
+            let mutex = Arc::clone(&mutex);
+            async move {
+                let tasks: Vec<_> = (0..4)
+                    .map(|id| {
+                        let mutex = Arc::clone(&mutex);
+                        runtime::spawn(async move {
+                            let mut guard = mutex.lock_owned().await;
+                            guard.push(id);
+                            runtime::yield_now().await;
+                            guard.push(id);
+                            Arc::clone(OwnedMutexGuard::mutex(&guard))
+                        })
+                    })
+                    .collect();
+                for task in tasks {
+                    assert!(Arc::ptr_eq(&task.await.unwrap(), &mutex));
+                }
+                mutex.lock().await.clone()
+            }
+        });
+
+        assert_eq!(log.len(), 8);
+        for pair in log.chunks(2) {
+            assert_eq!(pair[0], pair[1], "{log:?}");
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use super::trace_waker;
+
+/// Notifies a single task, or all waiting tasks, to wake up.
+///
+/// A task waits for a notification by awaiting [`notified`]. Another task
+/// sends one with [`notify_one`], which wakes the task which has been waiting
+/// longest, or [`notify_waiters`], which wakes every task waiting right now.
+///
+/// If `notify_one` is called while no task is waiting, a permit is stored and
+/// the next call to `notified` completes straight away. At most one permit is
+/// stored, calling `notify_one` several times with nobody waiting still only
+/// lets a single `notified` through. `notify_waiters` never stores a permit,
+/// but it does reach every [`Notified`] which was created before it was
+/// called, even one which hasn't been polled yet.
+///
+/// Every waker operation emits a trace event with the target
+/// `understanding_async_await::sync::waker`, in the same style as tokio's
+/// `tokio::task::waker` events.
+///
+/// [`notified`]: fn@Self::notified
+/// [`notify_one`]: fn@Self::notify_one
+/// [`notify_waiters`]: fn@Self::notify_waiters
+pub struct Notify {
+    state: std::sync::Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Whether a `notify_one` is stored for the next waiter.
+    permit: bool,
+    /// Tasks waiting for a notification in FIFO order.
+    waiters: VecDeque<(u64, Waker)>,
+    /// The id which will be given to the next waiter.
+    next_id: u64,
+    /// Waiters which have been notified, but haven't been polled since,
+    /// together with whether it was by `notify_one`.
+    notified: Vec<(u64, bool)>,
+    /// Incremented by each call to `notify_waiters`.
+    generation: u64,
+}
+
+impl Notify {
+    /// Creates a new `Notify` without a stored permit.
+    pub fn new() -> Self {
+        Self {
+            state: std::sync::Mutex::new(State {
+                permit: false,
+                waiters: VecDeque::new(),
+                next_id: 0,
+                notified: Vec::new(),
+                generation: 0,
+            }),
+        }
+    }
+
+    /// Waits for a notification.
+    ///
+    /// The returned future completes after any call to [`notify_waiters`]
+    /// made once it has been created, even if it hasn't been polled yet. It
+    /// only joins the queue for [`notify_one`] when it is first polled, so a
+    /// `notify_one` before that only counts if it leaves a permit.
+    ///
+    /// This method is cancel safe. If the returned future is dropped after it
+    /// was woken by `notify_one` but before it completed, the notification is
+    /// passed on to the next waiter, or stored as a permit.
+    ///
+    /// [`notify_one`]: fn@Self::notify_one
+    /// [`notify_waiters`]: fn@Self::notify_waiters
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            generation: self.lock_state().generation,
+            waiter_id: None,
+        }
+    }
+
+    /// Wakes the task which has been waiting the longest.
+    ///
+    /// If no task is waiting, a permit is stored for the next call to
+    /// [`notified`].
+    ///
+    /// [`notified`]: fn@Self::notified
+    pub fn notify_one(&self) {
+        let woken = {
+            let mut state = self.lock_state();
+            notify_one(&mut state)
+        };
+        wake("notify_one", woken);
+    }
+
+    /// Wakes all the tasks which are currently waiting.
+    ///
+    /// This also completes every [`Notified`] future which has been created
+    /// but not polled yet. No permit is stored, so a task which calls
+    /// [`notified`] afterwards will wait for the next notification.
+    ///
+    /// [`notified`]: fn@Self::notified
+    pub fn notify_waiters(&self) {
+        let woken: Vec<_> = {
+            let mut state = self.lock_state();
+            state.generation += 1;
+            let woken: Vec<_> = state.waiters.drain(..).collect();
+            state
+                .notified
+                .extend(woken.iter().map(|(id, _)| (*id, false)));
+            woken
+        };
+        for waiter in woken {
+            wake("notify_waiters", Some(waiter));
+        }
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(_) => panic!("Notify state has become corrupted."),
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Notify {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock_state();
+        f.debug_struct("Notify")
+            .field("permit", &state.permit)
+            .field("waiters", &state.waiters.len())
+            .finish()
+    }
+}
+
+/// Notifies the waiter at the front of the queue, or stores a permit.
+///
+/// Returns the waiter to wake.
+fn notify_one(state: &mut State) -> Option<(u64, Waker)> {
+    match state.waiters.pop_front() {
+        Some((id, waker)) => {
+            state.notified.push((id, true));
+            Some((id, waker))
+        }
+        None => {
+            state.permit = true;
+            None
+        }
+    }
+}
+
+/// Wakes a waiter outside of the state lock.
+fn wake(resource_op: &'static str, woken: Option<(u64, Waker)>) {
+    if let Some((id, waker)) = woken {
+        trace_waker("Notify", resource_op, "waker.wake", id);
+        waker.wake();
+    }
+}
+
+/// The future returned by [`Notify::notified`].
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    /// The number of calls to `notify_waiters` when this future was created.
+    generation: u64,
+    /// The id given to this future when it joined the queue.
+    waiter_id: Option<u64>,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut state = this.notify.lock_state();
+        let Some(id) = this.waiter_id else {
+            if state.generation != this.generation {
+                return Poll::Ready(());
+            }
+            if state.permit {
+                state.permit = false;
+                return Poll::Ready(());
+            }
+
+            let id = state.next_id;
+            state.next_id += 1;
+            trace_waker("Notify", "notified", "waker.clone", id);
+            state.waiters.push_back((id, cx.waker().clone()));
+            this.waiter_id = Some(id);
+            return Poll::Pending;
+        };
+
+        if let Some(position) = state.notified.iter().position(|(n, _)| *n == id) {
+            state.notified.swap_remove(position);
+            this.waiter_id = None;
+            return Poll::Ready(());
+        }
+        if let Some((_, waker)) = state.waiters.iter_mut().find(|(w, _)| *w == id) {
+            if !waker.will_wake(cx.waker()) {
+                trace_waker("Notify", "notified", "waker.drop", id);
+                trace_waker("Notify", "notified", "waker.clone", id);
+                waker.clone_from(cx.waker());
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        let Some(id) = self.waiter_id else {
+            return;
+        };
+
+        let woken = {
+            let mut state = self.notify.lock_state();
+            if let Some(position) = state.notified.iter().position(|(n, _)| *n == id) {
+                let (_, by_one) = state.notified.swap_remove(position);
+                // A `notify_one` which this waiter won't act on goes to the
+                // next one instead.
+                if by_one {
+                    notify_one(&mut state)
+                } else {
+                    None
+                }
+            } else {
+                if let Some(position) = state.waiters.iter().position(|(w, _)| *w == id) {
+                    state.waiters.remove(position);
+                    trace_waker("Notify", "notified", "waker.drop", id);
+                }
+                None
+            }
+        };
+        wake("notify_one", woken);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use super::Notify;
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn notify_one_stores_a_single_permit() {
+        let notify = Notify::new();
+        let waker = MockWaker::new();
+        notify.notify_one();
+        notify.notify_one();
+
+        poll_once(pin!(notify.notified()), &waker).assert_ready();
+        poll_once(pin!(notify.notified()), &waker).assert_registered_waker();
+    }
+
+    #[test]
+    fn notify_one_wakes_the_longest_waiting_task() {
+        let notify = Notify::new();
+        let (first_waker, second_waker) = (MockWaker::new(), MockWaker::new());
+        let mut first = pin!(notify.notified());
+        let mut second = pin!(notify.notified());
+
+        poll_once(first.as_mut(), &first_waker).assert_registered_waker();
+        poll_once(second.as_mut(), &second_waker).assert_registered_waker();
+        notify.notify_one();
+
+        assert_eq!(first_waker.counts().total_wakes(), 1);
+        assert_eq!(second_waker.counts().total_wakes(), 0);
+        poll_once(first.as_mut(), &first_waker).assert_ready();
+        poll_once(second.as_mut(), &second_waker).assert_pending();
+    }
+
+    #[test]
+    fn dropped_notified_passes_on_notify_one() {
+        let notify = Notify::new();
+        let (first_waker, second_waker) = (MockWaker::new(), MockWaker::new());
+        let mut first = Box::pin(notify.notified());
+        let mut second = Box::pin(notify.notified());
+
+        poll_once(first.as_mut(), &first_waker).assert_registered_waker();
+        poll_once(second.as_mut(), &second_waker).assert_registered_waker();
+        notify.notify_one();
+        drop(first);
+
+        assert_eq!(second_waker.counts().total_wakes(), 1);
+        poll_once(second.as_mut(), &second_waker).assert_ready();
+    }
+
+    #[test]
+    fn dropped_notified_stores_notify_one_as_a_permit() {
+        let notify = Notify::new();
+        let waker = MockWaker::new();
+        let mut notified = Box::pin(notify.notified());
+
+        poll_once(notified.as_mut(), &waker).assert_registered_waker();
+        notify.notify_one();
+        drop(notified);
+
+        poll_once(pin!(notify.notified()), &waker).assert_ready();
+    }
+
+    #[test]
+    fn notify_waiters_reaches_unpolled_notified() {
+        let notify = Notify::new();
+        let (waiting_waker, waker) = (MockWaker::new(), MockWaker::new());
+        let mut waiting = pin!(notify.notified());
+        let mut unpolled = pin!(notify.notified());
+
+        poll_once(waiting.as_mut(), &waiting_waker).assert_registered_waker();
+        notify.notify_waiters();
+        let mut later = pin!(notify.notified());
+
+        assert_eq!(waiting_waker.counts().total_wakes(), 1);
+        poll_once(waiting.as_mut(), &waiting_waker).assert_ready();
+        poll_once(unpolled.as_mut(), &waker).assert_ready();
+        // No permit was stored.
+        poll_once(later.as_mut(), &waker).assert_registered_waker();
+    }
+}
@@ -0,0 +1,584 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+/// An async counting semaphore with weighted permits.
+///
+/// The semaphore holds a number of permits. A task acquires one or more
+/// permits with [`acquire`], waiting until enough are available. The permits
+/// are returned to the semaphore when the [`SemaphorePermit`] is dropped.
+///
+/// Waiting tasks are served in strict FIFO order. A task asking for a large
+/// number of permits is never overtaken by tasks asking for fewer, even if
+/// there would be enough permits for the smaller request. Otherwise a large
+/// request could wait forever while a stream of small ones keep the permits
+/// in use.
+///
+/// Once the semaphore is [closed], waiting tasks and new calls to `acquire`
+/// fail with [`AcquireError`]. Permits which have already been acquired stay
+/// valid and are still returned when they are dropped.
+///
+/// [`acquire`]: fn@Self::acquire
+/// [closed]: fn@Self::close
+pub struct Semaphore {
+    state: std::sync::Mutex<State>,
+}
+
+/// The state of the semaphore, protected by a (synchronous) std mutex.
+#[derive(Debug)]
+struct State {
+    /// The permits available to be acquired.
+    permits: usize,
+    closed: bool,
+    /// Tasks waiting for permits in FIFO order.
+    waiters: VecDeque<Waiter>,
+    /// The id which will be given to the next waiter.
+    next_id: u64,
+    /// Waiters which have been assigned their permits, but haven't been
+    /// polled since.
+    granted: Vec<u64>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    id: u64,
+    /// The number of permits the waiter is asking for.
+    needed: usize,
+    waker: Waker,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the given number of permits.
+    pub fn new(permits: usize) -> Self {
+        Self {
+            state: std::sync::Mutex::new(State {
+                permits,
+                closed: false,
+                waiters: VecDeque::new(),
+                next_id: 0,
+                granted: Vec::new(),
+            }),
+        }
+    }
+
+    /// Returns the number of permits which are available right now.
+    pub fn available_permits(&self) -> usize {
+        self.lock_state().permits
+    }
+
+    /// Adds permits to the semaphore, waking waiters which can now acquire
+    /// theirs.
+    pub fn add_permits(&self, permits: usize) {
+        self.release(permits);
+    }
+
+    /// Acquires `permits` permits, waiting until they are available.
+    ///
+    /// Returns [`AcquireError`] if the semaphore is closed before the permits
+    /// could be acquired.
+    ///
+    /// This method is cancel safe. If the returned future is dropped before
+    /// it completes, it gives up its place in the queue. If permits had
+    /// already been assigned to it, they are returned to the semaphore.
+    pub fn acquire(&self, permits: usize) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            permits,
+            waiter_id: None,
+            done: false,
+        }
+    }
+
+    /// Acquires `permits` permits, returning a permit which owns a reference
+    /// to the semaphore.
+    ///
+    /// This works like [`acquire`], but the permit keeps the semaphore alive
+    /// rather than borrowing it, so it can be moved into a spawned task.
+    ///
+    /// [`acquire`]: fn@Self::acquire
+    pub fn acquire_owned(self: Arc<Self>, permits: usize) -> AcquireOwned {
+        AcquireOwned {
+            semaphore: Some(self),
+            permits,
+            waiter_id: None,
+        }
+    }
+
+    /// Acquires `permits` permits if they are available right now.
+    ///
+    /// This fails if other tasks are already waiting, even if there are
+    /// enough permits available, so that it doesn't jump the queue.
+    pub fn try_acquire(&self, permits: usize) -> Result<SemaphorePermit<'_>, TryAcquireError> {
+        self.try_take(permits)?;
+        Ok(SemaphorePermit {
+            semaphore: self,
+            permits,
+        })
+    }
+
+    /// Acquires `permits` permits if they are available right now, returning
+    /// a permit which owns a reference to the semaphore.
+    ///
+    /// See [`try_acquire`].
+    ///
+    /// [`try_acquire`]: fn@Self::try_acquire
+    pub fn try_acquire_owned(
+        self: Arc<Self>,
+        permits: usize,
+    ) -> Result<OwnedSemaphorePermit, TryAcquireError> {
+        self.try_take(permits)?;
+        Ok(OwnedSemaphorePermit {
+            semaphore: self,
+            permits,
+        })
+    }
+
+    /// Closes the semaphore.
+    ///
+    /// All waiting tasks are woken and will fail with [`AcquireError`], as
+    /// will any later attempt to acquire permits.
+    pub fn close(&self) {
+        let waiters: Vec<_> = {
+            let mut state = self.lock_state();
+            state.closed = true;
+            state.waiters.drain(..).collect()
+        };
+        for waiter in waiters {
+            waiter.waker.wake();
+        }
+    }
+
+    /// Returns whether the semaphore has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.lock_state().closed
+    }
+
+    fn try_take(&self, permits: usize) -> Result<(), TryAcquireError> {
+        let mut state = self.lock_state();
+        if state.closed {
+            return Err(TryAcquireError::Closed);
+        }
+        if !state.waiters.is_empty() || state.permits < permits {
+            return Err(TryAcquireError::NoPermits);
+        }
+        state.permits -= permits;
+
+        Ok(())
+    }
+
+    /// Tries to acquire permits on behalf of a waiter.
+    ///
+    /// On the first call, `waiter_id` is `None`. If nobody is waiting and
+    /// there are enough permits, they are taken straight away. Otherwise the
+    /// waiter joins the back of the queue and is given an id. On later calls,
+    /// the waiter completes if its permits have been assigned to it,
+    /// otherwise its waker is updated.
+    fn poll_acquire(
+        &self,
+        permits: usize,
+        waiter_id: &mut Option<u64>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), AcquireError>> {
+        let mut state = self.lock_state();
+        let Some(id) = *waiter_id else {
+            if state.closed {
+                return Poll::Ready(Err(AcquireError {}));
+            }
+            if state.waiters.is_empty() && state.permits >= permits {
+                state.permits -= permits;
+                return Poll::Ready(Ok(()));
+            }
+
+            let id = state.next_id;
+            state.next_id += 1;
+            state.waiters.push_back(Waiter {
+                id,
+                needed: permits,
+                waker: cx.waker().clone(),
+            });
+            *waiter_id = Some(id);
+            return Poll::Pending;
+        };
+
+        // Permits which were assigned before the semaphore was closed are
+        // still handed over.
+        if take(&mut state.granted, id) {
+            *waiter_id = None;
+            return Poll::Ready(Ok(()));
+        }
+        if state.closed {
+            *waiter_id = None;
+            return Poll::Ready(Err(AcquireError {}));
+        }
+        if let Some(waiter) = state.waiters.iter_mut().find(|waiter| waiter.id == id) {
+            waiter.waker.clone_from(cx.waker());
+        }
+
+        Poll::Pending
+    }
+
+    /// Stops waiting for permits.
+    ///
+    /// If permits were already assigned to this waiter, they are released
+    /// again. If the waiter was at the front of the queue, the waiters behind
+    /// it may now be able to acquire their permits.
+    fn cancel_acquire(&self, permits: usize, id: u64) {
+        let wakers = {
+            let mut state = self.lock_state();
+            if take(&mut state.granted, id) {
+                state.permits += permits;
+            } else if let Some(position) = state.waiters.iter().position(|w| w.id == id) {
+                state.waiters.remove(position);
+            }
+            assign(&mut state)
+        };
+        wake_all(wakers);
+    }
+
+    /// Returns permits to the semaphore and assigns them to waiters.
+    fn release(&self, permits: usize) {
+        let wakers = {
+            let mut state = self.lock_state();
+            state.permits += permits;
+            assign(&mut state)
+        };
+        // Wake outside of the state lock, in case a waker polls its task
+        // straight away.
+        wake_all(wakers);
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(_) => panic!("Semaphore state has become corrupted."),
+        }
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock_state();
+        f.debug_struct("Semaphore")
+            .field("permits", &state.permits)
+            .field("closed", &state.closed)
+            .field("waiters", &state.waiters.len())
+            .finish()
+    }
+}
+
+/// Assigns available permits to waiters from the front of the queue.
+///
+/// Stops at the first waiter which needs more permits than are available, so
+/// that smaller requests behind it don't overtake it. Returns the wakers of
+/// the waiters which were assigned permits.
+fn assign(state: &mut State) -> Vec<Waker> {
+    let mut wakers = Vec::new();
+    while let Some(waiter) = state.waiters.front() {
+        if waiter.needed > state.permits {
+            break;
+        }
+        let Some(waiter) = state.waiters.pop_front() else {
+            break;
+        };
+        state.permits -= waiter.needed;
+        state.granted.push(waiter.id);
+        wakers.push(waiter.waker);
+    }
+
+    wakers
+}
+
+/// Removes `id` from the list of granted waiters.
+///
+/// Returns whether it was found.
+fn take(granted: &mut Vec<u64>, id: u64) -> bool {
+    match granted.iter().position(|granted| *granted == id) {
+        Some(position) => {
+            granted.swap_remove(position);
+            true
+        }
+        None => false,
+    }
+}
+
+fn wake_all(wakers: Vec<Waker>) {
+    for waker in wakers {
+        waker.wake();
+    }
+}
+
+/// The future returned by [`Semaphore::acquire`].
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+    /// The id given to this future when it joined the queue.
+    waiter_id: Option<u64>,
+    /// Set once the future has returned `Poll::Ready`.
+    done: bool,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = Result<SemaphorePermit<'a>, AcquireError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if this.done {
+            panic!("Acquire polled after completion");
+        }
+        let poll = this
+            .semaphore
+            .poll_acquire(this.permits, &mut this.waiter_id, cx)
+            .map_ok(|()| SemaphorePermit {
+                semaphore: this.semaphore,
+                permits: this.permits,
+            });
+        this.done = poll.is_ready();
+
+        poll
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.waiter_id {
+            self.semaphore.cancel_acquire(self.permits, id);
+        }
+    }
+}
+
+/// The future returned by [`Semaphore::acquire_owned`].
+pub struct AcquireOwned {
+    /// Taken when the permits are acquired and moved into the permit.
+    semaphore: Option<Arc<Semaphore>>,
+    permits: usize,
+    /// The id given to this future when it joined the queue.
+    waiter_id: Option<u64>,
+}
+
+impl Future for AcquireOwned {
+    type Output = Result<OwnedSemaphorePermit, AcquireError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let Some(semaphore) = &this.semaphore else {
+            panic!("AcquireOwned polled after completion");
+        };
+        match semaphore.poll_acquire(this.permits, &mut this.waiter_id, cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(OwnedSemaphorePermit {
+                semaphore: this.semaphore.take().expect("checked above"),
+                permits: this.permits,
+            })),
+            Poll::Ready(Err(err)) => {
+                this.semaphore = None;
+                Poll::Ready(Err(err))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for AcquireOwned {
+    fn drop(&mut self) {
+        if let (Some(semaphore), Some(id)) = (&self.semaphore, self.waiter_id) {
+            semaphore.cancel_acquire(self.permits, id);
+        }
+    }
+}
+
+/// Permits acquired from a [`Semaphore`].
+///
+/// The permits are returned to the semaphore when this is dropped.
+#[derive(Debug)]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl SemaphorePermit<'_> {
+    /// Returns the number of permits held.
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Drops the permit without returning the permits to the semaphore.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.release(self.permits);
+        }
+    }
+}
+
+/// Permits acquired from a [`Semaphore`] with [`Semaphore::acquire_owned`].
+///
+/// Works like [`SemaphorePermit`], but holds an `Arc` of the semaphore
+/// instead of borrowing it, so it has no lifetime.
+#[derive(Debug)]
+pub struct OwnedSemaphorePermit {
+    semaphore: Arc<Semaphore>,
+    permits: usize,
+}
+
+impl OwnedSemaphorePermit {
+    /// Returns the number of permits held.
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Drops the permit without returning the permits to the semaphore.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for OwnedSemaphorePermit {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.release(self.permits);
+        }
+    }
+}
+
+/// Error returned when acquiring from a closed [`Semaphore`].
+#[derive(Debug)]
+pub struct AcquireError {}
+impl fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "semaphore closed")
+    }
+}
+impl Error for AcquireError {}
+
+/// Error returned from [`Semaphore::try_acquire`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum TryAcquireError {
+    /// The semaphore has been closed.
+    Closed,
+    /// There aren't enough permits available, or other tasks are waiting.
+    NoPermits,
+}
+impl fmt::Display for TryAcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Closed => write!(f, "semaphore closed"),
+            Self::NoPermits => write!(f, "no permits available"),
+        }
+    }
+}
+impl Error for TryAcquireError {}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+    use std::sync::Arc;
+
+    use super::{Semaphore, TryAcquireError};
+    use crate::testing::{poll_once, MockWaker};
+
+    #[test]
+    fn dropped_waiter_lets_the_one_behind_it_proceed() {
+        let semaphore = Semaphore::new(1);
+        let (large_waker, small_waker) = (MockWaker::new(), MockWaker::new());
+        let mut large = Box::pin(semaphore.acquire(2));
+        let mut small = pin!(semaphore.acquire(1));
+
+        poll_once(large.as_mut(), &large_waker).assert_registered_waker();
+        poll_once(small.as_mut(), &small_waker).assert_registered_waker();
+
+        drop(large);
+        assert_eq!(small_waker.counts().total_wakes(), 1);
+        let permit = poll_once(small.as_mut(), &small_waker).assert_ready();
+        assert_eq!(permit.unwrap().num_permits(), 1);
+    }
+
+    #[test]
+    fn permits_granted_to_a_dropped_waiter_are_returned() {
+        let semaphore = Semaphore::new(2);
+        let waker = MockWaker::new();
+        let permit = semaphore.try_acquire(2).unwrap();
+        let mut acquire = Box::pin(semaphore.acquire(2));
+
+        poll_once(acquire.as_mut(), &waker).assert_registered_waker();
+        drop(permit);
+        assert_eq!(waker.counts().total_wakes(), 1);
+        assert_eq!(semaphore.available_permits(), 0);
+
+        drop(acquire);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn large_request_isnt_overtaken_by_smaller_ones() {
+        let semaphore = Semaphore::new(3);
+        let (large_waker, small_waker) = (MockWaker::new(), MockWaker::new());
+        let permit = semaphore.try_acquire(2).unwrap();
+        let mut large = pin!(semaphore.acquire(2));
+        let mut small = pin!(semaphore.acquire(1));
+
+        // There is a permit free, but the large request is waiting for two.
+        poll_once(large.as_mut(), &large_waker).assert_registered_waker();
+        poll_once(small.as_mut(), &small_waker).assert_registered_waker();
+        assert_eq!(
+            semaphore.try_acquire(1).unwrap_err(),
+            TryAcquireError::NoPermits
+        );
+
+        drop(permit);
+        assert_eq!(large_waker.counts().total_wakes(), 1);
+        assert_eq!(small_waker.counts().total_wakes(), 1);
+        let _large = poll_once(large.as_mut(), &large_waker).assert_ready();
+        let _small = poll_once(small.as_mut(), &small_waker).assert_ready();
+        assert_eq!(semaphore.available_permits(), 0);
+    }
+
+    #[test]
+    fn close_fails_waiters_and_new_acquires() {
+        let semaphore = Arc::new(Semaphore::new(1));
+        let (waker, owned_waker) = (MockWaker::new(), MockWaker::new());
+        let permit = semaphore.try_acquire(1).unwrap();
+        let mut acquire = pin!(semaphore.acquire(1));
+        let mut owned = pin!(Arc::clone(&semaphore).acquire_owned(1));
+
+        poll_once(acquire.as_mut(), &waker).assert_registered_waker();
+        poll_once(owned.as_mut(), &owned_waker).assert_registered_waker();
+        semaphore.close();
+
+        assert_eq!(waker.counts().total_wakes(), 1);
+        assert_eq!(owned_waker.counts().total_wakes(), 1);
+        assert!(poll_once(acquire.as_mut(), &waker).assert_ready().is_err());
+        assert!(poll_once(owned.as_mut(), &owned_waker)
+            .assert_ready()
+            .is_err());
+        assert!(poll_once(pin!(semaphore.acquire(1)), &waker)
+            .assert_ready()
+            .is_err());
+        assert_eq!(
+            semaphore.try_acquire(1).unwrap_err(),
+            TryAcquireError::Closed
+        );
+
+        // Permits acquired before the close are still returned.
+        drop(permit);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Acquire polled after completion")]
+    fn repolled_acquire_panics() {
+        let semaphore = Semaphore::new(2);
+        let waker = MockWaker::new();
+        let mut acquire = pin!(semaphore.acquire(1));
+
+        let _permit = poll_once(acquire.as_mut(), &waker).assert_ready();
+        let _ = poll_once(acquire.as_mut(), &waker);
+    }
+}
@@ -0,0 +1,279 @@
+//! Tools for polling futures by hand and checking what they did with the
+//! waker.
+//!
+//! A [`MockWaker`] counts every operation on the wakers created from it:
+//! clones, wakes, wakes by reference and drops. [`poll_once`] polls a future
+//! a single time and returns a [`Polled`], which records what happened to the
+//! waker during that poll, so that the result can be checked with assertions
+//! like [`Polled::assert_woke_itself`].
+//!
+//! [`poll_until_ready`] plays the part of an executor. It keeps polling while
+//! the future wakes itself, and panics if the future returns `Pending`
+//! without having arranged to be woken, because a real executor would never
+//! poll it again.
+#[cfg(test)]
+pub(crate) mod fixtures;
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// A waker which counts what is done with it.
+///
+/// Each call to [`waker`] creates a new waker. All of them, and all of their
+/// clones, share the same counters.
+///
+/// [`waker`]: fn@Self::waker
+#[derive(Clone, Default)]
+pub struct MockWaker {
+    counters: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    created: AtomicUsize,
+    clones: AtomicUsize,
+    wakes: AtomicUsize,
+    wake_by_refs: AtomicUsize,
+    drops: AtomicUsize,
+}
+
+/// A snapshot of the counters of a [`MockWaker`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WakerCounts {
+    /// The number of times a waker was cloned.
+    pub clones: usize,
+    /// The number of times a waker was woken by value, which consumes it.
+    pub wakes: usize,
+    /// The number of times a waker was woken by reference.
+    pub wake_by_refs: usize,
+    /// The number of times a waker was dropped without being woken.
+    pub drops: usize,
+}
+
+impl WakerCounts {
+    /// Returns the number of times a waker was woken, by value or by
+    /// reference.
+    pub fn total_wakes(&self) -> usize {
+        self.wakes + self.wake_by_refs
+    }
+
+    fn since(&self, earlier: &WakerCounts) -> WakerCounts {
+        WakerCounts {
+            clones: self.clones - earlier.clones,
+            wakes: self.wakes - earlier.wakes,
+            wake_by_refs: self.wake_by_refs - earlier.wake_by_refs,
+            drops: self.drops - earlier.drops,
+        }
+    }
+}
+
+impl MockWaker {
+    /// Creates a new mock waker with all counters at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a waker which counts its operations in this mock.
+    pub fn waker(&self) -> Waker {
+        self.counters.created.fetch_add(1, Ordering::SeqCst);
+        let data = Arc::into_raw(Arc::clone(&self.counters)).cast::<()>();
+        // SAFETY: The vtable functions below uphold the `RawWaker` contract,
+        // each waker owns one strong reference to the counters.
+        unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) }
+    }
+
+    /// Returns the current counts.
+    pub fn counts(&self) -> WakerCounts {
+        let counters = &self.counters;
+        WakerCounts {
+            clones: counters.clones.load(Ordering::SeqCst),
+            wakes: counters.wakes.load(Ordering::SeqCst),
+            wake_by_refs: counters.wake_by_refs.load(Ordering::SeqCst),
+            drops: counters.drops.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Returns the number of wakers which currently exist, that is, have been
+    /// created or cloned but not yet woken by value or dropped.
+    pub fn live(&self) -> usize {
+        let created = self.counters.created.load(Ordering::SeqCst);
+        let counts = self.counts();
+        created + counts.clones - counts.wakes - counts.drops
+    }
+}
+
+impl fmt::Debug for MockWaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockWaker")
+            .field("counts", &self.counts())
+            .field("live", &self.live())
+            .finish()
+    }
+}
+
+const VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake_by_ref, drop_waker);
+
+unsafe fn clone_waker(data: *const ()) -> RawWaker {
+    // SAFETY: The waker being cloned is still alive.
+    let counters = unsafe { counters(data) };
+    counters.clones.fetch_add(1, Ordering::SeqCst);
+    // SAFETY: `data` came from `Arc::into_raw` and the waker being cloned
+    // still holds its strong reference, the new waker gets its own.
+    unsafe { Arc::increment_strong_count(data.cast::<Counters>()) };
+    RawWaker::new(data, &VTABLE)
+}
+
+unsafe fn wake(data: *const ()) {
+    // SAFETY: The waker owns one strong reference, which we take back.
+    let counters = unsafe { Arc::from_raw(data.cast::<Counters>()) };
+    counters.wakes.fetch_add(1, Ordering::SeqCst);
+}
+
+unsafe fn wake_by_ref(data: *const ()) {
+    // SAFETY: The waker is still alive, so the counters are too.
+    let counters = unsafe { counters(data) };
+    counters.wake_by_refs.fetch_add(1, Ordering::SeqCst);
+}
+
+unsafe fn drop_waker(data: *const ()) {
+    // SAFETY: The waker owns one strong reference, which we drop.
+    let counters = unsafe { Arc::from_raw(data.cast::<Counters>()) };
+    counters.drops.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Borrows the counters behind a waker's data pointer.
+///
+/// # Safety
+///
+/// `data` must belong to a live waker created by [`MockWaker::waker`].
+unsafe fn counters<'a>(data: *const ()) -> &'a Counters {
+    unsafe { &*data.cast::<Counters>() }
+}
+
+/// The result of polling a future once with [`poll_once`].
+#[derive(Debug)]
+pub struct Polled<T> {
+    /// What the future returned.
+    pub poll: Poll<T>,
+    /// What happened to the waker during the poll.
+    pub counts: WakerCounts,
+}
+
+impl<T> Polled<T> {
+    /// Asserts that the future returned `Ready`, and returns the output.
+    #[track_caller]
+    pub fn assert_ready(self) -> T {
+        match self.poll {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("future returned Pending, expected Ready"),
+        }
+    }
+
+    /// Asserts that the future returned `Pending`.
+    #[track_caller]
+    pub fn assert_pending(&self) {
+        if self.poll.is_ready() {
+            panic!("future returned Ready, expected Pending");
+        }
+    }
+
+    /// Asserts that the future returned `Pending` without keeping a clone of
+    /// the waker or waking it.
+    ///
+    /// Such a future will never be polled again by an executor. That's the
+    /// point of the `Pending` example future, but it's a lost wake up in
+    /// anything else.
+    #[track_caller]
+    pub fn assert_pending_without_waker(&self) {
+        self.assert_pending();
+        if self.counts.clones > self.counts.drops || self.counts.total_wakes() != 0 {
+            panic!(
+                "future registered a waker, expected it not to: {:?}",
+                self.counts
+            );
+        }
+    }
+
+    /// Asserts that the future returned `Pending` after waking itself, so
+    /// that it will be polled again straight away, like `YieldNow`.
+    #[track_caller]
+    pub fn assert_woke_itself(&self) {
+        self.assert_pending();
+        if self.counts.total_wakes() == 0 {
+            panic!(
+                "future didn't wake itself, expected it to: {:?}",
+                self.counts
+            );
+        }
+    }
+
+    /// Asserts that the future returned `Pending` and kept a clone of the
+    /// waker, to be woken later by something else.
+    #[track_caller]
+    pub fn assert_registered_waker(&self) {
+        self.assert_pending();
+        if self.counts.clones <= self.counts.drops + self.counts.wakes {
+            panic!(
+                "future didn't keep a clone of the waker, expected it to: {:?}",
+                self.counts
+            );
+        }
+    }
+}
+
+/// Polls `future` once with a waker from `waker`.
+///
+/// The returned [`Polled`] records what happened to the waker during this
+/// poll only. Futures which report the location they were polled from, like
+/// [`Checked`], report the caller of this function.
+///
+/// [`Checked`]: struct@crate::future::Checked
+#[track_caller]
+pub fn poll_once<F: Future + ?Sized>(future: Pin<&mut F>, waker: &MockWaker) -> Polled<F::Output> {
+    let before = waker.counts();
+    let poll = {
+        let waker = waker.waker();
+        let mut cx = Context::from_waker(&waker);
+        future.poll(&mut cx)
+    };
+    // The waker passed in `cx` has been dropped, which isn't something the
+    // future did, so don't count it.
+    let mut counts = waker.counts().since(&before);
+    counts.drops -= 1;
+
+    Polled { poll, counts }
+}
+
+/// Polls `future` until it returns `Ready`, and returns the output together
+/// with the number of polls it took.
+///
+/// The future is only polled again if it was woken since the previous poll,
+/// just like a real executor would.
+///
+/// # Panics
+///
+/// Panics if the future returns `Pending` and hasn't been woken, or if it
+/// isn't ready after `max_polls` polls.
+#[track_caller]
+pub fn poll_until_ready<F: Future + ?Sized>(
+    mut future: Pin<&mut F>,
+    waker: &MockWaker,
+    max_polls: usize,
+) -> (F::Output, usize) {
+    for polls in 1..=max_polls {
+        let polled = poll_once(future.as_mut(), waker);
+        match polled.poll {
+            Poll::Ready(output) => return (output, polls),
+            Poll::Pending if polled.counts.total_wakes() == 0 => panic!(
+                "future returned Pending without being woken on poll {polls}, \
+                 it would never be polled again"
+            ),
+            Poll::Pending => {}
+        }
+    }
+    panic!("future wasn't ready after {max_polls} polls");
+}
@@ -0,0 +1,265 @@
+//! Futures and streams for the crate's own tests, which record how they are
+//! polled or are completed by hand, and a layer which records `tracing`
+//! events.
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context as LayerContext, Layer};
+use tracing_subscriber::prelude::*;
+
+use crate::stream::Stream;
+
+/// A record of the polls and drops of the futures in a test.
+#[derive(Clone, Default)]
+pub(crate) struct Log {
+    entries: Arc<Mutex<Vec<String>>>,
+}
+
+impl Log {
+    fn push(&self, entry: String) {
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    /// Returns the entries recorded since the last call.
+    pub(crate) fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.entries.lock().unwrap())
+    }
+
+    /// A future which returns `Pending` for its first `pending` polls, waking
+    /// itself each time, and then returns its name.
+    pub(crate) fn probe(&self, name: &'static str, pending: usize) -> Logged<Probe> {
+        self.wrap(name, Probe { name, pending })
+    }
+
+    /// Wraps a future so that its polls and drop are recorded.
+    pub(crate) fn wrap<F>(&self, name: &'static str, future: F) -> Logged<F> {
+        Logged {
+            name,
+            log: self.clone(),
+            future: Box::pin(future),
+        }
+    }
+}
+
+/// A future whose polls and drop are recorded in a [`Log`].
+pub(crate) struct Logged<F> {
+    name: &'static str,
+    log: Log,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Logged<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.log.push(format!("poll {}", self.name));
+        self.future.as_mut().poll(cx)
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for Logged<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.future.fmt(f)
+    }
+}
+
+impl<F> Drop for Logged<F> {
+    fn drop(&mut self) {
+        self.log.push(format!("drop {}", self.name));
+    }
+}
+
+/// The future returned by [`Log::probe`].
+#[derive(Debug)]
+pub(crate) struct Probe {
+    name: &'static str,
+    pending: usize,
+}
+
+impl Future for Probe {
+    type Output = &'static str;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.pending == 0 {
+            return Poll::Ready(self.name);
+        }
+        self.pending -= 1;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// A future which completes once its [`Opener`] has been opened.
+pub(crate) struct Gate {
+    state: Arc<Mutex<(bool, Option<Waker>)>>,
+}
+
+/// Opens a [`Gate`].
+pub(crate) struct Opener {
+    state: Arc<Mutex<(bool, Option<Waker>)>>,
+}
+
+pub(crate) fn gate() -> (Gate, Opener) {
+    let state = Arc::new(Mutex::new((false, None)));
+    (
+        Gate {
+            state: Arc::clone(&state),
+        },
+        Opener { state },
+    )
+}
+
+impl Future for Gate {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        if state.0 {
+            return Poll::Ready(());
+        }
+        state.1 = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Opener {
+    /// Completes the gate, waking the task waiting on it.
+    pub(crate) fn open(&self) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.0 = true;
+            state.1.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// A stream of values pushed by hand with a [`Pusher`], which ends once it's
+/// closed.
+pub(crate) struct Queue {
+    state: Arc<Mutex<QueueState>>,
+}
+
+/// Pushes values onto a [`Queue`].
+pub(crate) struct Pusher {
+    state: Arc<Mutex<QueueState>>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    values: VecDeque<u32>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+pub(crate) fn queue() -> (Queue, Pusher) {
+    let state = Arc::new(Mutex::new(QueueState::default()));
+    (
+        Queue {
+            state: Arc::clone(&state),
+        },
+        Pusher { state },
+    )
+}
+
+impl Stream for Queue {
+    type Item = u32;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.state.lock().unwrap();
+        match state.values.pop_front() {
+            Some(value) => Poll::Ready(Some(value)),
+            None if state.closed => Poll::Ready(None),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Pusher {
+    /// Adds a value to the queue, waking the task waiting on it.
+    pub(crate) fn push(&self, value: u32) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.values.push_back(value);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Ends the stream once the values already pushed have been taken.
+    pub(crate) fn close(&self) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.closed = true;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// A `tracing` layer which records every event.
+#[derive(Clone, Default)]
+pub(crate) struct Capture {
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+
+/// An event recorded by a [`Capture`].
+#[derive(Debug, Clone)]
+pub(crate) struct CapturedEvent {
+    pub(crate) level: Level,
+    pub(crate) target: String,
+    /// The formatted fields, including the event's `message`.
+    pub(crate) fields: HashMap<String, String>,
+}
+
+impl Capture {
+    /// Runs `f` with this layer as the current thread's subscriber.
+    pub(crate) fn run<R>(&self, f: impl FnOnce() -> R) -> R {
+        let subscriber = tracing_subscriber::registry().with(self.clone());
+        tracing::subscriber::with_default(subscriber, f)
+    }
+
+    /// Returns the events recorded since the last call.
+    pub(crate) fn take(&self) -> Vec<CapturedEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Capture {
+    fn on_event(&self, event: &Event<'_>, _ctx: LayerContext<'_, S>) {
+        let mut fields = Fields(HashMap::new());
+        event.record(&mut fields);
+        self.events.lock().unwrap().push(CapturedEvent {
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_owned(),
+            fields: fields.0,
+        });
+    }
+}
+
+struct Fields(HashMap<String, String>);
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_owned(), format!("{value:?}"));
+    }
+}
@@ -0,0 +1,67 @@
+//! Utilities for waiting, with a timer that works on any executor.
+//!
+//! The free functions in this module, such as [`sleep`] and [`timeout`], use
+//! a global [`Timer`] which follows the system's monotonic clock. Its wakers
+//! are woken from a driver thread, so they work with tokio, with our own
+//! [`runtime`], or with anything else which can poll a future.
+//!
+//! To test timing dependent futures deterministically, create a
+//! [`Timer::paused`] and use its methods instead. Its clock only moves when
+//! [`Timer::advance`] is called.
+//!
+//! [`runtime`]: mod@crate::runtime
+mod interval;
+mod sleep;
+mod timer;
+mod wheel;
+
+pub use crate::future::Elapsed;
+pub use interval::{Interval, Tick};
+pub use sleep::Sleep;
+pub use timer::Timer;
+
+/// The future returned by [`timeout`] and [`Timer::timeout`].
+pub type Timeout<F> = crate::future::Timeout<F, Sleep>;
+
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Waits until `duration` has elapsed.
+///
+/// Uses the global timer, see the [module documentation](self).
+pub fn sleep(duration: Duration) -> Sleep {
+    global().sleep(duration)
+}
+
+/// Waits until `deadline` has been reached.
+///
+/// Uses the global timer, see the [module documentation](self).
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    global().sleep_until(deadline)
+}
+
+/// Creates an interval which ticks every `period`, the first tick completes
+/// straight away.
+///
+/// Uses the global timer, see the [module documentation](self).
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+pub fn interval(period: Duration) -> Interval {
+    global().interval(period)
+}
+
+/// Requires `future` to complete before `duration` has elapsed.
+///
+/// Uses the global timer, see the [module documentation](self).
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    global().timeout(duration, future)
+}
+
+/// Returns the global timer, which is created the first time it is used.
+pub(crate) fn global() -> &'static Timer {
+    static GLOBAL: OnceLock<Timer> = OnceLock::new();
+    GLOBAL.get_or_init(Timer::new)
+}
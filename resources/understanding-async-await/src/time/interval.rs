@@ -0,0 +1,81 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use super::Sleep;
+
+/// A stream of ticks, one every period, returned by [`interval`].
+///
+/// If a tick is late by a whole period or more, for example because the task
+/// was busy, the missed ticks are skipped rather than delivered in a burst.
+/// The next tick is then scheduled one period after the late one.
+///
+/// [`interval`]: fn@super::interval
+pub struct Interval {
+    sleep: Sleep,
+    period: Duration,
+}
+
+impl Interval {
+    pub(super) fn new(sleep: Sleep, period: Duration) -> Self {
+        if period.is_zero() {
+            panic!("interval period must be greater than zero");
+        }
+        Self { sleep, period }
+    }
+
+    /// Waits for the next tick.
+    ///
+    /// Returns the instant at which the tick was scheduled. This method is
+    /// cancel safe, if the returned future is dropped, no tick is lost.
+    pub fn tick(&mut self) -> Tick<'_> {
+        Tick { interval: self }
+    }
+
+    /// Polls for the next tick.
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let scheduled = self.sleep.deadline();
+        let now = self.sleep.timer().now();
+        let mut next = scheduled + self.period;
+        if next <= now {
+            next = now + self.period;
+        }
+        self.sleep.reset(next);
+
+        Poll::Ready(scheduled)
+    }
+
+    /// Returns the period of this interval.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+impl fmt::Debug for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interval")
+            .field("next", &self.sleep.deadline())
+            .field("period", &self.period)
+            .finish()
+    }
+}
+
+/// The future returned by [`Interval::tick`].
+#[derive(Debug)]
+pub struct Tick<'a> {
+    interval: &'a mut Interval,
+}
+
+impl Future for Tick<'_> {
+    type Output = Instant;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.interval.poll_tick(cx)
+    }
+}
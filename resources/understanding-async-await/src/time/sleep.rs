@@ -0,0 +1,83 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use super::Timer;
+
+/// The future returned by [`sleep`] and [`sleep_until`].
+///
+/// The waker is only registered with the timer when the future is first
+/// polled, and it's removed again when the future is dropped.
+///
+/// [`sleep`]: fn@super::sleep
+/// [`sleep_until`]: fn@super::sleep_until
+pub struct Sleep {
+    timer: Timer,
+    deadline: Instant,
+    /// The timer wheel tick at which the deadline is reached.
+    tick: u64,
+    /// The id of our timer wheel entry, once the waker has been registered.
+    entry: Option<u64>,
+}
+
+impl Sleep {
+    pub(super) fn new(timer: Timer, deadline: Instant) -> Self {
+        let tick = timer.deadline_tick(deadline);
+        Self {
+            timer,
+            deadline,
+            tick,
+            entry: None,
+        }
+    }
+
+    /// Returns the instant at which this future completes.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Returns whether the deadline has been reached.
+    pub fn is_elapsed(&self) -> bool {
+        self.timer.has_reached(self.tick)
+    }
+
+    pub(super) fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    /// Changes the deadline, which may be earlier or later than the current
+    /// one.
+    ///
+    /// This can also be used after the future has completed, to wait again.
+    pub fn reset(&mut self, deadline: Instant) {
+        self.timer.cancel(self.tick, &mut self.entry);
+        self.deadline = deadline;
+        self.tick = self.timer.deadline_tick(deadline);
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.timer.poll_tick(this.tick, &mut this.entry, cx)
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.timer.cancel(self.tick, &mut self.entry);
+    }
+}
+
+impl fmt::Debug for Sleep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sleep")
+            .field("deadline", &self.deadline)
+            .field("registered", &self.entry.is_some())
+            .finish()
+    }
+}
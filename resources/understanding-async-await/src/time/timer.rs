@@ -0,0 +1,473 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::wheel::Wheel;
+use super::{Interval, Sleep, Timeout};
+
+/// The length of a tick of the timer wheel.
+const TICK: Duration = Duration::from_millis(1);
+
+/// How long the driver thread waits while there are no timers, before
+/// checking whether the timer has been dropped.
+const IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A source of time and timers.
+///
+/// A timer either follows the system's monotonic clock, see [`new`], or is
+/// paused and only moves when it is told to, see [`paused`]. The futures
+/// returned by [`sleep`], [`interval`] and [`timeout`] use the timer they
+/// were created from.
+///
+/// Timers are kept in a hashed timer wheel with a resolution of one
+/// millisecond, a deadline is rounded up to the next whole millisecond since
+/// the timer was created.
+///
+/// Clones of a timer share the same clock and timer wheel.
+///
+/// [`new`]: fn@Self::new
+/// [`paused`]: fn@Self::paused
+/// [`sleep`]: fn@Self::sleep
+/// [`interval`]: fn@Self::interval
+/// [`timeout`]: fn@Self::timeout
+#[derive(Clone)]
+pub struct Timer {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// The time at tick 0.
+    start: Instant,
+    paused: bool,
+    state: Mutex<State>,
+    /// Notified when a timer is added, the driver thread waits on this.
+    condvar: Condvar,
+}
+
+struct State {
+    wheel: Wheel,
+    /// The id which will be given to the next timer.
+    next_id: u64,
+    /// How far a paused timer has been advanced.
+    advanced: Duration,
+    /// Whether the driver thread has been started.
+    driver_started: bool,
+}
+
+impl Timer {
+    /// Creates a new timer which follows the system's monotonic clock.
+    ///
+    /// Wakers are woken from a driver thread, which is started the first
+    /// time a future has to wait, so the timer's futures can be used on any
+    /// executor. The thread exits once every clone of the timer (and every
+    /// future created from it) has been dropped.
+    pub fn new() -> Self {
+        Self::with_paused(false)
+    }
+
+    /// Creates a new paused timer.
+    ///
+    /// The timer's clock starts at the time it was created and stays there
+    /// until [`advance`] is called, no driver thread is started. This makes
+    /// it possible to test timing dependent futures deterministically and
+    /// without waiting.
+    ///
+    /// [`advance`]: fn@Self::advance
+    pub fn paused() -> Self {
+        Self::with_paused(true)
+    }
+
+    fn with_paused(paused: bool) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                start: Instant::now(),
+                paused,
+                state: Mutex::new(State {
+                    wheel: Wheel::new(),
+                    next_id: 0,
+                    advanced: Duration::ZERO,
+                    driver_started: false,
+                }),
+                condvar: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Returns whether this timer is paused.
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused
+    }
+
+    /// Returns the current time according to this timer.
+    pub fn now(&self) -> Instant {
+        let state = self.inner.lock_state();
+        self.inner.now(&state)
+    }
+
+    /// Moves a paused timer forward by `duration`.
+    ///
+    /// Every future waiting for a deadline up to and including the new time
+    /// is woken before this method returns. It still has to be polled by its
+    /// executor before it completes, so a test will usually need to yield
+    /// after advancing the timer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the timer isn't paused.
+    pub fn advance(&self, duration: Duration) {
+        if !self.inner.paused {
+            panic!("only a paused timer can be advanced");
+        }
+
+        let due = {
+            let mut state = self.inner.lock_state();
+            state.advanced += duration;
+            let now = self.inner.tick_at(self.inner.now(&state));
+            state.wheel.advance(now)
+        };
+        // Wake outside of the lock, the woken task may be polled straight
+        // away on another thread.
+        for waker in due {
+            waker.wake();
+        }
+    }
+
+    /// Waits until `duration` has elapsed.
+    pub fn sleep(&self, duration: Duration) -> Sleep {
+        self.sleep_until(self.now() + duration)
+    }
+
+    /// Waits until `deadline` has been reached.
+    pub fn sleep_until(&self, deadline: Instant) -> Sleep {
+        Sleep::new(self.clone(), deadline)
+    }
+
+    /// Creates an interval which ticks every `period`.
+    ///
+    /// The first tick completes straight away.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn interval(&self, period: Duration) -> Interval {
+        Interval::new(self.sleep_until(self.now()), period)
+    }
+
+    /// Requires `future` to complete before `duration` has elapsed.
+    ///
+    /// If the future doesn't complete in time, it is dropped and
+    /// [`Elapsed`] is returned.
+    ///
+    /// [`Elapsed`]: struct@crate::future::Elapsed
+    pub fn timeout<F: Future>(&self, duration: Duration, future: F) -> Timeout<F> {
+        crate::future::timeout(future, self.sleep(duration))
+    }
+
+    /// Returns the tick at which `deadline` is reached, rounding up.
+    pub(super) fn deadline_tick(&self, deadline: Instant) -> u64 {
+        let since_start = deadline.saturating_duration_since(self.inner.start);
+        let tick = self.inner.tick_at(deadline);
+        if since_start > tick_start(tick) {
+            tick + 1
+        } else {
+            tick
+        }
+    }
+
+    /// Returns whether `tick` has been reached.
+    pub(super) fn has_reached(&self, tick: u64) -> bool {
+        let state = self.inner.lock_state();
+        self.inner.tick_at(self.inner.now(&state)) >= tick
+    }
+
+    /// Checks whether `tick` has been reached, registering the waker from
+    /// `cx` in the timer wheel if it hasn't.
+    ///
+    /// `entry` is the id of the timer wheel entry, it is set when the waker
+    /// is first registered and cleared once the tick has been reached.
+    pub(super) fn poll_tick(
+        &self,
+        tick: u64,
+        entry: &mut Option<u64>,
+        cx: &mut Context<'_>,
+    ) -> Poll<()> {
+        let mut state = self.inner.lock_state();
+        // The current tick is read under the lock, so the driver can't have
+        // advanced the wheel past it.
+        let now = self.inner.tick_at(self.inner.now(&state));
+        if now >= tick {
+            if let Some(id) = entry.take() {
+                state.wheel.remove(id, tick);
+            }
+            return Poll::Ready(());
+        }
+
+        if let Some(id) = *entry {
+            if state.wheel.update(id, tick, cx.waker()) {
+                return Poll::Pending;
+            }
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        state.wheel.insert(id, tick, cx.waker().clone());
+        *entry = Some(id);
+        self.notify_driver(&mut state);
+
+        Poll::Pending
+    }
+
+    /// Wakes `waker` once `deadline` has been reached.
+    ///
+    /// This is for wakers which don't belong to a [`Sleep`], the waker is put
+    /// straight into the timer wheel and can't be cancelled. It is never woken
+    /// from within this call: if the deadline has already passed, it is woken
+    /// by the driver thread (or the next call to [`advance`]) instead.
+    ///
+    /// [`advance`]: fn@Self::advance
+    pub(crate) fn wake_at(&self, deadline: Instant, waker: Waker) {
+        let mut state = self.inner.lock_state();
+        // The wheel only accepts ticks which haven't been fired yet.
+        let tick = self.deadline_tick(deadline).max(state.wheel.elapsed() + 1);
+        let id = state.next_id;
+        state.next_id += 1;
+        state.wheel.insert(id, tick, waker);
+        self.notify_driver(&mut state);
+    }
+
+    /// Lets the driver thread know that a timer has been added, starting it
+    /// if this is the first one.
+    ///
+    /// A paused timer has no driver thread, its timers are fired by
+    /// [`advance`].
+    ///
+    /// [`advance`]: fn@Self::advance
+    fn notify_driver(&self, state: &mut State) {
+        if self.inner.paused {
+            return;
+        }
+
+        if !state.driver_started {
+            state.driver_started = true;
+            let inner = Arc::downgrade(&self.inner);
+            thread::Builder::new()
+                .name("timer-driver".into())
+                .spawn(move || Inner::drive(inner))
+                .expect("failed to spawn timer driver thread");
+        }
+        self.inner.condvar.notify_one();
+    }
+
+    /// Removes the timer wheel entry, if there is one.
+    pub(super) fn cancel(&self, tick: u64, entry: &mut Option<u64>) {
+        if let Some(id) = entry.take() {
+            self.inner.lock_state().wheel.remove(id, tick);
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.inner.lock_state();
+        f.debug_struct("Timer")
+            .field("paused", &self.inner.paused)
+            .field("elapsed", &state.wheel.elapsed())
+            .field("timers", &state.wheel.len())
+            .finish()
+    }
+}
+
+impl Inner {
+    fn now(&self, state: &State) -> Instant {
+        if self.paused {
+            self.start + state.advanced
+        } else {
+            Instant::now()
+        }
+    }
+
+    /// Returns the tick which `instant` falls in.
+    fn tick_at(&self, instant: Instant) -> u64 {
+        let since_start = instant.saturating_duration_since(self.start);
+        (since_start.as_nanos() / TICK.as_nanos()) as u64
+    }
+
+    /// The body of the driver thread.
+    ///
+    /// Sleeps until the earliest deadline in the wheel, or until a timer is
+    /// added, then wakes the timers which are due.
+    fn drive(weak: Weak<Inner>) {
+        let mut due: Vec<Waker> = Vec::new();
+        loop {
+            // Wake outside of the lock, the woken task may register a new
+            // timer straight away.
+            for waker in due.drain(..) {
+                waker.wake();
+            }
+
+            let Some(inner) = weak.upgrade() else {
+                return;
+            };
+            let mut state = inner.lock_state();
+            let now = Instant::now();
+            let tick = inner.tick_at(now);
+            due = state.wheel.advance(tick);
+            if !due.is_empty() {
+                continue;
+            }
+
+            let timeout = match state.wheel.next_deadline() {
+                Some(deadline) => {
+                    (inner.start + tick_start(deadline)).saturating_duration_since(now)
+                }
+                // Wake up now and then to check if the timer has been
+                // dropped.
+                None => IDLE_TIMEOUT,
+            };
+            // Adding a timer notifies the condvar, so an earlier deadline is
+            // picked up. Spurious wake ups are fine, the wheel is only
+            // advanced to the current tick.
+            let _ = inner.condvar.wait_timeout(state, timeout);
+        }
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(_) => panic!("Timer state has become corrupted."),
+        }
+    }
+}
+
+/// Returns the time from tick 0 to the start of `tick`.
+fn tick_start(tick: u64) -> Duration {
+    Duration::from_nanos(tick.saturating_mul(TICK.as_nanos() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+    use std::pin::pin;
+    use std::time::{Duration, Instant};
+
+    use super::Timer;
+    use crate::testing::{poll_once, MockWaker};
+
+    fn timers(timer: &Timer) -> usize {
+        timer.inner.lock_state().wheel.len()
+    }
+
+    #[test]
+    fn sleep_completes_once_advanced_to_deadline() {
+        let timer = Timer::paused();
+        let waker = MockWaker::new();
+        let mut sleep = pin!(timer.sleep(Duration::from_millis(10)));
+        poll_once(sleep.as_mut(), &waker).assert_registered_waker();
+
+        timer.advance(Duration::from_millis(9));
+        assert_eq!(waker.counts().total_wakes(), 0);
+        poll_once(sleep.as_mut(), &waker).assert_pending();
+
+        timer.advance(Duration::from_millis(1));
+        assert_eq!(waker.counts().total_wakes(), 1);
+        poll_once(sleep.as_mut(), &waker).assert_ready();
+        assert_eq!(timers(&timer), 0);
+    }
+
+    #[test]
+    fn deadline_is_rounded_up_to_the_next_tick() {
+        let timer = Timer::paused();
+        let waker = MockWaker::new();
+        let mut sleep = pin!(timer.sleep(Duration::from_micros(100)));
+        poll_once(sleep.as_mut(), &waker).assert_registered_waker();
+
+        timer.advance(Duration::from_micros(100));
+        poll_once(sleep.as_mut(), &waker).assert_pending();
+        timer.advance(Duration::from_micros(900));
+        poll_once(sleep.as_mut(), &waker).assert_ready();
+    }
+
+    #[test]
+    fn deadline_more_than_a_revolution_away() {
+        let timer = Timer::paused();
+        let waker = MockWaker::new();
+        let mut sleep = pin!(timer.sleep(Duration::from_millis(1000)));
+        poll_once(sleep.as_mut(), &waker).assert_registered_waker();
+
+        timer.advance(Duration::from_millis(999));
+        assert_eq!(waker.counts().total_wakes(), 0);
+        timer.advance(Duration::from_millis(1));
+        poll_once(sleep.as_mut(), &waker).assert_ready();
+    }
+
+    #[test]
+    fn dropped_sleep_removes_its_timer() {
+        let timer = Timer::paused();
+        let waker = MockWaker::new();
+        {
+            let sleep = pin!(timer.sleep(Duration::from_millis(10)));
+            poll_once(sleep, &waker).assert_registered_waker();
+            assert_eq!(timers(&timer), 1);
+        }
+        assert_eq!(timers(&timer), 0);
+        assert_eq!(waker.live(), 0);
+    }
+
+    #[test]
+    fn interval_ticks_every_period() {
+        let timer = Timer::paused();
+        let start = timer.now();
+        let waker = MockWaker::new();
+        let mut interval = timer.interval(Duration::from_millis(5));
+
+        let first = poll_once(pin!(interval.tick()), &waker).assert_ready();
+        assert_eq!(first, start);
+        for n in 1..=3 {
+            let mut tick = pin!(interval.tick());
+            poll_once(tick.as_mut(), &waker).assert_registered_waker();
+            timer.advance(Duration::from_millis(5));
+            let at = poll_once(tick.as_mut(), &waker).assert_ready();
+            assert_eq!(at, start + Duration::from_millis(5 * n));
+        }
+    }
+
+    #[test]
+    fn timeout_elapses_at_deadline() {
+        let timer = Timer::paused();
+        let waker = MockWaker::new();
+        let mut timeout = pin!(timer.timeout(Duration::from_millis(10), future::pending::<()>()));
+        poll_once(timeout.as_mut(), &waker).assert_registered_waker();
+
+        timer.advance(Duration::from_millis(10));
+        assert!(poll_once(timeout.as_mut(), &waker).assert_ready().is_err());
+    }
+
+    #[test]
+    fn driver_wakes_at_earliest_deadline() {
+        let timer = Timer::new();
+        let start = Instant::now();
+        crate::runtime::block_on(async {
+            let long = timer.sleep(Duration::from_millis(200));
+            let short = timer.sleep(Duration::from_millis(20));
+            crate::future::select(pin!(long), pin!(short)).await;
+        });
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(20),
+            "woke after {elapsed:?}"
+        );
+        assert!(
+            elapsed < Duration::from_millis(200),
+            "woke after {elapsed:?}"
+        );
+    }
+}
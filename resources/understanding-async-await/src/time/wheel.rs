@@ -0,0 +1,149 @@
+use std::task::Waker;
+
+/// The number of slots in the wheel.
+const SLOTS: usize = 256;
+
+/// A hashed timer wheel.
+///
+/// Time is divided into ticks and each timer is put into the slot for its
+/// deadline tick, modulo the number of slots. Timers which are more than one
+/// revolution of the wheel away share a slot with timers which are due
+/// sooner, so each entry stores its full deadline tick and is only fired once
+/// that tick has been reached.
+///
+/// Inserting and removing a timer only touches a single slot. Advancing the
+/// wheel visits each slot between the last tick and the new one, but never
+/// more than one full revolution.
+#[derive(Debug)]
+pub(super) struct Wheel {
+    slots: Vec<Vec<Entry>>,
+    /// The tick up to and including which timers have been fired.
+    elapsed: u64,
+    /// The number of timers in the wheel.
+    len: usize,
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: u64,
+    tick: u64,
+    waker: Waker,
+}
+
+impl Wheel {
+    pub(super) fn new() -> Self {
+        Self {
+            slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+            elapsed: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns whether there are no timers in the wheel.
+    pub(super) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of timers in the wheel.
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the tick up to which timers have been fired.
+    pub(super) fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    /// Returns the earliest tick at which a timer is due, if there are any
+    /// timers.
+    pub(super) fn next_deadline(&self) -> Option<u64> {
+        if self.is_empty() {
+            return None;
+        }
+
+        // Within one revolution, the first slot which holds a timer for that
+        // very tick has the earliest deadline.
+        for tick in self.elapsed + 1..=self.elapsed + SLOTS as u64 {
+            if self.slots[slot(tick)]
+                .iter()
+                .any(|entry| entry.tick == tick)
+            {
+                return Some(tick);
+            }
+        }
+        // Every timer is more than a revolution away.
+        self.slots.iter().flatten().map(|entry| entry.tick).min()
+    }
+
+    /// Adds a timer which will be fired once `tick` has been reached.
+    ///
+    /// The tick must be later than [`elapsed`], a timer which is already due
+    /// shouldn't be put in the wheel at all.
+    ///
+    /// [`elapsed`]: fn@Self::elapsed
+    pub(super) fn insert(&mut self, id: u64, tick: u64, waker: Waker) {
+        debug_assert!(tick > self.elapsed, "timer inserted in the past");
+        self.slots[slot(tick)].push(Entry { id, tick, waker });
+        self.len += 1;
+    }
+
+    /// Replaces the waker of a timer which is in the wheel.
+    ///
+    /// Returns `false` if there is no timer with this id and tick, because it
+    /// has already been fired or removed.
+    pub(super) fn update(&mut self, id: u64, tick: u64, waker: &Waker) -> bool {
+        match self.slots[slot(tick)]
+            .iter_mut()
+            .find(|entry| entry.id == id)
+        {
+            Some(entry) => {
+                if !entry.waker.will_wake(waker) {
+                    entry.waker.clone_from(waker);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a timer which hasn't been fired yet.
+    pub(super) fn remove(&mut self, id: u64, tick: u64) {
+        let slot = &mut self.slots[slot(tick)];
+        if let Some(position) = slot.iter().position(|entry| entry.id == id) {
+            slot.swap_remove(position);
+            self.len -= 1;
+        }
+    }
+
+    /// Moves the wheel forward to `now`, removing every timer which is due.
+    ///
+    /// Returns the wakers of the fired timers, which the caller should wake
+    /// once it no longer holds any locks.
+    pub(super) fn advance(&mut self, now: u64) -> Vec<Waker> {
+        let mut due = Vec::new();
+        if now <= self.elapsed {
+            return due;
+        }
+
+        let ticks = (now - self.elapsed).min(SLOTS as u64);
+        for tick in self.elapsed + 1..=self.elapsed + ticks {
+            let slot = &mut self.slots[slot(tick)];
+            let mut index = 0;
+            while index < slot.len() {
+                if slot[index].tick <= now {
+                    due.push(slot.swap_remove(index).waker);
+                } else {
+                    index += 1;
+                }
+            }
+        }
+        self.len -= due.len();
+        self.elapsed = now;
+
+        due
+    }
+}
+
+fn slot(tick: u64) -> usize {
+    (tick % SLOTS as u64) as usize
+}
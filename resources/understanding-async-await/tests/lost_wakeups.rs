@@ -0,0 +1,49 @@
+//! Checks that the `Pending` example future is reported as a lost wake up.
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::prelude::*;
+use understanding_async_await::future::FutureExt;
+use understanding_async_await::testing::{poll_once, MockWaker};
+
+#[path = "../src/bin/ready_pending.rs"]
+#[allow(dead_code)]
+mod ready_pending;
+
+#[test]
+fn pending_is_reported_once() {
+    let warnings = CountWarnings::default();
+    let subscriber = tracing_subscriber::registry().with(warnings.clone());
+    let waker = MockWaker::new();
+
+    tracing::subscriber::with_default(subscriber, || {
+        let pending = pin!(ready_pending::pending().detect_lost_wakeups());
+        poll_once(pending, &waker).assert_pending();
+    });
+
+    assert_eq!(warnings.count(), 1);
+}
+
+/// Counts the warnings about lost wake ups.
+#[derive(Clone, Default)]
+struct CountWarnings(Arc<AtomicUsize>);
+
+impl CountWarnings {
+    fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl<S: Subscriber> Layer<S> for CountWarnings {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() == Level::WARN
+            && metadata.target() == "understanding_async_await::future::lost_wakeups"
+        {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Checks that the hand-written futures in the examples behave the way their
+//! comments say they do, by polling them by hand with a mock waker.
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::pin;
+use std::sync::Arc;
+
+use understanding_async_await::sync::Mutex;
+use understanding_async_await::testing::{poll_once, poll_until_ready, MockWaker};
+
+#[path = "../src/bin/hello.rs"]
+#[allow(dead_code)]
+mod hello;
+#[path = "../src/bin/mutex_guard_future.rs"]
+#[allow(dead_code)]
+mod mutex_guard_future;
+#[path = "../src/bin/ready_pending.rs"]
+#[allow(dead_code)]
+mod ready_pending;
+#[path = "../src/bin/simplest_async.rs"]
+#[allow(dead_code)]
+mod simplest_async;
+#[path = "../src/bin/two_step_async.rs"]
+#[allow(dead_code)]
+mod two_step_async;
+#[path = "../src/bin/yield_now.rs"]
+#[allow(dead_code)]
+mod yield_now;
+
+#[test]
+fn hello() {
+    let waker = MockWaker::new();
+    let mut hello = pin!(hello::manual_future::hello("world"));
+    poll_once(hello.as_mut(), &waker).assert_ready();
+
+    // Polling a future again after it has returned `Ready` is a bug, this
+    // one panics to say so.
+    expect_panic(|| poll_once(hello, &waker));
+    assert_eq!(waker.live(), 0);
+}
+
+#[test]
+fn add() {
+    let waker = MockWaker::new();
+    let add = pin!(simplest_async::manual_future::add(1, 2));
+    let polled = poll_once(add, &waker);
+    assert_eq!(polled.counts.total_wakes(), 0);
+    assert_eq!(polled.assert_ready(), 3);
+}
+
+#[test]
+fn triple_add() {
+    let waker = MockWaker::new();
+    let mut triple_add = pin!(two_step_async::manual_future::triple_add(1, 2, 3));
+    poll_once(triple_add.as_mut(), &waker).assert_woke_itself();
+    assert_eq!(poll_once(triple_add, &waker).assert_ready(), 6);
+
+    let mut triple_add2 = pin!(two_step_async::manual_future::triple_add2(1, 2, 3));
+    poll_once(triple_add2.as_mut(), &waker).assert_woke_itself();
+    assert_eq!(poll_once(triple_add2, &waker).assert_ready(), 6);
+
+    // The async function should behave just like the hand-written futures.
+    let triple_add = pin!(two_step_async::async_await::triple_add(1, 2, 3));
+    assert_eq!(poll_until_ready(triple_add, &waker, 10), (6, 2));
+
+    assert_eq!(waker.live(), 0);
+}
+
+#[test]
+fn yield_now() {
+    let waker = MockWaker::new();
+    let mut yield_now = pin!(yield_now::manual_future::yield_now());
+    let polled = poll_once(yield_now.as_mut(), &waker);
+    polled.assert_woke_itself();
+    assert_eq!(polled.counts.wake_by_refs, 1);
+    poll_once(yield_now, &waker).assert_ready();
+
+    let yield_now = pin!(yield_now::async_await::yield_now());
+    assert_eq!(poll_until_ready(yield_now, &waker, 10), ((), 2));
+}
+
+#[test]
+fn ready_pending() {
+    let waker = MockWaker::new();
+    poll_once(pin!(ready_pending::ready()), &waker).assert_ready();
+
+    // `Pending` never arranges to be woken, so an executor would never poll
+    // it again.
+    let mut pending = pin!(ready_pending::pending());
+    for _ in 0..3 {
+        poll_once(pending.as_mut(), &waker).assert_pending_without_waker();
+    }
+    expect_panic(|| poll_until_ready(pending, &waker, 10));
+    assert_eq!(waker.live(), 0);
+}
+
+#[test]
+fn hold_mutex_guard() {
+    // Uncontended, the future takes the lock on the first poll and then
+    // yields while holding it.
+    let waker = MockWaker::new();
+    let data = Arc::new(Mutex::new(0_u64));
+    let mut hold = pin!(mutex_guard_future::hold_mutex_guard(Arc::clone(&data)));
+    poll_once(hold.as_mut(), &waker).assert_woke_itself();
+    assert!(
+        data.try_lock().is_none(),
+        "lock should be held across the yield"
+    );
+    poll_once(hold, &waker).assert_ready();
+    assert_eq!(*data.try_lock().expect("lock should be released"), 1);
+
+    // Contended, the future waits for the lock and is woken when it's
+    // released.
+    let waker = MockWaker::new();
+    let guard = data.try_lock().expect("nothing else holds the lock");
+    let mut hold = pin!(mutex_guard_future::hold_mutex_guard(Arc::clone(&data)));
+    poll_once(hold.as_mut(), &waker).assert_registered_waker();
+    assert_eq!(waker.counts().total_wakes(), 0);
+    drop(guard);
+    assert_eq!(waker.counts().total_wakes(), 1);
+    let (_, polls) = poll_until_ready(hold, &waker, 10);
+    assert_eq!(polls, 2);
+    assert_eq!(*data.try_lock().expect("lock should be released"), 2);
+    assert_eq!(waker.live(), 0);
+}
+
+/// Runs `f` and checks that it panics.
+fn expect_panic<R>(f: impl FnOnce() -> R) {
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    assert!(result.is_err(), "expected a panic");
+}
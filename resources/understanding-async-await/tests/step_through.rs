@@ -0,0 +1,45 @@
+//! Checks the trace the stepper renders for the example futures.
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use understanding_async_await::runtime::{Command, Stepper};
+
+#[path = "../src/bin/two_step_async.rs"]
+#[allow(dead_code)]
+mod two_step_async;
+
+/// Collects a stepper's trace.
+#[derive(Clone, Default)]
+struct Trace(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Trace {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn triple_add_shows_its_state_between_steps() {
+    let trace = Trace::default();
+    let mut stepper = Stepper::scripted([Command::Continue]).with_output(trace.clone());
+    stepper.spawn(two_step_async::manual_future::triple_add(1, 2, 3));
+
+    assert!(stepper.run());
+    let trace = String::from_utf8(trace.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<_> = trace.lines().collect();
+    assert!(
+        lines.contains(&"> #1 TripleAdd: Init { x: 1, y: 2, z: 3 } (wakers held: 0)"),
+        "{trace}"
+    );
+    assert!(
+        lines.contains(&"> #1 TripleAdd: Step1 { c: 3, z: 3 } (wakers held: 0)"),
+        "{trace}"
+    );
+    assert!(lines.contains(&"task #1 completed with 6"), "{trace}");
+    assert_eq!(lines.last(), Some(&"all tasks completed after 2 step(s)"));
+}